@@ -0,0 +1,767 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Entropy decoding of baseline JPEG files into planes of dequantized DCT
+//! coefficients. The inverse DCT, chroma upsampling and the conversion to RGB
+//! are left to WebRender, see `YuvData::DctCoefficients`.
+
+use std::cmp;
+
+/// The position of each zigzag ordered coefficient in its 8x8 block.
+static ZIGZAG: [usize; 64] = [
+     0,  1,  8, 16,  9,  2,  3, 10,
+    17, 24, 32, 25, 18, 11,  4,  5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13,  6,  7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// The coefficients of one component, with each 8x8 block of the image
+/// stored as an 8x8 block of the plane in natural order.
+pub struct CoefficientPlane {
+    pub width: u32,
+    pub height: u32,
+    pub coefficients: Vec<i16>,
+}
+
+impl CoefficientPlane {
+    fn new(width: u32, height: u32) -> CoefficientPlane {
+        CoefficientPlane {
+            width: width,
+            height: height,
+            coefficients: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// The coefficients biased by 32768 as little endian `u16`s, packed two
+    /// per `RGBA8` texel, so the image is half the width of the plane.
+    pub fn to_rgba8_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.coefficients.len() * 2);
+        for &coefficient in &self.coefficients {
+            let value = (coefficient as i32 + 32768) as u16;
+            bytes.push(value as u8);
+            bytes.push((value >> 8) as u8);
+        }
+        bytes
+    }
+
+    /// Copies the `width` by `height` coefficients at `x, y`.
+    fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> CoefficientPlane {
+        let mut plane = CoefficientPlane::new(width, height);
+        for row in 0..height {
+            let src = ((y + row) * self.width + x) as usize;
+            let dst = (row * width) as usize;
+            plane.coefficients[dst..dst + width as usize]
+                 .copy_from_slice(&self.coefficients[src..src + width as usize]);
+        }
+        plane
+    }
+}
+
+pub struct DctImage {
+    pub width: u32,
+    pub height: u32,
+    /// The size covered by the planes, which are padded to whole MCUs so
+    /// that they all map to the same area.
+    pub padded_width: u32,
+    pub padded_height: u32,
+    /// The size of an MCU in pixels.
+    pub mcu_width: u32,
+    pub mcu_height: u32,
+    /// The Y, Cb and Cr planes, or only the Y plane of grayscale images.
+    pub planes: Vec<CoefficientPlane>,
+}
+
+impl DctImage {
+    /// Splits the image into parts of whole MCUs, which are at most `max_size`
+    /// pixels on a side unless a single MCU is larger, returning each one with
+    /// its position in the image. The parts of grayscale images get chroma
+    /// planes of a single block of zeros, which decodes to neutral chroma.
+    pub fn split(&self, max_size: u32) -> Vec<(u32, u32, DctImage)> {
+        let chunk_width = cmp::max(max_size / self.mcu_width, 1) * self.mcu_width;
+        let chunk_height = cmp::max(max_size / self.mcu_height, 1) * self.mcu_height;
+
+        let mut chunks = Vec::new();
+        let mut y = 0;
+        while y < self.height {
+            let mut x = 0;
+            while x < self.width {
+                let width = cmp::min(chunk_width, self.width - x);
+                let height = cmp::min(chunk_height, self.height - y);
+                let padded_width = cmp::min(chunk_width, self.padded_width - x);
+                let padded_height = cmp::min(chunk_height, self.padded_height - y);
+
+                // The planes cover the padded image, scaled by the sampling
+                // factor of their component, which maps MCUs to whole blocks.
+                let mut planes: Vec<CoefficientPlane> = self.planes.iter().map(|plane| {
+                    let scale_x = |value: u32| (value as u64 * plane.width as u64 /
+                                                self.padded_width as u64) as u32;
+                    let scale_y = |value: u32| (value as u64 * plane.height as u64 /
+                                                self.padded_height as u64) as u32;
+                    plane.crop(scale_x(x), scale_y(y), scale_x(padded_width), scale_y(padded_height))
+                }).collect();
+                if planes.len() == 1 {
+                    planes.push(CoefficientPlane::new(8, 8));
+                    planes.push(CoefficientPlane::new(8, 8));
+                }
+
+                chunks.push((x, y, DctImage {
+                    width: width,
+                    height: height,
+                    padded_width: padded_width,
+                    padded_height: padded_height,
+                    mcu_width: self.mcu_width,
+                    mcu_height: self.mcu_height,
+                    planes: planes,
+                }));
+                x += chunk_width;
+            }
+            y += chunk_height;
+        }
+        chunks
+    }
+}
+
+struct Component {
+    id: u8,
+    h: u32,
+    v: u32,
+    quant_table: usize,
+}
+
+struct Frame {
+    width: u32,
+    height: u32,
+    max_h: u32,
+    max_v: u32,
+    mcus_x: u32,
+    mcus_y: u32,
+    components: Vec<Component>,
+    planes: Vec<CoefficientPlane>,
+}
+
+/// A Huffman table laid out for the decoding procedure in F.2.2.3 of the spec.
+struct HuffmanTable {
+    max_code: [i32; 16],
+    min_code: [i32; 16],
+    value_index: [i32; 16],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8], values: Vec<u8>) -> HuffmanTable {
+        let mut table = HuffmanTable {
+            max_code: [-1; 16],
+            min_code: [0; 16],
+            value_index: [0; 16],
+            values: values,
+        };
+
+        let mut code = 0;
+        let mut index = 0;
+        for (length, &count) in counts.iter().enumerate() {
+            let count = count as i32;
+            table.value_index[length] = index;
+            table.min_code[length] = code;
+            if count > 0 {
+                table.max_code[length] = code + count - 1;
+            }
+            code = (code + count) << 1;
+            index += count;
+        }
+
+        table
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, String> {
+        let mut code = 0;
+        for length in 0..16 {
+            code = (code << 1) | reader.bit() as i32;
+            if code <= self.max_code[length] {
+                let index = self.value_index[length] + code - self.min_code[length];
+                return self.values.get(index as usize)
+                                  .cloned()
+                                  .ok_or("Invalid Huffman table".to_owned());
+            }
+        }
+        Err("Invalid Huffman code".to_owned())
+    }
+}
+
+/// Reads the entropy coded data of a scan, removing the stuffed zero bytes.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bit(&mut self) -> u32 {
+        if self.count == 0 {
+            self.bits = self.next_byte() as u32;
+            self.count = 8;
+        }
+        self.count -= 1;
+        (self.bits >> self.count) & 1
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        match (self.data.get(self.pos).cloned(), self.data.get(self.pos + 1).cloned()) {
+            (Some(0xFF), Some(0)) => {
+                self.pos += 2;
+                0xFF
+            }
+            // A marker, or the end of the file, pads the data with zeros.
+            (Some(0xFF), _) | (None, _) => 0,
+            (Some(byte), _) => {
+                self.pos += 1;
+                byte
+            }
+        }
+    }
+
+    fn receive(&mut self, count: u32) -> i32 {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.bit() as i32;
+        }
+        value
+    }
+
+    fn receive_extend(&mut self, count: u32) -> i32 {
+        if count == 0 {
+            return 0;
+        }
+        let value = self.receive(count);
+        if value < 1 << (count - 1) {
+            value - (1 << count) + 1
+        } else {
+            value
+        }
+    }
+
+    /// Drops the remaining bits and skips the next RSTn marker.
+    fn restart(&mut self) {
+        self.count = 0;
+        if self.pos + 1 < self.data.len() && self.data[self.pos] == 0xFF &&
+           is_restart_marker(self.data[self.pos + 1]) {
+            self.pos += 2;
+        }
+    }
+}
+
+fn is_restart_marker(marker: u8) -> bool {
+    marker >= 0xD0 && marker <= 0xD7
+}
+
+fn read_u16(data: &[u8], pos: usize) -> u32 {
+    ((data[pos] as u32) << 8) | data[pos + 1] as u32
+}
+
+fn parse_frame(segment: &[u8]) -> Result<Frame, String> {
+    if segment.len() < 6 || segment[0] != 8 {
+        return Err("Only 8 bit JPEG files are supported".to_owned());
+    }
+
+    let height = read_u16(segment, 1);
+    let width = read_u16(segment, 3);
+    let component_count = segment[5] as usize;
+    if component_count != 1 && component_count != 3 {
+        return Err("Only grayscale and YCbCr JPEG files are supported".to_owned());
+    }
+    if width == 0 || height == 0 || segment.len() < 6 + component_count * 3 {
+        return Err("Invalid frame header".to_owned());
+    }
+
+    let mut components = Vec::new();
+    for i in 0..component_count {
+        let data = &segment[6 + i * 3..9 + i * 3];
+        let (h, v) = ((data[1] >> 4) as u32, (data[1] & 15) as u32);
+        if h == 0 || h > 4 || v == 0 || v > 4 || data[2] > 3 {
+            return Err("Invalid frame header".to_owned());
+        }
+        components.push(Component {
+            id: data[0],
+            h: h,
+            v: v,
+            quant_table: data[2] as usize,
+        });
+    }
+
+    let max_h = components.iter().map(|c| c.h).max().unwrap();
+    let max_v = components.iter().map(|c| c.v).max().unwrap();
+    let mcus_x = (width + 8 * max_h - 1) / (8 * max_h);
+    let mcus_y = (height + 8 * max_v - 1) / (8 * max_v);
+
+    let planes = components.iter().map(|c| {
+        CoefficientPlane::new(mcus_x * c.h * 8, mcus_y * c.v * 8)
+    }).collect();
+
+    Ok(Frame {
+        width: width,
+        height: height,
+        max_h: max_h,
+        max_v: max_v,
+        mcus_x: mcus_x,
+        mcus_y: mcus_y,
+        components: components,
+        planes: planes,
+    })
+}
+
+fn decode_block(reader: &mut BitReader,
+                dc_table: &HuffmanTable,
+                ac_table: &HuffmanTable,
+                quant_table: &[u16; 64],
+                dc_prediction: &mut i32,
+                plane: &mut CoefficientPlane,
+                block_x: u32,
+                block_y: u32) -> Result<(), String> {
+    let mut block = [0i32; 64];
+
+    let size = try!(dc_table.decode(reader)) as u32;
+    if size > 11 {
+        return Err("Invalid DC coefficient size".to_owned());
+    }
+    *dc_prediction += reader.receive_extend(size);
+    block[0] = *dc_prediction * quant_table[0] as i32;
+
+    let mut k = 1;
+    while k < 64 {
+        let rs = try!(ac_table.decode(reader));
+        let (run, size) = ((rs >> 4) as usize, (rs & 15) as u32);
+        if size == 0 {
+            if run != 15 {
+                // End of block.
+                break;
+            }
+            k += 16;
+            continue;
+        }
+        k += run;
+        if k > 63 {
+            return Err("Invalid AC coefficient run".to_owned());
+        }
+        block[ZIGZAG[k]] = reader.receive_extend(size) * quant_table[k] as i32;
+        k += 1;
+    }
+
+    let origin = (block_y * 8 * plane.width + block_x * 8) as usize;
+    for (i, &coefficient) in block.iter().enumerate() {
+        let index = origin + (i / 8) * plane.width as usize + i % 8;
+        plane.coefficients[index] = cmp::max(cmp::min(coefficient, 32767), -32768) as i16;
+    }
+
+    Ok(())
+}
+
+/// Decodes the scan whose entropy coded data starts at `pos`, returning the
+/// position where it ends.
+fn decode_scan(data: &[u8],
+               pos: usize,
+               header: &[u8],
+               frame: &mut Frame,
+               dc_tables: &[Option<HuffmanTable>],
+               ac_tables: &[Option<HuffmanTable>],
+               quant_tables: &[[u16; 64]; 4],
+               restart_interval: u32) -> Result<usize, String> {
+    let component_count = header[0] as usize;
+    if component_count == 0 || header.len() < 1 + component_count * 2 + 3 {
+        return Err("Invalid scan header".to_owned());
+    }
+
+    // (component index, DC table, AC table) for each component of the scan.
+    let mut scan_components = Vec::new();
+    for i in 0..component_count {
+        let id = header[1 + i * 2];
+        let tables = header[2 + i * 2];
+        let index = match frame.components.iter().position(|c| c.id == id) {
+            Some(index) => index,
+            None => return Err("Scan refers to an unknown component".to_owned()),
+        };
+        let dc_table = match dc_tables.get((tables >> 4) as usize) {
+            Some(&Some(ref table)) => table,
+            _ => return Err("Scan refers to a missing Huffman table".to_owned()),
+        };
+        let ac_table = match ac_tables.get((tables & 15) as usize) {
+            Some(&Some(ref table)) => table,
+            _ => return Err("Scan refers to a missing Huffman table".to_owned()),
+        };
+        scan_components.push((index, dc_table, ac_table));
+    }
+
+    // A scan with a single component is not interleaved, so each MCU is one
+    // block and the blocks only cover that component.
+    let (mcus_x, mcus_y) = if component_count == 1 {
+        let component = &frame.components[scan_components[0].0];
+        let width = (frame.width * component.h + frame.max_h - 1) / frame.max_h;
+        let height = (frame.height * component.v + frame.max_v - 1) / frame.max_v;
+        ((width + 7) / 8, (height + 7) / 8)
+    } else {
+        (frame.mcus_x, frame.mcus_y)
+    };
+
+    let mut reader = BitReader {
+        data: data,
+        pos: pos,
+        bits: 0,
+        count: 0,
+    };
+    let mut dc_predictions = vec![0; component_count];
+
+    for mcu in 0..mcus_x * mcus_y {
+        if restart_interval != 0 && mcu != 0 && mcu % restart_interval == 0 {
+            reader.restart();
+            for prediction in &mut dc_predictions {
+                *prediction = 0;
+            }
+        }
+
+        let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+        for (i, &(index, dc_table, ac_table)) in scan_components.iter().enumerate() {
+            let (blocks_x, blocks_y) = if component_count == 1 {
+                (1, 1)
+            } else {
+                (frame.components[index].h, frame.components[index].v)
+            };
+            let quant_table = &quant_tables[frame.components[index].quant_table];
+            for y in 0..blocks_y {
+                for x in 0..blocks_x {
+                    try!(decode_block(&mut reader,
+                                      dc_table,
+                                      ac_table,
+                                      quant_table,
+                                      &mut dc_predictions[i],
+                                      &mut frame.planes[index],
+                                      mcu_x * blocks_x + x,
+                                      mcu_y * blocks_y + y));
+                }
+            }
+        }
+    }
+
+    Ok(reader.pos)
+}
+
+/// Decodes the DCT coefficients of a baseline JPEG file.
+pub fn decode(data: &[u8]) -> Result<DctImage, String> {
+    if data.len() < 2 || data[0] != 0xFF || data[1] != 0xD8 {
+        return Err("Not a JPEG file".to_owned());
+    }
+
+    let mut frame = None;
+    let mut quant_tables = [[1u16; 64]; 4];
+    let mut dc_tables = vec![None, None, None, None];
+    let mut ac_tables = vec![None, None, None, None];
+    let mut restart_interval = 0;
+    let mut pos = 2;
+
+    loop {
+        // Skip to the next marker, which also steps over any padding left
+        // at the end of a scan.
+        while pos + 1 < data.len() &&
+              !(data[pos] == 0xFF && data[pos + 1] != 0 && data[pos + 1] != 0xFF &&
+                !is_restart_marker(data[pos + 1])) {
+            pos += 1;
+        }
+        if pos + 1 >= data.len() {
+            return Err("Unexpected end of file".to_owned());
+        }
+
+        let marker = data[pos + 1];
+        pos += 2;
+        if marker == 0xD9 {
+            break;
+        }
+
+        if pos + 2 > data.len() || pos + (read_u16(data, pos) as usize) > data.len() {
+            return Err("Unexpected end of file".to_owned());
+        }
+        let length = read_u16(data, pos) as usize;
+        if length < 2 {
+            return Err("Invalid segment length".to_owned());
+        }
+        let segment = &data[pos + 2..pos + length];
+        pos += length;
+
+        match marker {
+            0xC0 | 0xC1 => {
+                frame = Some(try!(parse_frame(segment)));
+            }
+            0xC2 | 0xC3 | 0xC5 | 0xC6 | 0xC7 | 0xC9 | 0xCA | 0xCB | 0xCD | 0xCE | 0xCF => {
+                return Err("Only baseline JPEG files are supported".to_owned());
+            }
+            0xC4 => {
+                let mut offset = 0;
+                while offset + 17 <= segment.len() {
+                    let class = segment[offset] >> 4;
+                    let index = (segment[offset] & 15) as usize;
+                    let counts = &segment[offset + 1..offset + 17];
+                    let count = counts.iter().fold(0, |sum, &count| sum + count as usize);
+                    if index > 3 || offset + 17 + count > segment.len() {
+                        return Err("Invalid Huffman table".to_owned());
+                    }
+                    let values = segment[offset + 17..offset + 17 + count].to_vec();
+                    let table = Some(HuffmanTable::new(counts, values));
+                    if class == 0 {
+                        dc_tables[index] = table;
+                    } else {
+                        ac_tables[index] = table;
+                    }
+                    offset += 17 + count;
+                }
+            }
+            0xDB => {
+                let mut offset = 0;
+                while offset < segment.len() {
+                    let precision = segment[offset] >> 4;
+                    let index = (segment[offset] & 15) as usize;
+                    let entry_size = if precision == 0 { 1 } else { 2 };
+                    if index > 3 || offset + 1 + 64 * entry_size > segment.len() {
+                        return Err("Invalid quantization table".to_owned());
+                    }
+                    for k in 0..64 {
+                        let entry = offset + 1 + k * entry_size;
+                        quant_tables[index][k] = if precision == 0 {
+                            segment[entry] as u16
+                        } else {
+                            read_u16(segment, entry) as u16
+                        };
+                    }
+                    offset += 1 + 64 * entry_size;
+                }
+            }
+            0xDD => {
+                if segment.len() < 2 {
+                    return Err("Invalid restart interval".to_owned());
+                }
+                restart_interval = read_u16(segment, 0);
+            }
+            0xDA => {
+                let frame = match frame {
+                    Some(ref mut frame) => frame,
+                    None => return Err("Scan before the frame header".to_owned()),
+                };
+                pos = try!(decode_scan(data,
+                                       pos,
+                                       segment,
+                                       frame,
+                                       &dc_tables,
+                                       &ac_tables,
+                                       &quant_tables,
+                                       restart_interval));
+            }
+            _ => {}
+        }
+    }
+
+    let frame = match frame {
+        Some(frame) => frame,
+        None => return Err("Missing frame header".to_owned()),
+    };
+
+    Ok(DctImage {
+        width: frame.width,
+        height: frame.height,
+        padded_width: frame.mcus_x * frame.max_h * 8,
+        padded_height: frame.mcus_y * frame.max_v * 8,
+        mcu_width: frame.max_h * 8,
+        mcu_height: frame.max_v * 8,
+        planes: frame.planes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CoefficientPlane, DctImage, decode};
+
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let length = payload.len() + 2;
+        let mut bytes = vec![0xFF, marker, (length >> 8) as u8, length as u8];
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    /// Packs a string of bits into entropy coded data, padding the last byte
+    /// with ones and stuffing a zero after any 0xFF byte.
+    fn entropy_data(bits: &str) -> Vec<u8> {
+        let mut bits: Vec<u8> = bits.bytes().filter(|&bit| bit != b' ').map(|bit| bit - b'0').collect();
+        while bits.len() % 8 != 0 {
+            bits.push(1);
+        }
+        let mut bytes = Vec::new();
+        for byte_bits in bits.chunks(8) {
+            let byte = byte_bits.iter().fold(0, |byte, &bit| (byte << 1) | bit);
+            bytes.push(byte);
+            if byte == 0xFF {
+                bytes.push(0);
+            }
+        }
+        bytes
+    }
+
+    /// Builds a baseline JPEG file whose components, given as (id, sampling
+    /// factors), all use a quantization table of `quant` and these Huffman
+    /// tables:
+    ///
+    /// DC: `00` is a difference of size 0, `01` one of size 4.
+    /// AC: `00` ends the block, `01` is a run of 0 and size 2, `10` a run of 3
+    /// and size 1.
+    fn jpeg_file(width: u8, height: u8, quant: u8, components: &[(u8, u8)], bits: &str) -> Vec<u8> {
+        let mut file = vec![0xFF, 0xD8];
+
+        let mut dqt = vec![0x00];
+        dqt.extend_from_slice(&[quant; 64]);
+        file.extend(segment(0xDB, &dqt));
+
+        let mut sof = vec![8, 0, height, 0, width, components.len() as u8];
+        for &(id, sampling) in components {
+            sof.extend_from_slice(&[id, sampling, 0]);
+        }
+        file.extend(segment(0xC0, &sof));
+
+        let mut dht = vec![0x00, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x04];
+        dht.extend_from_slice(&[0x10, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x02, 0x31]);
+        file.extend(segment(0xC4, &dht));
+
+        let mut sos = vec![components.len() as u8];
+        for &(id, _) in components {
+            sos.extend_from_slice(&[id, 0x00]);
+        }
+        sos.extend_from_slice(&[0, 63, 0]);
+        file.extend(segment(0xDA, &sos));
+
+        file.extend(entropy_data(bits));
+        file.extend_from_slice(&[0xFF, 0xD9]);
+        file
+    }
+
+    /// Returns the coefficients of the 8x8 block at `block_x, block_y`.
+    fn block(plane: &CoefficientPlane, block_x: u32, block_y: u32) -> Vec<i16> {
+        let mut coefficients = Vec::new();
+        for y in 0..8 {
+            let start = ((block_y * 8 + y) * plane.width + block_x * 8) as usize;
+            coefficients.extend_from_slice(&plane.coefficients[start..start + 8]);
+        }
+        coefficients
+    }
+
+    /// A block with only its DC coefficient set.
+    fn dc_block(dc: i16) -> Vec<i16> {
+        let mut coefficients = vec![0; 64];
+        coefficients[0] = dc;
+        coefficients
+    }
+
+    #[test]
+    fn grayscale() {
+        // A DC difference of 12, then -3 at the first AC coefficient, and 1
+        // after a run of three zeros, which is the second one in natural order.
+        let file = jpeg_file(8, 8, 1, &[(1, 0x11)], "01 1100 01 00 10 1 00");
+        let image = decode(&file).unwrap();
+        assert_eq!((image.width, image.height), (8, 8));
+        assert_eq!((image.padded_width, image.padded_height), (8, 8));
+        assert_eq!((image.mcu_width, image.mcu_height), (8, 8));
+        assert_eq!(image.planes.len(), 1);
+
+        let mut expected = dc_block(12);
+        expected[1] = -3;
+        expected[2] = 1;
+        assert_eq!(block(&image.planes[0], 0, 0), expected);
+    }
+
+    #[test]
+    fn subsampled_chroma() {
+        // A single MCU of four luma blocks and one block of each chroma
+        // component. The DC coefficients are predicted from the previous
+        // block of their component, and everything is dequantized by 2.
+        let file = jpeg_file(16, 16, 2, &[(1, 0x22), (2, 0x11), (3, 0x11)],
+                             "01 1100 00  00 00  01 0011 00  01 1100 00  \
+                              00 01 11 00  01 1000 00");
+        let image = decode(&file).unwrap();
+        assert_eq!((image.width, image.height), (16, 16));
+        assert_eq!((image.mcu_width, image.mcu_height), (16, 16));
+        assert_eq!(image.planes.len(), 3);
+        assert_eq!((image.planes[0].width, image.planes[0].height), (16, 16));
+        assert_eq!((image.planes[1].width, image.planes[1].height), (8, 8));
+
+        assert_eq!(block(&image.planes[0], 0, 0), dc_block(24));
+        assert_eq!(block(&image.planes[0], 1, 0), dc_block(24));
+        assert_eq!(block(&image.planes[0], 0, 1), dc_block(0));
+        assert_eq!(block(&image.planes[0], 1, 1), dc_block(24));
+
+        let mut cb = dc_block(0);
+        cb[1] = 6;
+        assert_eq!(block(&image.planes[1], 0, 0), cb);
+        assert_eq!(block(&image.planes[2], 0, 0), dc_block(16));
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(decode(&[0x89, 0x50, 0x4E, 0x47]).is_err());
+
+        // Cut off in the middle of the scan, before the end of image marker.
+        let file = jpeg_file(8, 8, 1, &[(1, 0x11)], "01 1100 00");
+        assert!(decode(&file[..file.len() - 2]).is_err());
+
+        // A progressive frame header.
+        let mut file = jpeg_file(8, 8, 1, &[(1, 0x11)], "01 1100 00");
+        let sof = file.windows(2).position(|marker| marker == [0xFF, 0xC0]).unwrap();
+        file[sof + 1] = 0xC2;
+        assert!(decode(&file).is_err());
+
+        // An AC run past the end of the block.
+        let file = jpeg_file(8, 8, 1, &[(1, 0x11)], "00 10 1 10 1 10 1 10 1 10 1 10 1 10 1 10 1 \
+                                                    10 1 10 1 10 1 10 1 10 1 10 1 10 1 10 1 10 1");
+        assert!(decode(&file).is_err());
+    }
+
+    #[test]
+    fn rgba8_packing() {
+        let plane = CoefficientPlane {
+            width: 2,
+            height: 1,
+            coefficients: vec![-3, 1],
+        };
+        assert_eq!(plane.to_rgba8_bytes(), vec![0xFD, 0x7F, 0x01, 0x80]);
+    }
+
+    #[test]
+    fn split() {
+        // A grayscale image two and a half blocks wide, whose columns are
+        // numbered by their coefficients.
+        let image = DctImage {
+            width: 20,
+            height: 8,
+            padded_width: 24,
+            padded_height: 8,
+            mcu_width: 8,
+            mcu_height: 8,
+            planes: vec![CoefficientPlane {
+                width: 24,
+                height: 8,
+                coefficients: (0..8).flat_map(|_| 0..24).collect(),
+            }],
+        };
+
+        let chunks = image.split(16);
+        assert_eq!(chunks.len(), 2);
+
+        let (x, y, ref first) = chunks[0];
+        assert_eq!((x, y, first.width, first.padded_width), (0, 0, 16, 16));
+        assert_eq!(&first.planes[0].coefficients[..16], &(0..16).collect::<Vec<i16>>()[..]);
+
+        let (x, y, ref second) = chunks[1];
+        assert_eq!((x, y, second.width, second.padded_width), (16, 0, 4, 8));
+        assert_eq!(&second.planes[0].coefficients[..8], &(16..24).collect::<Vec<i16>>()[..]);
+
+        // Grayscale parts get neutral chroma planes.
+        assert_eq!(second.planes.len(), 3);
+        assert!(second.planes[1].coefficients.iter().all(|&coefficient| coefficient == 0));
+    }
+}
@@ -4,14 +4,16 @@ extern crate image;
 extern crate webrender;
 extern crate webrender_traits;
 
+mod jpeg;
+
 use gleam::gl;
 use image::GenericImage;
 use std::fs::File;
 use std::io::Read;
 use std::env;
-use webrender::{ExternalImageHandler, ExternalImage, ExternalImageSource};
-use webrender_traits::{ColorF, Epoch, ExternalImageId, ImageData, ImageFormat, ImageRendering};
-use webrender_traits::{ClipRegion, DeviceUintSize, DisplayListBuilder, LayoutPoint, LayoutRect, LayoutSize, LayoutTransform, PipelineId, RendererKind};
+use webrender_traits::{ColorF, Epoch, ImageData, ImageFormat, ImageKey, ImageRendering, RenderApi};
+use webrender_traits::{ClipRegion, DeviceUintPoint, DeviceUintRect, DeviceUintSize, DisplayListBuilder, LayoutPoint, LayoutRect, LayoutSize, LayoutTransform, PipelineId, RendererKind};
+use webrender_traits::{YuvColorRange, YuvColorSpace, YuvData};
 
 fn load_file(name: &str) -> Vec<u8> {
     let mut file = File::open(name).unwrap();
@@ -20,19 +22,98 @@ fn load_file(name: &str) -> Vec<u8> {
     buffer
 }
 
-struct Handler;
+enum DecodedImage {
+    /// Entropy decoded on the CPU, the rest is done by WebRender.
+    Coefficients(jpeg::DctImage),
+    /// Fully decoded on the CPU, for files the entropy decoder can't handle.
+    Pixels(u32, u32, Vec<u8>),
+}
 
-impl ExternalImageHandler for Handler {
-    fn lock(&mut self, key: ExternalImageId) -> ExternalImage {
-        //println!("in lock, key = {:?}", key);
-        ExternalImage {u0: 0.0, v0: 0.0, u1: 1.0, v1: 1.0, source: ExternalImageSource::NativeTexture(0) }
-    }
-    fn unlock(&mut self, key: ExternalImageId) {
-        //println!("in unlock, key = {:?}", key);
+impl DecodedImage {
+    fn dimensions(&self) -> (u32, u32) {
+        match *self {
+            DecodedImage::Coefficients(ref image) => (image.width, image.height),
+            DecodedImage::Pixels(width, height, _) => (width, height),
+        }
     }
-    fn release(&mut self, key: ExternalImageId) {
-        //println!("in release, key = {:?}", key);
+}
+
+fn decode(data: &[u8]) -> Result<DecodedImage, image::ImageError> {
+    match jpeg::decode(data) {
+        Ok(image) => return Ok(DecodedImage::Coefficients(image)),
+        Err(err) => println!("Decoding on the CPU: {}", err),
     }
+
+    let image = try!(image::load_from_memory_with_format(data, image::ImageFormat::JPEG));
+    let (width, height) = image.dimensions();
+    Ok(DecodedImage::Pixels(width, height, image.to_rgb().into_raw()))
+}
+
+/// The largest size in pixels of the parts an image of DCT coefficients is
+/// split into, which keeps their planes small enough to be decoded by render
+/// tasks in one go.
+const MAX_PART_SIZE: u32 = 1024;
+
+/// Uploads the decoded image and returns the keys `push_image` can draw,
+/// along with the rect in pixels of the image each of them covers.
+///
+/// The coefficient planes are turned into pixels by rendering a YUV image
+/// display list into an offscreen image, so the inverse DCT, chroma
+/// upsampling and color conversion all run on the GPU.
+fn add_image(api: &RenderApi, image: DecodedImage) -> Vec<(DeviceUintRect, ImageKey)> {
+    let image = match image {
+        DecodedImage::Coefficients(image) => image,
+        DecodedImage::Pixels(width, height, pixels) => {
+            let rect = DeviceUintRect::new(DeviceUintPoint::zero(), DeviceUintSize::new(width, height));
+            let key = api.add_image(width, height, None, ImageFormat::RGB8, ImageData::new(pixels));
+            return vec![(rect, key)];
+        }
+    };
+
+    image.split(MAX_PART_SIZE).into_iter().enumerate().map(|(index, (x, y, part))| {
+        // Each texel of a plane holds two coefficients.
+        let planes: Vec<ImageKey> = part.planes.iter().map(|plane| {
+            api.add_image(plane.width / 2,
+                          plane.height,
+                          None,
+                          ImageFormat::RGBA8,
+                          ImageData::new(plane.to_rgba8_bytes()))
+        }).collect();
+
+        let mut builder = DisplayListBuilder::new(PipelineId(1, index as u32));
+        let bounds = LayoutRect::new(LayoutPoint::new(0.0, 0.0),
+                                     LayoutSize::new(part.width as f32, part.height as f32));
+        let clip_region = builder.new_clip_region(&bounds, vec![], None);
+        builder.push_stacking_context(webrender_traits::ScrollPolicy::Scrollable,
+                                      bounds,
+                                      clip_region,
+                                      0,
+                                      &LayoutTransform::identity(),
+                                      &LayoutTransform::identity(),
+                                      webrender_traits::MixBlendMode::Normal,
+                                      Vec::new());
+
+        // The planes cover whole MCUs, the padding falls outside of the image.
+        let rect = LayoutRect::new(LayoutPoint::new(0.0, 0.0),
+                                   LayoutSize::new(part.padded_width as f32, part.padded_height as f32));
+        builder.push_yuv_image(rect,
+                               ClipRegion::simple(&bounds),
+                               YuvData::DctCoefficients(planes[0], planes[1], planes[2]),
+                               YuvColorSpace::Rec601,
+                               YuvColorRange::Full);
+        builder.pop_stacking_context();
+
+        // Rendered at a device pixel ratio of 1, so the image has a texel
+        // for each pixel of the part.
+        let key = api.render_display_list_to_image(None,
+                                                   Epoch(0),
+                                                   LayoutSize::new(part.width as f32, part.height as f32),
+                                                   1.0,
+                                                   builder);
+        let rect = DeviceUintRect::new(DeviceUintPoint::new(x, y),
+                                       DeviceUintSize::new(part.width, part.height));
+        (rect, key)
+    }).collect()
 }
 
 struct Notifier {
@@ -68,10 +149,9 @@ fn main() {
     if args.len() > 1 {
         let name = &args[1];
         let slice = &load_file(name);
-        let img = image::load_from_memory_with_format(slice, image::ImageFormat::JPEG);
-        match img {
-            Ok(data) => {
-                let (width, height) = data.dimensions();
+        match decode(slice) {
+            Ok(decoded) => {
+                let (width, height) = decoded.dimensions();
                 println!("Image size {}x{}", width, height);
 
                 let window = glutin::WindowBuilder::new()
@@ -89,14 +169,16 @@ fn main() {
 
                 println!("OpenGL version {}", gl::get_string(gl::VERSION));
 
-                {
-                    let (width, height) = window.get_inner_size().unwrap();
-
-                    println!("Width {} height {}", width, height);
-                }
+                // The image is drawn with a layout pixel per device pixel
+                // of the window, so that it isn't scaled on HiDPI displays.
+                let (width, height) = window.get_inner_size().unwrap();
+                let device_pixel_ratio = window.hidpi_factor();
+                let layout_size = LayoutSize::new(width as f32 / device_pixel_ratio,
+                                                  height as f32 / device_pixel_ratio);
+                println!("Width {} height {}, device pixel ratio {}", width, height, device_pixel_ratio);
 
                 let opts = webrender::RendererOptions {
-                    device_pixel_ratio: 1.0,
+                    device_pixel_ratio: device_pixel_ratio,
                     resource_override_path: None,
                     enable_aa: false,
                     enable_profiler: true,
@@ -109,10 +191,12 @@ fn main() {
                     clear_framebuffer: true,
                     clear_color: ColorF::new(1.0, 1.0, 1.0, 1.0),
                     render_target_debug: false,
+                    texture_cache_budget: None,
                 };
 
                 let (mut renderer, sender) = webrender::renderer::Renderer::new(opts);
                 let api = sender.create_api();
+                let document_id = api.add_document();
 
                 let notifier = Box::new(Notifier::new(window.create_window_proxy()));
                 renderer.set_render_notifier(notifier);
@@ -123,7 +207,7 @@ fn main() {
                 let pipeline_id = PipelineId(0, 0);
                 let mut builder = DisplayListBuilder::new(pipeline_id);
 
-                    let bounds = LayoutRect::new(LayoutPoint::new(0.0, 0.0), LayoutSize::new(width as f32, height as f32));
+                    let bounds = LayoutRect::new(LayoutPoint::new(0.0, 0.0), layout_size);
                     let clip_region = builder.new_clip_region(&bounds, vec![], None);
 
                     builder.push_stacking_context(webrender_traits::ScrollPolicy::Scrollable,
@@ -135,40 +219,33 @@ fn main() {
                                                   webrender_traits::MixBlendMode::Normal,
                                                   Vec::new());
 
-                renderer.set_external_image_handler(Box::new(Handler));
-
-                let bounds = LayoutRect::new(LayoutPoint::new(0.0, 0.0), LayoutSize::new(width as f32, height as f32));
-                let clip = ClipRegion::simple(&bounds);
-                let size = LayoutSize::new(width as f32, height as f32);
-                let spacing = LayoutSize::new(0.0, 0.0);
+                for (rect, image_key) in add_image(&api, decoded) {
+                    let bounds = LayoutRect::new(LayoutPoint::new(rect.origin.x as f32 / device_pixel_ratio,
+                                                                  rect.origin.y as f32 / device_pixel_ratio),
+                                                 LayoutSize::new(rect.size.width as f32 / device_pixel_ratio,
+                                                                 rect.size.height as f32 / device_pixel_ratio));
+                    let clip = ClipRegion::simple(&bounds);
+                    let spacing = LayoutSize::new(0.0, 0.0);
 
-                builder.push_image(bounds, clip, size, spacing, ImageRendering::Auto,
-                    api.add_image(width, height, None, ImageFormat::RGBA8, ImageData::External(ExternalImageId(0))));
+                    builder.push_image(bounds, clip, bounds.size, spacing, ImageRendering::Auto, image_key);
+                }
 
                 builder.pop_stacking_context();
 
                 api.set_root_display_list(
                     Some(root_background_color),
                     epoch,
-                    LayoutSize::new(width as f32, height as f32),
+                    layout_size,
                     builder);
-                api.set_root_pipeline(pipeline_id);
+                api.set_root_pipeline(document_id, pipeline_id);
 
                 let mut done = false;
 
                 while !done {
-                    let img = image::load_from_memory_with_format(slice, image::ImageFormat::JPEG);
-                    match img {
-                        Err(err) => {
-                            println!("Error loading image {}: {}", name, err)
-                        }
-                        _ => {}
-                    }
-
                     gl::clear(gl::COLOR_BUFFER_BIT);
                     renderer.update();
 
-                    renderer.render(DeviceUintSize::new(width, height));
+                    renderer.render(document_id, DeviceUintSize::new(width, height));
 
                     window.swap_buffers().ok();
 
@@ -16,6 +16,7 @@ use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::env;
 use webrender_traits::{ApiMsg, RenderApi, PipelineId, LayerSize, DeviceUintSize, ColorF};
+use webrender_traits::DocumentId;
 use webrender_traits::channel::PayloadHelperMethods;
 use glutin::{Event, ElementState, VirtualKeyCode as Key};
 
@@ -46,7 +47,7 @@ impl webrender_traits::RenderNotifier for Notifier {
     }
 }
 
-fn read_file(dir: &Path, frame: i32, api: &RenderApi) -> bool {
+fn read_file(dir: &Path, frame: i32, api: &RenderApi, document_id: &mut Option<DocumentId>) -> bool {
     let mut filename = PathBuf::from(dir);
     filename.push(format!("frame_{}.bin", frame));
     let mut file = match File::open(&filename) {
@@ -71,6 +72,12 @@ fn read_file(dir: &Path, frame: i32, api: &RenderApi) -> bool {
             let mut buffer = vec![0; len as usize];
             file.read_exact(&mut buffer).unwrap();
             let msg = deserialize(&buffer).unwrap();
+            // Only the first document of the recording is shown.
+            if let ApiMsg::AddDocument(recorded_document_id) = msg {
+                if document_id.is_none() {
+                    *document_id = Some(recorded_document_id);
+                }
+            }
             api.api_sender.send(msg).unwrap();
         } else {
             len = file.read_u32::<LittleEndian>().unwrap();
@@ -82,11 +89,38 @@ fn read_file(dir: &Path, frame: i32, api: &RenderApi) -> bool {
     true
 }
 
+/// Copies the last frame drawn by the software rasterizer to the window.
+fn present_software_frame(renderer: &webrender::renderer::Renderer,
+                          texture: gl::GLuint,
+                          fbo: gl::GLuint) {
+    let (size, pixels) = match renderer.software_framebuffer() {
+        Some(framebuffer) => framebuffer,
+        None => return,
+    };
+    let (width, height) = (size.width as gl::GLint, size.height as gl::GLint);
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    gl::bind_texture(gl::TEXTURE_2D, texture);
+    gl::tex_image_2d(gl::TEXTURE_2D, 0, gl::RGBA as gl::GLint, width, height, 0,
+                     gl::RGBA, gl::UNSIGNED_BYTE, Some(pixels));
+    gl::bind_framebuffer(gl::READ_FRAMEBUFFER, fbo);
+    gl::framebuffer_texture_2d(gl::READ_FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0);
+    gl::bind_framebuffer(gl::DRAW_FRAMEBUFFER, 0);
+    // The rows of the software framebuffer are stored top down.
+    gl::blit_framebuffer(0, 0, width, height, 0, height, width, 0,
+                         gl::COLOR_BUFFER_BIT, gl::NEAREST);
+    gl::bind_framebuffer(gl::READ_FRAMEBUFFER, 0);
+}
+
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let software = args.iter().any(|arg| arg == "--software");
+    args.retain(|arg| arg != "--software");
     if args.len() != 2 && args.len() != 3 {
-        println!("{} [<resources_path>] <directory>", args[0]);
+        println!("{} [--software] [<resources_path>] <directory>", args[0]);
         return;
     }
 
@@ -115,12 +149,17 @@ fn main() {
         enable_recording: false,
         enable_scrollbars: false,
         precache_shaders: false,
-        renderer_kind: webrender_traits::RendererKind::Native,
+        renderer_kind: if software {
+            webrender_traits::RendererKind::Software
+        } else {
+            webrender_traits::RendererKind::Native
+        },
         debug: false,
         enable_subpixel_aa: false,
         clear_framebuffer: true,
         clear_color: ColorF::new(1.0, 1.0, 1.0, 1.0),
         render_target_debug: false,
+        texture_cache_budget: None,
     };
 
     let (mut renderer, sender) = webrender::renderer::Renderer::new(opts);
@@ -129,9 +168,18 @@ fn main() {
     renderer.set_render_notifier(notifier);
     let (mut width, mut height) = window.get_inner_size().unwrap();
 
+    // The frames of the software rasterizer are copied to the window
+    // through a texture.
+    let software_target = if software {
+        Some((gl::gen_textures(1)[0], gl::gen_framebuffers(1)[0]))
+    } else {
+        None
+    };
+
     //read and send the resources file
     let mut frame_num = 0;
-    read_file(&dir, frame_num, &api);
+    let mut document_id = None;
+    read_file(&dir, frame_num, &api, &mut document_id);
 
     for event in window.wait_events() {
         match event {
@@ -146,19 +194,24 @@ fn main() {
                 println!("Rendering frame {}.", frame_num);
                 gl::clear(gl::COLOR_BUFFER_BIT);
                 renderer.update();
-                renderer.render(DeviceUintSize::new(width, height));
+                if let Some(document_id) = document_id {
+                    renderer.render(document_id, DeviceUintSize::new(width, height));
+                }
+                if let Some((texture, fbo)) = software_target {
+                    present_software_frame(&renderer, texture, fbo);
+                }
                 window.swap_buffers().unwrap();
             }
             Event::KeyboardInput(ElementState::Pressed, _, Some(Key::Right)) =>{
                 frame_num += 1;
-                if !read_file(&dir, frame_num, &api) {
+                if !read_file(&dir, frame_num, &api, &mut document_id) {
                     frame_num -= 1;
                     println!("At last frame.");
                 }
             }
             Event::KeyboardInput(ElementState::Pressed, _, Some(Key::Left)) => {
                 frame_num -= 1;
-                if frame_num < 0 || !read_file(&dir, frame_num, &api) {
+                if frame_num < 0 || !read_file(&dir, frame_num, &api, &mut document_id) {
                     frame_num +=1;
                     println!("At first frame.");
                 }
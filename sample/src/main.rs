@@ -49,8 +49,35 @@ impl webrender_traits::RenderNotifier for Notifier {
     }
 }
 
+/// Copies the last frame drawn by the software rasterizer to the window.
+fn present_software_frame(renderer: &webrender::renderer::Renderer,
+                          texture: gl::GLuint,
+                          fbo: gl::GLuint) {
+    let (size, pixels) = match renderer.software_framebuffer() {
+        Some(framebuffer) => framebuffer,
+        None => return,
+    };
+    let (width, height) = (size.width as gl::GLint, size.height as gl::GLint);
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    gl::bind_texture(gl::TEXTURE_2D, texture);
+    gl::tex_image_2d(gl::TEXTURE_2D, 0, gl::RGBA as gl::GLint, width, height, 0,
+                     gl::RGBA, gl::UNSIGNED_BYTE, Some(pixels));
+    gl::bind_framebuffer(gl::READ_FRAMEBUFFER, fbo);
+    gl::framebuffer_texture_2d(gl::READ_FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0);
+    gl::bind_framebuffer(gl::DRAW_FRAMEBUFFER, 0);
+    // The rows of the software framebuffer are stored top down.
+    gl::blit_framebuffer(0, 0, width, height, 0, height, width, 0,
+                         gl::COLOR_BUFFER_BIT, gl::NEAREST);
+    gl::bind_framebuffer(gl::READ_FRAMEBUFFER, 0);
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let software = args.iter().any(|arg| arg == "--software");
+    args.retain(|arg| arg != "--software");
     let res_path = if args.len() > 1 {
         Some(PathBuf::from(&args[1]))
     } else {
@@ -82,19 +109,29 @@ fn main() {
         enable_scrollbars: false,
         debug: true,
         precache_shaders: true,
-        renderer_kind: RendererKind::Native,
+        renderer_kind: if software { RendererKind::Software } else { RendererKind::Native },
         enable_subpixel_aa: false,
         clear_framebuffer: true,
         clear_color: ColorF::new(1.0, 1.0, 1.0, 1.0),
         render_target_debug: false,
+        texture_cache_budget: None,
     };
 
     let (mut renderer, sender) = webrender::renderer::Renderer::new(opts);
     let api = sender.create_api();
+    let document_id = api.add_document();
 
     let notifier = Box::new(Notifier::new(window.create_window_proxy()));
     renderer.set_render_notifier(notifier);
 
+    // The frames of the software rasterizer are copied to the window
+    // through a texture.
+    let software_target = if software {
+        Some((gl::gen_textures(1)[0], gl::gen_framebuffers(1)[0]))
+    } else {
+        None
+    };
+
     let epoch = Epoch(0);
     let root_background_color = ColorF::new(0.3, 0.0, 0.0, 1.0);
 
@@ -237,12 +274,15 @@ fn main() {
         epoch,
         LayoutSize::new(width as f32, height as f32),
         builder);
-    api.set_root_pipeline(pipeline_id);
+    api.set_root_pipeline(document_id, pipeline_id);
 
     for event in window.wait_events() {
         renderer.update();
 
-        renderer.render(DeviceUintSize::new(width, height));
+        renderer.render(document_id, DeviceUintSize::new(width, height));
+        if let Some((texture, fbo)) = software_target {
+            present_software_frame(&renderer, texture, fbo);
+        }
 
         window.swap_buffers().ok();
 
@@ -0,0 +1,56 @@
+#line 1
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The row pass is stored as a 16 bit value split across the red and green
+// channels, scaled by this and biased by 32768.
+#define ROW_SCALE   16.0
+
+// Returns the weight of the coefficient at `frequency` for the sample at
+// `pos` within its block, where pos is at the sample center.
+float idct_basis(float pos, int frequency) {
+    float scale = frequency == 0 ? 0.70711 : 1.0;
+    return scale * cos(pos * float(frequency) * 0.39270);
+}
+
+#ifdef WR_FEATURE_IDCT_ROWS
+// Each texel holds two horizontally adjacent coefficients as little endian
+// u16s biased by 32768, which the BGRA upload stores as (b, g) and (r, a).
+float fetch_coefficient(ivec2 pos) {
+    ivec2 texel_pos = ivec2(vSourceOrigin + 0.5) + ivec2(pos.x / 2, pos.y);
+    vec4 texel = texelFetch(sColor0, texel_pos, 0);
+    vec2 bytes = floor((pos.x % 2 == 0 ? texel.bg : texel.ra) * 255.0 + 0.5);
+    return bytes.x + bytes.y * 256.0 - 32768.0;
+}
+#else
+float fetch_row_value(ivec2 pos) {
+    ivec3 texel_pos = ivec3(ivec2(vSourceOrigin + 0.5) + pos, int(vSourceLayer));
+    vec2 bytes = floor(texelFetch(sCache, texel_pos, 0).rg * 255.0 + 0.5);
+    return (bytes.x * 256.0 + bytes.y - 32768.0) / ROW_SCALE;
+}
+#endif
+
+void main(void) {
+    ivec2 pos = ivec2(vPos);
+    ivec2 block_origin = (pos / 8) * 8;
+    vec2 block_pos = vPos - vec2(block_origin);
+
+    float sum = 0.0;
+#ifdef WR_FEATURE_IDCT_ROWS
+    for (int u = 0; u < 8; ++u) {
+        sum += idct_basis(block_pos.x, u) * fetch_coefficient(ivec2(block_origin.x + u, pos.y));
+    }
+
+    float value = clamp(floor(sum * ROW_SCALE + 32768.5), 0.0, 65535.0);
+    float high = floor(value / 256.0);
+    oFragColor = vec4(high, value - high * 256.0, 0.0, 255.0) / 255.0;
+#else
+    for (int v = 0; v < 8; ++v) {
+        sum += idct_basis(block_pos.y, v) * fetch_row_value(ivec2(pos.x, block_origin.y + v));
+    }
+
+    float value = clamp(0.25 * sum + 128.0, 0.0, 255.0) / 255.0;
+    oFragColor = vec4(value, value, value, 1.0);
+#endif
+}
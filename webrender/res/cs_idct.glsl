@@ -0,0 +1,9 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+varying vec2 vPos;                // Position in the plane, in samples.
+flat varying vec2 vSourceOrigin;  // Origin of the plane in the source texture, in texels.
+#ifndef WR_FEATURE_IDCT_ROWS
+flat varying float vSourceLayer;  // Layer of the row pass in the render task cache.
+#endif
@@ -0,0 +1,43 @@
+#line 1
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Applies the inverse DCT to the 8x8 blocks of a plane of a YUV image of
+// DCT coefficients. WR_FEATURE_IDCT_ROWS transforms the rows of each block,
+// reading the coefficients from the plane image in sColor0. Otherwise the
+// columns are transformed, reading the row pass from the cache.
+
+void main(void) {
+    CachePrimitiveInstance cpi = fetch_cache_instance();
+    RenderTaskData task = fetch_render_task(cpi.render_task_index);
+
+    vec2 p0 = task.data0.xy;
+    vec2 p1 = p0 + task.data0.zw;
+
+    vec2 pos = mix(p0, p1, aPosition.xy);
+    vPos = pos - p0;
+
+#ifdef WR_FEATURE_IDCT_ROWS
+    YuvImage image = fetch_yuv_image(cpi.specific_prim_index);
+    vec4 st_rect;
+    switch (cpi.sub_index) {
+        case 0:
+            st_rect = image.y_st_rect;
+            break;
+        case 1:
+            st_rect = image.u_st_rect;
+            break;
+        default:
+            st_rect = image.v_st_rect;
+            break;
+    }
+    vSourceOrigin = st_rect.xy;
+#else
+    RenderTaskData src_task = fetch_render_task(cpi.user_data.x);
+    vSourceOrigin = src_task.data0.xy;
+    vSourceLayer = src_task.data1.x;
+#endif
+
+    gl_Position = uTransform * vec4(pos, 0.0, 1.0);
+}
@@ -0,0 +1,26 @@
+#line 1
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Samples a chroma plane with bilinear filtering, clamped half a sample
+// inside the plane so that its neighbours in the cache don't bleed in.
+float sample_chroma(vec3 plane_rect, vec2 chroma_pos) {
+    vec2 texture_size = vec2(textureSize(sCache, 0).xy);
+    vec2 pos = clamp(chroma_pos, vec2(0.5), vChromaSize - 0.5);
+    return texture(sCache, vec3((plane_rect.xy + pos) / texture_size, plane_rect.z)).r;
+}
+
+void main(void) {
+    vec2 chroma_pos = vPos * vChromaScale;
+
+    float y = texelFetch(sCache, ivec3(ivec2(vLumaRect.xy + vPos), int(vLumaRect.z)), 0).r;
+    float u = sample_chroma(vCbRect, chroma_pos);
+    float v = sample_chroma(vCrRect, chroma_pos);
+
+    vec3 rgb = vYuvColorMatrix * (vec3(y, u, v) - vYuvOffsetVector);
+    if (vYuvColorSpace == YUV_REC2020) {
+        rgb = rec2020_to_rec709(rgb);
+    }
+    oFragColor = vec4(rgb, 1.0);
+}
@@ -0,0 +1,14 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+varying vec2 vPos;            // Position in the luma plane, in samples.
+flat varying vec3 vLumaRect;  // Origin and layer of the luma plane in the cache.
+flat varying vec3 vCbRect;    // Origin and layer of the Cb plane in the cache.
+flat varying vec3 vCrRect;    // Origin and layer of the Cr plane in the cache.
+flat varying vec2 vChromaScale;
+flat varying vec2 vChromaSize;
+
+flat varying mat3 vYuvColorMatrix;
+flat varying vec3 vYuvOffsetVector;
+flat varying int vYuvColorSpace;
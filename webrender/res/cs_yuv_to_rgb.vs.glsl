@@ -0,0 +1,36 @@
+#line 1
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Converts the decoded planes of a YUV image of DCT coefficients to RGB at
+// the size of the luma plane, upsampling the chroma planes. The task of the
+// luma plane is given by the sub index, and the chroma ones by the user data.
+
+void main(void) {
+    CachePrimitiveInstance cpi = fetch_cache_instance();
+    RenderTaskData task = fetch_render_task(cpi.render_task_index);
+    RenderTaskData y_task = fetch_render_task(cpi.sub_index);
+    RenderTaskData u_task = fetch_render_task(cpi.user_data.x);
+    RenderTaskData v_task = fetch_render_task(cpi.user_data.y);
+    YuvImage image = fetch_yuv_image(cpi.specific_prim_index);
+
+    vec2 p0 = task.data0.xy;
+    vec2 p1 = p0 + task.data0.zw;
+
+    vec2 pos = mix(p0, p1, aPosition.xy);
+    vPos = pos - p0;
+
+    // This assumes the U and V planes have the same size.
+    vLumaRect = vec3(y_task.data0.xy, y_task.data1.x);
+    vCbRect = vec3(u_task.data0.xy, u_task.data1.x);
+    vCrRect = vec3(v_task.data0.xy, v_task.data1.x);
+    vChromaScale = u_task.data0.zw / y_task.data0.zw;
+    vChromaSize = u_task.data0.zw;
+
+    vYuvColorMatrix = get_yuv_color_matrix(image.color_space, image.color_range);
+    vYuvOffsetVector = get_yuv_offset_vector(image.color_range);
+    vYuvColorSpace = image.color_space;
+
+    gl_Position = uTransform * vec4(pos, 0.0, 1.0);
+}
@@ -29,6 +29,21 @@
 #define UV_PIXEL         uint(1)
 
 #define MAX_STOPS_PER_ANGLE_GRADIENT 8
+// The stops of the gradients that share the helpers below.
+#define MAX_STOPS_PER_GRADIENT 8
+
+#define EXTEND_MODE_CLAMP       0
+#define EXTEND_MODE_REPEAT      1
+#define EXTEND_MODE_REFLECT     2
+
+// YUV color spaces
+#define YUV_REC601  1
+#define YUV_REC709  2
+#define YUV_REC2020 3
+
+// YUV color ranges
+#define YUV_RANGE_LIMITED 0
+#define YUV_RANGE_FULL    1
 
 uniform sampler2DArray sCache;
 
@@ -192,6 +207,36 @@ Gradient fetch_gradient(int index) {
     return gradient;
 }
 
+struct RadialGradient {
+    vec4 center_start_end_radius;
+    vec4 ratio_xy_extend_mode;
+};
+
+RadialGradient fetch_radial_gradient(int index) {
+    RadialGradient gradient;
+
+    ivec2 uv = get_fetch_uv_2(index);
+
+    gradient.center_start_end_radius = texelFetchOffset(sData32, uv, 0, ivec2(0, 0));
+    gradient.ratio_xy_extend_mode = texelFetchOffset(sData32, uv, 0, ivec2(1, 0));
+
+    return gradient;
+}
+
+struct ConicGradient {
+    vec4 center_angle_extend_mode;
+};
+
+ConicGradient fetch_conic_gradient(int index) {
+    ConicGradient gradient;
+
+    ivec2 uv = get_fetch_uv_2(index);
+
+    gradient.center_angle_extend_mode = texelFetchOffset(sData32, uv, 0, ivec2(0, 0));
+
+    return gradient;
+}
+
 struct GradientStop {
     vec4 color;
     vec4 offset;
@@ -555,16 +600,13 @@ Image fetch_image(int index) {
     return image;
 }
 
-// YUV color spaces
-#define YUV_REC601 1
-#define YUV_REC709 2
-
 struct YuvImage {
     vec4 y_st_rect;
     vec4 u_st_rect;
     vec4 v_st_rect;
     vec2 size;
     int color_space;
+    int color_range;
 };
 
 YuvImage fetch_yuv_image(int index) {
@@ -578,10 +620,70 @@ YuvImage fetch_yuv_image(int index) {
     vec4 size_color_space = texelFetchOffset(sData64, uv, 0, ivec2(3, 0));
     image.size = size_color_space.xy;
     image.color_space = int(size_color_space.z);
+    image.color_range = int(size_color_space.w);
 
     return image;
 }
 
+struct BorderImagePatch {
+    vec4 local_rect;
+    vec4 src_rect;
+    vec4 tile_size_offset;
+};
+
+BorderImagePatch fetch_border_image_patch(int index) {
+    BorderImagePatch patch;
+
+    ivec2 uv = get_fetch_uv_4(index);
+
+    patch.local_rect = texelFetchOffset(sData64, uv, 0, ivec2(0, 0));
+    patch.src_rect = texelFetchOffset(sData64, uv, 0, ivec2(1, 0));
+    patch.tile_size_offset = texelFetchOffset(sData64, uv, 0, ivec2(2, 0));
+
+    return patch;
+}
+
+// The values to subtract from the Y, U and V samples before applying
+// get_yuv_color_matrix, in the range [0,1] instead of [0,255].
+vec3 get_yuv_offset_vector(int color_range) {
+    if (color_range == YUV_RANGE_FULL) {
+        return vec3(0.0, 0.50196, 0.50196);
+    }
+    return vec3(0.06275, 0.50196, 0.50196);
+}
+
+// Builds the YUV to RGB matrix from the luma coefficients Kr and Kb of the
+// color space:
+// [R]   [1,  0,                     2(1 - Kr)          ]   [Y]
+// [G] = [1, -2Kb(1 - Kb) / Kg,     -2Kr(1 - Kr) / Kg   ] x [U]
+// [B]   [1,  2(1 - Kb),             0                  ]   [V]
+// with Kg = 1 - Kr - Kb. Limited range samples are scaled by 255/219 for
+// luma and 255/224 for chroma first, which gives the usual Rec601 matrix
+// with 1.16438 and 1.59603.
+mat3 get_yuv_color_matrix(int color_space, int color_range) {
+    vec2 k;
+    if (color_space == YUV_REC601) {
+        k = vec2(0.299, 0.114);
+    } else if (color_space == YUV_REC709) {
+        k = vec2(0.2126, 0.0722);
+    } else { // if (color_space == YUV_REC2020)
+        k = vec2(0.2627, 0.0593);
+    }
+    float kg = 1.0 - k.x - k.y;
+
+    vec2 scale = vec2(1.0);
+    if (color_range == YUV_RANGE_LIMITED) {
+        scale = vec2(255.0 / 219.0, 255.0 / 224.0);
+    }
+
+    vec3 y_column = vec3(scale.x);
+    vec3 u_column = vec3(0.0, -2.0 * k.y * (1.0 - k.y) / kg, 2.0 * (1.0 - k.y)) * scale.y;
+    vec3 v_column = vec3(2.0 * (1.0 - k.x), -2.0 * k.x * (1.0 - k.x) / kg, 0.0) * scale.y;
+
+    // mat3 is column major, so each column is the contribution of one channel.
+    return mat3(y_column, u_column, v_column);
+}
+
 struct BoxShadow {
     vec4 src_rect;
     vec4 bs_rect;
@@ -611,6 +713,50 @@ void write_clip(vec2 global_pos, ClipArea area) {
 #endif //WR_VERTEX_SHADER
 
 #ifdef WR_FRAGMENT_SHADER
+// Returns the offset of a gradient stop, from the offsets packed four
+// to a vec4.
+float offset(vec4 offsets[MAX_STOPS_PER_GRADIENT / 4], int index) {
+    return offsets[index / 4][index % 4];
+}
+
+float linearStep(float lo, float hi, float x) {
+    float d = hi - lo;
+    float v = x - lo;
+    if (d != 0.0) {
+        v /= d;
+    }
+    return clamp(v, 0.0, 1.0);
+}
+
+// Wraps a position along a gradient into the range covered by its
+// stops, from `first` to `first + period`, as the extend mode asks.
+float extendStopPosition(float x, float first, float period, int extend_mode) {
+    if (period > 0.0) {
+        switch (extend_mode) {
+            case EXTEND_MODE_REPEAT:
+                return first + mod(x - first, period);
+            case EXTEND_MODE_REFLECT:
+                return first + period - abs(mod(x - first, 2.0 * period) - period);
+        }
+    }
+    return x;
+}
+
+// Whether a position along a gradient is drawn by the segment of its stops
+// from `lo` to `hi`, when the stops don't all fit in the varyings. Each
+// segment draws up to where the next one starts, and the first and last
+// segments draw everything before and after them. `neighbors` tells if
+// there are segments before and after this one.
+bool inStopSegment(float x, float lo, float hi, float direction, ivec2 neighbors) {
+    if (neighbors.x != 0 && (x - lo) * direction < 0.0) {
+        return false;
+    }
+    if (neighbors.y != 0 && (x - hi) * direction >= 0.0) {
+        return false;
+    }
+    return true;
+}
+
 float distance_from_rect(vec2 p, vec2 origin, vec2 size) {
     vec2 clamped = clamp(p, origin, origin + size);
     return distance(clamped, p);
@@ -638,4 +784,17 @@ float do_clip() {
     return vClipMaskUvBounds.xy == vClipMaskUvBounds.zw ? 1.0:
         all(inside) ? textureLod(sCache, vClipMaskUv, 0.0).r : 0.0;
 }
+
+// Converts a color with Rec2020 primaries to the Rec709 (and sRGB) ones that
+// the rest of the frame uses. The primaries mix in linear light, so the color
+// is decoded with the gamma 2.4 of BT.1886 first and encoded again after.
+// Colors outside of the smaller Rec709 gamut are clipped.
+vec3 rec2020_to_rec709(vec3 rgb) {
+    vec3 linear_rgb = pow(clamp(rgb, vec3(0.0), vec3(1.0)), vec3(2.4));
+    // Column major, as in get_yuv_color_matrix.
+    mat3 primaries = mat3( 1.66050, -0.12455, -0.01815,
+                          -0.58764,  1.13290, -0.10058,
+                          -0.07286, -0.00835,  1.11873);
+    return pow(clamp(primaries * linear_rgb, vec3(0.0), vec3(1.0)), vec3(1.0 / 2.4));
+}
 #endif //WR_FRAGMENT_SHADER
@@ -6,15 +6,6 @@ float offset(int index) {
     return vOffsets[index / 4][index % 4];
 }
 
-float linearStep(float lo, float hi, float x) {
-    float d = hi - lo;
-    float v = x - lo;
-    if (d != 0.0) {
-        v /= d;
-    }
-    return clamp(v, 0.0, 1.0);
-}
-
 void main(void) {
     float angle = atan(-vEndPoint.y + vStartPoint.y,
                         vEndPoint.x - vStartPoint.x);
@@ -27,13 +18,24 @@ void main(void) {
 
     float x = vPos.x * ca - vPos.y * sa;
 
+    // The position along the gradient line, where the
+    // start point is 0.0 and the end point is 1.0.
+    float t = 0.0;
+    if (d != 0.0) {
+        t = (x - sx) / d;
+    }
+
+    // Wrap the position into the range covered by the stops.
+    float first = offset(0);
+    t = extendStopPosition(t, first, offset(vStopCount - 1) - first, vExtendMode);
+
     oFragColor = mix(vColors[0],
                      vColors[1],
-                     linearStep(sx + d * offset(0), sx + d * offset(1), x));
+                     linearStep(offset(0), offset(1), t));
 
     for (int i=1 ; i < vStopCount-1 ; ++i) {
         oFragColor = mix(oFragColor,
                          vColors[i+1],
-                         linearStep(sx + d * offset(i), sx + d * offset(i+1), x));
+                         linearStep(offset(i), offset(i+1), t));
     }
 }
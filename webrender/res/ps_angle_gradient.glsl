@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 flat varying int vStopCount;
+flat varying int vExtendMode;
 flat varying float vAngle;
 flat varying vec2 vStartPoint;
 flat varying vec2 vEndPoint;
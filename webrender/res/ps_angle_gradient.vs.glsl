@@ -14,6 +14,7 @@ void main(void) {
                                  prim.tile);
 
     vStopCount = int(prim.user_data.x);
+    vExtendMode = int(gradient.kind.y);
     vPos = vi.local_clamped_pos;
 
     // Snap the start/end points to device pixel units.
@@ -0,0 +1,21 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+void main(void) {
+#ifdef WR_FEATURE_TRANSFORM
+    float alpha = 0.0;
+    vec2 pos = init_transform_fs(vLocalPos, vLocalRect, alpha);
+#else
+    float alpha = 1.0;
+    vec2 pos = vLocalPos;
+#endif
+
+    alpha = min(alpha, do_clip());
+
+    vec2 position_in_tile = mod(pos - vTileOrigin, vTileSize);
+    vec2 st = vTextureOffset + (position_in_tile / vTileSize) * vTextureSize;
+    st = clamp(st, vStBounds.xy, vStBounds.zw);
+
+    oFragColor = vec4(1.0, 1.0, 1.0, alpha) * texture(sColor0, st);
+}
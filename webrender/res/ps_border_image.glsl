@@ -0,0 +1,16 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+flat varying vec2 vTextureOffset; // Offset of this part of the image into the texture atlas.
+flat varying vec2 vTextureSize;   // Size of this part of the image in the texture atlas.
+flat varying vec4 vStBounds;      // Texture coordinates that stay inside this part of the image.
+flat varying vec2 vTileOrigin;    // Local position of the start of a tile.
+flat varying vec2 vTileSize;      // Local size of a tile.
+
+#ifdef WR_FEATURE_TRANSFORM
+varying vec3 vLocalPos;
+flat varying vec4 vLocalRect;
+#else
+varying vec2 vLocalPos;
+#endif
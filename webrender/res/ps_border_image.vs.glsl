@@ -0,0 +1,45 @@
+#line 1
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+void main(void) {
+    Primitive prim = load_primitive();
+    BorderImagePatch patch = fetch_border_image_patch(prim.sub_index);
+    ResourceRect res = fetch_resource_rect(prim.user_data.x);
+
+#ifdef WR_FEATURE_TRANSFORM
+    TransformVertexInfo vi = write_transform_vertex(patch.local_rect,
+                                                    prim.local_clip_rect,
+                                                    prim.z,
+                                                    prim.layer,
+                                                    prim.tile);
+    vLocalRect = vi.clipped_local_rect;
+    vLocalPos = vi.local_pos;
+#else
+    VertexInfo vi = write_vertex(patch.local_rect,
+                                 prim.local_clip_rect,
+                                 prim.z,
+                                 prim.layer,
+                                 prim.tile);
+    vLocalPos = vi.local_clamped_pos;
+#endif
+
+    write_clip(vi.global_clamped_pos, prim.clip_area);
+
+    // The source rect of the patch is relative to the size of the image.
+    vec2 texture_size = vec2(textureSize(sColor0, 0));
+    vec2 st0 = mix(res.uv_rect.xy, res.uv_rect.zw, patch.src_rect.xy) / texture_size;
+    vec2 st1 = mix(res.uv_rect.xy, res.uv_rect.zw, patch.src_rect.zw) / texture_size;
+
+    vTextureSize = st1 - st0;
+    vTextureOffset = st0;
+
+    // Keep the samples half a texel inside the patch, so that
+    // the neighbouring patches don't bleed into it.
+    vec2 half_texel = vec2(0.5) / texture_size;
+    vStBounds = vec4(st0 + half_texel, st1 - half_texel);
+
+    vTileOrigin = patch.local_rect.xy + patch.tile_size_offset.zw;
+    vTileSize = patch.tile_size_offset.xy;
+}
@@ -0,0 +1,34 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#define TWO_PI  6.28318530718
+
+void main(void) {
+    // The angle of this fragment, clockwise from the top
+    // of the item, as a fraction of a whole turn after
+    // the start angle.
+    vec2 pos = vPos - vCenter;
+    float angle = atan(pos.x, -pos.y) - vAngle;
+    float x = fract(angle / TWO_PI);
+
+    // Wrap the position into the range covered by the stops.
+    x = extendStopPosition(x, vStopRange.x, vStopRange.y - vStopRange.x, vExtendMode);
+
+    // Leave the angles of other segments of the stops to their instances.
+    if (!inStopSegment(x,
+                       offset(vOffsets, 0),
+                       offset(vOffsets, vStopCount - 1),
+                       1.0,
+                       vStopSegment)) {
+        discard;
+    }
+
+    oFragColor = vColors[0];
+
+    for (int i=1 ; i < vStopCount ; ++i) {
+        oFragColor = mix(oFragColor,
+                         vColors[i],
+                         linearStep(offset(vOffsets, i-1), offset(vOffsets, i), x));
+    }
+}
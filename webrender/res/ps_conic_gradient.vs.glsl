@@ -0,0 +1,35 @@
+#line 1
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+void main(void) {
+    Primitive prim = load_primitive();
+    ConicGradient gradient = fetch_conic_gradient(prim.prim_index);
+
+    VertexInfo vi = write_vertex(prim.local_rect,
+                                 prim.local_clip_rect,
+                                 prim.z,
+                                 prim.layer,
+                                 prim.tile);
+
+    // Gradients with more stops than the varyings can hold are drawn by
+    // several instances, each with a segment of the stops.
+    int stop_count = int(prim.user_data.x);
+    int first_stop = int(prim.user_data.y);
+    vStopCount = min(stop_count - first_stop, MAX_STOPS_PER_GRADIENT);
+    vStopSegment = ivec2(first_stop > 0, first_stop + vStopCount < stop_count);
+    vStopRange = vec2(fetch_gradient_stop(prim.sub_index).offset.x,
+                      fetch_gradient_stop(prim.sub_index + stop_count - 1).offset.x);
+    vPos = vi.local_clamped_pos;
+
+    vCenter = gradient.center_angle_extend_mode.xy;
+    vAngle = gradient.center_angle_extend_mode.z;
+    vExtendMode = int(gradient.center_angle_extend_mode.w);
+
+    for (int i=0 ; i < vStopCount ; ++i) {
+        GradientStop stop = fetch_gradient_stop(prim.sub_index + first_stop + i);
+        vColors[i] = stop.color;
+        vOffsets[i/4][i%4] = stop.offset.x;
+    }
+}
@@ -10,15 +10,30 @@ void main(void) {
     GradientStop g0 = fetch_gradient_stop(prim.sub_index + 0);
     GradientStop g1 = fetch_gradient_stop(prim.sub_index + 1);
 
+    // Move the stops into the repetition of them that this
+    // instance draws. Every other repetition of a reflected
+    // gradient is mirrored.
+    int repetition = prim.user_data.x;
+    float first = gradient.kind.z;
+    float period = gradient.kind.w;
+    float o0 = g0.offset.x;
+    float o1 = g1.offset.x;
+    if (int(gradient.kind.y) == EXTEND_MODE_REFLECT && abs(repetition) % 2 == 1) {
+        o0 = 2.0 * first + period - o0;
+        o1 = 2.0 * first + period - o1;
+    }
+    o0 += float(repetition) * period;
+    o1 += float(repetition) * period;
+
     vec4 segment_rect;
     switch (int(gradient.kind.x)) {
         case GRADIENT_HORIZONTAL:
             float x0 = mix(gradient.start_end_point.x,
                            gradient.start_end_point.z,
-                           g0.offset.x);
+                           o0);
             float x1 = mix(gradient.start_end_point.x,
                            gradient.start_end_point.z,
-                           g1.offset.x);
+                           o1);
             segment_rect.yw = prim.local_rect.yw;
             segment_rect.x = x0;
             segment_rect.z = x1 - x0;
@@ -26,28 +41,45 @@ void main(void) {
         case GRADIENT_VERTICAL:
             float y0 = mix(gradient.start_end_point.y,
                            gradient.start_end_point.w,
-                           g0.offset.x);
+                           o0);
             float y1 = mix(gradient.start_end_point.y,
                            gradient.start_end_point.w,
-                           g1.offset.x);
+                           o1);
             segment_rect.xz = prim.local_rect.xz;
             segment_rect.y = y0;
             segment_rect.w = y1 - y0;
             break;
     }
 
+    // A mirrored segment runs backwards, so flip it around
+    // along with its colors.
+    vec4 c0 = g0.color;
+    vec4 c1 = g1.color;
+    if (segment_rect.z < 0.0 || segment_rect.w < 0.0) {
+        segment_rect.xy += min(segment_rect.zw, vec2(0.0));
+        segment_rect.zw = abs(segment_rect.zw);
+        c0 = g1.color;
+        c1 = g0.color;
+    }
+
+    // Repetitions of the stops can reach past the primitive.
+    vec2 clip_p0 = max(prim.local_clip_rect.xy, prim.local_rect.xy);
+    vec2 clip_p1 = min(prim.local_clip_rect.xy + prim.local_clip_rect.zw,
+                       prim.local_rect.xy + prim.local_rect.zw);
+    vec4 clip_rect = vec4(clip_p0, max(clip_p1 - clip_p0, vec2(0.0)));
+
 #ifdef WR_FEATURE_TRANSFORM
     TransformVertexInfo vi = write_transform_vertex(segment_rect,
-                                                    prim.local_clip_rect,
+                                                    clip_rect,
                                                     prim.z,
                                                     prim.layer,
                                                     prim.tile);
     vLocalRect = vi.clipped_local_rect;
     vLocalPos = vi.local_pos;
-    vec2 f = (vi.local_pos.xy - prim.local_rect.xy) / prim.local_rect.zw;
+    vec2 f = (vi.local_pos.xy - segment_rect.xy) / segment_rect.zw;
 #else
     VertexInfo vi = write_vertex(segment_rect,
-                                 prim.local_clip_rect,
+                                 clip_rect,
                                  prim.z,
                                  prim.layer,
                                  prim.tile);
@@ -60,10 +92,10 @@ void main(void) {
 
     switch (int(gradient.kind.x)) {
         case GRADIENT_HORIZONTAL:
-            vColor = mix(g0.color, g1.color, f.x);
+            vColor = mix(c0, c1, f.x);
             break;
         case GRADIENT_VERTICAL:
-            vColor = mix(g0.color, g1.color, f.y);
+            vColor = mix(c0, c1, f.y);
             break;
         case GRADIENT_ROTATED:
             vColor = vec4(1.0, 0.0, 1.0, 1.0);
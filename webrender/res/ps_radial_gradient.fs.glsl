@@ -0,0 +1,38 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+void main(void) {
+    // Stretch the vertical axis so that the ellipse becomes a
+    // circle, and measure the distance along the horizontal radius.
+    vec2 pos = vPos - vCenter;
+    pos.y *= vRatioXY;
+    float x = length(pos);
+
+    float sx = vStartRadius;
+    float d = vEndRadius - vStartRadius;
+
+    // Wrap the distance into the range covered by the stops.
+    float first = sx + d * vStopRange.x;
+    float period = d * (vStopRange.y - vStopRange.x);
+    x = extendStopPosition(x, first, period, vExtendMode);
+
+    // Leave the distances of other segments of the stops to their instances.
+    if (!inStopSegment(x,
+                       sx + d * offset(vOffsets, 0),
+                       sx + d * offset(vOffsets, vStopCount - 1),
+                       d < 0.0 ? -1.0 : 1.0,
+                       vStopSegment)) {
+        discard;
+    }
+
+    oFragColor = vColors[0];
+
+    for (int i=1 ; i < vStopCount ; ++i) {
+        oFragColor = mix(oFragColor,
+                         vColors[i],
+                         linearStep(sx + d * offset(vOffsets, i-1),
+                                    sx + d * offset(vOffsets, i),
+                                    x));
+    }
+}
@@ -20,14 +20,41 @@ void main(void) {
     alpha = min(alpha, do_clip());
 
     vec2 st_y = vTextureOffsetY + relative_pos_in_rect / vStretchSize * vTextureSizeY;
+
+#if defined(WR_FEATURE_DCT_COEFFICIENTS)
+    st_y = clamp(st_y, vCacheUvBounds.xy, vCacheUvBounds.zw);
+    oFragColor = vec4(texture(sCache, vec3(st_y, vCacheLayer)).rgb, alpha);
+#else
+#if defined(WR_FEATURE_INTERLEAVED_Y_CB_CR)
+    // Each texel holds Y0 U Y1 V, which the BGRA upload stores as b g r a.
+    // Sample the texel center and pick the luma of the half we are in.
+    vec2 texture_size = vec2(textureSize(sColor0, 0));
+    vec2 texel = st_y * texture_size;
+    vec4 yuyv = texture(sColor0, (floor(texel) + 0.5) / texture_size);
+    float y = fract(texel.x) < 0.5 ? yuyv.b : yuyv.r;
+    float u = yuyv.g;
+    float v = yuyv.a;
+#elif defined(WR_FEATURE_NV12)
+    vec2 st_uv = vTextureOffsetU + relative_pos_in_rect / vStretchSize * vTextureSizeUv;
+
+    // RG8 textures are swizzled to (r, r, r, g).
+    float y = texture(sColor0, st_y).r;
+    vec4 uv = texture(sColor1, st_uv);
+    float u = uv.r;
+    float v = uv.a;
+#else
     vec2 st_u = vTextureOffsetU + relative_pos_in_rect / vStretchSize * vTextureSizeUv;
     vec2 st_v = vTextureOffsetV + relative_pos_in_rect / vStretchSize * vTextureSizeUv;
 
     float y = texture(sColor0, st_y).r;
     float u = texture(sColor1, st_u).r;
     float v = texture(sColor2, st_v).r;
+#endif
 
-    // See the vertex shader for an explanation of where the constants come from.
-    vec3 rgb = vYuvColorMatrix * vec3(y - 0.06275, u - 0.50196, v - 0.50196);
+    vec3 rgb = vYuvColorMatrix * (vec3(y, u, v) - vYuvOffsetVector);
+    if (vYuvColorSpace == YUV_REC2020) {
+        rgb = rec2020_to_rec709(rgb);
+    }
     oFragColor = vec4(rgb, alpha);
+#endif
 }
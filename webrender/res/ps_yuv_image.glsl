@@ -2,14 +2,32 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+// The planar variant samples Y, U and V from sColor0, sColor1 and sColor2.
+// WR_FEATURE_NV12 samples interleaved U and V from an RG8 image in sColor1.
+// WR_FEATURE_INTERLEAVED_Y_CB_CR samples everything from a single YUYV image
+// in sColor0, where each texel holds two horizontally adjacent pixels.
+// WR_FEATURE_DCT_COEFFICIENTS samples RGB from the render task that decoded
+// the planes of DCT coefficients, whose rect in the cache is used in place
+// of the y plane.
 flat varying vec2 vTextureOffsetY; // Offset of the y plane into the texture atlas.
-flat varying vec2 vTextureOffsetU; // Offset of the u plane into the texture atlas.
+#ifdef WR_FEATURE_DCT_COEFFICIENTS
+flat varying float vCacheLayer;
+flat varying vec4 vCacheUvBounds;  // Bounds of the render task, half a texel inside.
+#elif !defined(WR_FEATURE_INTERLEAVED_Y_CB_CR)
+flat varying vec2 vTextureOffsetU; // Offset of the u (or uv) plane into the texture atlas.
+flat varying vec2 vTextureSizeUv;  // Size of the u and v planes in the texture atlas.
+#endif
+#if !defined(WR_FEATURE_NV12) && !defined(WR_FEATURE_INTERLEAVED_Y_CB_CR) && !defined(WR_FEATURE_DCT_COEFFICIENTS)
 flat varying vec2 vTextureOffsetV; // Offset of the v plane into the texture atlas.
+#endif
 flat varying vec2 vTextureSizeY;   // Size of the y plane in the texture atlas.
-flat varying vec2 vTextureSizeUv;  // Size of the u and v planes in the texture atlas.
 flat varying vec2 vStretchSize;
 
+#ifndef WR_FEATURE_DCT_COEFFICIENTS
 flat varying mat3 vYuvColorMatrix;
+flat varying vec3 vYuvOffsetVector;
+flat varying int vYuvColorSpace;
+#endif
 
 #ifdef WR_FEATURE_TRANSFORM
 varying vec3 vLocalPos;
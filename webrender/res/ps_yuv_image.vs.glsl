@@ -23,7 +23,21 @@ void main(void) {
 #endif
 
     YuvImage image = fetch_yuv_image(prim.prim_index);
+    vStretchSize = image.size;
+
+#ifdef WR_FEATURE_DCT_COEFFICIENTS
+    // The planes were decoded to RGB by the render task given in the
+    // user data, which is sampled like an image in the cache.
+    RenderTaskData child_task = fetch_render_task(prim.user_data.x);
+    vec2 cache_size = vec2(textureSize(sCache, 0).xy);
+    vec2 task_p0 = child_task.data0.xy;
+    vec2 task_p1 = task_p0 + child_task.data0.zw;
 
+    vTextureOffsetY = task_p0 / cache_size;
+    vTextureSizeY = child_task.data0.zw / cache_size;
+    vCacheLayer = child_task.data1.x;
+    vCacheUvBounds = vec4(task_p0 + 0.5, task_p1 - 0.5) / cache_size.xyxy;
+#else
     vec2 y_texture_size = vec2(textureSize(sColor0, 0));
     vec2 y_st0 = image.y_st_rect.xy / y_texture_size;
     vec2 y_st1 = image.y_st_rect.zw / y_texture_size;
@@ -31,46 +45,25 @@ void main(void) {
     vTextureSizeY = y_st1 - y_st0;
     vTextureOffsetY = y_st0;
 
+#ifndef WR_FEATURE_INTERLEAVED_Y_CB_CR
     vec2 uv_texture_size = vec2(textureSize(sColor1, 0));
     vec2 u_st0 = image.u_st_rect.xy / uv_texture_size;
     vec2 u_st1 = image.u_st_rect.zw / uv_texture_size;
 
-    vec2 v_st0 = image.v_st_rect.xy / uv_texture_size;
-    vec2 v_st1 = image.v_st_rect.zw / uv_texture_size;
-
-    // This assumes the U and V surfaces have the same size.
     vTextureSizeUv = u_st1 - u_st0;
     vTextureOffsetU = u_st0;
-    vTextureOffsetV = v_st0;
+#endif
 
-    vStretchSize = image.size;
+#if !defined(WR_FEATURE_NV12) && !defined(WR_FEATURE_INTERLEAVED_Y_CB_CR)
+    // This assumes the U and V surfaces have the same size.
+    vec2 v_st0 = image.v_st_rect.xy / uv_texture_size;
+    vTextureOffsetV = v_st0;
+#endif
 
-    // The constants added to the Y, U and V components are applied in the fragment shader.
-    if (image.color_space == YUV_REC601) {
-        // From Rec601:
-        // [R]   [1.1643835616438356,  0.0,                 1.5960267857142858   ]   [Y -  16]
-        // [G] = [1.1643835616438358, -0.3917622900949137, -0.8129676472377708   ] x [U - 128]
-        // [B]   [1.1643835616438356,  2.017232142857143,   8.862867620416422e-17]   [V - 128]
-        //
-        // For the range [0,1] instead of [0,255].
-        vYuvColorMatrix = mat3(
-            1.16438,  0.0,      1.59603,
-            1.16438, -0.39176, -0.81297,
-            1.16438,  2.01723,  0.0
-        );
-    } else { // if (image.color_space == YUV_REC709)
-        // From Rec709:
-        // [R]   [1.1643835616438356,  4.2781193979771426e-17, 1.7927410714285714]   [Y -  16]
-        // [G] = [1.1643835616438358, -0.21324861427372963,   -0.532909328559444 ] x [U - 128]
-        // [B]   [1.1643835616438356,  2.1124017857142854,     0.0               ]   [V - 128]
-        //
-        // For the range [0,1] instead of [0,255]:
-        vYuvColorMatrix = mat3(
-            1.16438,  0.0,      1.79274,
-            1.16438, -0.21325, -0.53291,
-            1.16438,  2.11240,  0.0
-        );
-    }
+    vYuvColorMatrix = get_yuv_color_matrix(image.color_space, image.color_range);
+    vYuvOffsetVector = get_yuv_offset_vector(image.color_range);
+    vYuvColorSpace = image.color_space;
+#endif
 
     write_clip(vi.global_clamped_pos, prim.clip_area);
 
@@ -14,6 +14,9 @@ pub trait BorderSideHelpers {
                     scale_factor_1: f32,
                     black_color_0: f32,
                     black_color_1: f32) -> ColorF;
+
+    /// Whether the side is drawn at all. `none` and `hidden` sides are not.
+    fn is_visible(&self) -> bool;
 }
 
 impl BorderSideHelpers for BorderSide {
@@ -40,4 +43,11 @@ impl BorderSideHelpers for BorderSide {
             _ => self.color,
         }
     }
+
+    fn is_visible(&self) -> bool {
+        match self.style {
+            BorderStyle::None | BorderStyle::Hidden => false,
+            _ => true,
+        }
+    }
 }
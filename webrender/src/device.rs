@@ -10,15 +10,21 @@ use internal_types::{BlurAttribute, ClearAttribute, ClipAttribute, VertexAttribu
 use internal_types::{DebugFontVertex, DebugColorVertex};
 //use notify::{self, Watcher};
 use super::shader_source;
+use std::cmp;
 use std::collections::HashMap;
+use std::ffi::CStr;
 use std::fs::File;
 use std::hash::BuildHasherDefault;
 use std::io::Read;
 use std::mem;
+use std::os::raw::c_char;
 use std::path::PathBuf;
+use std::ptr;
+use std::slice;
 //use std::sync::mpsc::{channel, Sender};
 //use std::thread;
 use webrender_traits::{ColorF, ImageFormat, DeviceIntRect};
+use webrender_traits::{DeviceUintPoint, DeviceUintRect};
 
 #[cfg(not(any(target_arch = "arm", target_arch = "aarch64")))]
 const GL_FORMAT_A: gl::GLuint = gl::RED;
@@ -40,6 +46,32 @@ const SHADER_VERSION: &'static str = "#version 300 es\n";
 
 static SHADER_PREAMBLE: &'static str = "shared";
 
+// Not in the ES bindings, where it needs EXT_texture_norm16.
+const GL_R16: gl::GLuint = 0x822A;
+
+const GL_TEXTURE_SWIZZLE_R: gl::GLuint = 0x8E42;
+const GL_TEXTURE_SWIZZLE_G: gl::GLuint = 0x8E43;
+const GL_TEXTURE_SWIZZLE_B: gl::GLuint = 0x8E44;
+const GL_TEXTURE_SWIZZLE_A: gl::GLuint = 0x8E45;
+
+// Compressed texture formats from EXT_texture_compression_s3tc,
+// ES 3.0 / ARB_ES3_compatibility and KHR_texture_compression_astc_ldr.
+const GL_COMPRESSED_RGBA_S3TC_DXT1: gl::GLuint = 0x83F1;
+const GL_COMPRESSED_RGBA_S3TC_DXT3: gl::GLuint = 0x83F2;
+const GL_COMPRESSED_RGBA_S3TC_DXT5: gl::GLuint = 0x83F3;
+const GL_COMPRESSED_RGB8_ETC2: gl::GLuint = 0x9274;
+const GL_COMPRESSED_RGBA8_ETC2_EAC: gl::GLuint = 0x9278;
+const GL_COMPRESSED_RGBA_ASTC_4x4: gl::GLuint = 0x93B0;
+
+static COMPRESSED_FORMATS: [(ImageFormat, gl::GLuint); 6] = [
+    (ImageFormat::BC1, GL_COMPRESSED_RGBA_S3TC_DXT1),
+    (ImageFormat::BC2, GL_COMPRESSED_RGBA_S3TC_DXT3),
+    (ImageFormat::BC3, GL_COMPRESSED_RGBA_S3TC_DXT5),
+    (ImageFormat::ETC2RGB8, GL_COMPRESSED_RGB8_ETC2),
+    (ImageFormat::ETC2RGBA8, GL_COMPRESSED_RGBA8_ETC2_EAC),
+    (ImageFormat::ASTC4x4, GL_COMPRESSED_RGBA_ASTC_4x4),
+];
+
 pub type ViewportDimensions = [u32; 2];
 
 lazy_static! {
@@ -59,10 +91,13 @@ pub enum TextureTarget {
     Array,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TextureFilter {
     Nearest,
     Linear,
+    /// Linear filtering between the levels of a mipmap, which is generated
+    /// whenever the texture is uploaded.
+    Trilinear,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -347,6 +382,7 @@ struct Texture {
     filter: TextureFilter,
     mode: RenderTargetMode,
     fbo_ids: Vec<FBOId>,
+    depth_rb: Option<RBOId>,
 }
 
 impl Drop for Texture {
@@ -355,6 +391,9 @@ impl Drop for Texture {
             let fbo_ids: Vec<_> = self.fbo_ids.iter().map(|&FBOId(fbo_id)| fbo_id).collect();
             gl::delete_framebuffers(&fbo_ids[..]);
         }
+        if let Some(RBOId(depth_rb)) = self.depth_rb {
+            gl::delete_renderbuffers(&[depth_rb]);
+        }
         gl::delete_textures(&[self.id]);
     }
 }
@@ -471,12 +510,18 @@ pub struct FBOId(gl::GLuint);
 #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
 pub struct VBOId(gl::GLuint);
 
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+struct RBOId(gl::GLuint);
+
 #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
 struct IBOId(gl::GLuint);
 
 #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
 pub struct UBOId(gl::GLuint);
 
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub struct PBOId(gl::GLuint);
+
 const MAX_EVENTS_PER_FRAME: usize = 256;
 const MAX_PROFILE_FRAMES: usize = 4;
 
@@ -779,6 +824,14 @@ impl FileWatcherThread {
 pub struct Capabilities {
     pub max_ubo_size: usize,
     pub supports_multisampling: bool,
+    /// The compressed image formats that can be uploaded as is.
+    pub compressed_formats: Vec<ImageFormat>,
+    /// The formats with fewer channels than RGBA are sampled through a
+    /// texture swizzle, which needs GL 3.3 or ES 3.0.
+    pub supports_texture_swizzle: bool,
+    /// The uncompressed image formats that can't be uploaded as is, and
+    /// are expanded to RGBA8 first.
+    pub expanded_formats: Vec<ImageFormat>,
 }
 
 pub struct Device {
@@ -821,6 +874,28 @@ impl Device {
         let shader_preamble = get_shader_source(SHADER_PREAMBLE, &resource_override_path);
         //file_watcher.add_watch(resource_path);
 
+        let gl_version = gl::get_string(gl::VERSION);
+        let is_gles = gl_version.starts_with("OpenGL ES");
+        let version = parse_gl_version(&gl_version);
+        let extensions = gl_extensions();
+        let has_extension = |name: &str| extensions.iter().any(|extension| extension == name);
+
+        let supports_texture_swizzle = if is_gles {
+            version >= (3, 0)
+        } else {
+            version >= (3, 3) || has_extension("GL_ARB_texture_swizzle")
+        };
+        // 16 bit normalized textures are core on desktop GL, but not on ES.
+        let supports_r16 = !is_gles || has_extension("GL_EXT_texture_norm16");
+
+        let mut expanded_formats = Vec::new();
+        if !supports_texture_swizzle || !supports_r16 {
+            expanded_formats.push(ImageFormat::R16);
+        }
+        if !supports_texture_swizzle {
+            expanded_formats.push(ImageFormat::RG8);
+        }
+
         Device {
             resource_override_path: resource_override_path,
             // This is initialized to 1 by default, but it is set
@@ -831,6 +906,9 @@ impl Device {
             capabilities: Capabilities {
                 max_ubo_size: gl::get_integer_v(gl::MAX_UNIFORM_BLOCK_SIZE) as usize,
                 supports_multisampling: false, //TODO
+                compressed_formats: supported_compressed_formats(),
+                supports_texture_swizzle: supports_texture_swizzle,
+                expanded_formats: expanded_formats,
             },
 
             bound_textures: [ TextureId::invalid(); 16 ],
@@ -1016,6 +1094,7 @@ impl Device {
                 filter: TextureFilter::Nearest,
                 mode: RenderTargetMode::None,
                 fbo_ids: vec![],
+                depth_rb: None,
             };
 
             debug_assert!(self.textures.contains_key(&texture_id) == false);
@@ -1032,21 +1111,47 @@ impl Device {
         (texture.width, texture.height)
     }
 
-    fn set_texture_parameters(&mut self, target: gl::GLuint, filter: TextureFilter) {
-        let filter = match filter {
+    pub fn get_texture_format(&self, texture_id: TextureId) -> ImageFormat {
+        self.textures[&texture_id].format
+    }
+
+    fn set_texture_parameters(&mut self,
+                              target: gl::GLuint,
+                              format: ImageFormat,
+                              filter: TextureFilter) {
+        let (mag_filter, min_filter) = match filter {
             TextureFilter::Nearest => {
-                gl::NEAREST
+                (gl::NEAREST, gl::NEAREST)
             }
             TextureFilter::Linear => {
-                gl::LINEAR
+                (gl::LINEAR, gl::LINEAR)
+            }
+            TextureFilter::Trilinear => {
+                (gl::LINEAR, gl::LINEAR_MIPMAP_LINEAR)
             }
         };
 
-        gl::tex_parameter_i(target, gl::TEXTURE_MAG_FILTER, filter as gl::GLint);
-        gl::tex_parameter_i(target, gl::TEXTURE_MIN_FILTER, filter as gl::GLint);
+        gl::tex_parameter_i(target, gl::TEXTURE_MAG_FILTER, mag_filter as gl::GLint);
+        gl::tex_parameter_i(target, gl::TEXTURE_MIN_FILTER, min_filter as gl::GLint);
 
         gl::tex_parameter_i(target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as gl::GLint);
         gl::tex_parameter_i(target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as gl::GLint);
+
+        // The shaders sample every image as RGBA, so the formats with fewer
+        // channels are swizzled into display colors. Without texture swizzles,
+        // the texture cache expands them to RGBA8 instead.
+        let swizzle = match format {
+            _ if !self.capabilities.supports_texture_swizzle => None,
+            ImageFormat::R16 => Some([gl::RED, gl::RED, gl::RED, gl::ONE]),
+            ImageFormat::RG8 => Some([gl::RED, gl::RED, gl::RED, gl::GREEN]),
+            _ => None,
+        };
+        if let Some(swizzle) = swizzle {
+            gl::tex_parameter_i(target, GL_TEXTURE_SWIZZLE_R, swizzle[0] as gl::GLint);
+            gl::tex_parameter_i(target, GL_TEXTURE_SWIZZLE_G, swizzle[1] as gl::GLint);
+            gl::tex_parameter_i(target, GL_TEXTURE_SWIZZLE_B, swizzle[2] as gl::GLint);
+            gl::tex_parameter_i(target, GL_TEXTURE_SWIZZLE_A, swizzle[3] as gl::GLint);
+        }
     }
 
     fn upload_texture_image(&mut self,
@@ -1067,6 +1172,33 @@ impl Device {
                          pixels);
     }
 
+    fn upload_compressed_texture_image(&mut self,
+                                       target: gl::GLuint,
+                                       width: u32,
+                                       height: u32,
+                                       format: ImageFormat,
+                                       pixels: Option<&[u8]>) {
+        let size = format.packed_size(width, height).unwrap();
+        let data = match pixels {
+            Some(pixels) => {
+                assert!(pixels.len() as u32 == size);
+                pixels.as_ptr()
+            }
+            None => ptr::null(),
+        };
+
+        unsafe {
+            gl::CompressedTexImage2D(target,
+                                     0,
+                                     gl_compressed_format_for_image_format(format),
+                                     width as gl::GLsizei,
+                                     height as gl::GLsizei,
+                                     0,
+                                     size as gl::GLsizei,
+                                     data as *const _);
+        }
+    }
+
     pub fn init_texture(&mut self,
                         texture_id: TextureId,
                         width: u32,
@@ -1092,7 +1224,7 @@ impl Device {
         match mode {
             RenderTargetMode::SimpleRenderTarget => {
                 self.bind_texture(DEFAULT_TEXTURE, texture_id);
-                self.set_texture_parameters(texture_id.target, filter);
+                self.set_texture_parameters(texture_id.target, format, filter);
                 self.upload_texture_image(texture_id.target,
                                           width,
                                           height,
@@ -1104,12 +1236,24 @@ impl Device {
             }
             RenderTargetMode::LayerRenderTarget(layer_count) => {
                 self.bind_texture(DEFAULT_TEXTURE, texture_id);
-                self.set_texture_parameters(texture_id.target, filter);
+                self.set_texture_parameters(texture_id.target, format, filter);
                 self.create_fbo_for_texture_if_necessary(texture_id, Some(layer_count));
             }
+            RenderTargetMode::None if format.is_compressed() => {
+                self.bind_texture(DEFAULT_TEXTURE, texture_id);
+                self.set_texture_parameters(texture_id.target, format, filter);
+                self.upload_compressed_texture_image(texture_id.target,
+                                                     width,
+                                                     height,
+                                                     format,
+                                                     pixels);
+                if filter == TextureFilter::Trilinear {
+                    gl::generate_mipmap(texture_id.target);
+                }
+            }
             RenderTargetMode::None => {
                 self.bind_texture(DEFAULT_TEXTURE, texture_id);
-                self.set_texture_parameters(texture_id.target, filter);
+                self.set_texture_parameters(texture_id.target, format, filter);
                 self.upload_texture_image(texture_id.target,
                                           width,
                                           height,
@@ -1117,6 +1261,9 @@ impl Device {
                                           gl_format,
                                           type_,
                                           pixels);
+                if filter == TextureFilter::Trilinear {
+                    gl::generate_mipmap(texture_id.target);
+                }
             }
         }
     }
@@ -1207,6 +1354,35 @@ impl Device {
         gl::bind_framebuffer(gl::DRAW_FRAMEBUFFER, self.bound_draw_fbo.0);
     }
 
+    /// Attaches a depth buffer to the framebuffer of a `SimpleRenderTarget` texture,
+    /// so that it can be drawn into like the layers of a `LayerRenderTarget`.
+    pub fn create_depth_target_if_necessary(&mut self, texture_id: TextureId) {
+        {
+            let texture = self.textures.get_mut(&texture_id).unwrap();
+            debug_assert!(texture.mode == RenderTargetMode::SimpleRenderTarget);
+            if texture.depth_rb.is_some() {
+                return;
+            }
+
+            let depth_rb = gl::gen_renderbuffers(1)[0];
+            gl::bind_renderbuffer(gl::RENDERBUFFER, depth_rb);
+            gl::renderbuffer_storage(gl::RENDERBUFFER,
+                                     gl::DEPTH_COMPONENT24,
+                                     texture.width as gl::GLsizei,
+                                     texture.height as gl::GLsizei);
+
+            gl::bind_framebuffer(gl::FRAMEBUFFER, texture.fbo_ids[0].0);
+            gl::framebuffer_renderbuffer(gl::FRAMEBUFFER,
+                                         gl::DEPTH_ATTACHMENT,
+                                         gl::RENDERBUFFER,
+                                         depth_rb);
+            texture.depth_rb = Some(RBOId(depth_rb));
+        }
+
+        gl::bind_framebuffer(gl::READ_FRAMEBUFFER, self.bound_read_fbo.0);
+        gl::bind_framebuffer(gl::DRAW_FRAMEBUFFER, self.bound_draw_fbo.0);
+    }
+
     pub fn blit_render_target(&mut self,
                               src_texture_id: TextureId,
                               src_texture_layer: i32,
@@ -1275,6 +1451,29 @@ impl Device {
         self.deinit_texture(temp_texture_id);
     }
 
+    /// Copies a rect of one texture into another, without going through the CPU.
+    pub fn copy_texture_region(&mut self,
+                               src_texture_id: TextureId,
+                               src_rect: DeviceUintRect,
+                               dest_texture_id: TextureId,
+                               dest_origin: DeviceUintPoint) {
+        debug_assert!(self.inside_frame);
+
+        self.bind_read_target(Some((src_texture_id, 0)));
+        self.bind_texture(DEFAULT_TEXTURE, dest_texture_id);
+
+        gl::copy_tex_sub_image_2d(dest_texture_id.target,
+                                  0,
+                                  dest_origin.x as i32,
+                                  dest_origin.y as i32,
+                                  src_rect.origin.x as i32,
+                                  src_rect.origin.y as i32,
+                                  src_rect.size.width as i32,
+                                  src_rect.size.height as i32);
+
+        self.bind_read_target(None);
+    }
+
     pub fn deinit_texture(&mut self, texture_id: TextureId) {
         debug_assert!(self.inside_frame);
 
@@ -1299,12 +1498,23 @@ impl Device {
             gl::delete_framebuffers(&fbo_ids[..]);
         }
 
+        if let Some(RBOId(depth_rb)) = texture.depth_rb.take() {
+            gl::delete_renderbuffers(&[depth_rb]);
+        }
+
         texture.format = ImageFormat::Invalid;
         texture.width = 0;
         texture.height = 0;
         texture.fbo_ids.clear();
     }
 
+    /// Deletes a texture created by `create_texture_ids`, along with its
+    /// render targets.
+    pub fn delete_texture(&mut self, texture_id: TextureId) {
+        // Dropping the texture deletes its GL objects.
+        self.textures.remove(&texture_id);
+    }
+
     pub fn create_program(&mut self,
                           base_filename: &str,
                           include_filename: &str) -> ProgramId {
@@ -1566,13 +1776,14 @@ impl Device {
                                    width: gl::GLint,
                                    height: gl::GLint,
                                    format: gl::GLuint,
+                                   type_: gl::GLuint,
                                    data: &[u8]) {
         gl::tex_sub_image_2d(target,
                              0,
                              x0, y0,
                              width, height,
                              format,
-                             gl::UNSIGNED_BYTE,
+                             type_,
                              data);
     }
 
@@ -1586,9 +1797,15 @@ impl Device {
                           data: &[u8]) {
         debug_assert!(self.inside_frame);
 
+        let format = self.textures[&texture_id].format;
+        if format.is_compressed() {
+            self.update_compressed_texture(texture_id, x0, y0, width, height, stride, data);
+            return;
+        }
+
         let mut expanded_data = Vec::new();
 
-        let (gl_format, bpp, data) = match self.textures.get(&texture_id).unwrap().format {
+        let (gl_format, bpp, data) = match format {
             ImageFormat::A8 => {
                 if cfg!(any(target_arch="arm", target_arch="aarch64")) {
                     for byte in data {
@@ -1604,7 +1821,17 @@ impl Device {
             }
             ImageFormat::RGB8 => (gl::RGB, 3, data),
             ImageFormat::RGBA8 => (GL_FORMAT_BGRA, 4, data),
-            ImageFormat::Invalid | ImageFormat::RGBAF32 => unreachable!(),
+            ImageFormat::R16 => (gl::RED, 2, data),
+            ImageFormat::RG8 => (gl::RG, 2, data),
+            ImageFormat::RGBA16F => (gl::RGBA, 8, data),
+            ImageFormat::RGBAF32 => (gl::RGBA, 16, data),
+            ImageFormat::Invalid |
+            ImageFormat::BC1 |
+            ImageFormat::BC2 |
+            ImageFormat::BC3 |
+            ImageFormat::ETC2RGB8 |
+            ImageFormat::ETC2RGBA8 |
+            ImageFormat::ASTC4x4 => unreachable!(),
         };
 
         let row_length = match stride {
@@ -1625,12 +1852,51 @@ impl Device {
                                          width as gl::GLint,
                                          height as gl::GLint,
                                          gl_format,
+                                         gl_type_for_texture_format(format),
                                          data);
 
         // Reset row length to 0, otherwise the stride would apply to all texture uploads.
         if let Some(..) = stride {
             gl::pixel_store_i(gl::UNPACK_ROW_LENGTH, 0 as gl::GLint);
         }
+
+        if self.textures[&texture_id].filter == TextureFilter::Trilinear {
+            gl::generate_mipmap(texture_id.target);
+        }
+    }
+
+    /// Compressed updates must be aligned to the block size, and the blocks
+    /// must be tightly packed.
+    fn update_compressed_texture(&mut self,
+                                 texture_id: TextureId,
+                                 x0: u32,
+                                 y0: u32,
+                                 width: u32,
+                                 height: u32,
+                                 stride: Option<u32>,
+                                 data: &[u8]) {
+        let format = self.textures[&texture_id].format;
+        let (block_size, _) = format.compressed_block_size().unwrap();
+        assert!(stride.is_none(), "Compressed texture updates can't have a stride");
+        assert!(x0 % block_size == 0 && y0 % block_size == 0);
+        assert!(data.len() as u32 == format.packed_size(width, height).unwrap());
+
+        self.bind_texture(DEFAULT_TEXTURE, texture_id);
+        unsafe {
+            gl::CompressedTexSubImage2D(texture_id.target,
+                                        0,
+                                        x0 as gl::GLint,
+                                        y0 as gl::GLint,
+                                        width as gl::GLsizei,
+                                        height as gl::GLsizei,
+                                        gl_compressed_format_for_image_format(format),
+                                        data.len() as gl::GLsizei,
+                                        data.as_ptr() as *const _);
+        }
+
+        if self.textures[&texture_id].filter == TextureFilter::Trilinear {
+            gl::generate_mipmap(texture_id.target);
+        }
     }
 
     fn clear_vertex_array(&mut self) {
@@ -1827,6 +2093,65 @@ impl Device {
         gl::delete_buffers(&[buffer.0]);
     }
 
+    /// Reads back a rectangle of the bound read target as RGBA8. The rect
+    /// is in GL window coordinates, and the rows are returned bottom up.
+    pub fn read_pixels(&mut self, rect: DeviceIntRect) -> Vec<u8> {
+        gl::read_pixels(rect.origin.x,
+                        rect.origin.y,
+                        rect.size.width,
+                        rect.size.height,
+                        gl::RGBA,
+                        gl::UNSIGNED_BYTE)
+    }
+
+    pub fn create_pbo(&mut self) -> PBOId {
+        PBOId(gl::gen_buffers(1)[0])
+    }
+
+    pub fn delete_pbo(&mut self, pbo: PBOId) {
+        gl::delete_buffers(&[pbo.0]);
+    }
+
+    /// Starts an asynchronous read of a rectangle of the bound read target
+    /// into a pixel buffer object, in the same layout as `read_pixels`.
+    pub fn read_pixels_into_pbo(&mut self, pbo: PBOId, rect: DeviceIntRect) {
+        let size = rect.size.width * rect.size.height * 4;
+
+        gl::bind_buffer(gl::PIXEL_PACK_BUFFER, pbo.0);
+        unsafe {
+            gl::BufferData(gl::PIXEL_PACK_BUFFER,
+                           size as gl::GLsizeiptr,
+                           ptr::null(),
+                           gl::STREAM_READ);
+            gl::ReadPixels(rect.origin.x,
+                           rect.origin.y,
+                           rect.size.width,
+                           rect.size.height,
+                           gl::RGBA,
+                           gl::UNSIGNED_BYTE,
+                           ptr::null_mut());
+        }
+        gl::bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+    }
+
+    /// Returns the contents of a pixel buffer object filled by
+    /// `read_pixels_into_pbo`. This blocks until the read has completed.
+    pub fn get_pbo_data(&mut self, pbo: PBOId, size: usize) -> Vec<u8> {
+        gl::bind_buffer(gl::PIXEL_PACK_BUFFER, pbo.0);
+        let data = unsafe {
+            let mapped = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER,
+                                            0,
+                                            size as gl::GLsizeiptr,
+                                            gl::MAP_READ_BIT) as *const u8;
+            assert!(!mapped.is_null(), "Unable to map the pixel buffer!");
+            let data = slice::from_raw_parts(mapped, size).to_vec();
+            gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+            data
+        };
+        gl::bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+        data
+    }
+
     #[cfg(target_os = "android")]
     pub fn set_multisample(&self, enable: bool) {
     }
@@ -1946,13 +2271,73 @@ fn gl_texture_formats_for_image_format(format: ImageFormat) -> (gl::GLint, gl::G
             }
         }
         ImageFormat::RGBAF32 => (gl::RGBA32F as gl::GLint, gl::RGBA),
+        ImageFormat::R16 => (GL_R16 as gl::GLint, gl::RED),
+        ImageFormat::RG8 => (gl::RG8 as gl::GLint, gl::RG),
+        ImageFormat::RGBA16F => (gl::RGBA16F as gl::GLint, gl::RGBA),
+        // Compressed textures are created with `CompressedTexImage2D`, this
+        // is only used to release their storage.
+        ImageFormat::BC1 |
+        ImageFormat::BC2 |
+        ImageFormat::BC3 |
+        ImageFormat::ETC2RGB8 |
+        ImageFormat::ETC2RGBA8 |
+        ImageFormat::ASTC4x4 => (gl::RGBA as gl::GLint, gl::RGBA),
         ImageFormat::Invalid => unreachable!(),
     }
 }
 
+fn gl_compressed_format_for_image_format(format: ImageFormat) -> gl::GLuint {
+    COMPRESSED_FORMATS.iter()
+                      .find(|&&(compressed_format, _)| compressed_format == format)
+                      .map(|&(_, gl_format)| gl_format)
+                      .expect("Not a compressed format!")
+}
+
+/// Returns the major and minor version from a GL version string, such as
+/// "3.3.0 NVIDIA 375.39" or "OpenGL ES 3.0 Mesa 17.0.3".
+fn parse_gl_version(version: &str) -> (u32, u32) {
+    let number = version.split_whitespace()
+                        .find(|word| word.starts_with(|c: char| c.is_digit(10)))
+                        .unwrap_or("");
+    let mut parts = number.split('.').map(|part| part.parse().unwrap_or(0));
+    (parts.next().unwrap_or(0), parts.next().unwrap_or(0))
+}
+
+fn gl_extensions() -> Vec<String> {
+    let count = gl::get_integer_v(gl::NUM_EXTENSIONS);
+    (0..cmp::max(count, 0) as gl::GLuint).filter_map(|index| unsafe {
+        let name = gl::GetStringi(gl::EXTENSIONS, index);
+        if name.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(name as *const c_char).to_string_lossy().into_owned())
+        }
+    }).collect()
+}
+
+/// Returns the compressed formats the driver reports that we know how to upload.
+fn supported_compressed_formats() -> Vec<ImageFormat> {
+    let count = gl::get_integer_v(gl::NUM_COMPRESSED_TEXTURE_FORMATS);
+    if count <= 0 {
+        return Vec::new();
+    }
+
+    let mut gl_formats = vec![0; count as usize];
+    unsafe {
+        gl::GetIntegerv(gl::COMPRESSED_TEXTURE_FORMATS, gl_formats.as_mut_ptr());
+    }
+
+    COMPRESSED_FORMATS.iter()
+                      .filter(|&&(_, gl_format)| gl_formats.contains(&(gl_format as gl::GLint)))
+                      .map(|&(format, _)| format)
+                      .collect()
+}
+
 fn gl_type_for_texture_format(format: ImageFormat) -> gl::GLuint {
     match format {
         ImageFormat::RGBAF32 => gl::FLOAT,
+        ImageFormat::RGBA16F => gl::HALF_FLOAT,
+        ImageFormat::R16 => gl::UNSIGNED_SHORT,
         _ => gl::UNSIGNED_BYTE,
     }
 }
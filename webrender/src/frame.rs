@@ -21,7 +21,7 @@ use webrender_traits::{ClipRegion, ColorF, DisplayItem, StackingContext, FilterO
 use webrender_traits::{ScrollEventPhase, ScrollLayerInfo, ScrollLocation, SpecificDisplayItem, ScrollLayerState};
 use webrender_traits::{LayerRect, LayerPoint, LayerSize};
 use webrender_traits::{ServoScrollRootId, ScrollLayerRect, as_scroll_parent_rect, ScrollLayerPixel};
-use webrender_traits::{WorldPoint, WorldPoint4D};
+use webrender_traits::{DeviceUintSize, WorldPoint, WorldPoint4D, YuvData};
 use webrender_traits::{LayerToScrollTransform, ScrollToWorldTransform};
 
 #[cfg(target_os = "macos")]
@@ -37,6 +37,7 @@ static DEFAULT_SCROLLBAR_COLOR: ColorF = ColorF { r: 0.3, g: 0.3, b: 0.3, a: 0.6
 
 struct FlattenContext<'a> {
     scene: &'a Scene,
+    resource_cache: &'a ResourceCache,
     pipeline_sizes: &'a mut HashMap<PipelineId, LayerSize>,
     builder: &'a mut FrameBuilder,
 }
@@ -152,6 +153,44 @@ impl StackingContextHelpers for StackingContext {
     }
 }
 
+/// Returns false if the item draws a font or an image that isn't in the
+/// resource cache.
+fn item_resources_available(resource_cache: &ResourceCache, item: &SpecificDisplayItem) -> bool {
+    match *item {
+        SpecificDisplayItem::Image(ref info) => resource_cache.has_image(info.image_key),
+        SpecificDisplayItem::BorderImage(ref info) => resource_cache.has_image(info.image_key),
+        SpecificDisplayItem::YuvImage(ref info) => {
+            match info.yuv_data {
+                YuvData::PlanarYCbCr(y, u, v) |
+                YuvData::DctCoefficients(y, u, v) => {
+                    resource_cache.has_image(y) &&
+                    resource_cache.has_image(u) &&
+                    resource_cache.has_image(v)
+                }
+                YuvData::NV12(y, uv) => resource_cache.has_image(y) && resource_cache.has_image(uv),
+                YuvData::InterleavedYCbCr(yuv) => resource_cache.has_image(yuv),
+            }
+        }
+        SpecificDisplayItem::Text(ref info) => resource_cache.has_font(info.font_key),
+        _ => true,
+    }
+}
+
+/// Returns the clip without its image mask if the image isn't in the
+/// resource cache.
+fn clip_with_available_mask(resource_cache: &ResourceCache, clip: &ClipRegion) -> ClipRegion {
+    match clip.image_mask {
+        Some(ref mask) if !resource_cache.has_image(mask.image) => {
+            warn!("Ignoring an image mask whose image was deleted");
+            ClipRegion {
+                image_mask: None,
+                ..*clip
+            }
+        }
+        _ => *clip,
+    }
+}
+
 struct DisplayListTraversal<'a> {
     pub display_list: &'a [DisplayItem],
     pub next_item_index: usize,
@@ -229,9 +268,6 @@ impl Frame {
             old_layer_scrolling_states.insert(layer_id, old_layer.scrolling);
         }
 
-        // Advance to the next frame.
-        self.id.0 += 1;
-
         old_layer_scrolling_states
     }
 
@@ -469,12 +505,35 @@ impl Frame {
 
     pub fn create(&mut self,
                   scene: &Scene,
+                  resource_cache: &ResourceCache,
+                  root_pipeline_id: PipelineId,
+                  frame_id: FrameId,
                   pipeline_sizes: &mut HashMap<PipelineId, LayerSize>) {
-        let root_pipeline_id = match scene.root_pipeline_id {
-            Some(root_pipeline_id) => root_pipeline_id,
+        let viewport_size = match scene.pipeline_map.get(&root_pipeline_id) {
+            Some(root_pipeline) => root_pipeline.viewport_size,
             None => return,
         };
 
+        self.create_for_pipeline(scene,
+                                 resource_cache,
+                                 root_pipeline_id,
+                                 viewport_size,
+                                 frame_id,
+                                 pipeline_sizes);
+    }
+
+    /// Flattens the display list of `root_pipeline_id` as if it was the root of
+    /// the scene, laid out in a viewport of `viewport_size`.
+    ///
+    /// The frames of all the documents share the resource cache, so their ids
+    /// are handed out by the render backend, in increasing order.
+    pub fn create_for_pipeline(&mut self,
+                               scene: &Scene,
+                               resource_cache: &ResourceCache,
+                               root_pipeline_id: PipelineId,
+                               viewport_size: LayerSize,
+                               frame_id: FrameId,
+                               pipeline_sizes: &mut HashMap<PipelineId, LayerSize>) {
         let root_pipeline = match scene.pipeline_map.get(&root_pipeline_id) {
             Some(root_pipeline) => root_pipeline,
             None => return,
@@ -487,6 +546,7 @@ impl Frame {
         };
 
         let old_layer_scrolling_states = self.reset();
+        self.id = frame_id;
         self.pipeline_auxiliary_lists = scene.pipeline_auxiliary_lists.clone();
 
         self.pipeline_epoch_map.insert(root_pipeline_id, root_pipeline.epoch);
@@ -505,7 +565,7 @@ impl Frame {
         // Insert global position: fixed elements layer
         debug_assert!(self.layers.is_empty());
         let root_fixed_layer_id = ScrollLayerId::create_fixed(root_pipeline_id);
-        let root_viewport = LayerRect::new(LayerPoint::zero(), root_pipeline.viewport_size);
+        let root_viewport = LayerRect::new(LayerPoint::zero(), viewport_size);
         let layer = Layer::new(&root_viewport,
                                root_clip.main.size,
                                &LayerToScrollTransform::identity(),
@@ -521,7 +581,7 @@ impl Frame {
             }
         });
 
-        let mut frame_builder = FrameBuilder::new(root_pipeline.viewport_size,
+        let mut frame_builder = FrameBuilder::new(viewport_size,
                                                   background_color,
                                                   self.debug,
                                                   self.frame_builder_config);
@@ -529,6 +589,7 @@ impl Frame {
         {
             let mut context = FlattenContext {
                 scene: scene,
+                resource_cache: resource_cache,
                 pipeline_sizes: pipeline_sizes,
                 builder: &mut frame_builder,
             };
@@ -788,30 +849,40 @@ impl Frame {
                          layer_relative_transform: LayerToScrollTransform,
                          level: i32) {
         while let Some(item) = traversal.next() {
+            // The fonts and images of a namespace are freed along with it, even if
+            // the display lists of other namespaces still refer to them. The items
+            // that draw them are left out, and the image masks ignored.
+            if !item_resources_available(context.resource_cache, &item.item) {
+                warn!("Leaving out a display item whose font or image was deleted");
+                continue;
+            }
+            let clip = clip_with_available_mask(context.resource_cache, &item.clip);
+
             match item.item {
                 SpecificDisplayItem::WebGL(ref info) => {
                     context.builder.add_webgl_rectangle(item.rect,
-                                                        &item.clip, info.context_id);
+                                                        &clip, info.context_id);
                 }
                 SpecificDisplayItem::Image(ref info) => {
+                    let tiling = context.resource_cache.get_image_tiling(info.image_key);
                     context.builder.add_image(item.rect,
-                                              &item.clip,
+                                              &clip,
                                               &info.stretch_size,
                                               &info.tile_spacing,
                                               info.image_key,
-                                              info.image_rendering);
+                                              info.image_rendering,
+                                              tiling);
                 }
                 SpecificDisplayItem::YuvImage(ref info) => {
                     context.builder.add_yuv_image(item.rect,
-                                                  &item.clip,
-                                                  info.y_image_key,
-                                                  info.u_image_key,
-                                                  info.v_image_key,
-                                                  info.color_space);
+                                                  &clip,
+                                                  info.yuv_data,
+                                                  info.color_space,
+                                                  info.color_range);
                 }
                 SpecificDisplayItem::Text(ref text_info) => {
                     context.builder.add_text(item.rect,
-                                             &item.clip,
+                                             &clip,
                                              text_info.font_key,
                                              text_info.size,
                                              text_info.blur_radius,
@@ -820,20 +891,39 @@ impl Frame {
                 }
                 SpecificDisplayItem::Rectangle(ref info) => {
                     context.builder.add_solid_rectangle(&item.rect,
-                                                        &item.clip,
+                                                        &clip,
                                                         &info.color,
                                                         PrimitiveFlags::None);
                 }
                 SpecificDisplayItem::Gradient(ref info) => {
                     context.builder.add_gradient(item.rect,
-                                                 &item.clip,
+                                                 &clip,
                                                  info.start_point,
                                                  info.end_point,
-                                                 info.stops);
+                                                 info.stops,
+                                                 info.extend_mode);
+                }
+                SpecificDisplayItem::RadialGradient(ref info) => {
+                    context.builder.add_radial_gradient(item.rect,
+                                                        &clip,
+                                                        info.center,
+                                                        info.radius,
+                                                        info.start_radius,
+                                                        info.end_radius,
+                                                        info.stops,
+                                                        info.extend_mode);
+                }
+                SpecificDisplayItem::ConicGradient(ref info) => {
+                    context.builder.add_conic_gradient(item.rect,
+                                                       &clip,
+                                                       info.center,
+                                                       info.angle,
+                                                       info.stops,
+                                                       info.extend_mode);
                 }
                 SpecificDisplayItem::BoxShadow(ref box_shadow_info) => {
                     context.builder.add_box_shadow(&box_shadow_info.box_bounds,
-                                                   &item.clip,
+                                                   &clip,
                                                    &box_shadow_info.offset,
                                                    &box_shadow_info.color,
                                                    box_shadow_info.blur_radius,
@@ -842,7 +932,16 @@ impl Frame {
                                                    box_shadow_info.clip_mode);
                 }
                 SpecificDisplayItem::Border(ref info) => {
-                    context.builder.add_border(item.rect, &item.clip, info);
+                    context.builder.add_border(item.rect, &clip, info);
+                }
+                SpecificDisplayItem::BorderImage(ref info) => {
+                    // The nine parts all come from one texture, so a border image
+                    // that is too large for the texture cache isn't split into
+                    // tiles, and is drawn from a smaller copy instead.
+                    let image_properties = context.resource_cache.get_image_properties(info.image_key);
+                    let image_size = DeviceUintSize::new(image_properties.width,
+                                                         image_properties.height);
+                    context.builder.add_border_image(item.rect, &clip, info, image_size);
                 }
                 SpecificDisplayItem::PushStackingContext(ref info) => {
                     self.flatten_stacking_context(traversal,
@@ -853,7 +952,7 @@ impl Frame {
                                                   layer_relative_transform,
                                                   level + 1,
                                                   &info.stacking_context,
-                                                  &item.clip);
+                                                  &clip);
                 }
                 SpecificDisplayItem::PushScrollLayer(ref info) => {
                     self.flatten_scroll_layer(traversal,
@@ -880,17 +979,23 @@ impl Frame {
         }
     }
 
+    /// Builds the frame to send to the renderer.
+    ///
+    /// The texture cache is shared with the frames of the other documents, so
+    /// compacting it and expiring old resources is left to the render backend.
     pub fn build(&mut self,
                  resource_cache: &mut ResourceCache,
                  auxiliary_lists_map: &AuxiliaryListsMap,
                  device_pixel_ratio: f32)
                  -> RendererFrame {
         self.update_layer_transforms();
-        let frame = self.build_frame(resource_cache,
-                                     auxiliary_lists_map,
-                                     device_pixel_ratio);
-        resource_cache.expire_old_resources(self.id);
-        frame
+        self.build_frame(resource_cache,
+                         auxiliary_lists_map,
+                         device_pixel_ratio)
+    }
+
+    pub fn id(&self) -> FrameId {
+        self.id
     }
 
     fn update_layer_transform(&mut self,
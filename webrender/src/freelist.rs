@@ -100,7 +100,7 @@ impl<T: FreeListItem> FreeList<T> {
         self.first_free_index = Some(id);
     }
 
-    pub fn for_each_item<F>(&mut self, f: F) where F: Fn(&mut T) {
+    fn free_ids(&self) -> HashSet<FreeListItemId> {
         let mut free_ids = HashSet::new();
 
         let mut next_free_id = self.first_free_index;
@@ -111,6 +111,20 @@ impl<T: FreeListItem> FreeList<T> {
             next_free_id = free_item.next_free_id();
         }
 
+        free_ids
+    }
+
+    /// Returns the ids of all the items that are in use.
+    pub fn ids(&self) -> Vec<FreeListItemId> {
+        let free_ids = self.free_ids();
+        (0..self.items.len()).map(|index| FreeListItemId(index as u32))
+                             .filter(|id| !free_ids.contains(id))
+                             .collect()
+    }
+
+    pub fn for_each_item<F>(&mut self, f: F) where F: Fn(&mut T) {
+        let free_ids = self.free_ids();
+
         for (index, mut item) in self.items.iter_mut().enumerate() {
             let id = FreeListItemId(index as u32);
             if !free_ids.contains(&id) {
@@ -19,6 +19,7 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use tiling;
 use webrender_traits::{Epoch, ColorF, PipelineId, DeviceIntSize};
+use webrender_traits::{DeviceUintPoint, DeviceUintRect, DocumentId};
 use webrender_traits::{ImageFormat, MixBlendMode, NativeFontHandle};
 use webrender_traits::{ExternalImageId, ScrollLayerId, WebGLCommand};
 
@@ -345,6 +346,8 @@ pub enum TextureUpdateOp {
     Create(u32, u32, ImageFormat, TextureFilter, RenderTargetMode, Option<Arc<Vec<u8>>>),
     Update(u32, u32, u32, u32, Arc<Vec<u8>>, Option<u32>),
     Grow(u32, u32, ImageFormat, TextureFilter, RenderTargetMode),
+    /// Copies a rect of another texture to the given location in this one.
+    Copy(CacheTextureId, DeviceUintRect, DeviceUintPoint),
     Free
 }
 
@@ -399,7 +402,12 @@ impl RendererFrame {
 
 pub enum ResultMsg {
     RefreshShader(PathBuf),
-    NewFrame(RendererFrame, TextureUpdateList, ExternalImageUpdateList, BackendProfileCounters),
+    NewFrame(DocumentId, RendererFrame, TextureUpdateList, ExternalImageUpdateList, BackendProfileCounters),
+    /// Drops the frame of a deleted document.
+    DeleteDocument(DocumentId),
+    /// A frame to draw into the render target of a rendered image, after
+    /// applying the texture updates.
+    RenderedImage(CacheTextureId, RendererFrame, TextureUpdateList),
 }
 
 #[repr(u32)]
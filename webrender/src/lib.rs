@@ -67,8 +67,10 @@ mod record;
 mod render_backend;
 mod resource_cache;
 mod scene;
+mod software;
 mod spring;
 mod texture_cache;
+mod texture_decoder;
 mod tiling;
 mod util;
 
@@ -127,4 +129,4 @@ extern crate byteorder;
 extern crate threadpool;
 
 pub use renderer::{ExternalImage, ExternalImageSource, ExternalImageHandler};
-pub use renderer::{Renderer, RendererOptions};
+pub use renderer::{AsyncReadPixels, ReadPixelsFormat, Renderer, RendererOptions};
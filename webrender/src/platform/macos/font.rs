@@ -119,6 +119,21 @@ impl FontContext {
         self.cg_fonts.insert((*font_key).clone(), native_font_handle);
     }
 
+    pub fn delete_font(&mut self, font_key: &FontKey) {
+        if self.cg_fonts.remove(font_key).is_none() {
+            return
+        }
+
+        let ct_font_keys: Vec<(FontKey, Au)> = self.ct_fonts
+                                                   .keys()
+                                                   .filter(|&&(key, _)| key == *font_key)
+                                                   .cloned()
+                                                   .collect();
+        for ct_font_key in &ct_font_keys {
+            self.ct_fonts.remove(ct_font_key);
+        }
+    }
+
     fn get_ct_font(&mut self,
                    font_key: FontKey,
                    size: Au) -> Option<CTFont> {
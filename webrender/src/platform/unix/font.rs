@@ -6,7 +6,7 @@ use app_units::Au;
 use webrender_traits::{FontKey, FontRenderMode, GlyphDimensions, NativeFontHandle};
 
 use freetype::freetype::{FTErrorMethods, FT_PIXEL_MODE_GRAY, FT_PIXEL_MODE_MONO, FT_PIXEL_MODE_LCD};
-use freetype::freetype::{FT_Done_FreeType, FT_RENDER_MODE_LCD, FT_Library_SetLcdFilter};
+use freetype::freetype::{FT_Done_Face, FT_Done_FreeType, FT_RENDER_MODE_LCD, FT_Library_SetLcdFilter};
 use freetype::freetype::{FT_RENDER_MODE_NORMAL, FT_RENDER_MODE_MONO};
 use freetype::freetype::{FT_Library, FT_Set_Char_Size};
 use freetype::freetype::{FT_Face, FT_Long, FT_UInt, FT_F26Dot6};
@@ -86,6 +86,15 @@ impl FontContext {
         panic!("TODO: Not supported on Linux");
     }
 
+    pub fn delete_font(&mut self, font_key: &FontKey) {
+        if let Some(face) = self.faces.remove(font_key) {
+            let result = unsafe {
+                FT_Done_Face(face.face)
+            };
+            assert!(result.succeeded());
+        }
+    }
+
     fn load_glyph(&self,
                   font_key: FontKey,
                   size: Au,
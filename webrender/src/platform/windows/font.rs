@@ -54,6 +54,10 @@ impl FontContext {
         self.fonts.insert((*font_key).clone(), realized_font);
     }
 
+    pub fn delete_font(&mut self, font_key: &FontKey) {
+        self.fonts.remove(font_key);
+    }
+
     fn get_glyph_dimensions_and_maybe_rasterize(&self,
                                                 font_key: FontKey,
                                                 size: Au,
@@ -7,12 +7,14 @@ use euclid::Size2D;
 use gpu_store::{GpuStore, GpuStoreAddress};
 use internal_types::SourceTexture;
 use mask_cache::{ClipSource, MaskCacheInfo};
-use resource_cache::{ImageProperties, ResourceCache};
+use resource_cache::{ImageProperties, ResourceCache, TileOffset, fits_texture_cache};
+use std::i32;
 use std::mem;
+use std::ops::Range;
 use std::usize;
-use tiling::{RenderTask, RenderTaskLocation};
-use util::TransformedRect;
-use webrender_traits::{AuxiliaryLists, ColorF, ImageKey, ImageRendering, YuvColorSpace};
+use tiling::{RenderTask, RenderTaskLocation, RENDERABLE_CACHE_SIZE};
+use util::{TransformedRect, pack_as_float};
+use webrender_traits::{AuxiliaryLists, ColorF, ExtendMode, ImageKey, ImageRendering, YuvColorRange, YuvColorSpace, YuvFormat};
 use webrender_traits::{ClipRegion, ComplexClipRegion, ItemRange, GlyphKey};
 use webrender_traits::{FontKey, FontRenderMode, WebGLContextId};
 use webrender_traits::{device_length, DeviceIntRect, DeviceIntSize};
@@ -23,6 +25,10 @@ use webrender_traits::LayerToWorldTransform;
 pub const CLIP_DATA_GPU_SIZE: usize = 5;
 pub const MASK_DATA_GPU_SIZE: usize = 1;
 
+/// The most repetitions of its stops an axis-aligned gradient is drawn
+/// with, before it is drawn by the angle gradient shader instead.
+const MAX_ALIGNED_GRADIENT_REPETITIONS: f32 = 64.0;
+
 /// Stores two coordinates in texel space. The coordinates
 /// are stored in texel coordinates because the texture atlas
 /// may grow. Storing them as texel coords and normalizing
@@ -75,7 +81,10 @@ pub enum PrimitiveKind {
     Image,
     YuvImage,
     Border,
+    BorderImage,
     Gradient,
+    RadialGradient,
+    ConicGradient,
     BoxShadow,
 }
 
@@ -122,7 +131,7 @@ pub struct RectanglePrimitive {
 
 #[derive(Debug)]
 pub enum ImagePrimitiveKind {
-    Image(ImageKey, ImageRendering, LayerSize),
+    Image(ImageKey, ImageRendering, Option<TileOffset>, LayerSize),
     WebGL(WebGLContextId),
 }
 
@@ -140,16 +149,28 @@ pub struct ImagePrimitiveGpu {
     pub tile_spacing: LayerSize,
 }
 
+/// One of the nine parts of a border image: where it is drawn, the part of
+/// the image it shows as a fraction of the image size, and the size and
+/// offset of its tiles.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct BorderImagePatch {
+    pub local_rect: LayerRect,
+    pub src_rect: [f32; 4],
+    pub tile_size: LayerSize,
+    pub tile_offset: LayerPoint,
+    pub padding: [f32; 4],
+}
+
 #[derive(Debug)]
 pub struct YuvImagePrimitiveCpu {
-    pub y_key: ImageKey,
-    pub u_key: ImageKey,
-    pub v_key: ImageKey,
-    pub y_texture_id: SourceTexture,
-    pub u_texture_id: SourceTexture,
-    pub v_texture_id: SourceTexture,
+    pub yuv_key: [ImageKey; 3],
+    pub yuv_texture_id: [SourceTexture; 3],
+    pub format: YuvFormat,
 }
 
+/// The uv rects of the planes in `YuvImagePrimitiveCpu::yuv_key` order, so
+/// the interleaved UV plane of NV12 goes in the `u` slot.
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub struct YuvImagePrimitiveGpu {
@@ -161,11 +182,11 @@ pub struct YuvImagePrimitiveGpu {
     pub v_uv1: DevicePoint,
     pub size: LayerSize,
     pub color_space: f32,
-    pub padding: f32,
+    pub color_range: f32,
 }
 
 impl YuvImagePrimitiveGpu {
-    pub fn new(size: LayerSize, color_space: YuvColorSpace) -> Self {
+    pub fn new(size: LayerSize, color_space: YuvColorSpace, color_range: YuvColorRange) -> Self {
         YuvImagePrimitiveGpu {
             y_uv0: DevicePoint::zero(),
             y_uv1: DevicePoint::zero(),
@@ -175,7 +196,7 @@ impl YuvImagePrimitiveGpu {
             v_uv1: DevicePoint::zero(),
             size: size,
             color_space: color_space as u32 as f32,
-            padding: 0.0,
+            color_range: color_range as u32 as f32,
         }
     }
 }
@@ -236,14 +257,52 @@ pub struct GradientPrimitiveGpu {
     pub start_point: LayerPoint,
     pub end_point: LayerPoint,
     pub kind: f32,
-    pub padding: [f32; 3],
+    pub extend_mode: f32,
+    pub first_offset: f32,
+    pub period: f32,
 }
 
 #[derive(Debug)]
 pub struct GradientPrimitiveCpu {
     pub stops_range: ItemRange,
     pub kind: GradientType,
+    pub extend_mode: ExtendMode,
     pub reverse_stops: bool,
+    /// The repetitions of the stops that an axis-aligned gradient
+    /// draws, where the stops themselves are repetition 0.
+    pub repetitions: Range<i32>,
+    pub cache_dirty: bool,
+}
+
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct RadialGradientPrimitiveGpu {
+    pub center: LayerPoint,
+    pub start_radius: f32,
+    pub end_radius: f32,
+    pub ratio_xy: f32,
+    pub extend_mode: f32,
+    pub padding: [f32; 2],
+}
+
+#[derive(Debug)]
+pub struct RadialGradientPrimitiveCpu {
+    pub stops_range: ItemRange,
+    pub cache_dirty: bool,
+}
+
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct ConicGradientPrimitiveGpu {
+    pub center: LayerPoint,
+    pub angle: f32,
+    pub extend_mode: f32,
+    pub padding: [f32; 4],
+}
+
+#[derive(Debug)]
+pub struct ConicGradientPrimitiveCpu {
+    pub stops_range: ItemRange,
     pub cache_dirty: bool,
 }
 
@@ -410,7 +469,10 @@ pub enum PrimitiveContainer {
     Image(ImagePrimitiveCpu, ImagePrimitiveGpu),
     YuvImage(YuvImagePrimitiveCpu, YuvImagePrimitiveGpu),
     Border(BorderPrimitiveCpu, BorderPrimitiveGpu),
+    BorderImage(ImagePrimitiveCpu, Vec<BorderImagePatch>),
     Gradient(GradientPrimitiveCpu, GradientPrimitiveGpu),
+    RadialGradient(RadialGradientPrimitiveCpu, RadialGradientPrimitiveGpu),
+    ConicGradient(ConicGradientPrimitiveCpu, ConicGradientPrimitiveGpu),
     BoxShadow(BoxShadowPrimitiveGpu, Vec<LayerRect>),
 }
 
@@ -421,6 +483,8 @@ pub struct PrimitiveStore {
     pub cpu_images: Vec<ImagePrimitiveCpu>,
     pub cpu_yuv_images: Vec<YuvImagePrimitiveCpu>,
     pub cpu_gradients: Vec<GradientPrimitiveCpu>,
+    pub cpu_radial_gradients: Vec<RadialGradientPrimitiveCpu>,
+    pub cpu_conic_gradients: Vec<ConicGradientPrimitiveCpu>,
     pub cpu_metadata: Vec<PrimitiveMetadata>,
     pub cpu_borders: Vec<BorderPrimitiveCpu>,
 
@@ -447,6 +511,8 @@ impl PrimitiveStore {
             cpu_images: Vec::new(),
             cpu_yuv_images: Vec::new(),
             cpu_gradients: Vec::new(),
+            cpu_radial_gradients: Vec::new(),
+            cpu_conic_gradients: Vec::new(),
             cpu_borders: Vec::new(),
             gpu_geometry: GpuStore::new(),
             gpu_data16: GpuStore::new(),
@@ -570,6 +636,30 @@ impl PrimitiveStore {
                 self.cpu_borders.push(border_cpu);
                 metadata
             }
+            PrimitiveContainer::BorderImage(mut image_cpu, patches) => {
+                image_cpu.resource_address = self.gpu_resource_rects.alloc(1);
+
+                let gpu_patches_address = self.gpu_data64.get_next_address();
+
+                let metadata = PrimitiveMetadata {
+                    is_opaque: false,
+                    clip_source: clip_source,
+                    clip_cache_info: clip_info,
+                    prim_kind: PrimitiveKind::BorderImage,
+                    cpu_prim_index: SpecificPrimitiveIndex(self.cpu_images.len()),
+                    gpu_prim_index: gpu_patches_address,
+                    gpu_data_address: gpu_patches_address,
+                    gpu_data_count: patches.len() as i32,
+                    render_task: None,
+                };
+
+                for patch in patches {
+                    self.gpu_data64.push(patch);
+                }
+
+                self.cpu_images.push(image_cpu);
+                metadata
+            }
             PrimitiveContainer::Gradient(gradient_cpu, gradient_gpu) => {
                 let gpu_address = self.gpu_data32.push(gradient_gpu);
                 let gpu_stops_address = self.gpu_data32.alloc(gradient_cpu.stops_range.length);
@@ -589,6 +679,44 @@ impl PrimitiveStore {
                 self.cpu_gradients.push(gradient_cpu);
                 metadata
             }
+            PrimitiveContainer::RadialGradient(radial_gradient_cpu, radial_gradient_gpu) => {
+                let gpu_address = self.gpu_data32.push(radial_gradient_gpu);
+                let gpu_stops_address = self.gpu_data32.alloc(radial_gradient_cpu.stops_range.length);
+
+                let metadata = PrimitiveMetadata {
+                    is_opaque: false,
+                    clip_source: clip_source,
+                    clip_cache_info: clip_info,
+                    prim_kind: PrimitiveKind::RadialGradient,
+                    cpu_prim_index: SpecificPrimitiveIndex(self.cpu_radial_gradients.len()),
+                    gpu_prim_index: gpu_address,
+                    gpu_data_address: gpu_stops_address,
+                    gpu_data_count: radial_gradient_cpu.stops_range.length as i32,
+                    render_task: None,
+                };
+
+                self.cpu_radial_gradients.push(radial_gradient_cpu);
+                metadata
+            }
+            PrimitiveContainer::ConicGradient(conic_gradient_cpu, conic_gradient_gpu) => {
+                let gpu_address = self.gpu_data32.push(conic_gradient_gpu);
+                let gpu_stops_address = self.gpu_data32.alloc(conic_gradient_cpu.stops_range.length);
+
+                let metadata = PrimitiveMetadata {
+                    is_opaque: false,
+                    clip_source: clip_source,
+                    clip_cache_info: clip_info,
+                    prim_kind: PrimitiveKind::ConicGradient,
+                    cpu_prim_index: SpecificPrimitiveIndex(self.cpu_conic_gradients.len()),
+                    gpu_prim_index: gpu_address,
+                    gpu_data_address: gpu_stops_address,
+                    gpu_data_count: conic_gradient_cpu.stops_range.length as i32,
+                    render_task: None,
+                };
+
+                self.cpu_conic_gradients.push(conic_gradient_cpu);
+                metadata
+            }
             PrimitiveContainer::BoxShadow(box_shadow_gpu, instance_rects) => {
                 let cache_key = PrimitiveCacheKey::BoxShadow(BoxShadowPrimitiveCacheKey {
                     blur_radius: Au::from_f32_px(box_shadow_gpu.blur_radius),
@@ -649,7 +777,7 @@ impl PrimitiveStore {
                                    clip_info: &MaskCacheInfo,
                                    resource_cache: &ResourceCache) {
         if let Some((ref mask, gpu_address)) = clip_info.image {
-            let cache_item = resource_cache.get_cached_image(mask.image, ImageRendering::Auto);
+            let cache_item = resource_cache.get_cached_image(mask.image, ImageRendering::Auto, None);
             let mask_data = gpu_data32.get_slice_mut(gpu_address, MASK_DATA_GPU_SIZE);
             mask_data[0] = GpuBlock32::from(ImageMaskData {
                 uv_rect: DeviceRect::new(cache_item.uv0,
@@ -681,7 +809,9 @@ impl PrimitiveStore {
                 PrimitiveKind::Rectangle |
                 PrimitiveKind::Border |
                 PrimitiveKind::BoxShadow |
-                PrimitiveKind::Gradient => {}
+                PrimitiveKind::Gradient |
+                PrimitiveKind::RadialGradient |
+                PrimitiveKind::ConicGradient => {}
                 PrimitiveKind::TextRun => {
                     let text = &mut self.cpu_text_runs[metadata.cpu_prim_index.0];
                     let font_size_dp = text.logical_font_size.scale_by(device_pixel_ratio);
@@ -700,11 +830,12 @@ impl PrimitiveStore {
 
                     text.color_texture_id = texture_id;
                 }
-                PrimitiveKind::Image => {
+                PrimitiveKind::Image |
+                PrimitiveKind::BorderImage => {
                     let image_cpu = &mut self.cpu_images[metadata.cpu_prim_index.0];
 
                     let (texture_id, cache_item) = match image_cpu.kind {
-                        ImagePrimitiveKind::Image(image_key, image_rendering, _) => {
+                        ImagePrimitiveKind::Image(image_key, image_rendering, tile, _) => {
                             // Check if an external image that needs to be resolved
                             // by the render thread.
                             let image_properties = resource_cache.get_image_properties(image_key);
@@ -722,7 +853,7 @@ impl PrimitiveStore {
                                     (SourceTexture::External(external_id), None)
                                 }
                                 None => {
-                                    let cache_item = resource_cache.get_cached_image(image_key, image_rendering);
+                                    let cache_item = resource_cache.get_cached_image(image_key, image_rendering, tile);
                                     (cache_item.texture_id, Some(cache_item))
                                 }
                             }
@@ -746,25 +877,20 @@ impl PrimitiveStore {
                         mem::transmute(self.gpu_data64.get_mut(metadata.gpu_prim_index))
                     };
 
-                    if image_cpu.y_texture_id == SourceTexture::Invalid {
-                        let y_cache_item = resource_cache.get_cached_image(image_cpu.y_key, ImageRendering::Auto);
-                        image_cpu.y_texture_id = y_cache_item.texture_id;
-                        image_gpu.y_uv0 = y_cache_item.uv0;
-                        image_gpu.y_uv1 = y_cache_item.uv1;
-                    }
-
-                    if image_cpu.u_texture_id == SourceTexture::Invalid {
-                        let u_cache_item = resource_cache.get_cached_image(image_cpu.u_key, ImageRendering::Auto);
-                        image_cpu.u_texture_id = u_cache_item.texture_id;
-                        image_gpu.u_uv0 = u_cache_item.uv0;
-                        image_gpu.u_uv1 = u_cache_item.uv1;
-                    }
+                    for plane in 0..image_cpu.format.get_plane_num() {
+                        if image_cpu.yuv_texture_id[plane] != SourceTexture::Invalid {
+                            continue;
+                        }
 
-                    if image_cpu.v_texture_id == SourceTexture::Invalid {
-                        let v_cache_item = resource_cache.get_cached_image(image_cpu.v_key, ImageRendering::Auto);
-                        image_cpu.v_texture_id = v_cache_item.texture_id;
-                        image_gpu.v_uv0 = v_cache_item.uv0;
-                        image_gpu.v_uv1 = v_cache_item.uv1;
+                        let cache_item = resource_cache.get_cached_image(image_cpu.yuv_key[plane], ImageRendering::Auto, None);
+                        image_cpu.yuv_texture_id[plane] = cache_item.texture_id;
+                        let (uv0, uv1) = match plane {
+                            0 => (&mut image_gpu.y_uv0, &mut image_gpu.y_uv1),
+                            1 => (&mut image_gpu.u_uv0, &mut image_gpu.u_uv1),
+                            _ => (&mut image_gpu.v_uv0, &mut image_gpu.v_uv1),
+                        };
+                        *uv0 = cache_item.uv0;
+                        *uv1 = cache_item.uv1;
                     }
                 }
             }
@@ -843,7 +969,7 @@ impl PrimitiveStore {
                              device_pixel_ratio,
                              auxiliary_lists);
             if let &ClipSource::Region(ClipRegion{ image_mask: Some(ref mask), .. }) = metadata.clip_source.as_ref() {
-                resource_cache.request_image(mask.image, ImageRendering::Auto);
+                resource_cache.request_image(mask.image, ImageRendering::Auto, None);
                 prim_needs_resolve = true;
             }
         }
@@ -957,8 +1083,8 @@ impl PrimitiveStore {
 
                 prim_needs_resolve = true;
                 match image_cpu.kind {
-                    ImagePrimitiveKind::Image(image_key, image_rendering, tile_spacing) => {
-                        resource_cache.request_image(image_key, image_rendering);
+                    ImagePrimitiveKind::Image(image_key, image_rendering, tile, tile_spacing) => {
+                        resource_cache.request_image(image_key, image_rendering, tile);
 
                         // TODO(gw): This doesn't actually need to be calculated each frame.
                         // It's cheap enough that it's not worth introducing a cache for images
@@ -972,13 +1098,52 @@ impl PrimitiveStore {
                     ImagePrimitiveKind::WebGL(..) => {}
                 }
             }
+            PrimitiveKind::BorderImage => {
+                let image_cpu = &self.cpu_images[metadata.cpu_prim_index.0];
+
+                prim_needs_resolve = true;
+                if let ImagePrimitiveKind::Image(image_key, image_rendering, tile, _) = image_cpu.kind {
+                    resource_cache.request_image(image_key, image_rendering, tile);
+                }
+            }
             PrimitiveKind::YuvImage => {
                 let image_cpu = &mut self.cpu_yuv_images[metadata.cpu_prim_index.0];
+
+                if image_cpu.format == YuvFormat::DctCoefficients {
+                    // The planes are decoded by render tasks the size of the
+                    // plane in samples, two of which are held by each texel.
+                    // A shrunk copy of a plane would mix up its coefficients,
+                    // so planes that don't fit in a render target or in the
+                    // texture cache can't be drawn at all.
+                    let mut plane_sizes = [DeviceIntSize::zero(); 3];
+                    let mut fits = true;
+                    for plane in 0..3 {
+                        let properties = resource_cache.get_image_properties(image_cpu.yuv_key[plane]);
+                        let size = DeviceIntSize::new(2 * properties.width as i32,
+                                                      properties.height as i32);
+                        fits = fits &&
+                               fits_texture_cache(properties.width, properties.height) &&
+                               size.width <= RENDERABLE_CACHE_SIZE &&
+                               size.height <= RENDERABLE_CACHE_SIZE;
+                        plane_sizes[plane] = size;
+                    }
+
+                    if !fits {
+                        warn!("YUV image planes of {:?} samples are too large to decode, skipping",
+                              plane_sizes);
+                        self.gpu_geometry.get_mut(GpuStoreAddress(prim_index.0 as i32)).local_rect =
+                            LayerRect::zero();
+                        return true;
+                    }
+
+                    metadata.render_task = Some(RenderTask::new_dct_decode(&plane_sizes, prim_index));
+                }
+
                 prim_needs_resolve = true;
 
-                resource_cache.request_image(image_cpu.y_key, ImageRendering::Auto);
-                resource_cache.request_image(image_cpu.u_key, ImageRendering::Auto);
-                resource_cache.request_image(image_cpu.v_key, ImageRendering::Auto);
+                for plane in 0..image_cpu.format.get_plane_num() {
+                    resource_cache.request_image(image_cpu.yuv_key[plane], ImageRendering::Auto, None);
+                }
 
                 // TODO(nical): Currently assuming no tile_spacing for yuv images.
                 metadata.is_opaque = true;
@@ -988,26 +1153,110 @@ impl PrimitiveStore {
                 if gradient.cache_dirty {
                     let src_stops = auxiliary_lists.gradient_stops(&gradient.stops_range);
 
+                    debug_assert!(metadata.gpu_data_count == gradient.stops_range.length as i32);
+                    {
+                        let dest_stops = self.gpu_data32.get_slice_mut(metadata.gpu_data_address,
+                                                                       gradient.stops_range.length);
+
+                        if gradient.reverse_stops {
+                            for (src, dest) in src_stops.iter().rev().zip(dest_stops.iter_mut()) {
+                                *dest = GpuBlock32::from(GradientStop {
+                                    offset: 1.0 - src.offset,
+                                    color: src.color,
+                                    padding: [0.0; 3],
+                                });
+                            }
+                        } else {
+                            for (src, dest) in src_stops.iter().zip(dest_stops.iter_mut()) {
+                                *dest = GpuBlock32::from(GradientStop {
+                                    offset: src.offset,
+                                    color: src.color,
+                                    padding: [0.0; 3],
+                                });
+                            }
+                        }
+                    }
+
+                    // The axis-aligned shader draws a segment per pair of
+                    // stops, so repeating gradients draw the stops once for
+                    // each period of them that overlaps the primitive.
+                    let aligned = gradient.kind != GradientType::Rotated;
+                    if aligned && gradient.extend_mode != ExtendMode::Clamp && src_stops.len() > 1 {
+                        let local_rect = self.gpu_geometry.get(GpuStoreAddress(prim_index.0 as i32)).local_rect;
+                        let gradient_gpu: &mut GradientPrimitiveGpu = unsafe {
+                            mem::transmute(self.gpu_data32.get_mut(metadata.gpu_prim_index))
+                        };
+
+                        let (start, end, rect_start, rect_end) = match gradient.kind {
+                            GradientType::Horizontal => (gradient_gpu.start_point.x,
+                                                         gradient_gpu.end_point.x,
+                                                         local_rect.min_x(),
+                                                         local_rect.max_x()),
+                            _ => (gradient_gpu.start_point.y,
+                                  gradient_gpu.end_point.y,
+                                  local_rect.min_y(),
+                                  local_rect.max_y()),
+                        };
+                        let first = src_stops[0].offset;
+                        let period = src_stops[src_stops.len() - 1].offset - first;
+
+                        if period > 0.0 && end != start {
+                            // The range of offsets that the primitive covers.
+                            let t0 = (rect_start - start) / (end - start);
+                            let t1 = (rect_end - start) / (end - start);
+                            let k0 = ((t0.min(t1) - first) / period).floor();
+                            let k1 = ((t0.max(t1) - first) / period).ceil();
+
+                            if k1 - k0 <= MAX_ALIGNED_GRADIENT_REPETITIONS &&
+                               k0 > i32::MIN as f32 && k1 < i32::MAX as f32 {
+                                gradient.repetitions = k0 as i32..k1 as i32;
+                                gradient_gpu.first_offset = first;
+                                gradient_gpu.period = period;
+                            } else {
+                                gradient.kind = GradientType::Rotated;
+                                gradient_gpu.kind = pack_as_float(GradientType::Rotated as u32);
+                            }
+                        }
+                    }
+
+                    gradient.cache_dirty = false;
+                }
+            }
+            PrimitiveKind::RadialGradient => {
+                let gradient = &mut self.cpu_radial_gradients[metadata.cpu_prim_index.0];
+                if gradient.cache_dirty {
+                    let src_stops = auxiliary_lists.gradient_stops(&gradient.stops_range);
+
                     debug_assert!(metadata.gpu_data_count == gradient.stops_range.length as i32);
                     let dest_stops = self.gpu_data32.get_slice_mut(metadata.gpu_data_address,
                                                                    gradient.stops_range.length);
 
-                    if gradient.reverse_stops {
-                        for (src, dest) in src_stops.iter().rev().zip(dest_stops.iter_mut()) {
-                            *dest = GpuBlock32::from(GradientStop {
-                                offset: 1.0 - src.offset,
-                                color: src.color,
-                                padding: [0.0; 3],
-                            });
-                        }
-                    } else {
-                        for (src, dest) in src_stops.iter().zip(dest_stops.iter_mut()) {
-                            *dest = GpuBlock32::from(GradientStop {
-                                offset: src.offset,
-                                color: src.color,
-                                padding: [0.0; 3],
-                            });
-                        }
+                    for (src, dest) in src_stops.iter().zip(dest_stops.iter_mut()) {
+                        *dest = GpuBlock32::from(GradientStop {
+                            offset: src.offset,
+                            color: src.color,
+                            padding: [0.0; 3],
+                        });
+                    }
+
+                    gradient.cache_dirty = false;
+                }
+            }
+            PrimitiveKind::ConicGradient => {
+                let gradient = &mut self.cpu_conic_gradients[metadata.cpu_prim_index.0];
+                if gradient.cache_dirty {
+                    let src_stops = auxiliary_lists.gradient_stops(&gradient.stops_range);
+
+                    debug_assert!(metadata.gpu_data_count == gradient.stops_range.length as i32);
+                    let dest_stops = self.gpu_data32.get_slice_mut(metadata.gpu_data_address,
+                                                                   gradient.stops_range.length);
+
+                    for (src, dest) in src_stops.iter().zip(dest_stops.iter_mut()) {
+                        *dest = GpuBlock32::from(GradientStop {
+                            offset: src.offset,
+                            color: src.color,
+                            padding: [0.0; 3],
+                        });
                     }
 
                     gradient.cache_dirty = false;
@@ -1026,7 +1275,7 @@ impl PrimitiveStore {
 #[derive(Clone)]
 #[repr(C)]
 pub struct GpuBlock16 {
-    data: [f32; 4],
+    pub data: [f32; 4],
 }
 
 impl Default for GpuBlock16 {
@@ -1080,7 +1329,7 @@ impl From<GlyphPrimitive> for GpuBlock16 {
 #[derive(Clone)]
 #[repr(C)]
 pub struct GpuBlock32 {
-    data: [f32; 8],
+    pub data: [f32; 8],
 }
 
 impl Default for GpuBlock32 {
@@ -1099,6 +1348,22 @@ impl From<GradientPrimitiveGpu> for GpuBlock32 {
     }
 }
 
+impl From<RadialGradientPrimitiveGpu> for GpuBlock32 {
+    fn from(data: RadialGradientPrimitiveGpu) -> GpuBlock32 {
+        unsafe {
+            mem::transmute::<RadialGradientPrimitiveGpu, GpuBlock32>(data)
+        }
+    }
+}
+
+impl From<ConicGradientPrimitiveGpu> for GpuBlock32 {
+    fn from(data: ConicGradientPrimitiveGpu) -> GpuBlock32 {
+        unsafe {
+            mem::transmute::<ConicGradientPrimitiveGpu, GpuBlock32>(data)
+        }
+    }
+}
+
 impl From<GradientStop> for GpuBlock32 {
     fn from(data: GradientStop) -> GpuBlock32 {
         unsafe {
@@ -1107,6 +1372,14 @@ impl From<GradientStop> for GpuBlock32 {
     }
 }
 
+impl From<BorderImagePatch> for GpuBlock64 {
+    fn from(data: BorderImagePatch) -> GpuBlock64 {
+        unsafe {
+            mem::transmute::<BorderImagePatch, GpuBlock64>(data)
+        }
+    }
+}
+
 impl From<YuvImagePrimitiveGpu> for GpuBlock64 {
     fn from(data: YuvImagePrimitiveGpu) -> GpuBlock64 {
         unsafe {
@@ -1142,7 +1415,7 @@ impl From<ClipCorner> for GpuBlock32 {
 #[derive(Clone)]
 #[repr(C)]
 pub struct GpuBlock64 {
-    data: [f32; 16],
+    pub data: [f32; 16],
 }
 
 impl Default for GpuBlock64 {
@@ -1164,7 +1437,7 @@ impl From<BoxShadowPrimitiveGpu> for GpuBlock64 {
 #[derive(Clone)]
 #[repr(C)]
 pub struct GpuBlock128 {
-    data: [f32; 32],
+    pub data: [f32; 32],
 }
 
 impl Default for GpuBlock128 {
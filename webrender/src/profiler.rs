@@ -260,6 +260,8 @@ impl FrameProfileCounters {
 pub struct BackendProfileCounters {
     pub font_templates: ResourceProfileCounter,
     pub image_templates: ResourceProfileCounter,
+    pub evicted_glyphs: IntProfileCounter,
+    pub evicted_images: IntProfileCounter,
     pub total_time: TimeProfileCounter,
 }
 
@@ -268,11 +270,15 @@ impl BackendProfileCounters {
         BackendProfileCounters {
             font_templates: ResourceProfileCounter::new("Font Templates"),
             image_templates: ResourceProfileCounter::new("Image Templates"),
+            evicted_glyphs: IntProfileCounter::new("Evicted Glyphs"),
+            evicted_images: IntProfileCounter::new("Evicted Images"),
             total_time: TimeProfileCounter::new("Backend CPU Time", false),
         }
     }
 
     pub fn reset(&mut self) {
+        self.evicted_glyphs.reset();
+        self.evicted_images.reset();
         self.total_time.reset();
     }
 }
@@ -639,6 +645,8 @@ impl Profiler {
         self.draw_counters(&[
             &backend_profile.font_templates,
             &backend_profile.image_templates,
+            &backend_profile.evicted_glyphs,
+            &backend_profile.evicted_images,
         ], debug_renderer, true);
 
         self.draw_counters(&[
@@ -55,13 +55,19 @@ pub fn write_msg(frame: u32, msg: &ApiMsg) {
     match msg {
         &ApiMsg::AddRawFont(..) |
         &ApiMsg::AddNativeFont(..) |
+        &ApiMsg::DeleteFont(..) |
         &ApiMsg::AddImage(..) |
         &ApiMsg::UpdateImage(..) |
+        &ApiMsg::UpdateImageRegion(..) |
         &ApiMsg::DeleteImage(..)|
+        &ApiMsg::AddDocument(..) |
+        &ApiMsg::DeleteDocument(..) |
         &ApiMsg::SetRootDisplayList(..) |
         &ApiMsg::SetRootPipeline(..) |
+        &ApiMsg::SetDevicePixelRatio(..) |
+        &ApiMsg::RenderToImage(..) |
         &ApiMsg::Scroll(..) |
-        &ApiMsg::TickScrollingBounce |
+        &ApiMsg::TickScrollingBounce(..) |
         &ApiMsg::WebGLCommand(..) => {
             let mut recorder = WEBRENDER_RECORDING_DETOUR.lock();
             if let Some(ref mut recorder) = recorder.as_mut().unwrap().as_mut() {
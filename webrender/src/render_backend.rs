@@ -3,12 +3,12 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use byteorder::{LittleEndian, ReadBytesExt};
-use frame::Frame;
+use frame::{Frame, FrameId};
 use internal_types::{FontTemplate, GLContextHandleWrapper, GLContextWrapper};
 use internal_types::{SourceTexture, ResultMsg, RendererFrame};
 use profiler::BackendProfileCounters;
 use record;
-use resource_cache::ResourceCache;
+use resource_cache::{ImageTiling, ResourceCache};
 use scene::Scene;
 use std::collections::HashMap;
 use std::fs;
@@ -20,9 +20,43 @@ use webrender_traits::{ApiMsg, AuxiliaryLists, BuiltDisplayList, IdNamespace, Im
 use webrender_traits::{RenderNotifier, RenderDispatcher, WebGLCommand, WebGLContextId};
 use webrender_traits::channel::{PayloadHelperMethods, PayloadReceiver, PayloadSender, MsgReceiver};
 use webrender_traits::{VRCompositorCommand, VRCompositorHandler};
+use webrender_traits::{DocumentId, ImageKey, LayerSize, PipelineId};
 use tiling::FrameBuilderConfig;
 use offscreen_gl_context::GLContextDispatcher;
 
+/// A document is drawn by the renderer on its own, with one of the pipelines
+/// of the scene as its root.
+struct Document {
+    root_pipeline_id: Option<PipelineId>,
+    frame: Frame,
+    pipeline_sizes: HashMap<PipelineId, LayerSize>,
+    device_pixel_ratio: f32,
+}
+
+impl Document {
+    fn new(debug: bool, config: FrameBuilderConfig, device_pixel_ratio: f32) -> Document {
+        Document {
+            root_pipeline_id: None,
+            frame: Frame::new(debug, config),
+            pipeline_sizes: HashMap::new(),
+            device_pixel_ratio: device_pixel_ratio,
+        }
+    }
+
+    /// Returns true if the frame of the document shows the pipeline, either as
+    /// its root or in an iframe, including iframes that had no display list yet.
+    fn shows_pipeline(&self, pipeline_id: PipelineId) -> bool {
+        self.root_pipeline_id == Some(pipeline_id) ||
+            self.pipeline_sizes.contains_key(&pipeline_id)
+    }
+}
+
+/// Messages can refer to a document that was deleted while they were in
+/// flight, so they are ignored rather than treated as a bug.
+fn warn_unknown_document(document_id: DocumentId) {
+    warn!("Ignoring a message for unknown document {:?}", document_id);
+}
+
 /// The render backend is responsible for transforming high level display lists into
 /// GPU-friendly work which is then submitted to the renderer in the form of a frame::Frame.
 ///
@@ -33,17 +67,24 @@ pub struct RenderBackend {
     payload_tx: PayloadSender,
     result_tx: Sender<ResultMsg>,
 
+    /// The device pixel ratio new documents start with.
     device_pixel_ratio: f32,
     next_namespace_id: IdNamespace,
 
     resource_cache: ResourceCache,
 
     scene: Scene,
-    frame: Frame,
+    documents: HashMap<DocumentId, Document>,
+    /// The id of the last frame created, for any document.
+    last_frame_id: FrameId,
+    debug: bool,
+    frame_builder_config: FrameBuilderConfig,
 
     notifier: Arc<Mutex<Option<Box<RenderNotifier>>>>,
     webrender_context_handle: Option<GLContextHandleWrapper>,
     webgl_contexts: HashMap<WebGLContextId, GLContextWrapper>,
+    /// The namespace of the API that requested each WebGL context.
+    webgl_context_namespaces: HashMap<WebGLContextId, IdNamespace>,
     current_bound_webgl_context_id: Option<WebGLContextId>,
     enable_recording: bool,
     main_thread_dispatcher: Arc<Mutex<Option<Box<RenderDispatcher>>>>,
@@ -80,11 +121,15 @@ impl RenderBackend {
             device_pixel_ratio: device_pixel_ratio,
             resource_cache: resource_cache,
             scene: Scene::new(),
-            frame: Frame::new(debug, config),
+            documents: HashMap::new(),
+            last_frame_id: FrameId(0),
+            debug: debug,
+            frame_builder_config: config,
             next_namespace_id: IdNamespace(1),
             notifier: notifier,
             webrender_context_handle: webrender_context_handle,
             webgl_contexts: HashMap::new(),
+            webgl_context_namespaces: HashMap::new(),
             current_bound_webgl_context_id: None,
             enable_recording:enable_recording,
             main_thread_dispatcher: main_thread_dispatcher,
@@ -117,6 +162,9 @@ impl RenderBackend {
                             self.resource_cache
                                 .add_font_template(id, FontTemplate::Native(native_font_handle));
                         }
+                        ApiMsg::DeleteFont(id) => {
+                            self.resource_cache.delete_font_template(id);
+                        }
                         ApiMsg::GetGlyphDimensions(glyph_keys, tx) => {
                             let mut glyph_dimensions = Vec::with_capacity(glyph_keys.len());
                             for glyph_key in &glyph_keys {
@@ -129,19 +177,29 @@ impl RenderBackend {
                             if let ImageData::Raw(ref bytes) = data {
                                 profile_counters.image_templates.inc(bytes.len());
                             }
+                            let old_tiling = self.resource_cache.get_image_tiling(id);
                             self.resource_cache.add_image_template(id,
                                                                    width,
                                                                    height,
                                                                    stride,
                                                                    format,
                                                                    data);
+                            self.update_image_tiling(id, old_tiling);
                         }
                         ApiMsg::UpdateImage(id, width, height, format, bytes) => {
+                            let old_tiling = self.resource_cache.get_image_tiling(id);
                             self.resource_cache.update_image_template(id,
                                                                       width,
                                                                       height,
                                                                       format,
                                                                       bytes);
+                            self.update_image_tiling(id, old_tiling);
+                        }
+                        ApiMsg::UpdateImageRegion(id, dirty_rect, bytes, stride) => {
+                            self.resource_cache.update_image_region(id,
+                                                                    dirty_rect,
+                                                                    bytes,
+                                                                    stride);
                         }
                         ApiMsg::DeleteImage(id) => {
                             self.resource_cache.delete_image_template(id);
@@ -154,6 +212,19 @@ impl RenderBackend {
 
                             sender.send(result).unwrap();
                         }
+                        ApiMsg::ClearNamespace(namespace) => {
+                            self.clear_namespace(namespace);
+                        }
+                        ApiMsg::AddDocument(document_id) => {
+                            let document = Document::new(self.debug,
+                                                         self.frame_builder_config,
+                                                         self.device_pixel_ratio);
+                            self.documents.insert(document_id, document);
+                        }
+                        ApiMsg::DeleteDocument(document_id) => {
+                            self.documents.remove(&document_id);
+                            self.result_tx.send(ResultMsg::DeleteDocument(document_id)).unwrap();
+                        }
                         ApiMsg::SetRootDisplayList(background_color,
                                                    epoch,
                                                    pipeline_id,
@@ -196,7 +267,7 @@ impl RenderBackend {
                                 AuxiliaryLists::from_data(auxiliary_lists_data,
                                                           auxiliary_lists_descriptor);
 
-                            let frame = profile_counters.total_time.profile(|| {
+                            let frames = profile_counters.total_time.profile(|| {
                                 self.scene.set_root_display_list(pipeline_id,
                                                                  epoch,
                                                                  built_display_list,
@@ -204,78 +275,154 @@ impl RenderBackend {
                                                                  viewport_size,
                                                                  auxiliary_lists);
 
-                                self.build_scene();
-                                self.render()
+                                let document_ids: Vec<DocumentId> = self.documents
+                                    .iter()
+                                    .filter(|&(_, document)| document.shows_pipeline(pipeline_id))
+                                    .map(|(document_id, _)| *document_id)
+                                    .collect();
+                                for &document_id in &document_ids {
+                                    self.build_scene(document_id);
+                                }
+                                self.render(document_ids)
                             });
 
-                            if self.scene.root_pipeline_id.is_some() {
-                                self.publish_frame_and_notify_compositor(frame, &mut profile_counters);
+                            if !frames.is_empty() {
+                                self.publish_frames_and_notify_compositor(frames, &mut profile_counters);
                                 frame_counter += 1;
                             }
                         }
-                        ApiMsg::SetRootPipeline(pipeline_id) => {
-                            let frame = profile_counters.total_time.profile(|| {
-                                self.scene.set_root_pipeline_id(pipeline_id);
+                        ApiMsg::SetRootPipeline(document_id, pipeline_id) => {
+                            match self.documents.get_mut(&document_id) {
+                                Some(document) => document.root_pipeline_id = Some(pipeline_id),
+                                None => {
+                                    warn_unknown_document(document_id);
+                                    continue;
+                                }
+                            }
 
-                                self.build_scene();
-                                self.render()
+                            let frames = profile_counters.total_time.profile(|| {
+                                self.build_scene(document_id);
+                                self.render(vec![document_id])
                             });
 
                             // the root pipeline is guaranteed to be Some() at this point
-                            self.publish_frame_and_notify_compositor(frame, &mut profile_counters);
+                            self.publish_frames_and_notify_compositor(frames, &mut profile_counters);
                             frame_counter += 1;
                         }
-                        ApiMsg::Scroll(delta, cursor, move_phase) => {
+                        ApiMsg::SetDevicePixelRatio(document_id, device_pixel_ratio) => {
+                            match self.documents.get_mut(&document_id) {
+                                Some(document) => document.device_pixel_ratio = device_pixel_ratio,
+                                None => {
+                                    warn_unknown_document(document_id);
+                                    continue;
+                                }
+                            }
+
+                            let frames = profile_counters.total_time.profile(|| {
+                                self.render(vec![document_id])
+                            });
+                            if !frames.is_empty() {
+                                self.publish_frames_and_notify_compositor(frames, &mut profile_counters);
+                                frame_counter += 1;
+                            }
+                        }
+                        ApiMsg::RenderToImage(image_key, pipeline_id, size, device_pixel_ratio) => {
+                            let texture_id = match self.resource_cache.add_rendered_image(image_key,
+                                                                                          (size.width * device_pixel_ratio).ceil() as u32,
+                                                                                          (size.height * device_pixel_ratio).ceil() as u32) {
+                                Some(texture_id) => texture_id,
+                                None => continue,
+                            };
                             let frame = profile_counters.total_time.profile(|| {
-                                if self.frame.scroll(delta, cursor, move_phase) {
-                                    Some(self.render())
+                                self.render_to_image(pipeline_id, size, device_pixel_ratio)
+                            });
+
+                            let pending_update = self.resource_cache.pending_updates();
+                            let msg = ResultMsg::RenderedImage(texture_id, frame, pending_update);
+                            self.result_tx.send(msg).unwrap();
+                        }
+                        ApiMsg::Scroll(document_id, delta, cursor, move_phase) => {
+                            let frames = profile_counters.total_time.profile(|| {
+                                let scrolled = match self.documents.get_mut(&document_id) {
+                                    Some(document) => document.frame.scroll(delta, cursor, move_phase),
+                                    None => {
+                                        warn_unknown_document(document_id);
+                                        false
+                                    }
+                                };
+                                if scrolled {
+                                    Some(self.render(vec![document_id]))
                                 } else {
                                     None
                                 }
                             });
 
-                            match frame {
-                                Some(frame) => {
-                                    self.publish_frame(frame, &mut profile_counters);
+                            match frames {
+                                Some(frames) => {
+                                    self.publish_frames(frames, &mut profile_counters);
                                     self.notify_compositor_of_new_scroll_frame(true)
                                 }
                                 None => self.notify_compositor_of_new_scroll_frame(false),
                             }
                         }
-                        ApiMsg::ScrollLayersWithScrollId(origin, pipeline_id, scroll_root_id) => {
-                            let frame = profile_counters.total_time.profile(|| {
-                                if self.frame.scroll_layers(origin, pipeline_id, scroll_root_id) {
-                                    Some(self.render())
+                        ApiMsg::ScrollLayersWithScrollId(document_id, origin, pipeline_id, scroll_root_id) => {
+                            let frames = profile_counters.total_time.profile(|| {
+                                let scrolled = match self.documents.get_mut(&document_id) {
+                                    Some(document) => {
+                                        document.frame.scroll_layers(origin, pipeline_id, scroll_root_id)
+                                    }
+                                    None => {
+                                        warn_unknown_document(document_id);
+                                        false
+                                    }
+                                };
+                                if scrolled {
+                                    Some(self.render(vec![document_id]))
                                 } else {
                                     None
                                 }
                             });
 
-                            match frame {
-                                Some(frame) => {
-                                    self.publish_frame(frame, &mut profile_counters);
+                            match frames {
+                                Some(frames) => {
+                                    self.publish_frames(frames, &mut profile_counters);
                                     self.notify_compositor_of_new_scroll_frame(true)
                                 }
                                 None => self.notify_compositor_of_new_scroll_frame(false),
                             }
 
                         }
-                        ApiMsg::TickScrollingBounce => {
-                            let frame = profile_counters.total_time.profile(|| {
-                                self.frame.tick_scrolling_bounce_animations();
-                                self.render()
+                        ApiMsg::TickScrollingBounce(document_id) => {
+                            match self.documents.get_mut(&document_id) {
+                                Some(document) => document.frame.tick_scrolling_bounce_animations(),
+                                None => {
+                                    warn_unknown_document(document_id);
+                                    continue;
+                                }
+                            }
+
+                            let frames = profile_counters.total_time.profile(|| {
+                                self.render(vec![document_id])
                             });
 
-                            self.publish_frame_and_notify_compositor(frame, &mut profile_counters);
+                            self.publish_frames_and_notify_compositor(frames, &mut profile_counters);
                         }
                         ApiMsg::TranslatePointToLayerSpace(..) => {
                             panic!("unused api - remove from webrender_traits");
                         }
-                        ApiMsg::GetScrollLayerState(tx) => {
-                            tx.send(self.frame.get_scroll_layer_state())
-                              .unwrap()
+                        ApiMsg::GetScrollLayerState(document_id, tx) => {
+                            // The caller waits for a reply, so an unknown document
+                            // has no scroll layers rather than no answer.
+                            let scroll_layer_state = match self.documents.get(&document_id) {
+                                Some(document) => document.frame.get_scroll_layer_state(),
+                                None => {
+                                    warn_unknown_document(document_id);
+                                    Vec::new()
+                                }
+                            };
+                            tx.send(scroll_layer_state).unwrap()
                         }
-                        ApiMsg::RequestWebGLContext(size, attributes, tx) => {
+                        ApiMsg::RequestWebGLContext(namespace, size, attributes, tx) => {
                             if let Some(ref wrapper) = self.webrender_context_handle {
                                 let dispatcher: Option<Box<GLContextDispatcher>> = if cfg!(target_os = "windows") {
                                     Some(Box::new(WebRenderGLDispatcher {
@@ -295,6 +442,7 @@ impl RenderBackend {
                                         let (real_size, texture_id, limits) = ctx.get_info();
 
                                         self.webgl_contexts.insert(id, ctx);
+                                        self.webgl_context_namespaces.insert(id, namespace);
 
                                         self.resource_cache
                                             .add_webgl_texture(id, SourceTexture::WebGL(texture_id),
@@ -338,12 +486,12 @@ impl RenderBackend {
                         ApiMsg::VRCompositorCommand(context_id, command) => {
                             self.handle_vr_compositor_command(context_id, command);
                         }
-                        ApiMsg::GenerateFrame => {
-                            let frame = profile_counters.total_time.profile(|| {
-                                self.render()
+                        ApiMsg::GenerateFrame(document_id) => {
+                            let frames = profile_counters.total_time.profile(|| {
+                                self.render(vec![document_id])
                             });
-                            if self.scene.root_pipeline_id.is_some() {
-                                self.publish_frame_and_notify_compositor(frame, &mut profile_counters);
+                            if !frames.is_empty() {
+                                self.publish_frames_and_notify_compositor(frames, &mut profile_counters);
                                 frame_counter += 1;
                             }
                         }
@@ -356,7 +504,63 @@ impl RenderBackend {
         }
     }
 
-    fn build_scene(&mut self) {
+    fn clear_namespace(&mut self, namespace: IdNamespace) {
+        let IdNamespace(namespace_id) = namespace;
+
+        let document_ids: Vec<DocumentId> = self.documents
+                                                .keys()
+                                                .filter(|document_id| document_id.0 == namespace_id)
+                                                .cloned()
+                                                .collect();
+        for document_id in document_ids {
+            self.documents.remove(&document_id);
+            self.result_tx.send(ResultMsg::DeleteDocument(document_id)).unwrap();
+        }
+
+        let context_ids: Vec<WebGLContextId> = self.webgl_context_namespaces
+                                                   .iter()
+                                                   .filter(|&(_, context_namespace)| {
+                                                       context_namespace.0 == namespace_id
+                                                   })
+                                                   .map(|(context_id, _)| *context_id)
+                                                   .collect();
+        for context_id in context_ids {
+            if self.current_bound_webgl_context_id == Some(context_id) {
+                self.webgl_contexts[&context_id].unbind();
+                self.current_bound_webgl_context_id = None;
+            }
+            // Dropping the context destroys it, along with its texture.
+            self.webgl_contexts.remove(&context_id);
+            self.webgl_context_namespaces.remove(&context_id);
+            self.resource_cache.delete_webgl_texture(context_id);
+        }
+
+        self.resource_cache.clear_namespace(namespace);
+
+        // The documents that are left may show display lists that refer to the
+        // fonts and images that were deleted, so their scenes are built again
+        // without them.
+        let document_ids: Vec<DocumentId> = self.documents.keys().cloned().collect();
+        for document_id in document_ids {
+            self.build_scene(document_id);
+        }
+    }
+
+    /// Images too large for the texture cache are split into tiles when the
+    /// scene is built, so the scenes are built again when an image starts or
+    /// stops needing tiles, or its tiles change.
+    fn update_image_tiling(&mut self, image_key: ImageKey, old_tiling: Option<ImageTiling>) {
+        if self.resource_cache.get_image_tiling(image_key) == old_tiling {
+            return;
+        }
+
+        let document_ids: Vec<DocumentId> = self.documents.keys().cloned().collect();
+        for document_id in document_ids {
+            self.build_scene(document_id);
+        }
+    }
+
+    fn build_scene(&mut self, document_id: DocumentId) {
         // Flatten the stacking context hierarchy
         let mut new_pipeline_sizes = HashMap::new();
 
@@ -378,11 +582,25 @@ impl RenderBackend {
             webgl_context.unbind();
         }
 
-        self.frame.create(&self.scene, &mut new_pipeline_sizes);
+        let document = match self.documents.get_mut(&document_id) {
+            Some(document) => document,
+            None => return,
+        };
+        let root_pipeline_id = match document.root_pipeline_id {
+            Some(root_pipeline_id) => root_pipeline_id,
+            None => return,
+        };
+
+        self.last_frame_id.0 += 1;
+        document.frame.create(&self.scene,
+                              &self.resource_cache,
+                              root_pipeline_id,
+                              self.last_frame_id,
+                              &mut new_pipeline_sizes);
 
         let mut updated_pipeline_sizes = HashMap::new();
 
-        for (pipeline_id, old_size) in self.scene.pipeline_sizes.drain() {
+        for (pipeline_id, old_size) in document.pipeline_sizes.drain() {
             let new_size = new_pipeline_sizes.remove(&pipeline_id);
 
             match new_size {
@@ -423,31 +641,97 @@ impl RenderBackend {
             updated_pipeline_sizes.insert(pipeline_id, new_size);
         }
 
-        self.scene.pipeline_sizes = updated_pipeline_sizes;
+        document.pipeline_sizes = updated_pipeline_sizes;
     }
 
-    fn render(&mut self) -> RendererFrame {
-        let frame = self.frame.build(&mut self.resource_cache,
-                                     &self.scene.pipeline_auxiliary_lists,
-                                     self.device_pixel_ratio);
+    /// Builds the frames of the given documents, skipping those without a
+    /// root pipeline and those that don't exist.
+    fn render(&mut self, document_ids: Vec<DocumentId>) -> Vec<(DocumentId, RendererFrame)> {
+        // Items may move when compacting the texture cache, so it must be
+        // done before they are requested for a frame. The frames of the other
+        // documents use the old texture coordinates, so they are built again.
+        let document_ids = if self.resource_cache.compact_texture_cache() {
+            self.documents.keys().cloned().collect()
+        } else {
+            document_ids
+        };
 
-        frame
+        let mut frames = Vec::with_capacity(document_ids.len());
+        for document_id in document_ids {
+            let document = match self.documents.get_mut(&document_id) {
+                Some(document) => document,
+                None => {
+                    warn_unknown_document(document_id);
+                    continue;
+                }
+            };
+            if document.root_pipeline_id.is_none() {
+                continue;
+            }
+            let device_pixel_ratio = document.device_pixel_ratio;
+            let frame = document.frame.build(&mut self.resource_cache,
+                                             &self.scene.pipeline_auxiliary_lists,
+                                             device_pixel_ratio);
+            frames.push((document_id, frame));
+        }
+
+        // Resources are kept as long as the current frame of any document
+        // may use them.
+        let oldest_frame_id = self.documents
+                                  .values()
+                                  .filter(|document| document.root_pipeline_id.is_some())
+                                  .map(|document| document.frame.id().0)
+                                  .min();
+        if let Some(oldest_frame_id) = oldest_frame_id {
+            self.resource_cache.expire_old_resources(FrameId(oldest_frame_id));
+        }
+
+        frames
+    }
+
+    fn render_to_image(&mut self,
+                       pipeline_id: PipelineId,
+                       viewport_size: LayerSize,
+                       device_pixel_ratio: f32) -> RendererFrame {
+        // The pipeline gets a frame of its own, so the scrolling state of the
+        // documents is left alone. It takes the id of the last frame created,
+        // so that the resources it uses are kept as long as those of that frame.
+        // No resources are expired, since that is left to the documents.
+        let mut frame = Frame::new(self.debug, self.frame_builder_config);
+        frame.create_for_pipeline(&self.scene,
+                                  &self.resource_cache,
+                                  pipeline_id,
+                                  viewport_size,
+                                  self.last_frame_id,
+                                  &mut HashMap::new());
+        frame.build(&mut self.resource_cache,
+                    &self.scene.pipeline_auxiliary_lists,
+                    device_pixel_ratio)
     }
 
-    fn publish_frame(&mut self,
-                     frame: RendererFrame,
-                     profile_counters: &mut BackendProfileCounters) {
-        let pending_update = self.resource_cache.pending_updates();
-        let pending_external_image_update = self.resource_cache.pending_external_image_updates();
-        let msg = ResultMsg::NewFrame(frame, pending_update, pending_external_image_update, profile_counters.clone());
-        self.result_tx.send(msg).unwrap();
-        profile_counters.reset();
+    fn publish_frames(&mut self,
+                      frames: Vec<(DocumentId, RendererFrame)>,
+                      profile_counters: &mut BackendProfileCounters) {
+        // The pending updates go with the first frame, since the renderer
+        // applies them before drawing any document.
+        for (document_id, frame) in frames {
+            let pending_update = self.resource_cache.pending_updates();
+            let pending_external_image_update = self.resource_cache.pending_external_image_updates();
+            self.resource_cache.report_evictions(profile_counters);
+            let msg = ResultMsg::NewFrame(document_id,
+                                          frame,
+                                          pending_update,
+                                          pending_external_image_update,
+                                          profile_counters.clone());
+            self.result_tx.send(msg).unwrap();
+            profile_counters.reset();
+        }
     }
 
-    fn publish_frame_and_notify_compositor(&mut self,
-                                           frame: RendererFrame,
-                                           profile_counters: &mut BackendProfileCounters) {
-        self.publish_frame(frame, profile_counters);
+    fn publish_frames_and_notify_compositor(&mut self,
+                                            frames: Vec<(DocumentId, RendererFrame)>,
+                                            profile_counters: &mut BackendProfileCounters) {
+        self.publish_frames(frames, profile_counters);
 
         // TODO(gw): This is kindof bogus to have to lock the notifier
         //           each time it's used. This is due to some nastiness
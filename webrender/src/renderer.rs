@@ -12,7 +12,7 @@
 use debug_colors;
 use debug_render::DebugRenderer;
 use device::{DepthFunction, Device, ProgramId, TextureId, VertexFormat, GpuMarker, GpuProfiler};
-use device::{TextureFilter, VAOId, VertexUsageHint, FileWatcherHandler, TextureTarget};
+use device::{TextureFilter, VAOId, VertexUsageHint, FileWatcherHandler, TextureTarget, PBOId};
 use euclid::Matrix4D;
 use fnv::FnvHasher;
 use internal_types::{CacheTextureId, RendererFrame, ResultMsg, TextureUpdateOp};
@@ -22,12 +22,14 @@ use internal_types::{BatchTextures, TextureSampler, GLContextHandleWrapper};
 use profiler::{Profiler, BackendProfileCounters};
 use profiler::{GpuProfileTag, RendererProfileTimers, RendererProfileCounters};
 use render_backend::RenderBackend;
+use software::SoftwareRasterizer;
 use std::cmp;
 use std::collections::HashMap;
 use std::f32;
 use std::hash::BuildHasherDefault;
 use std::mem;
 use std::path::PathBuf;
+use std::slice;
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
@@ -39,14 +41,18 @@ use util::TransformedRectKind;
 use webrender_traits::{ColorF, Epoch, PipelineId, RenderNotifier, RenderDispatcher};
 use webrender_traits::{ExternalImageId, ImageFormat, RenderApiSender, RendererKind};
 use webrender_traits::{DeviceIntRect, DeviceSize, DevicePoint, DeviceIntPoint, DeviceIntSize, DeviceUintSize};
+use webrender_traits::{DeviceUintPoint, DeviceUintRect, DocumentId};
 use webrender_traits::channel;
 use webrender_traits::VRCompositorHandler;
+use webrender_traits::YuvFormat;
 
 pub const MAX_VERTEX_TEXTURE_WIDTH: usize = 1024;
 
 const GPU_TAG_CACHE_BOX_SHADOW: GpuProfileTag = GpuProfileTag { label: "C_BoxShadow", color: debug_colors::BLACK };
 const GPU_TAG_CACHE_CLIP: GpuProfileTag = GpuProfileTag { label: "C_Clip", color: debug_colors::PURPLE };
 const GPU_TAG_CACHE_TEXT_RUN: GpuProfileTag = GpuProfileTag { label: "C_TextRun", color: debug_colors::MISTYROSE };
+const GPU_TAG_CACHE_IDCT: GpuProfileTag = GpuProfileTag { label: "C_Idct", color: debug_colors::OLIVE };
+const GPU_TAG_CACHE_YUV_TO_RGB: GpuProfileTag = GpuProfileTag { label: "C_YuvToRgb", color: debug_colors::SEAGREEN };
 const GPU_TAG_INIT: GpuProfileTag = GpuProfileTag { label: "Init", color: debug_colors::WHITE };
 const GPU_TAG_SETUP_TARGET: GpuProfileTag = GpuProfileTag { label: "Target", color: debug_colors::SLATEGREY };
 const GPU_TAG_PRIM_RECT: GpuProfileTag = GpuProfileTag { label: "Rect", color: debug_colors::RED };
@@ -57,8 +63,11 @@ const GPU_TAG_PRIM_COMPOSITE: GpuProfileTag = GpuProfileTag { label: "Composite"
 const GPU_TAG_PRIM_TEXT_RUN: GpuProfileTag = GpuProfileTag { label: "TextRun", color: debug_colors::BLUE };
 const GPU_TAG_PRIM_GRADIENT: GpuProfileTag = GpuProfileTag { label: "Gradient", color: debug_colors::YELLOW };
 const GPU_TAG_PRIM_ANGLE_GRADIENT: GpuProfileTag = GpuProfileTag { label: "AngleGradient", color: debug_colors::POWDERBLUE };
+const GPU_TAG_PRIM_RADIAL_GRADIENT: GpuProfileTag = GpuProfileTag { label: "RadialGradient", color: debug_colors::LIGHTPINK };
+const GPU_TAG_PRIM_CONIC_GRADIENT: GpuProfileTag = GpuProfileTag { label: "ConicGradient", color: debug_colors::PLUM };
 const GPU_TAG_PRIM_BOX_SHADOW: GpuProfileTag = GpuProfileTag { label: "BoxShadow", color: debug_colors::CYAN };
 const GPU_TAG_PRIM_BORDER: GpuProfileTag = GpuProfileTag { label: "Border", color: debug_colors::ORANGE };
+const GPU_TAG_PRIM_BORDER_IMAGE: GpuProfileTag = GpuProfileTag { label: "BorderImage", color: debug_colors::LIGHTGREEN };
 const GPU_TAG_PRIM_CACHE_IMAGE: GpuProfileTag = GpuProfileTag { label: "CacheImage", color: debug_colors::SILVER };
 const GPU_TAG_BLUR: GpuProfileTag = GpuProfileTag { label: "Blur", color: debug_colors::VIOLET };
 
@@ -119,6 +128,10 @@ impl VertexDataTexture {
 const TRANSFORM_FEATURE: &'static str = "TRANSFORM";
 const SUBPIXEL_AA_FEATURE: &'static str = "SUBPIXEL_AA";
 const CLIP_FEATURE: &'static str = "CLIP";
+const NV12_FEATURE: &'static str = "NV12";
+const INTERLEAVED_Y_CB_CR_FEATURE: &'static str = "INTERLEAVED_Y_CB_CR";
+const DCT_COEFFICIENTS_FEATURE: &'static str = "DCT_COEFFICIENTS";
+const IDCT_ROWS_FEATURE: &'static str = "IDCT_ROWS";
 
 enum ShaderKind {
     Primitive,
@@ -270,14 +283,57 @@ fn create_clip_shader(name: &'static str, device: &mut Device) -> ProgramId {
     program_id
 }
 
+/// Returns the position of the shader for a YUV format in `ps_yuv_image`.
+fn yuv_shader_index(format: YuvFormat) -> usize {
+    match format {
+        YuvFormat::PlanarYCbCr => 0,
+        YuvFormat::NV12 => 1,
+        YuvFormat::InterleavedYCbCr => 2,
+        YuvFormat::DctCoefficients => 3,
+    }
+}
+
 /// The renderer is responsible for submitting to the GPU the work prepared by the
 /// RenderBackend.
 pub struct Renderer {
     result_rx: Receiver<ResultMsg>,
-    device: Device,
     pending_texture_updates: Vec<TextureUpdateList>,
+    /// Frames to draw into the render targets of rendered images, each
+    /// once the given number of pending texture update lists is applied.
+    pending_rendered_images: Vec<(usize, CacheTextureId, RendererFrame)>,
     pending_shader_updates: Vec<PathBuf>,
-    current_frame: Option<RendererFrame>,
+    /// The last frame of each document.
+    frames: HashMap<DocumentId, RendererFrame>,
+
+    notifier: Arc<Mutex<Option<Box<RenderNotifier>>>>,
+
+    /// The framebuffer size given to the last call to `render`,
+    /// used to flip the rows of pixels read back from GL.
+    framebuffer_size: DeviceUintSize,
+
+    pipeline_epoch_map: HashMap<PipelineId, Epoch, BuildHasherDefault<FnvHasher>>,
+    /// Used to dispatch functions to the main thread's event loop.
+    /// Required to allow GLContext sharing in some implementations like WGL.
+    main_thread_dispatcher: Arc<Mutex<Option<Box<RenderDispatcher>>>>,
+
+    // Optional trait object that handles WebVR commands.
+    // Some WebVR commands such as SubmitFrame must be synced with the WebGL render thread.
+    vr_compositor_handler: Arc<Mutex<Option<Box<VRCompositorHandler>>>>,
+
+    /// Draws the frames, see `RendererKind`.
+    rasterizer: Rasterizer,
+}
+
+/// The two ways the renderer can draw a frame. Only the GL one needs a
+/// GL context, the software one doesn't make any GL call.
+enum Rasterizer {
+    Gl(GlRenderer),
+    Software(SoftwareRasterizer),
+}
+
+/// The GL state of a renderer that isn't created with `RendererKind::Software`.
+struct GlRenderer {
+    device: Device,
 
     // These are "cache shaders". These shaders are used to
     // draw intermediate results to cache targets. The results
@@ -285,6 +341,9 @@ pub struct Renderer {
     cs_box_shadow: LazilyCompiledShader,
     cs_text_run: LazilyCompiledShader,
     cs_blur: LazilyCompiledShader,
+    cs_idct_rows: LazilyCompiledShader,
+    cs_idct_columns: LazilyCompiledShader,
+    cs_yuv_to_rgb: LazilyCompiledShader,
     /// These are "cache clip shaders". These shaders are used to
     /// draw clip instances into the cached clip mask. The results
     /// of these shaders are also used by the primitive shaders.
@@ -304,18 +363,20 @@ pub struct Renderer {
     ps_text_run: PrimitiveShader,
     ps_text_run_subpixel: PrimitiveShader,
     ps_image: PrimitiveShader,
-    ps_yuv_image: PrimitiveShader,
+    /// One shader per plane layout, in the order of `yuv_shader_index`.
+    ps_yuv_image: Vec<PrimitiveShader>,
     ps_border: PrimitiveShader,
+    ps_border_image: PrimitiveShader,
     ps_gradient: PrimitiveShader,
     ps_angle_gradient: PrimitiveShader,
+    ps_radial_gradient: PrimitiveShader,
+    ps_conic_gradient: PrimitiveShader,
     ps_box_shadow: PrimitiveShader,
     ps_cache_image: PrimitiveShader,
 
     ps_blend: LazilyCompiledShader,
     ps_composite: LazilyCompiledShader,
 
-    notifier: Arc<Mutex<Option<Box<RenderNotifier>>>>,
-
     enable_profiler: bool,
     clear_framebuffer: bool,
     clear_color: ColorF,
@@ -342,11 +403,6 @@ pub struct Renderer {
     data128_texture: VertexDataTexture,
     resource_rects_texture: VertexDataTexture,
 
-    pipeline_epoch_map: HashMap<PipelineId, Epoch, BuildHasherDefault<FnvHasher>>,
-    /// Used to dispatch functions to the main thread's event loop.
-    /// Required to allow GLContext sharing in some implementations like WGL.
-    main_thread_dispatcher: Arc<Mutex<Option<Box<RenderDispatcher>>>>,
-
     /// A vector for fast resolves of texture cache IDs to
     /// native texture IDs. This maps to a free-list managed
     /// by the backend thread / texture cache. We free the
@@ -361,47 +417,21 @@ pub struct Renderer {
     /// application to provide external buffers for image data.
     external_image_handler: Option<Box<ExternalImageHandler>>,
 
-    /// Map of external image IDs to native textures.
-    external_images: HashMap<ExternalImageId, TextureId, BuildHasherDefault<FnvHasher>>,
+    /// Map of the external images locked for the current frame to their
+    /// textures. Each image is locked once per frame, however many
+    /// primitives use it.
+    external_images: HashMap<ExternalImageId, LockedExternalImage, BuildHasherDefault<FnvHasher>>,
 
-    // Optional trait object that handles WebVR commands.
-    // Some WebVR commands such as SubmitFrame must be synced with the WebGL render thread.
-    vr_compositor_handler: Arc<Mutex<Option<Box<VRCompositorHandler>>>>
+    /// Textures holding the contents of `ExternalImageSource::RawData`
+    /// images. They are reused on every lock until the image is released,
+    /// and deleted then.
+    raw_external_images: HashMap<ExternalImageId, RawExternalImage, BuildHasherDefault<FnvHasher>>,
 }
 
-impl Renderer {
-    /// Initializes webrender and creates a Renderer and RenderApiSender.
-    ///
-    /// # Examples
-    /// Initializes a Renderer with some reasonable values. For more information see
-    /// [RendererOptions][rendereroptions].
-    /// [rendereroptions]: struct.RendererOptions.html
-    ///
-    /// ```rust,ignore
-    /// # use webrender::renderer::Renderer;
-    /// # use std::path::PathBuf;
-    /// let opts = webrender::RendererOptions {
-    ///    device_pixel_ratio: 1.0,
-    ///    resource_override_path: None,
-    ///    enable_aa: false,
-    ///    enable_profiler: false,
-    /// };
-    /// let (renderer, sender) = Renderer::new(opts);
-    /// ```
-    pub fn new(options: RendererOptions) -> (Renderer, RenderApiSender) {
-        let (api_tx, api_rx) = channel::msg_channel().unwrap();
-        let (payload_tx, payload_rx) = channel::payload_channel().unwrap();
-        let (result_tx, result_rx) = channel();
-
-        let notifier = Arc::new(Mutex::new(None));
-
-        let file_watch_handler = FileWatcher {
-            result_tx: result_tx.clone(),
-            notifier: notifier.clone(),
-        };
-
+impl GlRenderer {
+    fn new(options: &RendererOptions, file_watch_handler: Box<FileWatcherHandler>) -> GlRenderer {
         let mut device = Device::new(options.resource_override_path.clone(),
-                                     Box::new(file_watch_handler));
+                                     file_watch_handler);
         // device-pixel ratio doesn't matter here - we are just creating resources.
         device.begin_frame(1.0);
 
@@ -420,6 +450,21 @@ impl Renderer {
                                                  &[],
                                                  &mut device,
                                                  options.precache_shaders);
+        let cs_idct_rows = LazilyCompiledShader::new(ShaderKind::Cache,
+                                                     "cs_idct",
+                                                     &[ IDCT_ROWS_FEATURE ],
+                                                     &mut device,
+                                                     options.precache_shaders);
+        let cs_idct_columns = LazilyCompiledShader::new(ShaderKind::Cache,
+                                                        "cs_idct",
+                                                        &[],
+                                                        &mut device,
+                                                        options.precache_shaders);
+        let cs_yuv_to_rgb = LazilyCompiledShader::new(ShaderKind::Cache,
+                                                      "cs_yuv_to_rgb",
+                                                      &[],
+                                                      &mut device,
+                                                      options.precache_shaders);
 
         let cs_clip_copy = LazilyCompiledShader::new(ShaderKind::ClipCache,
                                                      "cs_clip_copy",
@@ -457,14 +502,32 @@ impl Renderer {
                                             &mut device,
                                             &[],
                                             options.precache_shaders);
-        let ps_yuv_image = PrimitiveShader::new("ps_yuv_image",
-                                                &mut device,
-                                                &[],
-                                                options.precache_shaders);
+        let ps_yuv_image = vec![
+            PrimitiveShader::new("ps_yuv_image",
+                                 &mut device,
+                                 &[],
+                                 options.precache_shaders),
+            PrimitiveShader::new("ps_yuv_image",
+                                 &mut device,
+                                 &[ NV12_FEATURE ],
+                                 options.precache_shaders),
+            PrimitiveShader::new("ps_yuv_image",
+                                 &mut device,
+                                 &[ INTERLEAVED_Y_CB_CR_FEATURE ],
+                                 options.precache_shaders),
+            PrimitiveShader::new("ps_yuv_image",
+                                 &mut device,
+                                 &[ DCT_COEFFICIENTS_FEATURE ],
+                                 options.precache_shaders),
+        ];
         let ps_border = PrimitiveShader::new("ps_border",
                                              &mut device,
                                              &[],
                                              options.precache_shaders);
+        let ps_border_image = PrimitiveShader::new("ps_border_image",
+                                                   &mut device,
+                                                   &[],
+                                                   options.precache_shaders);
 
         let ps_box_shadow = PrimitiveShader::new("ps_box_shadow",
                                                  &mut device,
@@ -479,6 +542,14 @@ impl Renderer {
                                                      &mut device,
                                                      &[],
                                                      options.precache_shaders);
+        let ps_radial_gradient = PrimitiveShader::new("ps_radial_gradient",
+                                                      &mut device,
+                                                      &[],
+                                                      options.precache_shaders);
+        let ps_conic_gradient = PrimitiveShader::new("ps_conic_gradient",
+                                                     &mut device,
+                                                     &[],
+                                                     options.precache_shaders);
         let ps_cache_image = PrimitiveShader::new("ps_cache_image",
                                                   &mut device,
                                                   &[],
@@ -495,37 +566,6 @@ impl Renderer {
                                                      &mut device,
                                                      options.precache_shaders);
 
-        let mut texture_cache = TextureCache::new();
-
-        let white_pixels: Vec<u8> = vec![
-            0xff, 0xff, 0xff, 0xff,
-            0xff, 0xff, 0xff, 0xff,
-            0xff, 0xff, 0xff, 0xff,
-            0xff, 0xff, 0xff, 0xff,
-        ];
-        let mask_pixels: Vec<u8> = vec![
-            0xff, 0xff,
-            0xff, 0xff,
-        ];
-        // TODO: Ensure that the white texture can never get evicted when the cache supports LRU eviction!
-        let white_image_id = texture_cache.new_item_id();
-        texture_cache.insert(white_image_id,
-                             2,
-                             2,
-                             None,
-                             ImageFormat::RGBA8,
-                             TextureFilter::Linear,
-                             Arc::new(white_pixels));
-
-        let dummy_mask_image_id = texture_cache.new_item_id();
-        texture_cache.insert(dummy_mask_image_id,
-                             2,
-                             2,
-                             None,
-                             ImageFormat::A8,
-                             TextureFilter::Linear,
-                             Arc::new(mask_pixels));
-
         let debug_renderer = DebugRenderer::new(&mut device);
 
         let layer_texture = VertexDataTexture::new(&mut device);
@@ -570,55 +610,14 @@ impl Renderer {
 
         device.end_frame();
 
-        let main_thread_dispatcher = Arc::new(Mutex::new(None));
-        let backend_notifier = notifier.clone();
-        let backend_main_thread_dispatcher = main_thread_dispatcher.clone();
-
-        let vr_compositor = Arc::new(Mutex::new(None));
-        let backend_vr_compositor = vr_compositor.clone();
-
-        // We need a reference to the webrender context from the render backend in order to share
-        // texture ids
-        let context_handle = match options.renderer_kind {
-            RendererKind::Native => GLContextHandleWrapper::current_native_handle(),
-            RendererKind::OSMesa => GLContextHandleWrapper::current_osmesa_handle(),
-        };
-
-        let config = FrameBuilderConfig::new(options.enable_scrollbars,
-                                             options.enable_subpixel_aa);
-
-        let debug = options.debug;
-        let (device_pixel_ratio, enable_aa) = (options.device_pixel_ratio, options.enable_aa);
-        let render_target_debug = options.render_target_debug;
-        let payload_tx_for_backend = payload_tx.clone();
-        let enable_recording = options.enable_recording;
-        thread::spawn(move || {
-            let mut backend = RenderBackend::new(api_rx,
-                                                 payload_rx,
-                                                 payload_tx_for_backend,
-                                                 result_tx,
-                                                 device_pixel_ratio,
-                                                 texture_cache,
-                                                 enable_aa,
-                                                 backend_notifier,
-                                                 context_handle,
-                                                 config,
-                                                 debug,
-                                                 enable_recording,
-                                                 backend_main_thread_dispatcher,
-                                                 backend_vr_compositor);
-            backend.run();
-        });
-
-        let renderer = Renderer {
-            result_rx: result_rx,
+        GlRenderer {
             device: device,
-            current_frame: None,
-            pending_texture_updates: Vec::new(),
-            pending_shader_updates: Vec::new(),
             cs_box_shadow: cs_box_shadow,
             cs_text_run: cs_text_run,
             cs_blur: cs_blur,
+            cs_idct_rows: cs_idct_rows,
+            cs_idct_columns: cs_idct_columns,
+            cs_yuv_to_rgb: cs_yuv_to_rgb,
             cs_clip_copy: cs_clip_copy,
             cs_clip_rectangle: cs_clip_rectangle,
             cs_clip_image: cs_clip_image,
@@ -629,15 +628,17 @@ impl Renderer {
             ps_image: ps_image,
             ps_yuv_image: ps_yuv_image,
             ps_border: ps_border,
+            ps_border_image: ps_border_image,
             ps_box_shadow: ps_box_shadow,
             ps_gradient: ps_gradient,
             ps_angle_gradient: ps_angle_gradient,
+            ps_radial_gradient: ps_radial_gradient,
+            ps_conic_gradient: ps_conic_gradient,
             ps_cache_image: ps_cache_image,
             ps_blend: ps_blend,
             ps_composite: ps_composite,
-            notifier: notifier,
             debug: debug_renderer,
-            render_target_debug: render_target_debug,
+            render_target_debug: options.render_target_debug,
             backend_profile_counters: BackendProfileCounters::new(),
             profile_counters: RendererProfileCounters::new(),
             profiler: Profiler::new(),
@@ -658,77 +659,67 @@ impl Renderer {
             data64_texture: data64_texture,
             data128_texture: data128_texture,
             resource_rects_texture: resource_rects_texture,
-            pipeline_epoch_map: HashMap::with_hasher(Default::default()),
-            main_thread_dispatcher: main_thread_dispatcher,
             cache_texture_id_map: Vec::new(),
             external_image_handler: None,
             external_images: HashMap::with_hasher(Default::default()),
-            vr_compositor_handler: vr_compositor
-        };
-
-        let sender = RenderApiSender::new(api_tx, payload_tx);
-        (renderer, sender)
-    }
-
-    /// Sets the new RenderNotifier.
-    ///
-    /// The RenderNotifier will be called when processing e.g. of a (scrolling) frame is done,
-    /// and therefore the screen should be updated.
-    pub fn set_render_notifier(&self, notifier: Box<RenderNotifier>) {
-        let mut notifier_arc = self.notifier.lock().unwrap();
-        *notifier_arc = Some(notifier);
+            raw_external_images: HashMap::with_hasher(Default::default()),
+        }
     }
 
-    /// Sets the new MainThreadDispatcher.
-    ///
-    /// Allows to dispatch functions to the main thread's event loop.
-    pub fn set_main_thread_dispatcher(&self, dispatcher: Box<RenderDispatcher>) {
-        let mut dispatcher_arc = self.main_thread_dispatcher.lock().unwrap();
-        *dispatcher_arc = Some(dispatcher);
-    }
+    /// Draws a frame, after applying the texture updates and drawing the
+    /// rendered images that came before it.
+    fn render(&mut self,
+              frame: &mut Frame,
+              framebuffer_size: DeviceUintSize,
+              texture_updates: Vec<TextureUpdateList>,
+              rendered_images: Vec<(usize, CacheTextureId, RendererFrame)>) {
+        let mut profile_timers = RendererProfileTimers::new();
+
+        // Block CPU waiting for last frame's GPU profiles to arrive.
+        // In general this shouldn't block unless heavily GPU limited.
+        if let Some(samples) = self.gpu_profile.build_samples() {
+            profile_timers.gpu_samples = samples;
+        }
 
-    /// Sets the VRCompositorHandler.
-    ///
-    /// It's used to handle WebVR render commands.
-    /// Some WebVR commands such as Vsync and SubmitFrame must be called in the WebGL render thread.
-    pub fn set_vr_compositor_handler(&self, creator: Box<VRCompositorHandler>) {
-        let mut handler_arc = self.vr_compositor_handler.lock().unwrap();
-        *handler_arc = Some(creator);
-    }
+        profile_timers.cpu_time.profile(|| {
+            self.device.begin_frame(frame.device_pixel_ratio);
+            self.gpu_profile.begin_frame();
+            {
+                let _gm = self.gpu_profile.add_marker(GPU_TAG_INIT);
 
-    /// Returns the Epoch of the current frame in a pipeline.
-    pub fn current_epoch(&self, pipeline_id: PipelineId) -> Option<Epoch> {
-        self.pipeline_epoch_map.get(&pipeline_id).map(|epoch| *epoch)
-    }
+                self.device.disable_scissor();
+                self.device.disable_depth();
+                self.device.set_blend(false);
 
-    /// Processes the result queue.
-    ///
-    /// Should be called before `render()`, as texture cache updates are done here.
-    pub fn update(&mut self) {
-        // Pull any pending results and return the most recent.
-        while let Ok(msg) = self.result_rx.try_recv() {
-            match msg {
-                ResultMsg::NewFrame(frame, texture_update_list, external_image_update_list, profile_counters) => {
-                    self.pending_texture_updates.push(texture_update_list);
+                //self.update_shaders();
+                self.update_texture_cache(texture_updates, rendered_images);
+            }
 
-                    // When a new frame is ready, we could start to update all pending external image requests here.
-                    self.release_external_images(external_image_update_list);
+            self.draw_tile_frame(frame, &framebuffer_size);
 
-                    self.backend_profile_counters = profile_counters;
+            self.gpu_profile.end_frame();
+        });
 
-                    // Update the list of available epochs for use during reftests.
-                    // This is a workaround for https://github.com/servo/servo/issues/13149.
-                    for (pipeline_id, epoch) in &frame.pipeline_epoch_map {
-                        self.pipeline_epoch_map.insert(*pipeline_id, *epoch);
-                    }
+        let current_time = precise_time_ns();
+        let ns = current_time - self.last_time;
+        self.profile_counters.frame_time.set(ns);
 
-                    self.current_frame = Some(frame);
-                }
-                ResultMsg::RefreshShader(path) => {
-                    self.pending_shader_updates.push(path);
-                }
-            }
+        if self.enable_profiler {
+            self.profiler.draw_profile(&frame.profile_counters,
+                                       &self.backend_profile_counters,
+                                       &self.profile_counters,
+                                       &mut profile_timers,
+                                       &mut self.debug);
         }
+
+        self.profile_counters.reset();
+        self.profile_counters.frame_counter.inc();
+
+        let debug_size = DeviceUintSize::new(framebuffer_size.width as u32,
+                                             framebuffer_size.height as u32);
+        self.debug.render(&mut self.device, &debug_size);
+        self.device.end_frame();
+        self.last_time = current_time;
     }
 
     // Get the real (OpenGL) texture ID for a given source texture.
@@ -742,9 +733,10 @@ impl Renderer {
             &SourceTexture::Invalid => TextureId::invalid(),
             &SourceTexture::WebGL(id) => TextureId::new(id),
             &SourceTexture::External(ref key) => {
-                *self.external_images
-                     .get(key)
-                     .expect("BUG: External image should be resolved by now!")
+                self.external_images
+                    .get(key)
+                    .expect("BUG: External image should be resolved by now!")
+                    .texture_id
             }
             &SourceTexture::TextureCache(index) => {
                 self.cache_texture_id_map[index.0]
@@ -752,79 +744,6 @@ impl Renderer {
         }
     }
 
-    /// Set a callback for handling external images.
-    pub fn set_external_image_handler(&mut self, handler: Box<ExternalImageHandler>) {
-        self.external_image_handler = Some(handler);
-    }
-
-    /// Renders the current frame.
-    ///
-    /// A Frame is supplied by calling [set_root_stacking_context()][newframe].
-    /// [newframe]: ../../webrender_traits/struct.RenderApi.html#method.set_root_stacking_context
-    pub fn render(&mut self, framebuffer_size: DeviceUintSize) {
-        if let Some(mut frame) = self.current_frame.take() {
-            if let Some(ref mut frame) = frame.frame {
-                let mut profile_timers = RendererProfileTimers::new();
-
-                // Block CPU waiting for last frame's GPU profiles to arrive.
-                // In general this shouldn't block unless heavily GPU limited.
-                if let Some(samples) = self.gpu_profile.build_samples() {
-                    profile_timers.gpu_samples = samples;
-                }
-
-                profile_timers.cpu_time.profile(|| {
-                    self.device.begin_frame(frame.device_pixel_ratio);
-                    self.gpu_profile.begin_frame();
-                    {
-                        let _gm = self.gpu_profile.add_marker(GPU_TAG_INIT);
-
-                        self.device.disable_scissor();
-                        self.device.disable_depth();
-                        self.device.set_blend(false);
-
-                        //self.update_shaders();
-                        self.update_texture_cache();
-                    }
-
-                    self.draw_tile_frame(frame, &framebuffer_size);
-
-                    self.gpu_profile.end_frame();
-                });
-
-                let current_time = precise_time_ns();
-                let ns = current_time - self.last_time;
-                self.profile_counters.frame_time.set(ns);
-
-                if self.enable_profiler {
-                    self.profiler.draw_profile(&frame.profile_counters,
-                                               &self.backend_profile_counters,
-                                               &self.profile_counters,
-                                               &mut profile_timers,
-                                               &mut self.debug);
-                }
-
-                self.profile_counters.reset();
-                self.profile_counters.frame_counter.inc();
-
-                let debug_size = DeviceUintSize::new(framebuffer_size.width as u32,
-                                                     framebuffer_size.height as u32);
-                self.debug.render(&mut self.device, &debug_size);
-                self.device.end_frame();
-                self.last_time = current_time;
-            }
-
-            // Restore frame - avoid borrow checker!
-            self.current_frame = Some(frame);
-        }
-    }
-
-    pub fn layers_are_bouncing_back(&self) -> bool {
-        match self.current_frame {
-            None => false,
-            Some(ref current_frame) => !current_frame.layers_bouncing_back.is_empty(),
-        }
-    }
-
 /*
     fn update_shaders(&mut self) {
         let update_uniforms = !self.pending_shader_updates.is_empty();
@@ -840,10 +759,12 @@ impl Renderer {
     }
 */
 
-    fn update_texture_cache(&mut self) {
+    fn update_texture_cache(&mut self,
+                            mut pending_texture_updates: Vec<TextureUpdateList>,
+                            pending_rendered_images: Vec<(usize, CacheTextureId, RendererFrame)>) {
         let _gm = GpuMarker::new("texture cache update");
-        let mut pending_texture_updates = mem::replace(&mut self.pending_texture_updates, vec![]);
-        for update_list in pending_texture_updates.drain(..) {
+        let mut pending_rendered_images = pending_rendered_images.into_iter().peekable();
+        for (update_index, update_list) in pending_texture_updates.drain(..).enumerate() {
             for update in update_list.updates {
                 match update.op {
                     TextureUpdateOp::Create(width, height, format, filter, mode, maybe_bytes) => {
@@ -886,12 +807,31 @@ impl Renderer {
                                                    width, height, stride,
                                                    bytes.as_slice());
                     }
+                    TextureUpdateOp::Copy(src_id, src_rect, dest_origin) => {
+                        let src_texture_id = self.cache_texture_id_map[src_id.0];
+                        let texture_id = self.cache_texture_id_map[update.id.0];
+                        self.device.copy_texture_region(src_texture_id,
+                                                        src_rect,
+                                                        texture_id,
+                                                        dest_origin);
+                    }
                     TextureUpdateOp::Free => {
                         let texture_id = self.cache_texture_id_map[update.id.0];
                         self.device.deinit_texture(texture_id);
                     }
                 }
             }
+
+            // Rendered images are drawn before any later updates, which
+            // may free the resources they use.
+            while pending_rendered_images.peek().map_or(false, |&(update_count, _, _)| {
+                update_count == update_index + 1
+            }) {
+                let (_, texture_id, mut frame) = pending_rendered_images.next().unwrap();
+                if let Some(ref mut frame) = frame.frame {
+                    self.draw_rendered_image(texture_id, frame);
+                }
+            }
         }
     }
 
@@ -990,8 +930,12 @@ impl Renderer {
                 let shader = self.ps_image.get(&mut self.device, transform_kind);
                 (data, GPU_TAG_PRIM_IMAGE, shader)
             }
-            &PrimitiveBatchData::YuvImage(ref data) => {
-                let shader = self.ps_yuv_image.get(&mut self.device, transform_kind);
+            &PrimitiveBatchData::BorderImage(ref data) => {
+                let shader = self.ps_border_image.get(&mut self.device, transform_kind);
+                (data, GPU_TAG_PRIM_BORDER_IMAGE, shader)
+            }
+            &PrimitiveBatchData::YuvImage(format, ref data) => {
+                let shader = self.ps_yuv_image[yuv_shader_index(format)].get(&mut self.device, transform_kind);
                 (data, GPU_TAG_PRIM_YUV_IMAGE, shader)
             }
             &PrimitiveBatchData::Borders(ref data) => {
@@ -1017,6 +961,14 @@ impl Renderer {
                 let shader = self.ps_angle_gradient.get(&mut self.device, transform_kind);
                 (data, GPU_TAG_PRIM_ANGLE_GRADIENT, shader)
             }
+            &PrimitiveBatchData::RadialGradient(ref data) => {
+                let shader = self.ps_radial_gradient.get(&mut self.device, transform_kind);
+                (data, GPU_TAG_PRIM_RADIAL_GRADIENT, shader)
+            }
+            &PrimitiveBatchData::ConicGradient(ref data) => {
+                let shader = self.ps_conic_gradient.get(&mut self.device, transform_kind);
+                (data, GPU_TAG_PRIM_CONIC_GRADIENT, shader)
+            }
         };
 
         let _gm = self.gpu_profile.add_marker(marker);
@@ -1033,8 +985,7 @@ impl Renderer {
                    target: &RenderTarget,
                    target_size: &DeviceSize,
                    cache_texture: Option<TextureId>,
-                   should_clear: bool,
-                   background_color: Option<ColorF>) {
+                   clear_color: Option<[f32; 4]>) {
         self.device.disable_depth();
         self.device.enable_depth_write();
 
@@ -1049,45 +1000,26 @@ impl Renderer {
                 self.device.bind_texture(TextureSampler::Cache, cache_texture);
             }
 
-            let (color, projection) = match render_target {
-                Some(..) => (
-                    // The clear color here is chosen specifically such that:
-                    // - The red channel is cleared to 1, so that the clip mask
-                    //   generation (which reads/writes the red channel) can
-                    //   assume that each allocated rect is opaque / non-clipped
-                    //   initially.
-                    // - The alpha channel is cleared to 0, so that visual render
-                    //   tasks can assume that pixels are transparent if not
-                    //   rendered. (This is relied on by the compositing support
-                    //   for mix-blend-mode etc).
-                    [1.0, 0.0, 0.0, 0.0],
+            let projection = match render_target {
+                Some(..) => {
                     Matrix4D::ortho(0.0,
                                    target_size.width,
                                    0.0,
                                    target_size.height,
                                    ORTHO_NEAR_PLANE,
                                    ORTHO_FAR_PLANE)
-                ),
-                None => (
-                    background_color.map_or(self.clear_color.to_array(), |color| {
-                        color.to_array()
-                    }),
+                }
+                None => {
                     Matrix4D::ortho(0.0,
                                    target_size.width,
                                    target_size.height,
                                    0.0,
                                    ORTHO_NEAR_PLANE,
                                    ORTHO_FAR_PLANE)
-                ),
+                }
             };
 
             let clear_depth = Some(1.0);
-            let clear_color = if should_clear {
-                Some(color)
-            } else {
-                None
-            };
-
             self.device.clear_target(clear_color, clear_depth);
 
             projection
@@ -1120,6 +1052,47 @@ impl Renderer {
                                       &projection);
         }
 
+        // Draw the inverse DCT passes of any YUV images for this target.
+        if !target.idct_rows.is_empty() || !target.idct_columns.is_empty() {
+            let _gm = self.gpu_profile.add_marker(GPU_TAG_CACHE_IDCT);
+            let vao = self.prim_vao_id;
+
+            self.device.set_blend(false);
+            let shader = self.cs_idct_rows.get(&mut self.device);
+            for (texture_id, items) in target.idct_rows.iter() {
+                let textures = BatchTextures {
+                    colors: [*texture_id, SourceTexture::Invalid, SourceTexture::Invalid],
+                };
+                self.draw_instanced_batch(items,
+                                          vao,
+                                          shader,
+                                          &textures,
+                                          &projection);
+            }
+
+            if !target.idct_columns.is_empty() {
+                let shader = self.cs_idct_columns.get(&mut self.device);
+                self.draw_instanced_batch(&target.idct_columns,
+                                          vao,
+                                          shader,
+                                          &BatchTextures::no_texture(),
+                                          &projection);
+            }
+        }
+
+        if !target.yuv_to_rgb.is_empty() {
+            let _gm = self.gpu_profile.add_marker(GPU_TAG_CACHE_YUV_TO_RGB);
+            let vao = self.prim_vao_id;
+
+            self.device.set_blend(false);
+            let shader = self.cs_yuv_to_rgb.get(&mut self.device);
+            self.draw_instanced_batch(&target.yuv_to_rgb,
+                                      vao,
+                                      shader,
+                                      &BatchTextures::no_texture(),
+                                      &projection);
+        }
+
         // Draw any box-shadow caches for this target.
         if !target.box_shadow_cache_prims.is_empty() {
             self.device.set_blend(false);
@@ -1249,17 +1222,82 @@ impl Renderer {
                 let props = &deferred_resolve.image_properties;
                 let external_id = props.external_id
                                        .expect("BUG: Deferred resolves must be external images!");
+
+                if let Some(locked_image) = self.external_images.get(&external_id) {
+                    let resource_rect_index = deferred_resolve.resource_address.0 as usize;
+                    let resource_rect = &mut frame.gpu_resource_rects[resource_rect_index];
+                    resource_rect.uv0 = locked_image.uv0;
+                    resource_rect.uv1 = locked_image.uv1;
+                    continue;
+                }
+
                 let image = handler.lock(external_id);
 
                 let texture_id = match image.source {
                     ExternalImageSource::NativeTexture(texture_id) => TextureId::new(texture_id),
+                    ExternalImageSource::RawData(data, size) => {
+                        // Only upload when the handler reports new contents.
+                        let raw_image = self.raw_external_images.get(&external_id).cloned();
+                        let (texture_id, mut generation) = match raw_image {
+                            Some(raw_image) => (raw_image.texture_id, raw_image.generation),
+                            None => (self.device.create_texture_ids(1, TextureTarget::Default)[0], None),
+                        };
+
+                        if self.device.get_texture_dimensions(texture_id) != (props.width, props.height) ||
+                           self.device.get_texture_format(texture_id) != props.format {
+                            self.device.init_texture(texture_id,
+                                                     props.width,
+                                                     props.height,
+                                                     props.format,
+                                                     TextureFilter::Linear,
+                                                     RenderTargetMode::None,
+                                                     None);
+                            generation = None;
+                        }
+
+                        if generation != Some(image.generation) {
+                            let required_size = props.data_size();
+                            if size < required_size {
+                                warn!("External image {:?} has {} bytes, {} are needed",
+                                      external_id, size, required_size);
+                            } else {
+                                let data = unsafe { slice::from_raw_parts(data, size) };
+                                self.device.update_texture(texture_id,
+                                                           0,
+                                                           0,
+                                                           props.width,
+                                                           props.height,
+                                                           props.stride,
+                                                           data);
+                                generation = Some(image.generation);
+                            }
+                        }
+
+                        self.raw_external_images.insert(external_id, RawExternalImage {
+                            texture_id: texture_id,
+                            generation: generation,
+                        });
+
+                        // A texture that nothing could be uploaded into is left
+                        // unbound, like the samplers that a batch doesn't use,
+                        // rather than sampling its uninitialized contents.
+                        match generation {
+                            Some(..) => texture_id,
+                            None => TextureId::invalid(),
+                        }
+                    }
                 };
 
-                self.external_images.insert(external_id, texture_id);
+                let locked_image = LockedExternalImage {
+                    texture_id: texture_id,
+                    uv0: DevicePoint::new(image.u0, image.v0),
+                    uv1: DevicePoint::new(image.u1, image.v1),
+                };
                 let resource_rect_index = deferred_resolve.resource_address.0 as usize;
                 let resource_rect = &mut frame.gpu_resource_rects[resource_rect_index];
-                resource_rect.uv0 = DevicePoint::new(image.u0, image.v0);
-                resource_rect.uv1 = DevicePoint::new(image.u1, image.v1);
+                resource_rect.uv0 = locked_image.uv0;
+                resource_rect.uv1 = locked_image.uv1;
+                self.external_images.insert(external_id, locked_image);
             }
         }
     }
@@ -1283,6 +1321,9 @@ impl Renderer {
                               .expect("found external image updates, but no handler set!");
 
             for external_id in pending_external_image_updates.drain(..) {
+                if let Some(raw_image) = self.raw_external_images.remove(&external_id) {
+                    self.device.delete_texture(raw_image.texture_id);
+                }
                 handler.release(external_id);
             }
         }
@@ -1312,15 +1353,62 @@ impl Renderer {
             }
         }
 
+        self.draw_passes(frame,
+                         None,
+                         framebuffer_size,
+                         self.clear_framebuffer || needs_clear);
+
+        if !frame.passes.is_empty() {
+            self.draw_render_target_debug(framebuffer_size);
+        }
+
+        self.unlock_external_images();
+    }
+
+    /// Draws a frame sent with `ResultMsg::RenderedImage` into the render
+    /// target of its image.
+    fn draw_rendered_image(&mut self,
+                           texture_id: CacheTextureId,
+                           frame: &mut Frame) {
+        let _gm = GpuMarker::new("rendered image draw");
+        self.update_deferred_resolves(frame);
+
+        let texture_id = self.cache_texture_id_map[texture_id.0];
+        let (width, height) = self.device.get_texture_dimensions(texture_id);
+        self.device.create_depth_target_if_necessary(texture_id);
+
+        self.draw_passes(frame,
+                         Some(texture_id),
+                         &DeviceUintSize::new(width, height),
+                         true);
+
+        self.unlock_external_images();
+    }
+
+    /// Draws the passes of a frame, with the last pass going to `output`, or
+    /// to the framebuffer if there is no output texture.
+    fn draw_passes(&mut self,
+                   frame: &mut Frame,
+                   output: Option<TextureId>,
+                   output_size: &DeviceUintSize,
+                   clear_output: bool) {
         self.device.disable_depth_write();
         self.device.disable_stencil();
         self.device.set_blend(false);
 
+        // Rendered images are transparent where there is no background color.
+        let default_clear_color = match output {
+            Some(..) => [0.0, 0.0, 0.0, 0.0],
+            None => self.clear_color.to_array(),
+        };
+
         if frame.passes.is_empty() {
-            self.device.clear_target(Some(self.clear_color.to_array()), Some(1.0));
+            self.device.bind_draw_target(output.map(|texture_id| (texture_id, 0)),
+                                         Some([output_size.width, output_size.height]));
+            self.device.clear_target(Some(default_clear_color), Some(1.0));
         } else {
             // Add new render targets to the pool if required.
-            let needed_targets = frame.passes.len() - 1;     // framebuffer doesn't need a target!
+            let needed_targets = frame.passes.len() - 1;     // output doesn't need a target!
             let current_target_count = self.render_targets.len();
             if needed_targets > current_target_count {
                 let new_target_count = needed_targets - current_target_count;
@@ -1362,12 +1450,26 @@ impl Renderer {
             let mut src_id = None;
 
             for (pass_index, pass) in frame.passes.iter().enumerate() {
-                let (do_clear, size, target_id) = if pass.is_framebuffer {
-                    (self.clear_framebuffer || needs_clear,
-                     DeviceSize::new(framebuffer_size.width as f32, framebuffer_size.height as f32),
-                     None)
+                let (clear_color, size, target_id) = if pass.is_framebuffer {
+                    let clear_color = frame.background_color.map_or(default_clear_color, |color| {
+                        color.to_array()
+                    });
+                    (if clear_output { Some(clear_color) } else { None },
+                     DeviceSize::new(output_size.width as f32, output_size.height as f32),
+                     output)
                 } else {
-                    (true, frame.cache_size, Some(self.render_targets[pass_index]))
+                    // The clear color here is chosen specifically such that:
+                    // - The red channel is cleared to 1, so that the clip mask
+                    //   generation (which reads/writes the red channel) can
+                    //   assume that each allocated rect is opaque / non-clipped
+                    //   initially.
+                    // - The alpha channel is cleared to 0, so that visual render
+                    //   tasks can assume that pixels are transparent if not
+                    //   rendered. (This is relied on by the compositing support
+                    //   for mix-blend-mode etc).
+                    (Some([1.0, 0.0, 0.0, 0.0]),
+                     frame.cache_size,
+                     Some(self.render_targets[pass_index]))
                 };
 
                 for (target_index, target) in pass.targets.iter().enumerate() {
@@ -1378,30 +1480,13 @@ impl Renderer {
                                      target,
                                      &size,
                                      src_id,
-                                     do_clear,
-                                     frame.background_color);
+                                     clear_color);
 
                 }
 
                 src_id = target_id;
             }
-
-            self.draw_render_target_debug(framebuffer_size);
         }
-
-        self.unlock_external_images();
-    }
-
-    pub fn debug_renderer<'a>(&'a mut self) -> &'a mut DebugRenderer {
-        &mut self.debug
-    }
-
-    pub fn get_profiler_enabled(&mut self) -> bool {
-        self.enable_profiler
-    }
-
-    pub fn set_profiler_enabled(&mut self, enabled: bool) {
-        self.enable_profiler = enabled;
     }
 
     fn draw_render_target_debug(&mut self,
@@ -1440,20 +1525,492 @@ impl Renderer {
     }
 }
 
+impl Renderer {
+    /// Initializes webrender and creates a Renderer and RenderApiSender.
+    ///
+    /// # Examples
+    /// Initializes a Renderer with some reasonable values. For more information see
+    /// [RendererOptions][rendereroptions].
+    /// [rendereroptions]: struct.RendererOptions.html
+    ///
+    /// ```rust,ignore
+    /// # use webrender::renderer::Renderer;
+    /// # use std::path::PathBuf;
+    /// let opts = webrender::RendererOptions {
+    ///    device_pixel_ratio: 1.0,
+    ///    resource_override_path: None,
+    ///    enable_aa: false,
+    ///    enable_profiler: false,
+    /// };
+    /// let (renderer, sender) = Renderer::new(opts);
+    /// ```
+    pub fn new(options: RendererOptions) -> (Renderer, RenderApiSender) {
+        let (api_tx, api_rx) = channel::msg_channel().unwrap();
+        let (payload_tx, payload_rx) = channel::payload_channel().unwrap();
+        let (result_tx, result_rx) = channel();
+
+        let notifier = Arc::new(Mutex::new(None));
+
+        let file_watch_handler = FileWatcher {
+            result_tx: result_tx.clone(),
+            notifier: notifier.clone(),
+        };
+
+        let (rasterizer, compressed_formats, expanded_formats) = match options.renderer_kind {
+            RendererKind::Native | RendererKind::OSMesa => {
+                let gl_renderer = GlRenderer::new(&options, Box::new(file_watch_handler));
+                let (compressed_formats, expanded_formats) = {
+                    let capabilities = gl_renderer.device.get_capabilities();
+                    (capabilities.compressed_formats.clone(), capabilities.expanded_formats.clone())
+                };
+                (Rasterizer::Gl(gl_renderer), compressed_formats, expanded_formats)
+            }
+            // The software rasterizer doesn't touch GL at all, and understands
+            // all the uncompressed formats.
+            RendererKind::Software => {
+                (Rasterizer::Software(SoftwareRasterizer::new(options.clear_color)), Vec::new(), Vec::new())
+            }
+        };
+        let mut texture_cache = TextureCache::new(options.texture_cache_budget,
+                                                  compressed_formats,
+                                                  expanded_formats);
+
+        let white_pixels: Vec<u8> = vec![
+            0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff,
+        ];
+        let mask_pixels: Vec<u8> = vec![
+            0xff, 0xff,
+            0xff, 0xff,
+        ];
+        let white_image_id = texture_cache.new_item_id();
+        texture_cache.insert(white_image_id,
+                             2,
+                             2,
+                             None,
+                             ImageFormat::RGBA8,
+                             TextureFilter::Linear,
+                             Arc::new(white_pixels));
+
+        let dummy_mask_image_id = texture_cache.new_item_id();
+        texture_cache.insert(dummy_mask_image_id,
+                             2,
+                             2,
+                             None,
+                             ImageFormat::A8,
+                             TextureFilter::Linear,
+                             Arc::new(mask_pixels));
+
+        // The white and dummy mask textures are used by the shaders, so they
+        // must never get evicted from the cache.
+        texture_cache.pin(white_image_id);
+        texture_cache.pin(dummy_mask_image_id);
+
+        let main_thread_dispatcher = Arc::new(Mutex::new(None));
+        let backend_notifier = notifier.clone();
+        let backend_main_thread_dispatcher = main_thread_dispatcher.clone();
+
+        let vr_compositor = Arc::new(Mutex::new(None));
+        let backend_vr_compositor = vr_compositor.clone();
+
+        // We need a reference to the webrender context from the render backend in order to share
+        // texture ids
+        let context_handle = match options.renderer_kind {
+            RendererKind::Native => GLContextHandleWrapper::current_native_handle(),
+            RendererKind::OSMesa => GLContextHandleWrapper::current_osmesa_handle(),
+            // There is no context to share WebGL textures with.
+            RendererKind::Software => None,
+        };
+
+        let config = FrameBuilderConfig::new(options.enable_scrollbars,
+                                             options.enable_subpixel_aa);
+
+        let debug = options.debug;
+        let (device_pixel_ratio, enable_aa) = (options.device_pixel_ratio, options.enable_aa);
+        let payload_tx_for_backend = payload_tx.clone();
+        let enable_recording = options.enable_recording;
+        thread::spawn(move || {
+            let mut backend = RenderBackend::new(api_rx,
+                                                 payload_rx,
+                                                 payload_tx_for_backend,
+                                                 result_tx,
+                                                 device_pixel_ratio,
+                                                 texture_cache,
+                                                 enable_aa,
+                                                 backend_notifier,
+                                                 context_handle,
+                                                 config,
+                                                 debug,
+                                                 enable_recording,
+                                                 backend_main_thread_dispatcher,
+                                                 backend_vr_compositor);
+            backend.run();
+        });
+
+        let renderer = Renderer {
+            result_rx: result_rx,
+            frames: HashMap::new(),
+            pending_texture_updates: Vec::new(),
+            pending_rendered_images: Vec::new(),
+            pending_shader_updates: Vec::new(),
+            notifier: notifier,
+            framebuffer_size: DeviceUintSize::zero(),
+            pipeline_epoch_map: HashMap::with_hasher(Default::default()),
+            main_thread_dispatcher: main_thread_dispatcher,
+            vr_compositor_handler: vr_compositor,
+            rasterizer: rasterizer,
+        };
+
+        let sender = RenderApiSender::new(api_tx, payload_tx);
+        (renderer, sender)
+    }
+
+    /// Sets the new RenderNotifier.
+    ///
+    /// The RenderNotifier will be called when processing e.g. of a (scrolling) frame is done,
+    /// and therefore the screen should be updated.
+    pub fn set_render_notifier(&self, notifier: Box<RenderNotifier>) {
+        let mut notifier_arc = self.notifier.lock().unwrap();
+        *notifier_arc = Some(notifier);
+    }
+
+    /// Sets the new MainThreadDispatcher.
+    ///
+    /// Allows to dispatch functions to the main thread's event loop.
+    pub fn set_main_thread_dispatcher(&self, dispatcher: Box<RenderDispatcher>) {
+        let mut dispatcher_arc = self.main_thread_dispatcher.lock().unwrap();
+        *dispatcher_arc = Some(dispatcher);
+    }
+
+    /// Sets the VRCompositorHandler.
+    ///
+    /// It's used to handle WebVR render commands.
+    /// Some WebVR commands such as Vsync and SubmitFrame must be called in the WebGL render thread.
+    pub fn set_vr_compositor_handler(&self, creator: Box<VRCompositorHandler>) {
+        let mut handler_arc = self.vr_compositor_handler.lock().unwrap();
+        *handler_arc = Some(creator);
+    }
+
+    /// Returns the Epoch of the current frame in a pipeline.
+    pub fn current_epoch(&self, pipeline_id: PipelineId) -> Option<Epoch> {
+        self.pipeline_epoch_map.get(&pipeline_id).map(|epoch| *epoch)
+    }
+
+    /// Processes the result queue.
+    ///
+    /// Should be called before `render()`, as texture cache updates are done here.
+    pub fn update(&mut self) {
+        // Pull any pending results and return the most recent.
+        while let Ok(msg) = self.result_rx.try_recv() {
+            match msg {
+                ResultMsg::NewFrame(document_id, frame, texture_update_list, external_image_update_list, profile_counters) => {
+                    self.pending_texture_updates.push(texture_update_list);
+
+                    // When a new frame is ready, we could start to update all pending external image requests here.
+                    match self.rasterizer {
+                        Rasterizer::Gl(ref mut gl) => {
+                            gl.release_external_images(external_image_update_list);
+                            gl.backend_profile_counters = profile_counters;
+                        }
+                        Rasterizer::Software(ref mut rasterizer) => {
+                            rasterizer.release_external_images(external_image_update_list);
+                        }
+                    }
+
+                    // Update the list of available epochs for use during reftests.
+                    // This is a workaround for https://github.com/servo/servo/issues/13149.
+                    for (pipeline_id, epoch) in &frame.pipeline_epoch_map {
+                        self.pipeline_epoch_map.insert(*pipeline_id, *epoch);
+                    }
+
+                    self.frames.insert(document_id, frame);
+                }
+                ResultMsg::DeleteDocument(document_id) => {
+                    self.frames.remove(&document_id);
+                }
+                ResultMsg::RenderedImage(texture_id, frame, texture_update_list) => {
+                    self.pending_texture_updates.push(texture_update_list);
+                    let update_count = self.pending_texture_updates.len();
+                    self.pending_rendered_images.push((update_count, texture_id, frame));
+                }
+                ResultMsg::RefreshShader(path) => {
+                    self.pending_shader_updates.push(path);
+                }
+            }
+        }
+    }
+
+    /// Set a callback for handling external images.
+    pub fn set_external_image_handler(&mut self, handler: Box<ExternalImageHandler>) {
+        match self.rasterizer {
+            Rasterizer::Gl(ref mut gl) => gl.external_image_handler = Some(handler),
+            Rasterizer::Software(ref mut rasterizer) => rasterizer.set_external_image_handler(handler),
+        }
+    }
+
+    /// Renders the current frame of a document.
+    ///
+    /// A Frame is supplied by calling [set_root_stacking_context()][newframe].
+    /// [newframe]: ../../webrender_traits/struct.RenderApi.html#method.set_root_stacking_context
+    ///
+    /// Each document is drawn on its own, usually into the framebuffer of its
+    /// window, so the right GL context should be made current first. The
+    /// pending texture updates are applied by whichever call comes first, and
+    /// all documents draw with the same textures and shaders, so the contexts
+    /// of their windows must share them with the context the renderer was
+    /// created in.
+    pub fn render(&mut self, document_id: DocumentId, framebuffer_size: DeviceUintSize) {
+        self.framebuffer_size = framebuffer_size;
+
+        if let Some(mut frame) = self.frames.remove(&document_id) {
+            if let Some(ref mut frame) = frame.frame {
+                let texture_updates = mem::replace(&mut self.pending_texture_updates, vec![]);
+                let rendered_images = mem::replace(&mut self.pending_rendered_images, vec![]);
+                match self.rasterizer {
+                    Rasterizer::Gl(ref mut gl) => {
+                        gl.render(frame, framebuffer_size, texture_updates, rendered_images);
+                    }
+                    Rasterizer::Software(ref mut rasterizer) => {
+                        rasterizer.render(frame, framebuffer_size, texture_updates, rendered_images);
+                    }
+                }
+            }
+
+            // Restore frame - avoid borrow checker!
+            self.frames.insert(document_id, frame);
+        }
+    }
+
+    /// Returns the last frame drawn by the software rasterizer, as RGBA8
+    /// pixels with the rows stored top down. This is `None` unless the
+    /// renderer was created with `RendererKind::Software`.
+    pub fn software_framebuffer(&self) -> Option<(DeviceUintSize, &[u8])> {
+        match self.rasterizer {
+            Rasterizer::Gl(..) => None,
+            Rasterizer::Software(ref rasterizer) => {
+                Some((rasterizer.framebuffer_size(), rasterizer.framebuffer()))
+            }
+        }
+    }
+
+    /// Reads back a rectangle of the frame drawn by the last call to `render`.
+    ///
+    /// The rect is in device pixels with the origin at the top left of the
+    /// framebuffer, so layout rects need to be scaled by the device pixel
+    /// ratio first. It is clamped to the framebuffer. The returned rows are
+    /// tightly packed and stored top down.
+    pub fn read_pixels(&mut self, rect: DeviceUintRect, format: ReadPixelsFormat) -> Vec<u8> {
+        let rect = self.clamp_to_framebuffer(rect);
+        if is_empty_rect(&rect) {
+            return Vec::new();
+        }
+        let gl_rect = self.gl_read_rect(rect);
+        let pixels = match self.rasterizer {
+            Rasterizer::Gl(ref mut gl) => flip_rows(gl.device.read_pixels(gl_rect), rect),
+            Rasterizer::Software(ref rasterizer) => read_software_pixels(rasterizer, rect),
+        };
+        convert_pixels(pixels, format)
+    }
+
+    /// Starts reading back a rectangle of the frame drawn by the last call
+    /// to `render`, see `read_pixels`. With GL, the pixels are copied into a
+    /// pixel buffer object, so this doesn't wait for the GPU to finish drawing.
+    /// The result is fetched with `resolve_read_pixels`.
+    pub fn read_pixels_async(&mut self, rect: DeviceUintRect, format: ReadPixelsFormat) -> AsyncReadPixels {
+        let rect = self.clamp_to_framebuffer(rect);
+        let gl_rect = self.gl_read_rect(rect);
+        let (pbo, pixels) = match self.rasterizer {
+            // A pixel buffer can't be mapped with no pixels in it.
+            _ if is_empty_rect(&rect) => (None, Vec::new()),
+            Rasterizer::Gl(ref mut gl) => {
+                let pbo = gl.device.create_pbo();
+                gl.device.read_pixels_into_pbo(pbo, gl_rect);
+                (Some(pbo), Vec::new())
+            }
+            Rasterizer::Software(ref rasterizer) => (None, read_software_pixels(rasterizer, rect)),
+        };
+
+        AsyncReadPixels {
+            rect: rect,
+            format: format,
+            pbo: pbo,
+            pixels: pixels,
+        }
+    }
+
+    /// Returns the pixels of a read started with `read_pixels_async`,
+    /// waiting for the GPU if they aren't available yet.
+    pub fn resolve_read_pixels(&mut self, request: AsyncReadPixels) -> Vec<u8> {
+        let pixels = match request.pbo {
+            Some(pbo) => {
+                let device = match self.rasterizer {
+                    Rasterizer::Gl(ref mut gl) => &mut gl.device,
+                    Rasterizer::Software(..) => unreachable!(),
+                };
+                let size = request.rect.size.width as usize * request.rect.size.height as usize * 4;
+                let pixels = device.get_pbo_data(pbo, size);
+                device.delete_pbo(pbo);
+                flip_rows(pixels, request.rect)
+            }
+            None => request.pixels,
+        };
+
+        convert_pixels(pixels, request.format)
+    }
+
+    /// Drops a read started with `read_pixels_async` without waiting for
+    /// its pixels.
+    pub fn cancel_read_pixels(&mut self, request: AsyncReadPixels) {
+        if let Some(pbo) = request.pbo {
+            if let Rasterizer::Gl(ref mut gl) = self.rasterizer {
+                gl.device.delete_pbo(pbo);
+            }
+        }
+    }
+
+    fn clamp_to_framebuffer(&self, rect: DeviceUintRect) -> DeviceUintRect {
+        let framebuffer_size = match self.rasterizer {
+            Rasterizer::Gl(..) => self.framebuffer_size,
+            // No frame may have been drawn at the size given to `render`.
+            Rasterizer::Software(ref rasterizer) => rasterizer.framebuffer_size(),
+        };
+        let framebuffer_rect = DeviceUintRect::new(DeviceUintPoint::zero(), framebuffer_size);
+        rect.intersection(&framebuffer_rect).unwrap_or(DeviceUintRect::zero())
+    }
+
+    /// GL window coordinates have their origin at the bottom left.
+    fn gl_read_rect(&self, rect: DeviceUintRect) -> DeviceIntRect {
+        DeviceIntRect::new(DeviceIntPoint::new(rect.origin.x as i32,
+                                               (self.framebuffer_size.height - rect.max_y()) as i32),
+                           DeviceIntSize::new(rect.size.width as i32,
+                                              rect.size.height as i32))
+    }
+
+    pub fn layers_are_bouncing_back(&self, document_id: DocumentId) -> bool {
+        match self.frames.get(&document_id) {
+            None => false,
+            Some(current_frame) => !current_frame.layers_bouncing_back.is_empty(),
+        }
+    }
+
+    /// Returns the renderer of the debug overlay, which the software
+    /// rasterizer doesn't have.
+    pub fn debug_renderer<'a>(&'a mut self) -> Option<&'a mut DebugRenderer> {
+        match self.rasterizer {
+            Rasterizer::Gl(ref mut gl) => Some(&mut gl.debug),
+            Rasterizer::Software(..) => None,
+        }
+    }
+
+    pub fn get_profiler_enabled(&mut self) -> bool {
+        match self.rasterizer {
+            Rasterizer::Gl(ref gl) => gl.enable_profiler,
+            Rasterizer::Software(..) => false,
+        }
+    }
+
+    pub fn set_profiler_enabled(&mut self, enabled: bool) {
+        if let Rasterizer::Gl(ref mut gl) = self.rasterizer {
+            gl.enable_profiler = enabled;
+        }
+    }
+}
+
+/// The pixel layouts `Renderer::read_pixels` can return.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReadPixelsFormat {
+    /// Red, green, blue and alpha bytes.
+    Rgba8,
+    /// Blue, green, red and alpha bytes, the layout of `ImageFormat::RGBA8` images.
+    Bgra8,
+    /// Red, green and blue bytes.
+    Rgb8,
+}
+
+/// A read back started with `Renderer::read_pixels_async`. It holds a GL
+/// pixel buffer object, so it must be passed to either
+/// `Renderer::resolve_read_pixels` or `Renderer::cancel_read_pixels`, or
+/// the buffer leaks.
+#[must_use]
+pub struct AsyncReadPixels {
+    rect: DeviceUintRect,
+    format: ReadPixelsFormat,
+    pbo: Option<PBOId>,
+    pixels: Vec<u8>,
+}
+
+/// Whether a rectangle that was clamped to the framebuffer has no pixels
+/// left, because it was offscreen or no frame has been drawn yet.
+fn is_empty_rect(rect: &DeviceUintRect) -> bool {
+    rect.size.width == 0 || rect.size.height == 0
+}
+
+/// Copies a rectangle of the software framebuffer, which is RGBA8 with the
+/// rows stored top down.
+fn read_software_pixels(rasterizer: &SoftwareRasterizer, rect: DeviceUintRect) -> Vec<u8> {
+    let framebuffer = rasterizer.framebuffer();
+    let stride = rasterizer.framebuffer_size().width as usize * 4;
+    let row_len = rect.size.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_len * rect.size.height as usize);
+    for y in rect.origin.y..rect.max_y() {
+        let start = y as usize * stride + rect.origin.x as usize * 4;
+        pixels.extend_from_slice(&framebuffer[start..start + row_len]);
+    }
+    pixels
+}
+
+/// GL returns the rows of RGBA8 pixels bottom up.
+fn flip_rows(pixels: Vec<u8>, rect: DeviceUintRect) -> Vec<u8> {
+    let row_len = rect.size.width as usize * 4;
+    if row_len == 0 {
+        return pixels;
+    }
+    let mut flipped = Vec::with_capacity(pixels.len());
+    for row in pixels.chunks(row_len).rev() {
+        flipped.extend_from_slice(row);
+    }
+    flipped
+}
+
+fn convert_pixels(pixels: Vec<u8>, format: ReadPixelsFormat) -> Vec<u8> {
+    if format == ReadPixelsFormat::Rgba8 {
+        return pixels;
+    }
+
+    let mut converted = Vec::with_capacity(pixels.len());
+    for p in pixels.chunks(4) {
+        match format {
+            ReadPixelsFormat::Rgba8 => unreachable!(),
+            ReadPixelsFormat::Bgra8 => converted.extend_from_slice(&[p[2], p[1], p[0], p[3]]),
+            ReadPixelsFormat::Rgb8 => converted.extend_from_slice(&[p[0], p[1], p[2]]),
+        }
+    }
+    converted
+}
+
 pub enum ExternalImageSource {
-    // TODO(gw): Work out the API for raw buffers.
-    //RawData(*const u8, usize),
+    /// A pointer to the image data and its length in bytes, laid out as
+    /// given by the format and stride of the image. The renderer uploads
+    /// it into a texture it manages, so the data only needs to stay valid
+    /// until `unlock` is called. Data that is too short for the image is
+    /// not uploaded.
+    RawData(*const u8, usize),
     NativeTexture(u32),                // Is a gl::GLuint texture handle
 }
 
 /// The data that an external client should provide about
-/// an external image. The timestamp is used to test if
+/// an external image. The generation is used to test if
 /// the renderer should upload new texture data this
 /// frame. For instance, if providing video frames, the
 /// application could call wr.render() whenever a new
 /// video frame is ready. If the callback increments
-/// the returned timestamp for a given image, the renderer
+/// the returned generation for a given image, the renderer
 /// will know to re-upload the image data to the GPU.
+/// The generation only applies to `ExternalImageSource::RawData`;
+/// native textures are sampled directly, so the renderer always
+/// sees their current contents.
 /// Note that the UV coords are supplied in texel-space!
 pub struct ExternalImage {
     pub u0: f32,
@@ -1461,6 +2018,23 @@ pub struct ExternalImage {
     pub u1: f32,
     pub v1: f32,
     pub source: ExternalImageSource,
+    pub generation: u64,
+}
+
+/// The texture and UV rect of an external image locked for the current
+/// frame.
+struct LockedExternalImage {
+    texture_id: TextureId,
+    uv0: DevicePoint,
+    uv1: DevicePoint,
+}
+
+/// The texture of an `ExternalImageSource::RawData` image, along with
+/// the generation of the data it was last uploaded from, if any was.
+#[derive(Clone, Copy)]
+struct RawExternalImage {
+    texture_id: TextureId,
+    generation: Option<u64>,
 }
 
 /// The interfaces that an application can implement to support providing
@@ -1479,6 +2053,11 @@ pub trait ExternalImageHandler {
     fn release(&mut self, key: ExternalImageId);
 }
 
+/// The options a renderer is created with.
+///
+/// The renderer creates its textures and shaders in the GL context that is
+/// current when it is created. Documents drawn into other windows need GL
+/// contexts that share them with it, see `Renderer::render`.
 #[derive(Clone, Debug)]
 pub struct RendererOptions {
     pub device_pixel_ratio: f32,
@@ -1494,4 +2073,8 @@ pub struct RendererOptions {
     pub clear_framebuffer: bool,
     pub clear_color: ColorF,
     pub render_target_debug: bool,
+    /// The maximum number of bytes of texture memory used by cached images
+    /// and glyphs. When it is exceeded, the least recently used ones are evicted.
+    /// If `None`, everything that wasn't used in the current frame is evicted.
+    pub texture_cache_budget: Option<usize>,
 }
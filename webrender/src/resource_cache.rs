@@ -3,12 +3,16 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use app_units::Au;
+use byteorder::{ByteOrder, LittleEndian};
 use device::TextureFilter;
 use fnv::FnvHasher;
 use frame::FrameId;
-use internal_types::{ExternalImageUpdateList, FontTemplate, SourceTexture, TextureUpdateList};
+use internal_types::{CacheTextureId, ExternalImageUpdateList, FontTemplate, SourceTexture};
+use internal_types::TextureUpdateList;
 use platform::font::{FontContext, RasterizedGlyph};
+use profiler::BackendProfileCounters;
 use std::cell::RefCell;
+use std::cmp;
 use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::Entry::{self, Occupied, Vacant};
 use std::fmt::Debug;
@@ -18,10 +22,12 @@ use std::mem;
 use std::sync::{Arc, Barrier};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
-use texture_cache::{TextureCache, TextureCacheItemId};
+use texture_cache::{TextureCache, TextureCacheItemId, max_texture_size};
+use texture_decoder;
+use util::{f32_to_half, half_to_f32};
 use webrender_traits::{Epoch, FontKey, GlyphKey, ImageKey, ImageFormat, ImageRendering};
-use webrender_traits::{FontRenderMode, ImageData, GlyphDimensions, WebGLContextId};
-use webrender_traits::{DevicePoint, DeviceIntSize};
+use webrender_traits::{FontRenderMode, IdNamespace, ImageData, GlyphDimensions, WebGLContextId};
+use webrender_traits::{DevicePoint, DeviceIntSize, DeviceUintPoint, DeviceUintRect, DeviceUintSize};
 use webrender_traits::ExternalImageId;
 use threadpool::ThreadPool;
 
@@ -35,6 +41,8 @@ enum GlyphCacheMsg {
     BeginFrame(FrameId, GlyphCache),
     /// Add a new font.
     AddFont(FontKey, FontTemplate),
+    /// Unload a font.
+    DeleteFont(FontKey),
     /// Request glyphs for a text run.
     RequestGlyphs(FontKey, Au, Vec<u32>, FontRenderMode),
     /// Finished requesting glyphs. Reply with new glyphs.
@@ -89,6 +97,25 @@ pub struct ImageProperties {
     pub stride: Option<u32>,
 }
 
+impl ImageProperties {
+    /// Returns the number of bytes the data of the image must hold, as laid
+    /// out by its format and stride. This is checked before reading the raw
+    /// data of external images, which only comes with a pointer and a length.
+    pub fn data_size(&self) -> usize {
+        let (block_size, block_bytes) = match self.format.compressed_block_size() {
+            Some((block_size, block_bytes)) => (block_size, block_bytes as usize),
+            None => (1, self.format.bytes_per_pixel().unwrap_or(0) as usize),
+        };
+        let blocks = |pixels: u32| ((pixels + block_size - 1) / block_size) as usize;
+        let row_len = blocks(self.width) * block_bytes;
+        let stride = self.stride.map_or(row_len, |stride| stride as usize);
+        match blocks(self.height) {
+            0 => 0,
+            rows => stride * (rows - 1) + row_len,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum State {
     Idle,
@@ -104,13 +131,25 @@ struct ImageResource {
     format: ImageFormat,
     epoch: Epoch,
     is_opaque: bool,
+    /// The brightest color value of a float image, when it is too bright to
+    /// be displayed as it is. See `tone_map_image`.
+    white_point: Option<f32>,
 }
 
 struct CachedImageInfo {
     texture_cache_id: TextureCacheItemId,
+    format: ImageFormat,
     epoch: Epoch,
 }
 
+/// An image whose contents are drawn by the renderer, rather than supplied
+/// by the API. It lives in the texture cache until the image is deleted.
+struct RenderedImage {
+    texture_cache_id: TextureCacheItemId,
+    width: u32,
+    height: u32,
+}
+
 pub struct ResourceClassCache<K,V> {
     resources: HashMap<K, V, BuildHasherDefault<FnvHasher>>,
     last_access_times: HashMap<K, FrameId, BuildHasherDefault<FnvHasher>>,
@@ -128,6 +167,10 @@ impl<K,V> ResourceClassCache<K,V> where K: Clone + Hash + Eq + Debug, V: Resourc
         self.resources.contains_key(key)
     }
 
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.resources.get_mut(key)
+    }
+
     fn get(&self, key: &K, frame: FrameId) -> &V {
         // This assert catches cases in which we accidentally request a resource that we forgot to
         // mark as needed this frame.
@@ -152,30 +195,77 @@ impl<K,V> ResourceClassCache<K,V> where K: Clone + Hash + Eq + Debug, V: Resourc
         self.last_access_times.insert((*key).clone(), frame);
     }
 
-    fn expire_old_resources(&mut self, texture_cache: &mut TextureCache, frame_id: FrameId) {
-        let mut resources_to_destroy = vec![];
+    /// Returns the resources that weren't used in the given frame, along with
+    /// the frame they were last used in.
+    fn unused_resources(&self, frame_id: FrameId) -> Vec<(FrameId, K)> {
+        let mut unused_resources = vec![];
         for (key, this_frame_id) in &self.last_access_times {
             if *this_frame_id < frame_id {
-                resources_to_destroy.push((*key).clone())
+                unused_resources.push((*this_frame_id, (*key).clone()))
             }
         }
-        for key in resources_to_destroy {
-            let resource =
-                self.resources
-                    .remove(&key)
-                    .expect("Resource was in `last_access_times` but not in `resources`!");
-            self.last_access_times.remove(&key);
-            if let Some(texture_cache_item_id) = resource.texture_cache_item_id() {
-                texture_cache.free(texture_cache_item_id)
-            }
+        unused_resources
+    }
+
+    fn remove(&mut self, key: &K, texture_cache: &mut TextureCache) {
+        let resource =
+            self.resources
+                .remove(key)
+                .expect("Resource was in `last_access_times` but not in `resources`!");
+        self.last_access_times.remove(key);
+        if let Some(texture_cache_item_id) = resource.texture_cache_item_id() {
+            texture_cache.free(texture_cache_item_id)
+        }
+    }
+
+    /// Removes all the resources whose key matches the predicate.
+    fn remove_matching<F>(&mut self, texture_cache: &mut TextureCache, predicate: F)
+                          where F: Fn(&K) -> bool {
+        let keys: Vec<K> = self.resources.keys().filter(|key| predicate(key)).cloned().collect();
+        for key in &keys {
+            self.remove(key, texture_cache);
+        }
+    }
+
+    /// Removes all the resources that weren't used in the given frame, and
+    /// returns how many there were.
+    fn expire_old_resources(&mut self, texture_cache: &mut TextureCache, frame_id: FrameId) -> usize {
+        let resources_to_destroy = self.unused_resources(frame_id);
+        for &(_, ref key) in &resources_to_destroy {
+            self.remove(key, texture_cache);
         }
+        resources_to_destroy.len()
     }
 }
 
+/// A cached resource that may get evicted when the texture cache is over budget.
+enum EvictionCandidate {
+    Image(ImageRequest),
+    Glyph(RenderedGlyphKey),
+}
+
+/// The size of the tiles that images too large for the texture cache are split into.
+const IMAGE_TILE_SIZE: u32 = 512;
+
+/// The position of a tile of an image, in tiles.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct TileOffset {
+    pub x: u32,
+    pub y: u32,
+}
+
+/// How an image that is too large for the texture cache is split into tiles.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImageTiling {
+    pub image_size: DeviceUintSize,
+    pub tile_size: u32,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 struct ImageRequest {
     key: ImageKey,
     rendering: ImageRendering,
+    tile: Option<TileOffset>,
 }
 
 struct GlyphRasterJob {
@@ -197,6 +287,7 @@ pub struct ResourceCache {
 
     font_templates: HashMap<FontKey, FontTemplate, BuildHasherDefault<FnvHasher>>,
     image_templates: HashMap<ImageKey, ImageResource, BuildHasherDefault<FnvHasher>>,
+    rendered_images: HashMap<ImageKey, RenderedImage, BuildHasherDefault<FnvHasher>>,
     enable_aa: bool,
     state: State,
     current_frame_id: FrameId,
@@ -209,6 +300,10 @@ pub struct ResourceCache {
     glyph_cache_tx: Sender<GlyphCacheMsg>,
     glyph_cache_result_queue: Receiver<GlyphCacheResultMsg>,
     pending_external_image_update_list: ExternalImageUpdateList,
+
+    // The number of resources evicted since the last call to `report_evictions`.
+    evicted_glyphs: usize,
+    evicted_images: usize,
 }
 
 impl ResourceCache {
@@ -222,6 +317,7 @@ impl ResourceCache {
             webgl_textures: HashMap::with_hasher(Default::default()),
             font_templates: HashMap::with_hasher(Default::default()),
             image_templates: HashMap::with_hasher(Default::default()),
+            rendered_images: HashMap::with_hasher(Default::default()),
             cached_glyph_dimensions: HashMap::with_hasher(Default::default()),
             texture_cache: texture_cache,
             state: State::Idle,
@@ -231,6 +327,8 @@ impl ResourceCache {
             glyph_cache_tx: glyph_cache_tx,
             glyph_cache_result_queue: glyph_cache_result_queue,
             pending_external_image_update_list: ExternalImageUpdateList::new(),
+            evicted_glyphs: 0,
+            evicted_images: 0,
         }
     }
 
@@ -243,6 +341,33 @@ impl ResourceCache {
         self.font_templates.insert(font_key, template);
     }
 
+    /// Deletes a font, along with its glyphs in the texture cache.
+    pub fn delete_font_template(&mut self, font_key: FontKey) {
+        // Unload the font from the glyph cache thread, and from the local
+        // font context used for glyph metric requests.
+        self.glyph_cache_tx
+            .send(GlyphCacheMsg::DeleteFont(font_key))
+            .unwrap();
+        FONT_CONTEXT.with(|font_context| {
+            font_context.borrow_mut().delete_font(&font_key);
+        });
+        self.font_templates.remove(&font_key);
+
+        let glyph_keys: Vec<GlyphKey> = self.cached_glyph_dimensions
+                                            .keys()
+                                            .filter(|glyph_key| glyph_key.font_key == font_key)
+                                            .cloned()
+                                            .collect();
+        for glyph_key in &glyph_keys {
+            self.cached_glyph_dimensions.remove(glyph_key);
+        }
+
+        let cached_glyphs = self.cached_glyphs.as_mut().unwrap();
+        cached_glyphs.remove_matching(&mut self.texture_cache, |glyph_key| {
+            glyph_key.key.font_key == font_key
+        });
+    }
+
     pub fn add_image_template(&mut self,
                               image_key: ImageKey,
                               width: u32,
@@ -250,12 +375,31 @@ impl ResourceCache {
                               stride: Option<u32>,
                               format: ImageFormat,
                               data: ImageData) {
+        // Compressed images are always tightly packed blocks.
+        let stride = match stride {
+            Some(stride) if format.is_compressed() => {
+                warn!("Compressed image {:?} can't have a stride, ignoring stride {}", image_key, stride);
+                None
+            }
+            stride => stride,
+        };
+        if let ImageData::Raw(ref bytes) = data {
+            if !has_all_rows(image_key, format, width, height, stride, bytes) {
+                return;
+            }
+        }
+
         let is_opaque = match data {
             ImageData::Raw(ref bytes) => is_image_opaque(format, bytes),
             ImageData::External(..) => false,           // TODO: Allow providing this through API.
         };
+        let white_point = match data {
+            ImageData::Raw(ref bytes) => image_white_point(format, width, height, stride, bytes),
+            ImageData::External(..) => None,
+        };
         let resource = ImageResource {
             is_opaque: is_opaque,
+            white_point: white_point,
             width: width,
             height: height,
             stride: stride,
@@ -290,9 +434,13 @@ impl ResourceCache {
                 Epoch(0)
             }
         };
+        if !has_all_rows(image_key, format, width, height, None, &bytes) {
+            return;
+        }
 
         let resource = ImageResource {
             is_opaque: is_image_opaque(format, &bytes),
+            white_point: image_white_point(format, width, height, None, &bytes),
             width: width,
             height: height,
             stride: None,
@@ -304,9 +452,162 @@ impl ResourceCache {
         self.image_templates.insert(image_key, resource);
     }
 
+    pub fn update_image_region(&mut self,
+                               image_key: ImageKey,
+                               dirty_rect: DeviceUintRect,
+                               bytes: Vec<u8>,
+                               stride: Option<u32>) {
+        // Bad updates are dropped rather than taking the backend down.
+        let image = match self.image_templates.get_mut(&image_key) {
+            Some(image) => image,
+            None => {
+                warn!("Update a region of a non-existent image {:?}", image_key);
+                return;
+            }
+        };
+        if dirty_rect.max_x() > image.width || dirty_rect.max_y() > image.height {
+            warn!("Update region {:?} is outside of image {:?}", dirty_rect, image_key);
+            return;
+        }
+        if image.format.is_compressed() {
+            warn!("Compressed image {:?} can only be updated as a whole", image_key);
+            return;
+        }
+
+        let bpp = image.format.bytes_per_pixel().unwrap() as usize;
+        let row_len = dirty_rect.size.width as usize * bpp;
+        let src_stride = stride.map_or(row_len, |stride| stride as usize);
+        let dst_stride = image.stride.map_or(image.width as usize * bpp, |stride| stride as usize);
+        let required_len = match dirty_rect.size.height {
+            0 => 0,
+            height => src_stride * (height as usize - 1) + row_len,
+        };
+        if src_stride < row_len || bytes.len() < required_len {
+            warn!("Update of image {:?} has {} bytes with stride {}, {} are needed",
+                  image_key, bytes.len(), src_stride, required_len);
+            return;
+        }
+
+        let (format, width, height) = (image.format, image.width as usize, image.height as usize);
+        let was_opaque = image.is_opaque;
+        let was_white_point = image.white_point;
+        match image.data {
+            ImageData::External(id) => {
+                warn!("Update an external image with buffer, id={} image_key={:?}", id.0, image_key);
+                return;
+            }
+            ImageData::Raw(ref mut data) => {
+                // Keep the template up to date, for when the whole image needs
+                // to be uploaded again.
+                let data = Arc::make_mut(data);
+                for row in 0..dirty_rect.size.height as usize {
+                    let src = row * src_stride;
+                    let dst = (dirty_rect.origin.y as usize + row) * dst_stride +
+                              dirty_rect.origin.x as usize * bpp;
+                    data[dst..dst + row_len].copy_from_slice(&bytes[src..src + row_len]);
+                }
+
+                // Translucent new pixels make the image translucent. Opaque
+                // ones can only make it opaque again if they replaced all the
+                // translucent ones, which needs the whole image checking.
+                let region_is_opaque = (0..dirty_rect.size.height as usize).all(|row| {
+                    let src = row * src_stride;
+                    is_image_opaque(format, &bytes[src..src + row_len])
+                });
+                image.is_opaque = region_is_opaque && (was_opaque || (0..height).all(|row| {
+                    let start = row * dst_stride;
+                    is_image_opaque(format, &data[start..start + width * bpp])
+                }));
+                image.white_point = image_white_point(format,
+                                                      width as u32,
+                                                      height as u32,
+                                                      Some(dst_stride as u32),
+                                                      data);
+            }
+        }
+
+        let Epoch(current_epoch) = image.epoch;
+        let next_epoch = Epoch(current_epoch + 1);
+
+        // Cached copies that were up to date only need the region uploading.
+        // Any others get the whole image when they are next requested, as do
+        // the copies that aren't uploaded as they are: shrunk copies of images
+        // too large for the texture cache, and converted or tone mapped ones.
+        let bytes = Arc::new(bytes);
+        let can_update_region = fits_texture_cache(image.width, image.height) &&
+                                self.texture_cache.supports_format(format) &&
+                                image.white_point.is_none() &&
+                                was_white_point.is_none();
+        for rendering in &[ImageRendering::Auto,
+                           ImageRendering::CrispEdges,
+                           ImageRendering::Pixelated,
+                           ImageRendering::Mipmapped] {
+            let request = ImageRequest {
+                key: image_key,
+                rendering: *rendering,
+                tile: None,
+            };
+            if let Some(cached_image) = self.cached_images.get_mut(&request) {
+                if can_update_region && cached_image.epoch == image.epoch {
+                    self.texture_cache.update_region(cached_image.texture_cache_id,
+                                                     dirty_rect,
+                                                     stride,
+                                                     bytes.clone());
+                    cached_image.epoch = next_epoch;
+                }
+            }
+        }
+
+        image.epoch = next_epoch;
+    }
+
+    /// Registers `image_key` as an image rendered into a texture cache render
+    /// target of the given size, and returns the texture to draw it into.
+    /// Returns `None` if the key is already used by an image template.
+    ///
+    /// Rendering again into an image of the same size reuses its texture.
+    pub fn add_rendered_image(&mut self,
+                              image_key: ImageKey,
+                              width: u32,
+                              height: u32) -> Option<CacheTextureId> {
+        if self.image_templates.contains_key(&image_key) {
+            warn!("Can't render into image {:?}, it already has an image template", image_key);
+            return None;
+        }
+
+        if let Some(image) = self.rendered_images.remove(&image_key) {
+            if image.width == width && image.height == height {
+                let texture_id = self.texture_cache.get(image.texture_cache_id).texture_id;
+                self.rendered_images.insert(image_key, image);
+                return Some(texture_id);
+            }
+            self.texture_cache.free(image.texture_cache_id);
+        }
+
+        let image_id = self.texture_cache.new_item_id();
+        let texture_id = self.texture_cache.allocate_render_target(image_id, width, height);
+        self.rendered_images.insert(image_key, RenderedImage {
+            texture_cache_id: image_id,
+            width: width,
+            height: height,
+        });
+
+        Some(texture_id)
+    }
+
     pub fn delete_image_template(&mut self, image_key: ImageKey) {
+        if let Some(image) = self.rendered_images.remove(&image_key) {
+            self.texture_cache.free(image.texture_cache_id);
+            return;
+        }
+
         let value = self.image_templates.remove(&image_key);
 
+        // The cached copies can't be requested anymore, so they are freed
+        // now rather than when they expire, which only happens without a
+        // budget or when over it.
+        self.cached_images.remove_matching(&mut self.texture_cache, |request| request.key == image_key);
+
         // If the key is associated to an external image, pass the external id to renderer for cleanup.
         if let Some(image) = value {
             match image.data {
@@ -322,6 +623,18 @@ impl ResourceCache {
         println!("Delete the non-exist key:{:?}", image_key);
     }
 
+    /// Returns true if the image was added and not deleted since, either as a
+    /// template or by rendering a pipeline into it.
+    pub fn has_image(&self, image_key: ImageKey) -> bool {
+        self.image_templates.contains_key(&image_key) ||
+            self.rendered_images.contains_key(&image_key)
+    }
+
+    /// Returns true if the font was added and not deleted since.
+    pub fn has_font(&self, font_key: FontKey) -> bool {
+        self.font_templates.contains_key(&font_key)
+    }
+
     pub fn add_webgl_texture(&mut self, id: WebGLContextId, texture_id: SourceTexture, size: DeviceIntSize) {
         self.webgl_textures.insert(id, WebGLTexture {
             id: texture_id,
@@ -329,6 +642,10 @@ impl ResourceCache {
         });
     }
 
+    pub fn delete_webgl_texture(&mut self, id: WebGLContextId) {
+        self.webgl_textures.remove(&id);
+    }
+
     pub fn update_webgl_texture(&mut self, id: WebGLContextId, texture_id: SourceTexture, size: DeviceIntSize) {
         let webgl_texture = self.webgl_textures.get_mut(&id).unwrap();
 
@@ -337,13 +654,72 @@ impl ResourceCache {
         webgl_texture.size = size;
     }
 
+    /// Deletes all the fonts and images of a namespace, along with the glyphs
+    /// and images they have in the texture cache.
+    ///
+    /// The display lists of other namespaces may still refer to them, so the
+    /// scenes have to be built again, which leaves out what is missing.
+    pub fn clear_namespace(&mut self, namespace: IdNamespace) {
+        let image_keys: Vec<ImageKey> = self.image_templates
+                                            .keys()
+                                            .chain(self.rendered_images.keys())
+                                            .filter(|image_key| image_key.namespace() == namespace)
+                                            .cloned()
+                                            .collect();
+        for image_key in image_keys {
+            self.delete_image_template(image_key);
+        }
+        self.cached_images.remove_matching(&mut self.texture_cache, |request| {
+            request.key.namespace() == namespace
+        });
+
+        let font_keys: Vec<FontKey> = self.font_templates
+                                          .keys()
+                                          .filter(|font_key| font_key.namespace() == namespace)
+                                          .cloned()
+                                          .collect();
+        for font_key in font_keys {
+            self.delete_font_template(font_key);
+        }
+    }
+
+    /// Returns how the image is split into tiles, if it is too large to fit in
+    /// the texture cache. Each tile is then requested on its own.
+    ///
+    /// This is decided when the scene is built. The render backend builds the
+    /// scenes again when it changes, but the image may still be requested as a
+    /// whole, e.g. as a mask or a plane of a YUV image, which are drawn from a
+    /// single texture. It is then cached at a smaller size that fits.
+    pub fn get_image_tiling(&self, image_key: ImageKey) -> Option<ImageTiling> {
+        let image = match self.image_templates.get(&image_key) {
+            Some(image) => image,
+            None => return None,
+        };
+
+        match image.data {
+            ImageData::External(..) => return None,
+            ImageData::Raw(..) => {}
+        }
+
+        if fits_texture_cache(image.width, image.height) {
+            return None;
+        }
+
+        Some(ImageTiling {
+            image_size: DeviceUintSize::new(image.width, image.height),
+            tile_size: IMAGE_TILE_SIZE,
+        })
+    }
+
     pub fn request_image(&mut self,
                          key: ImageKey,
-                         rendering: ImageRendering) {
+                         rendering: ImageRendering,
+                         tile: Option<TileOffset>) {
         debug_assert!(self.state == State::AddResources);
         self.pending_image_requests.push(ImageRequest {
             key: key,
             rendering: rendering,
+            tile: tile,
         });
     }
 
@@ -410,7 +786,10 @@ impl ResourceCache {
             Occupied(entry) => *entry.get(),
             Vacant(entry) => {
                 let mut dimensions = None;
-                let font_template = &self.font_templates[&glyph_key.font_key];
+                let font_template = match self.font_templates.get(&glyph_key.font_key) {
+                    Some(font_template) => font_template,
+                    None => return None,
+                };
 
                 FONT_CONTEXT.with(|font_context| {
                     let mut font_context = font_context.borrow_mut();
@@ -437,24 +816,57 @@ impl ResourceCache {
     #[inline]
     pub fn get_cached_image(&self,
                             image_key: ImageKey,
-                            image_rendering: ImageRendering) -> CacheItem {
+                            image_rendering: ImageRendering,
+                            tile: Option<TileOffset>) -> CacheItem {
         debug_assert!(self.state == State::QueryResources);
-        let key = ImageRequest {
-            key: image_key,
-            rendering: image_rendering,
+        let texture_cache_id = match self.rendered_images.get(&image_key) {
+            Some(image) => image.texture_cache_id,
+            None => {
+                let key = ImageRequest {
+                    key: image_key,
+                    rendering: image_rendering,
+                    tile: tile,
+                };
+                self.cached_images.get(&key, self.current_frame_id).texture_cache_id
+            }
         };
-        let image_info = &self.cached_images.get(&key, self.current_frame_id);
-        let item = self.texture_cache.get(image_info.texture_cache_id);
+        let item = self.texture_cache.get(texture_cache_id);
+        let mut uv0 = DevicePoint::new(item.pixel_rect.top_left.x as f32,
+                                       item.pixel_rect.top_left.y as f32);
+        let mut uv1 = DevicePoint::new(item.pixel_rect.bottom_right.x as f32,
+                                       item.pixel_rect.bottom_right.y as f32);
+
+        // Tiles are cached along with the pixels around them, which are
+        // left out of the uv rect.
+        if let Some(tile) = tile {
+            let image_template = &self.image_templates[&image_key];
+            let tile_rect = image_tile_rect(image_template, tile);
+            let padded_rect = padded_tile_rect(image_template, tile);
+            uv0.x += (tile_rect.origin.x - padded_rect.origin.x) as f32;
+            uv0.y += (tile_rect.origin.y - padded_rect.origin.y) as f32;
+            uv1.x -= (padded_rect.max_x() - tile_rect.max_x()) as f32;
+            uv1.y -= (padded_rect.max_y() - tile_rect.max_y()) as f32;
+        }
+
         CacheItem {
             texture_id: SourceTexture::TextureCache(item.texture_id),
-            uv0: DevicePoint::new(item.pixel_rect.top_left.x as f32,
-                                  item.pixel_rect.top_left.y as f32),
-            uv1: DevicePoint::new(item.pixel_rect.bottom_right.x as f32,
-                                  item.pixel_rect.bottom_right.y as f32),
+            uv0: uv0,
+            uv1: uv1,
         }
     }
 
     pub fn get_image_properties(&self, image_key: ImageKey) -> ImageProperties {
+        if let Some(image) = self.rendered_images.get(&image_key) {
+            return ImageProperties {
+                format: ImageFormat::RGBA8,
+                is_opaque: false,
+                external_id: None,
+                width: image.width,
+                height: image.height,
+                stride: None,
+            };
+        }
+
         let image_template = &self.image_templates[&image_key];
 
         let external_id = match image_template.data {
@@ -483,10 +895,60 @@ impl ResourceCache {
     }
 
     pub fn expire_old_resources(&mut self, frame_id: FrameId) {
-        self.cached_images.expire_old_resources(&mut self.texture_cache, frame_id);
-
         let cached_glyphs = self.cached_glyphs.as_mut().unwrap();
-        cached_glyphs.expire_old_resources(&mut self.texture_cache, frame_id);
+
+        if !self.texture_cache.has_budget() {
+            self.evicted_images += self.cached_images.expire_old_resources(&mut self.texture_cache,
+                                                                           frame_id);
+            self.evicted_glyphs += cached_glyphs.expire_old_resources(&mut self.texture_cache,
+                                                                      frame_id);
+            return;
+        }
+
+        if !self.texture_cache.is_over_budget() {
+            return;
+        }
+
+        // Evict the resources that were used the longest time ago first,
+        // until the rest fit in the budget. Resources used in this frame
+        // are kept even if that isn't enough. The texture pages are then
+        // released when the cache is compacted.
+        let mut candidates = vec![];
+        for (last_access, request) in self.cached_images.unused_resources(frame_id) {
+            candidates.push((last_access, EvictionCandidate::Image(request)));
+        }
+        for (last_access, key) in cached_glyphs.unused_resources(frame_id) {
+            candidates.push((last_access, EvictionCandidate::Glyph(key)));
+        }
+        candidates.sort_by_key(|&(last_access, _)| last_access.0);
+
+        for (_, candidate) in candidates {
+            if !self.texture_cache.needs_eviction() {
+                break;
+            }
+            match candidate {
+                EvictionCandidate::Image(request) => {
+                    self.cached_images.remove(&request, &mut self.texture_cache);
+                    self.evicted_images += 1;
+                }
+                EvictionCandidate::Glyph(key) => {
+                    cached_glyphs.remove(&key, &mut self.texture_cache);
+                    self.evicted_glyphs += 1;
+                }
+            }
+        }
+    }
+
+    pub fn report_evictions(&mut self, profile_counters: &mut BackendProfileCounters) {
+        profile_counters.evicted_glyphs.add(self.evicted_glyphs);
+        profile_counters.evicted_images.add(self.evicted_images);
+        self.evicted_glyphs = 0;
+        self.evicted_images = 0;
+    }
+
+    /// Compacts the texture cache, see `TextureCache::compact`.
+    pub fn compact_texture_cache(&mut self) -> bool {
+        self.texture_cache.compact()
     }
 
     pub fn begin_frame(&mut self, frame_id: FrameId) {
@@ -545,51 +1007,85 @@ impl ResourceCache {
         }
 
         for request in self.pending_image_requests.drain(..) {
+            // Rendered images are already in the texture cache.
+            if self.rendered_images.contains_key(&request.key) {
+                continue;
+            }
+
             let cached_images = &mut self.cached_images;
             let image_template = &self.image_templates[&request.key];
 
             match image_template.data {
                 ImageData::External(..) => {}
                 ImageData::Raw(ref bytes) => {
+                    // A tile is cached as if it was an image of its own. The pixels
+                    // are only copied out of the template when they are uploaded.
+                    let size = cached_image_size(image_template, request.tile);
+                    let (width, height) = (size.width, size.height);
+
                     match cached_images.entry(request.clone(), self.current_frame_id) {
                         Occupied(entry) => {
-                            let image_id = entry.get().texture_cache_id;
+                            let mut image_id = entry.get().texture_cache_id;
 
                             if entry.get().epoch != image_template.epoch {
-                                // TODO: Can we avoid the clone of the bytes here?
-                                self.texture_cache.update(image_id,
-                                                          image_template.width,
-                                                          image_template.height,
-                                                          image_template.stride,
-                                                          image_template.format,
-                                                          bytes.clone());
-
-                                // Update the cached epoch
+                                let size_changed = {
+                                    let item = self.texture_cache.get(image_id);
+                                    item.requested_rect.size.width != width ||
+                                    item.requested_rect.size.height != height
+                                };
+
+                                let (format, stride, bytes) = cached_image_data(&self.texture_cache,
+                                                                                image_template,
+                                                                                bytes,
+                                                                                request.tile);
+                                if size_changed || entry.get().format != image_template.format {
+                                    // The old allocation can't hold the new image, so
+                                    // make a new one that matches it.
+                                    self.texture_cache.free(image_id);
+                                    image_id = self.texture_cache.new_item_id();
+
+                                    self.texture_cache.insert(image_id,
+                                                              width,
+                                                              height,
+                                                              stride,
+                                                              format,
+                                                              texture_filter(request.rendering),
+                                                              bytes);
+                                } else {
+                                    self.texture_cache.update(image_id,
+                                                              width,
+                                                              height,
+                                                              stride,
+                                                              format,
+                                                              bytes);
+                                }
+
+                                // Update the cached item and epoch
                                 *entry.into_mut() = CachedImageInfo {
                                     texture_cache_id: image_id,
+                                    format: image_template.format,
                                     epoch: image_template.epoch,
                                 };
                             }
                         }
                         Vacant(entry) => {
+                            let (format, stride, bytes) = cached_image_data(&self.texture_cache,
+                                                                            image_template,
+                                                                            bytes,
+                                                                            request.tile);
                             let image_id = self.texture_cache.new_item_id();
 
-                            let filter = match request.rendering {
-                                ImageRendering::Pixelated => TextureFilter::Nearest,
-                                ImageRendering::Auto | ImageRendering::CrispEdges => TextureFilter::Linear,
-                            };
-
-                            // TODO: Can we avoid the clone of the bytes here?
                             self.texture_cache.insert(image_id,
-                                                      image_template.width,
-                                                      image_template.height,
-                                                      image_template.stride,
-                                                      image_template.format,
-                                                      filter,
-                                                      bytes.clone());
+                                                      width,
+                                                      height,
+                                                      stride,
+                                                      format,
+                                                      texture_filter(request.rendering),
+                                                      bytes);
 
                             entry.insert(CachedImageInfo {
                                 texture_cache_id: image_id,
+                                format: image_template.format,
                                 epoch: image_template.epoch,
                             });
                         }
@@ -635,6 +1131,312 @@ impl Resource for CachedImageInfo {
     }
 }
 
+/// Whether an image of this size fits in the texture cache, whose allocations
+/// have a border of one pixel on each side.
+pub fn fits_texture_cache(width: u32, height: u32) -> bool {
+    let max_size = max_texture_size();
+    width + 2 < max_size && height + 2 < max_size
+}
+
+/// Returns the size of the blocks an image is stored in, and the number of bytes
+/// per block. A pixel of an uncompressed image is treated as a 1x1 block.
+fn image_block_size(image: &ImageResource) -> (u32, usize) {
+    match image.format.compressed_block_size() {
+        Some((block_size, block_bytes)) => (block_size, block_bytes as usize),
+        None => (1, image.format.bytes_per_pixel().unwrap() as usize),
+    }
+}
+
+/// Returns the part of an image covered by one of its tiles, see `get_image_tiling`.
+fn image_tile_rect(image: &ImageResource, tile: TileOffset) -> DeviceUintRect {
+    let origin = DeviceUintPoint::new(tile.x * IMAGE_TILE_SIZE, tile.y * IMAGE_TILE_SIZE);
+    debug_assert!(origin.x < image.width && origin.y < image.height);
+    DeviceUintRect::new(origin,
+                        DeviceUintSize::new(cmp::min(IMAGE_TILE_SIZE, image.width - origin.x),
+                                            cmp::min(IMAGE_TILE_SIZE, image.height - origin.y)))
+}
+
+/// Returns the part of an image that is cached for one of its tiles: the tile,
+/// along with a block of pixels of the tiles next to it. Filtering the edges of
+/// the tile then blends in its neighbours as it would with the whole image,
+/// instead of leaving a seam between the tiles.
+fn padded_tile_rect(image: &ImageResource, tile: TileOffset) -> DeviceUintRect {
+    let (block_size, _) = image_block_size(image);
+    let tile_rect = image_tile_rect(image, tile);
+    let x0 = tile_rect.origin.x.saturating_sub(block_size);
+    let y0 = tile_rect.origin.y.saturating_sub(block_size);
+    let x1 = cmp::min(tile_rect.max_x() + block_size, image.width);
+    let y1 = cmp::min(tile_rect.max_y() + block_size, image.height);
+    DeviceUintRect::new(DeviceUintPoint::new(x0, y0), DeviceUintSize::new(x1 - x0, y1 - y0))
+}
+
+/// Returns the number of pixels (or blocks, for compressed images) that are
+/// skipped between each one that is kept, when an image that is too large for
+/// the texture cache is requested as a whole.
+fn shrink_step(image: &ImageResource) -> u32 {
+    let (block_size, _) = image_block_size(image);
+    let max_blocks = (max_texture_size() - 3) / block_size;
+    let blocks = |pixels: u32| (pixels + block_size - 1) / block_size;
+    cmp::max((blocks(image.width) + max_blocks - 1) / max_blocks,
+             (blocks(image.height) + max_blocks - 1) / max_blocks)
+}
+
+/// Returns the size an image request is cached at: the padded tile for a tile,
+/// the image itself if it fits in the texture cache, or else a shrunk copy of it.
+fn cached_image_size(image: &ImageResource, tile: Option<TileOffset>) -> DeviceUintSize {
+    if let Some(tile) = tile {
+        return padded_tile_rect(image, tile).size;
+    }
+
+    if fits_texture_cache(image.width, image.height) {
+        return DeviceUintSize::new(image.width, image.height);
+    }
+
+    let (block_size, _) = image_block_size(image);
+    let step = shrink_step(image);
+    let shrunk_blocks = |pixels: u32| {
+        let blocks = (pixels + block_size - 1) / block_size;
+        (blocks + step - 1) / step
+    };
+    DeviceUintSize::new(cmp::min(shrunk_blocks(image.width) * block_size, image.width),
+                        cmp::min(shrunk_blocks(image.height) * block_size, image.height))
+}
+
+/// Returns the format, stride and pixels that an image request is uploaded
+/// with, of the size given by `cached_image_size`.
+///
+/// Compressed images in formats that the texture cache can't hold keep their
+/// format in the template, and are only decoded here.
+fn cached_image_data(texture_cache: &TextureCache,
+                     image: &ImageResource,
+                     bytes: &Arc<Vec<u8>>,
+                     tile: Option<TileOffset>) -> (ImageFormat, Option<u32>, Arc<Vec<u8>>) {
+    let (stride, bytes) = if let Some(tile) = tile {
+        let rect = padded_tile_rect(image, tile);
+        (None, Arc::new(copy_image_rect(image, bytes, rect)))
+    } else if fits_texture_cache(image.width, image.height) {
+        (image.stride, bytes.clone())
+    } else {
+        warn!("Image of {}x{} is too large for the texture cache and can't be split into tiles \
+               where it is used, drawing it at a lower resolution.", image.width, image.height);
+        (None, Arc::new(shrink_image(image, bytes)))
+    };
+
+    let size = cached_image_size(image, tile);
+    if let Some(white_point) = image.white_point {
+        let pixels = tone_map_image(image.format, size.width, size.height, stride, &bytes, white_point);
+        return (image.format, None, Arc::new(pixels));
+    }
+
+    if texture_cache.supports_format(image.format) {
+        return (image.format, stride, bytes);
+    }
+
+    if !image.format.is_compressed() {
+        let pixels = expand_to_rgba8(image.format, size.width, size.height, stride, &bytes);
+        return (ImageFormat::RGBA8, None, Arc::new(pixels));
+    }
+
+    let pixels = match texture_decoder::decode_image(image.format, size.width, size.height, &bytes) {
+        Ok(pixels) => pixels,
+        Err(error) => {
+            warn!("Can't decode {:?} image: {}, drawing it transparent.", image.format, error);
+            vec![0; (size.width * size.height * 4) as usize]
+        }
+    };
+    (ImageFormat::RGBA8, None, Arc::new(pixels))
+}
+
+/// Reads a pixel of a float image.
+fn read_float_pixel(format: ImageFormat, pixel: &[u8]) -> [f32; 4] {
+    let mut color = [0.0; 4];
+    for (i, channel) in color.iter_mut().enumerate() {
+        *channel = match format {
+            ImageFormat::RGBA16F => half_to_f32(LittleEndian::read_u16(&pixel[i * 2..])),
+            _ => LittleEndian::read_f32(&pixel[i * 4..]),
+        };
+    }
+    color
+}
+
+/// Whether the pixels of an uncompressed image cover all of its rows, so they
+/// can be read a row at a time. Images that don't are dropped with a warning.
+fn has_all_rows(image_key: ImageKey,
+                format: ImageFormat,
+                width: u32,
+                height: u32,
+                stride: Option<u32>,
+                bytes: &[u8]) -> bool {
+    let bpp = match format.bytes_per_pixel() {
+        Some(bpp) => bpp as usize,
+        None => return true,
+    };
+    let row_len = width as usize * bpp;
+    let stride = stride.map_or(row_len, |stride| stride as usize);
+    let required_len = match height {
+        0 => 0,
+        height => stride * (height as usize - 1) + row_len,
+    };
+    if stride < row_len || bytes.len() < required_len {
+        warn!("Image {:?} has {} bytes with stride {}, {} are needed",
+              image_key, bytes.len(), stride, required_len);
+        return false;
+    }
+    true
+}
+
+/// Returns the brightest color value of a float image, if it is more than 1.
+fn image_white_point(format: ImageFormat,
+                     width: u32,
+                     height: u32,
+                     stride: Option<u32>,
+                     bytes: &[u8]) -> Option<f32> {
+    match format {
+        ImageFormat::RGBA16F | ImageFormat::RGBAF32 => {}
+        _ => return None,
+    }
+
+    let bpp = format.bytes_per_pixel().unwrap() as usize;
+    let row_len = width as usize * bpp;
+    let stride = stride.map_or(row_len, |stride| stride as usize);
+    let mut white_point = 1.0f32;
+    for row in 0..height as usize {
+        for pixel in bytes[row * stride..row * stride + row_len].chunks(bpp) {
+            let color = read_float_pixel(format, pixel);
+            white_point = white_point.max(color[0]).max(color[1]).max(color[2]);
+        }
+    }
+
+    if white_point > 1.0 {
+        Some(white_point)
+    } else {
+        None
+    }
+}
+
+/// Maps the colors of a float image into the range that can be displayed, with
+/// the extended Reinhard operator. It compresses the bright colors more than the
+/// dark ones, and brings `white_point` down to 1. Returns tightly packed pixels
+/// of the same format.
+fn tone_map_image(format: ImageFormat,
+                  width: u32,
+                  height: u32,
+                  stride: Option<u32>,
+                  bytes: &[u8],
+                  white_point: f32) -> Vec<u8> {
+    let bpp = format.bytes_per_pixel().unwrap() as usize;
+    let row_len = width as usize * bpp;
+    let stride = stride.map_or(row_len, |stride| stride as usize);
+    let map = |value: f32| {
+        let value = value.max(0.0);
+        value * (1.0 + value / (white_point * white_point)) / (1.0 + value)
+    };
+
+    let mut pixels = Vec::with_capacity(row_len * height as usize);
+    for row in 0..height as usize {
+        for pixel in bytes[row * stride..row * stride + row_len].chunks(bpp) {
+            let color = read_float_pixel(format, pixel);
+            let mapped = [map(color[0]), map(color[1]), map(color[2]), color[3].max(0.0).min(1.0)];
+            for &channel in &mapped {
+                match format {
+                    ImageFormat::RGBA16F => {
+                        let mut half = [0; 2];
+                        LittleEndian::write_u16(&mut half, f32_to_half(channel));
+                        pixels.extend_from_slice(&half);
+                    }
+                    _ => {
+                        let mut float = [0; 4];
+                        LittleEndian::write_f32(&mut float, channel);
+                        pixels.extend_from_slice(&float);
+                    }
+                }
+            }
+        }
+    }
+    pixels
+}
+
+/// Expands an image with fewer channels than RGBA into RGBA8 pixels, in the
+/// BGRA byte order, for devices that can't swizzle its texture. The colors
+/// match the swizzles in `Device::set_texture_parameters`.
+fn expand_to_rgba8(format: ImageFormat,
+                   width: u32,
+                   height: u32,
+                   stride: Option<u32>,
+                   bytes: &[u8]) -> Vec<u8> {
+    let bpp = format.bytes_per_pixel().unwrap() as usize;
+    let row_len = width as usize * bpp;
+    let stride = stride.map_or(row_len, |stride| stride as usize);
+
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for row in 0..height as usize {
+        for pixel in bytes[row * stride..row * stride + row_len].chunks(bpp) {
+            let bgra = match format {
+                ImageFormat::R16 => {
+                    let value = (LittleEndian::read_u16(pixel) >> 8) as u8;
+                    [value, value, value, 255]
+                }
+                ImageFormat::RG8 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+                _ => unreachable!("{:?} images don't need expanding", format),
+            };
+            pixels.extend_from_slice(&bgra);
+        }
+    }
+    pixels
+}
+
+/// Copies the pixels of a part of an image into a tightly packed buffer.
+///
+/// Compressed images are copied a row of blocks at a time, so the rect must
+/// start on a block boundary, which is the case for tiles.
+fn copy_image_rect(image: &ImageResource, bytes: &[u8], rect: DeviceUintRect) -> Vec<u8> {
+    let (block_size, block_bytes) = image_block_size(image);
+    debug_assert!(rect.origin.x % block_size == 0 && rect.origin.y % block_size == 0);
+    let blocks = |pixels: u32| ((pixels + block_size - 1) / block_size) as usize;
+
+    let row_len = blocks(rect.size.width) * block_bytes;
+    let stride = image.stride.map_or(blocks(image.width) * block_bytes, |stride| stride as usize);
+    let origin_x = blocks(rect.origin.x);
+    let origin_y = blocks(rect.origin.y);
+
+    let mut rect_bytes = Vec::with_capacity(row_len * blocks(rect.size.height));
+    for row in 0..blocks(rect.size.height) {
+        let start = (origin_y + row) * stride + origin_x * block_bytes;
+        rect_bytes.extend_from_slice(&bytes[start..start + row_len]);
+    }
+    rect_bytes
+}
+
+/// Copies every `shrink_step`-th pixel (or block) of an image into a tightly
+/// packed buffer, to draw it where it can't be split into tiles.
+fn shrink_image(image: &ImageResource, bytes: &[u8]) -> Vec<u8> {
+    let (block_size, block_bytes) = image_block_size(image);
+    let step = shrink_step(image) as usize;
+    let blocks = |pixels: u32| ((pixels + block_size - 1) / block_size) as usize;
+
+    let stride = image.stride.map_or(blocks(image.width) * block_bytes, |stride| stride as usize);
+    let shrunk_width = (blocks(image.width) + step - 1) / step;
+    let shrunk_height = (blocks(image.height) + step - 1) / step;
+
+    let mut shrunk_bytes = Vec::with_capacity(shrunk_width * shrunk_height * block_bytes);
+    for row in 0..shrunk_height {
+        let row_start = row * step * stride;
+        for column in 0..shrunk_width {
+            let start = row_start + column * step * block_bytes;
+            shrunk_bytes.extend_from_slice(&bytes[start..start + block_bytes]);
+        }
+    }
+    shrunk_bytes
+}
+
+fn texture_filter(rendering: ImageRendering) -> TextureFilter {
+    match rendering {
+        ImageRendering::Pixelated => TextureFilter::Nearest,
+        ImageRendering::Auto | ImageRendering::CrispEdges => TextureFilter::Linear,
+        ImageRendering::Mipmapped => TextureFilter::Trilinear,
+    }
+}
+
 // TODO(gw): If this ever shows up in profiles, consider calculating
 // this lazily on demand, possibly via the resource cache thread.
 // It can probably be made a lot faster with SIMD too!
@@ -653,9 +1455,25 @@ fn is_image_opaque(format: ImageFormat, bytes: &[u8]) -> bool {
             }
             is_opaque
         }
-        ImageFormat::RGB8 => true,
+        ImageFormat::RG8 => {
+            bytes.chunks(2).all(|pixel| pixel[1] == 255)
+        }
+        ImageFormat::RGBA16F => {
+            bytes.chunks(8).all(|pixel| half_to_f32(LittleEndian::read_u16(&pixel[6..8])) >= 1.0)
+        }
+        ImageFormat::RGBAF32 => {
+            bytes.chunks(16).all(|pixel| LittleEndian::read_f32(&pixel[12..16]) >= 1.0)
+        }
+        ImageFormat::RGB8 | ImageFormat::R16 => true,
         ImageFormat::A8 => false,
-        ImageFormat::Invalid | ImageFormat::RGBAF32 => unreachable!(),
+        ImageFormat::ETC2RGB8 => true,
+        // Finding out would mean decoding the blocks.
+        ImageFormat::BC1 |
+        ImageFormat::BC2 |
+        ImageFormat::BC3 |
+        ImageFormat::ETC2RGBA8 |
+        ImageFormat::ASTC4x4 => false,
+        ImageFormat::Invalid => unreachable!(),
     }
 }
 
@@ -719,6 +1537,21 @@ fn spawn_glyph_cache_thread() -> (Sender<GlyphCacheMsg>, Receiver<GlyphCacheResu
                         });
                     }
                 }
+                GlyphCacheMsg::DeleteFont(font_key) => {
+                    // Unload the font from the font context in each worker thread,
+                    // using a barrier in the same way as when adding it.
+                    let barrier = Arc::new(Barrier::new(worker_count));
+                    for _ in 0..worker_count {
+                        let barrier = barrier.clone();
+                        thread_pool.execute(move || {
+                            FONT_CONTEXT.with(|font_context| {
+                                font_context.borrow_mut().delete_font(&font_key);
+                            });
+
+                            barrier.wait();
+                        });
+                    }
+                }
                 GlyphCacheMsg::RequestGlyphs(key, size, indices, render_mode) => {
                     // Request some glyphs for a text run.
                     // For any glyph that isn't currently in the cache,
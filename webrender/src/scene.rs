@@ -20,10 +20,11 @@ pub struct ScenePipeline {
 }
 
 /// A complete representation of the layout bundling visible pipelines together.
+///
+/// The scene is shared by all the documents, which each show one of its pipelines
+/// as their root.
 pub struct Scene {
-    pub root_pipeline_id: Option<PipelineId>,
     pub pipeline_map: HashMap<PipelineId, ScenePipeline, BuildHasherDefault<FnvHasher>>,
-    pub pipeline_sizes: HashMap<PipelineId, LayerSize>,
     pub pipeline_auxiliary_lists: AuxiliaryListsMap,
     pub display_lists: HashMap<PipelineId, Vec<DisplayItem>, BuildHasherDefault<FnvHasher>>,
 }
@@ -31,18 +32,12 @@ pub struct Scene {
 impl Scene {
     pub fn new() -> Scene {
         Scene {
-            root_pipeline_id: None,
-            pipeline_sizes: HashMap::new(),
             pipeline_map: HashMap::with_hasher(Default::default()),
             pipeline_auxiliary_lists: HashMap::with_hasher(Default::default()),
             display_lists: HashMap::with_hasher(Default::default()),
         }
     }
 
-    pub fn set_root_pipeline_id(&mut self, pipeline_id: PipelineId) {
-        self.root_pipeline_id = Some(pipeline_id);
-    }
-
     pub fn set_root_display_list(&mut self,
                                  pipeline_id: PipelineId,
                                  epoch: Epoch,
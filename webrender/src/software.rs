@@ -0,0 +1,2243 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A CPU implementation of the drawing step of the renderer.
+//!
+//! The software rasterizer consumes the same `tiling::Frame` that is built
+//! for the GL renderer. It walks the render passes and their targets in the
+//! same order as `Renderer::draw_tile_frame`, and for every pixel covered by
+//! an instance it evaluates the equivalent of the cache, clip and primitive
+//! shaders in `res/`. Intermediate render targets are kept in memory and
+//! sampled by the following pass, just like `sCache` on the GPU.
+//!
+//! This is much slower than the GL path, but it doesn't need a GPU to draw,
+//! which makes it useful for headless reftests and as a reference when
+//! debugging the shaders.
+
+use byteorder::{ByteOrder, LittleEndian};
+use device::TextureFilter;
+use internal_types::{CacheTextureId, ExternalImageUpdateList, RendererFrame, SourceTexture};
+use internal_types::{TextureUpdateList, TextureUpdateOp};
+use prim_store::{PrimitiveGeometry, TexelRect};
+use renderer::{BlendMode, ExternalImageHandler, ExternalImageSource};
+use std::collections::HashMap;
+use std::f32;
+use std::i32;
+use std::mem;
+use std::slice;
+use tiling::{BlurCommand, BlurDirection, CacheClipInstance, Frame, PackedStackingContext};
+use tiling::{PrimitiveBatch, PrimitiveBatchData, PrimitiveInstance, RenderTarget};
+use tiling::TransformedRectKind;
+use util::half_to_f32;
+use webrender_traits::{ColorF, DevicePoint, DeviceUintRect, DeviceUintSize, ExternalImageId};
+use webrender_traits::{ImageFormat, LayerRect, WorldPoint4D};
+use webrender_traits::YuvFormat;
+
+type Rgba = [f32; 4];
+
+/// The color render targets are cleared to, see `Renderer::draw_target`.
+const TARGET_CLEAR_COLOR: Rgba = [1.0, 0.0, 0.0, 0.0];
+
+/// Must match `OPAQUE_TASK_INDEX` in tiling.rs.
+const OPAQUE_TASK_INDEX: i32 = i32::MAX;
+
+/// Must match `ROW_SCALE` in cs_idct.fs.glsl.
+const IDCT_ROW_SCALE: f32 = 16.0;
+
+/// The per-pixel blend equations the renderer sets up with `Device::set_blend_mode_*`.
+#[derive(Debug, Copy, Clone)]
+enum PixelBlend {
+    Replace,
+    Alpha,
+    Subpixel(ColorF),
+    Multiply,
+}
+
+impl PixelBlend {
+    fn from_blend_mode(mode: BlendMode) -> PixelBlend {
+        match mode {
+            BlendMode::None => PixelBlend::Replace,
+            BlendMode::Alpha => PixelBlend::Alpha,
+            BlendMode::Subpixel(color) => PixelBlend::Subpixel(color),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum DepthMode {
+    Ignore,
+    Test,
+    TestAndWrite,
+}
+
+/// An axis aligned rectangle stored as its edges.
+#[derive(Debug, Copy, Clone)]
+struct Bounds {
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl Bounds {
+    fn new(x: f32, y: f32, width: f32, height: f32) -> Bounds {
+        Bounds {
+            x0: x,
+            y0: y,
+            x1: x + width,
+            y1: y + height,
+        }
+    }
+
+    fn from_rect(rect: &LayerRect) -> Bounds {
+        Bounds::new(rect.origin.x, rect.origin.y, rect.size.width, rect.size.height)
+    }
+
+    fn width(&self) -> f32 {
+        self.x1 - self.x0
+    }
+
+    fn height(&self) -> f32 {
+        self.y1 - self.y0
+    }
+
+    /// Rounds the edges to device pixels, like `write_vertex` does.
+    fn snap(&self, device_pixel_ratio: f32) -> Bounds {
+        let snap = |v: f32| (0.5 + v * device_pixel_ratio).floor() / device_pixel_ratio;
+        Bounds {
+            x0: snap(self.x0),
+            y0: snap(self.y0),
+            x1: snap(self.x1),
+            y1: snap(self.y1),
+        }
+    }
+
+    fn intersect(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.x0 >= self.x1 || self.y0 >= self.y1
+    }
+
+    fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x0 && x <= self.x1 && y >= self.y0 && y <= self.y1
+    }
+}
+
+/// Returns the range of pixels whose centers are inside `[start, end)`,
+/// clamped to `[0, limit)`.
+fn pixel_range(start: f32, end: f32, limit: i32) -> (i32, i32) {
+    let first = (start - 0.5).ceil().max(0.0) as i32;
+    let last = ((end - 0.5).ceil() as i32).min(limit);
+    (first, last)
+}
+
+fn mix(a: f32, b: f32, f: f32) -> f32 {
+    a + (b - a) * f
+}
+
+fn mix_color(a: &Rgba, b: &Rgba, f: f32) -> Rgba {
+    [mix(a[0], b[0], f), mix(a[1], b[1], f), mix(a[2], b[2], f), mix(a[3], b[3], f)]
+}
+
+fn clamp01(v: f32) -> f32 {
+    v.max(0.0).min(1.0)
+}
+
+fn bilinear<F>(u: f32, v: f32, fetch: F) -> Rgba where F: Fn(i32, i32) -> Rgba {
+    let x = u - 0.5;
+    let y = v - 0.5;
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+    let (x0, y0) = (x0 as i32, y0 as i32);
+    let top = mix_color(&fetch(x0, y0), &fetch(x0 + 1, y0), fx);
+    let bottom = mix_color(&fetch(x0, y0 + 1), &fetch(x0 + 1, y0 + 1), fx);
+    mix_color(&top, &bottom, fy)
+}
+
+fn bytes_per_pixel(format: ImageFormat) -> u32 {
+    match format {
+        ImageFormat::A8 => 1,
+        ImageFormat::RGB8 => 3,
+        ImageFormat::RGBA8 => 4,
+        ImageFormat::RGBAF32 => 16,
+        ImageFormat::R16 => 2,
+        ImageFormat::RG8 => 2,
+        ImageFormat::RGBA16F => 8,
+        // Compressed images are decoded before they reach the texture cache.
+        ImageFormat::BC1 |
+        ImageFormat::BC2 |
+        ImageFormat::BC3 |
+        ImageFormat::ETC2RGB8 |
+        ImageFormat::ETC2RGBA8 |
+        ImageFormat::ASTC4x4 |
+        ImageFormat::Invalid => unreachable!(),
+    }
+}
+
+/// A texture cache texture, stored in the same byte layout as the uploads.
+struct CpuTexture {
+    width: u32,
+    height: u32,
+    format: ImageFormat,
+    filter: TextureFilter,
+    pixels: Vec<u8>,
+}
+
+impl CpuTexture {
+    fn new(width: u32,
+           height: u32,
+           format: ImageFormat,
+           filter: TextureFilter,
+           bytes: Option<&[u8]>) -> CpuTexture {
+        let size = (width * height * bytes_per_pixel(format)) as usize;
+        let pixels = match bytes {
+            Some(bytes) => bytes[..size].to_vec(),
+            None => vec![0; size],
+        };
+
+        CpuTexture {
+            width: width,
+            height: height,
+            format: format,
+            filter: filter,
+            pixels: pixels,
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32, format: ImageFormat, filter: TextureFilter) {
+        debug_assert!(format == self.format);
+        let bpp = bytes_per_pixel(format) as usize;
+        let mut pixels = vec![0; width as usize * height as usize * bpp];
+        let row_len = width.min(self.width) as usize * bpp;
+        for row in 0..height.min(self.height) as usize {
+            let src = row * self.width as usize * bpp;
+            let dst = row * width as usize * bpp;
+            pixels[dst..dst + row_len].copy_from_slice(&self.pixels[src..src + row_len]);
+        }
+
+        self.width = width;
+        self.height = height;
+        self.filter = filter;
+        self.pixels = pixels;
+    }
+
+    fn update(&mut self,
+              x0: u32,
+              y0: u32,
+              width: u32,
+              height: u32,
+              stride: Option<u32>,
+              data: &[u8]) {
+        let bpp = bytes_per_pixel(self.format) as usize;
+        let row_len = width as usize * bpp;
+        let src_stride = stride.map_or(row_len, |stride| stride as usize);
+        for row in 0..height as usize {
+            let src = row * src_stride;
+            let dst = ((y0 as usize + row) * self.width as usize + x0 as usize) * bpp;
+            self.pixels[dst..dst + row_len].copy_from_slice(&data[src..src + row_len]);
+        }
+    }
+
+    fn read(&self, rect: DeviceUintRect) -> Vec<u8> {
+        let bpp = bytes_per_pixel(self.format) as usize;
+        let row_len = rect.size.width as usize * bpp;
+        let mut bytes = Vec::with_capacity(row_len * rect.size.height as usize);
+        for row in 0..rect.size.height as usize {
+            let src = ((rect.origin.y as usize + row) * self.width as usize +
+                       rect.origin.x as usize) * bpp;
+            bytes.extend_from_slice(&self.pixels[src..src + row_len]);
+        }
+        bytes
+    }
+
+    fn texel(&self, x: i32, y: i32) -> Rgba {
+        let x = x.max(0).min(self.width as i32 - 1) as usize;
+        let y = y.max(0).min(self.height as i32 - 1) as usize;
+        let bpp = bytes_per_pixel(self.format) as usize;
+        let offset = (y * self.width as usize + x) * bpp;
+        let p = &self.pixels[offset..offset + bpp];
+        let unorm = |v: u8| v as f32 / 255.0;
+
+        match self.format {
+            // A8 textures are uploaded to the red channel on desktop GL.
+            ImageFormat::A8 => [unorm(p[0]), 0.0, 0.0, 1.0],
+            ImageFormat::RGB8 => [unorm(p[0]), unorm(p[1]), unorm(p[2]), 1.0],
+            // RGBA8 data is stored as BGRA.
+            ImageFormat::RGBA8 => [unorm(p[2]), unorm(p[1]), unorm(p[0]), unorm(p[3])],
+            ImageFormat::RGBAF32 => [LittleEndian::read_f32(&p[0..4]),
+                                     LittleEndian::read_f32(&p[4..8]),
+                                     LittleEndian::read_f32(&p[8..12]),
+                                     LittleEndian::read_f32(&p[12..16])],
+            // These match the swizzles the device sets up.
+            ImageFormat::R16 => {
+                let value = LittleEndian::read_u16(&p[0..2]) as f32 / 65535.0;
+                [value, value, value, 1.0]
+            }
+            ImageFormat::RG8 => [unorm(p[0]), unorm(p[0]), unorm(p[0]), unorm(p[1])],
+            ImageFormat::RGBA16F => [half_to_f32(LittleEndian::read_u16(&p[0..2])),
+                                     half_to_f32(LittleEndian::read_u16(&p[2..4])),
+                                     half_to_f32(LittleEndian::read_u16(&p[4..6])),
+                                     half_to_f32(LittleEndian::read_u16(&p[6..8]))],
+            ImageFormat::BC1 |
+            ImageFormat::BC2 |
+            ImageFormat::BC3 |
+            ImageFormat::ETC2RGB8 |
+            ImageFormat::ETC2RGBA8 |
+            ImageFormat::ASTC4x4 |
+            ImageFormat::Invalid => unreachable!(),
+        }
+    }
+
+    /// Samples the texture at a position given in texels.
+    fn sample(&self, u: f32, v: f32) -> Rgba {
+        match self.filter {
+            TextureFilter::Nearest => self.texel(u.floor() as i32, v.floor() as i32),
+            // Mipmaps aren't generated, so trilinear filtering falls back to bilinear.
+            TextureFilter::Linear | TextureFilter::Trilinear => bilinear(u, v, |x, y| self.texel(x, y)),
+        }
+    }
+}
+
+/// The copy of an `ExternalImageSource::RawData` image, along with the
+/// generation of the data it was last copied from.
+struct RawExternalImage {
+    texture: CpuTexture,
+    generation: u64,
+}
+
+/// A render target (or the framebuffer) being drawn into.
+struct Surface {
+    width: i32,
+    height: i32,
+    pixels: Vec<Rgba>,
+    depth: Vec<i32>,
+}
+
+impl Surface {
+    fn new(width: i32, height: i32, clear_color: Rgba) -> Surface {
+        let count = (width.max(0) * height.max(0)) as usize;
+        Surface {
+            width: width,
+            height: height,
+            pixels: vec![clear_color; count],
+            depth: vec![i32::MIN; count],
+        }
+    }
+
+    fn texel(&self, x: i32, y: i32) -> Rgba {
+        let x = x.max(0).min(self.width - 1);
+        let y = y.max(0).min(self.height - 1);
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Samples the surface at a position given in pixels with bilinear
+    /// filtering, like the render target arrays are set up.
+    fn sample(&self, u: f32, v: f32) -> Rgba {
+        bilinear(u, v, |x, y| self.texel(x, y))
+    }
+
+    fn write(&mut self, x: i32, y: i32, z: i32, color: Rgba, blend: PixelBlend, depth: DepthMode) {
+        let index = (y * self.width + x) as usize;
+
+        // Higher z values are in front, see `ORTHO_NEAR_PLANE`.
+        match depth {
+            DepthMode::Ignore => {}
+            DepthMode::Test => {
+                if z <= self.depth[index] {
+                    return;
+                }
+            }
+            DepthMode::TestAndWrite => {
+                if z <= self.depth[index] {
+                    return;
+                }
+                self.depth[index] = z;
+            }
+        }
+
+        let c = color;
+        let d = self.pixels[index];
+        let result = match blend {
+            PixelBlend::Replace => c,
+            PixelBlend::Alpha => {
+                [c[0] * c[3] + d[0] * (1.0 - c[3]),
+                 c[1] * c[3] + d[1] * (1.0 - c[3]),
+                 c[2] * c[3] + d[2] * (1.0 - c[3]),
+                 c[3] + d[3]]
+            }
+            PixelBlend::Subpixel(k) => {
+                [k.r * c[0] + d[0] * (1.0 - c[0]),
+                 k.g * c[1] + d[1] * (1.0 - c[1]),
+                 k.b * c[2] + d[2] * (1.0 - c[2]),
+                 k.a * c[3] + d[3] * (1.0 - c[3])]
+            }
+            PixelBlend::Multiply => [d[0] * c[0], d[1] * c[1], d[2] * c[2], d[3] * c[3]],
+        };
+
+        self.pixels[index] = [clamp01(result[0]),
+                              clamp01(result[1]),
+                              clamp01(result[2]),
+                              clamp01(result[3])];
+    }
+}
+
+/// Maps a point in CSS space back into the local space of a layer, the same
+/// way `get_layer_pos` does in the shaders.
+fn layer_pos(layer: &PackedStackingContext, x: f32, y: f32) -> (f32, f32) {
+    fn project(v: &WorldPoint4D) -> [f32; 3] {
+        [v.x / v.w, v.y / v.w, v.z / v.w]
+    }
+    fn sub(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    }
+    fn dot(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    let a = project(&layer.screen_vertices[0]);
+    let b = project(&layer.screen_vertices[3]);
+    let c = project(&layer.screen_vertices[2]);
+    let (ab, ac) = (sub(&b, &a), sub(&c, &a));
+    let n = [ab[1] * ac[2] - ab[2] * ac[1],
+             ab[2] * ac[0] - ab[0] * ac[2],
+             ab[0] * ac[1] - ab[1] * ac[0]];
+    let length = dot(&n, &n).sqrt();
+
+    // Intersect the layer plane with a ray along the Z axis from the point.
+    let p = [x, y, -10000.0];
+    let mut t = 0.0;
+    if length > 0.0 {
+        let denom = n[2] / length;
+        if denom > 1e-6 {
+            t = dot(&sub(&a, &p), &n) / length / denom;
+        }
+    }
+
+    let r = layer.inv_transform.transform_point4d(&WorldPoint4D::new(x, y, p[2] + t, 1.0));
+    (r.x / r.w, r.y / r.w)
+}
+
+/// Everything the shaders read from the data textures of a frame.
+struct FrameContext<'a> {
+    frame: &'a Frame,
+    textures: &'a [Option<CpuTexture>],
+    external_textures: &'a HashMap<ExternalImageId, RawExternalImage>,
+    device_pixel_ratio: f32,
+}
+
+impl<'a> FrameContext<'a> {
+    fn task(&self, index: i32) -> &[f32; 8] {
+        &self.frame.render_task_data[index as usize].data
+    }
+
+    fn layer(&self, index: i32) -> &PackedStackingContext {
+        &self.frame.layer_texture_data[index as usize]
+    }
+
+    fn geometry(&self, index: i32) -> &PrimitiveGeometry {
+        &self.frame.gpu_geometry[index as usize]
+    }
+
+    fn resource_rect(&self, index: i32) -> &TexelRect {
+        &self.frame.gpu_resource_rects[index as usize]
+    }
+
+    fn data16(&self, index: i32) -> &[f32; 4] {
+        &self.frame.gpu_data16[index as usize].data
+    }
+
+    fn data32(&self, index: i32) -> &[f32; 8] {
+        &self.frame.gpu_data32[index as usize].data
+    }
+
+    fn data64(&self, index: i32) -> &[f32; 16] {
+        &self.frame.gpu_data64[index as usize].data
+    }
+
+    fn data128(&self, index: i32) -> &[f32; 32] {
+        &self.frame.gpu_data128[index as usize].data
+    }
+
+    /// Native external textures and WebGL textures live on the GPU and
+    /// can't be sampled here.
+    fn texture(&self, texture: &SourceTexture) -> Option<&CpuTexture> {
+        match *texture {
+            SourceTexture::TextureCache(CacheTextureId(index)) => {
+                self.textures.get(index).and_then(|texture| texture.as_ref())
+            }
+            SourceTexture::External(external_id) => {
+                self.external_textures.get(&external_id).map(|raw_image| &raw_image.texture)
+            }
+            SourceTexture::Invalid |
+            SourceTexture::WebGL(..) => None,
+        }
+    }
+
+    fn sample(&self, texture: Option<&CpuTexture>, u: f32, v: f32) -> Rgba {
+        match texture {
+            Some(texture) => texture.sample(u, v),
+            None => [0.0, 0.0, 0.0, 0.0],
+        }
+    }
+
+    fn draw_target(&self, surface: &mut Surface, target: &RenderTarget, cache: &[Surface]) {
+        for blur in &target.vertical_blurs {
+            self.draw_blur(surface, blur, cache);
+        }
+        for blur in &target.horizontal_blurs {
+            self.draw_blur(surface, blur, cache);
+        }
+
+        for (texture, instances) in &target.idct_rows {
+            let texture = self.texture(texture);
+            for instance in instances {
+                self.draw_idct_rows(surface, instance, texture);
+            }
+        }
+        for instance in &target.idct_columns {
+            self.draw_idct_columns(surface, instance, cache);
+        }
+        for instance in &target.yuv_to_rgb {
+            self.draw_yuv_to_rgb(surface, instance, cache);
+        }
+
+        for instance in &target.box_shadow_cache_prims {
+            self.draw_box_shadow_cache(surface, instance);
+        }
+
+        for instance in &target.clip_batcher.copies {
+            self.draw_clip_copy(surface, instance, cache);
+        }
+        for instance in &target.clip_batcher.rectangles {
+            self.draw_clip_rectangle(surface, instance);
+        }
+        for (texture, instances) in &target.clip_batcher.images {
+            let texture = self.texture(texture);
+            for instance in instances {
+                self.draw_clip_image(surface, instance, texture);
+            }
+        }
+
+        let text_texture = self.texture(&target.text_run_textures.colors[0]);
+        for instance in &target.text_run_cache_prims {
+            self.draw_text_run_cache(surface, instance, text_texture);
+        }
+
+        for batch in &target.alpha_batcher.opaque_batches {
+            self.draw_batch(surface, batch, cache, DepthMode::TestAndWrite);
+        }
+        for batch in &target.alpha_batcher.alpha_batches {
+            self.draw_batch(surface, batch, cache, DepthMode::Test);
+        }
+    }
+
+    fn draw_blur(&self, surface: &mut Surface, blur: &BlurCommand, cache: &[Surface]) {
+        let task = self.task(blur.task_id);
+        let src = self.task(blur.src_task_id);
+        let source = &cache[src[4] as usize];
+        let radius = task[5] as i32;
+        let sigma = task[5] * 0.5;
+        let (dx, dy) = if blur.blur_direction == BlurDirection::Horizontal as i32 {
+            (1.0, 0.0)
+        } else {
+            (0.0, 1.0)
+        };
+
+        let (x0, x1) = pixel_range(task[0], task[0] + task[2], surface.width);
+        let (y0, y1) = pixel_range(task[1], task[1] + task[3], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let u = src[0] + (x as f32 + 0.5 - task[0]) / task[2] * src[2];
+                let v = src[1] + (y as f32 + 0.5 - task[1]) / task[3] * src[3];
+
+                // Accumulate premultiplied colors, so that transparent
+                // texels don't bleed their color into the result.
+                let mut color = [0.0; 4];
+                for i in -radius..radius + 1 {
+                    let offset = i as f32;
+                    let su = (u + offset * dx).max(src[0]).min(src[0] + src[2]);
+                    let sv = (v + offset * dy).max(src[1]).min(src[1] + src[3]);
+                    let texel = source.sample(su, sv);
+                    let weight = gauss(offset, sigma) * texel[3];
+                    color[0] += texel[0] * weight;
+                    color[1] += texel[1] * weight;
+                    color[2] += texel[2] * weight;
+                    color[3] += weight;
+                }
+                if color[3] > 0.0 {
+                    color[0] /= color[3];
+                    color[1] /= color[3];
+                    color[2] /= color[3];
+                }
+
+                surface.write(x, y, 0, color, PixelBlend::Replace, DepthMode::Ignore);
+            }
+        }
+    }
+
+    /// Applies the inverse DCT along the rows of the blocks of a coefficient
+    /// plane, see cs_idct.fs.glsl.
+    fn draw_idct_rows(&self, surface: &mut Surface, instance: &PrimitiveInstance, texture: Option<&CpuTexture>) {
+        let task = self.task(instance.task_index);
+        let yuv = self.data64(instance.prim_address.0);
+        let plane = instance.sub_index as usize;
+        let origin_x = (yuv[plane * 4] + 0.5) as i32;
+        let origin_y = (yuv[plane * 4 + 1] + 0.5) as i32;
+        let texture = match texture {
+            Some(texture) => texture,
+            None => return,
+        };
+
+        // Each texel holds two coefficients as little endian u16s, which read
+        // back as (low, high) in the blue and green channels for the even one
+        // and the red and alpha channels for the odd one.
+        let coefficient = |x: i32, y: i32| {
+            let texel = texture.texel(origin_x + x / 2, origin_y + y);
+            let (low, high) = if x % 2 == 0 { (texel[2], texel[1]) } else { (texel[0], texel[3]) };
+            (low * 255.0).round() + (high * 255.0).round() * 256.0 - 32768.0
+        };
+
+        let (x0, x1) = pixel_range(task[0], task[0] + task[2], surface.width);
+        let (y0, y1) = pixel_range(task[1], task[1] + task[3], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (px, py) = (x - task[0] as i32, y - task[1] as i32);
+                let block_x = px / 8 * 8;
+                let mut sum = 0.0;
+                for u in 0..8 {
+                    sum += idct_basis(px - block_x, u) * coefficient(block_x + u, py);
+                }
+
+                // Store the result as 16 bits split across red and green, like
+                // the 8 bit render targets on the GPU.
+                let value = (sum * IDCT_ROW_SCALE + 32768.0).round().max(0.0).min(65535.0);
+                let high = (value / 256.0).floor();
+                let color = [high / 255.0, (value - high * 256.0) / 255.0, 0.0, 1.0];
+                surface.write(x, y, 0, color, PixelBlend::Replace, DepthMode::Ignore);
+            }
+        }
+    }
+
+    /// Applies the inverse DCT along the columns of the row pass of a plane,
+    /// see cs_idct.fs.glsl.
+    fn draw_idct_columns(&self, surface: &mut Surface, instance: &PrimitiveInstance, cache: &[Surface]) {
+        let task = self.task(instance.task_index);
+        let src = self.task(instance.user_data[0]);
+        let source = &cache[src[4] as usize];
+        let row_value = |x: i32, y: i32| {
+            let texel = source.texel(src[0] as i32 + x, src[1] as i32 + y);
+            let (high, low) = ((texel[0] * 255.0).round(), (texel[1] * 255.0).round());
+            (high * 256.0 + low - 32768.0) / IDCT_ROW_SCALE
+        };
+
+        let (x0, x1) = pixel_range(task[0], task[0] + task[2], surface.width);
+        let (y0, y1) = pixel_range(task[1], task[1] + task[3], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (px, py) = (x - task[0] as i32, y - task[1] as i32);
+                let block_y = py / 8 * 8;
+                let mut sum = 0.0;
+                for v in 0..8 {
+                    sum += idct_basis(py - block_y, v) * row_value(px, block_y + v);
+                }
+
+                let value = (0.25 * sum + 128.0).round().max(0.0).min(255.0) / 255.0;
+                surface.write(x, y, 0, [value, value, value, 1.0], PixelBlend::Replace, DepthMode::Ignore);
+            }
+        }
+    }
+
+    /// Upsamples the chroma planes of a decoded YUV image and converts it to
+    /// RGB, see cs_yuv_to_rgb.fs.glsl.
+    fn draw_yuv_to_rgb(&self, surface: &mut Surface, instance: &PrimitiveInstance, cache: &[Surface]) {
+        let task = self.task(instance.task_index);
+        let yuv = self.data64(instance.prim_address.0);
+        let (color_space, color_range) = (yuv[14], yuv[15]);
+        let y_task = self.task(instance.sub_index);
+        let u_task = self.task(instance.user_data[0]);
+        let v_task = self.task(instance.user_data[1]);
+        let scale_x = u_task[2] / y_task[2];
+        let scale_y = u_task[3] / y_task[3];
+
+        // Bilinear filtering is clamped half a sample inside the plane, so
+        // that its neighbours in the cache don't bleed in.
+        let chroma = |plane: &[f32; 8], cx: f32, cy: f32| {
+            let cx = cx.max(0.5).min(plane[2] - 0.5);
+            let cy = cy.max(0.5).min(plane[3] - 0.5);
+            cache[plane[4] as usize].sample(plane[0] + cx, plane[1] + cy)[0]
+        };
+
+        let (x0, x1) = pixel_range(task[0], task[0] + task[2], surface.width);
+        let (y0, y1) = pixel_range(task[1], task[1] + task[3], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let px = x as f32 + 0.5 - task[0];
+                let py = y as f32 + 0.5 - task[1];
+                let luma = cache[y_task[4] as usize].texel(y_task[0] as i32 + px.floor() as i32,
+                                                           y_task[1] as i32 + py.floor() as i32)[0];
+                let u = chroma(u_task, px * scale_x, py * scale_y);
+                let v = chroma(v_task, px * scale_x, py * scale_y);
+                let rgb = yuv_to_rgb(luma, u, v, color_space, color_range);
+                surface.write(x, y, 0, [rgb[0], rgb[1], rgb[2], 1.0], PixelBlend::Replace, DepthMode::Ignore);
+            }
+        }
+    }
+
+    fn draw_box_shadow_cache(&self, surface: &mut Surface, instance: &PrimitiveInstance) {
+        let task = self.task(instance.task_index);
+        let bs = self.data64(instance.prim_address.0);
+        let blur_radius = bs[14];
+        let inverted = bs[15] == 1.0;
+        let shadow_rect = Bounds::new(bs[4], bs[5], bs[6], bs[7]);
+        let sigma = blur_radius / 2.0;
+        let dpr = self.device_pixel_ratio;
+
+        let (x0, x1) = pixel_range(task[0], task[0] + task[2], surface.width);
+        let (y0, y1) = pixel_range(task[1], task[1] + task[3], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // The position within the patch, in logical pixels, starting
+                // where the blur begins. See cs_box_shadow.vs.glsl.
+                let px = (x as f32 + 0.5 - 1.0 - task[0]) / dpr + bs[4] - 2.0 * blur_radius;
+                let py = (y as f32 + 0.5 - 1.0 - task[1]) / dpr + bs[5] - 2.0 * blur_radius;
+                let value = box_shadow_color(px, py, &shadow_rect, bs[12], sigma).max(0.0);
+                let alpha = if inverted { 1.0 - value } else { value };
+                surface.write(x, y, 0, [1.0, 1.0, 1.0, alpha], PixelBlend::Replace, DepthMode::Ignore);
+            }
+        }
+    }
+
+    fn draw_clip_copy(&self, surface: &mut Surface, instance: &CacheClipInstance, cache: &[Surface]) {
+        let area = self.task(instance.task_id);
+        let source = self.task(instance.base_task_id);
+        let source_target = &cache[source[6] as usize];
+
+        let (x0, x1) = pixel_range(area[0], area[2], surface.width);
+        let (y0, y1) = pixel_range(area[1], area[3], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let u = x as f32 + 0.5 - area[0] + area[4] + source[0] - source[4];
+                let v = y as f32 + 0.5 - area[1] + area[5] + source[1] - source[5];
+                let value = source_target.texel(u.floor() as i32, v.floor() as i32)[0];
+                surface.write(x, y, 0, [value, 0.0, 0.0, 1.0], PixelBlend::Replace, DepthMode::Ignore);
+            }
+        }
+    }
+
+    fn draw_clip_rectangle(&self, surface: &mut Surface, instance: &CacheClipInstance) {
+        let area = self.task(instance.task_id);
+        let layer = self.layer(instance.layer_index);
+        let address = instance.address.0;
+        let clip = self.data32(address);
+        let clip_rect = Bounds::new(clip[0], clip[1], clip[2], clip[3]);
+        let clipped_rect = clip_rect.intersect(&Bounds::from_rect(&layer.local_clip_rect));
+        // The corners are stored top left, top right, bottom left, bottom right,
+        // and only their outer horizontal radius is used.
+        let radii = [self.data32(address + 1)[4],
+                     self.data32(address + 2)[4],
+                     self.data32(address + 4)[4],
+                     self.data32(address + 3)[4]];
+        let dpr = self.device_pixel_ratio;
+        let pixels_per_fragment = 2.0f32.sqrt() / dpr;
+
+        let (x0, x1) = pixel_range(area[0], area[2], surface.width);
+        let (y0, y1) = pixel_range(area[1], area[3], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let gx = x as f32 + 0.5 - area[0] + area[4];
+                let gy = y as f32 + 0.5 - area[1] + area[5];
+                let (lx, ly) = layer_pos(layer, gx / dpr, gy / dpr);
+                let value = if clipped_rect.contains(lx, ly) {
+                    rounded_rect_alpha(lx, ly, &clip_rect, &radii, pixels_per_fragment)
+                } else {
+                    0.0
+                };
+                surface.write(x, y, 0, [value, 0.0, 0.0, 1.0], PixelBlend::Multiply, DepthMode::Ignore);
+            }
+        }
+    }
+
+    fn draw_clip_image(&self,
+                       surface: &mut Surface,
+                       instance: &CacheClipInstance,
+                       texture: Option<&CpuTexture>) {
+        let area = self.task(instance.task_id);
+        let layer = self.layer(instance.layer_index);
+        let mask = self.data32(instance.address.0);
+        let mask_rect = Bounds::new(mask[4], mask[5], mask[6], mask[7]);
+        let clipped_rect = mask_rect.intersect(&Bounds::from_rect(&layer.local_clip_rect));
+        let dpr = self.device_pixel_ratio;
+
+        let (x0, x1) = pixel_range(area[0], area[2], surface.width);
+        let (y0, y1) = pixel_range(area[1], area[3], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let gx = x as f32 + 0.5 - area[0] + area[4];
+                let gy = y as f32 + 0.5 - area[1] + area[5];
+                let (lx, ly) = layer_pos(layer, gx / dpr, gy / dpr);
+                let value = if clipped_rect.contains(lx, ly) {
+                    let u = mask[0] + clamp01((lx - mask_rect.x0) / mask_rect.width()) * mask[2];
+                    let v = mask[1] + clamp01((ly - mask_rect.y0) / mask_rect.height()) * mask[3];
+                    self.sample(texture, u, v)[0]
+                } else {
+                    0.0
+                };
+                surface.write(x, y, 0, [value, 1.0, 1.0, 1.0], PixelBlend::Multiply, DepthMode::Ignore);
+            }
+        }
+    }
+
+    fn draw_text_run_cache(&self,
+                           surface: &mut Surface,
+                           instance: &PrimitiveInstance,
+                           texture: Option<&CpuTexture>) {
+        let task = self.task(instance.task_index);
+        let color = *self.data16(instance.prim_address.0);
+        let glyph = self.data16(instance.sub_index);
+        let geometry = self.geometry(instance.global_prim_id);
+        let res = self.resource_rect(instance.user_data[0]);
+        let dpr = self.device_pixel_ratio;
+
+        let width = res.uv1.x - res.uv0.x;
+        let height = res.uv1.y - res.uv0.y;
+        let origin_x = task[0] + dpr * (glyph[0] - geometry.local_rect.origin.x);
+        let origin_y = task[1] + dpr * (glyph[1] - geometry.local_rect.origin.y);
+
+        let (x0, x1) = pixel_range(origin_x, origin_x + width, surface.width);
+        let (y0, y1) = pixel_range(origin_y, origin_y + height, surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let u = res.uv0.x + (x as f32 + 0.5 - origin_x);
+                let v = res.uv0.y + (y as f32 + 0.5 - origin_y);
+                let alpha = self.sample(texture, u, v)[3];
+                let texel = [color[0], color[1], color[2], color[3] * alpha];
+                surface.write(x, y, 0, texel, PixelBlend::Alpha, DepthMode::Ignore);
+            }
+        }
+    }
+
+    fn draw_batch(&self,
+                  surface: &mut Surface,
+                  batch: &PrimitiveBatch,
+                  cache: &[Surface],
+                  depth: DepthMode) {
+        let blend = PixelBlend::from_blend_mode(batch.key.blend_mode);
+        let snap = batch.key.flags.transform_kind() == TransformedRectKind::AxisAligned;
+        let textures = &batch.key.textures.colors;
+
+        match batch.data {
+            PrimitiveBatchData::Rectangles(ref instances) => {
+                for instance in instances {
+                    let color = *self.data16(instance.prim_address.0);
+                    let rect = Bounds::from_rect(&self.geometry(instance.global_prim_id).local_rect);
+                    self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth,
+                                        |_, _, _| Some(color));
+                }
+            }
+            PrimitiveBatchData::TextRun(ref instances) => {
+                let texture = self.texture(&textures[0]);
+                for instance in instances {
+                    self.draw_glyph(surface, cache, instance, texture, snap, blend, depth);
+                }
+            }
+            PrimitiveBatchData::Image(ref instances) => {
+                let texture = self.texture(&textures[0]);
+                for instance in instances {
+                    self.draw_image(surface, cache, instance, texture, snap, blend, depth);
+                }
+            }
+            PrimitiveBatchData::BorderImage(ref instances) => {
+                let texture = self.texture(&textures[0]);
+                for instance in instances {
+                    self.draw_border_image(surface, cache, instance, texture, snap, blend, depth);
+                }
+            }
+            PrimitiveBatchData::YuvImage(format, ref instances) => {
+                let planes = [self.texture(&textures[0]),
+                              self.texture(&textures[1]),
+                              self.texture(&textures[2])];
+                for instance in instances {
+                    self.draw_yuv_image(surface, cache, instance, format, &planes, snap, blend, depth);
+                }
+            }
+            PrimitiveBatchData::Borders(ref instances) => {
+                for instance in instances {
+                    // The segments all cover the same border, so it is
+                    // evaluated in one go for the first one.
+                    if instance.sub_index != 0 {
+                        continue;
+                    }
+                    let border = self.data128(instance.prim_address.0);
+                    let rect = Bounds::from_rect(&self.geometry(instance.global_prim_id).local_rect);
+                    self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth,
+                                        |x, y, rect| border_color(border, x, y, rect));
+                }
+            }
+            PrimitiveBatchData::AlignedGradient(ref instances) => {
+                for instance in instances {
+                    self.draw_aligned_gradient(surface, cache, instance, snap, blend, depth);
+                }
+            }
+            PrimitiveBatchData::AngleGradient(ref instances) => {
+                for instance in instances {
+                    self.draw_angle_gradient(surface, cache, instance, snap, blend, depth);
+                }
+            }
+            PrimitiveBatchData::RadialGradient(ref instances) => {
+                for instance in instances {
+                    self.draw_radial_gradient(surface, cache, instance, snap, blend, depth);
+                }
+            }
+            PrimitiveBatchData::ConicGradient(ref instances) => {
+                for instance in instances {
+                    self.draw_conic_gradient(surface, cache, instance, snap, blend, depth);
+                }
+            }
+            PrimitiveBatchData::BoxShadow(ref instances) => {
+                for instance in instances {
+                    self.draw_box_shadow(surface, cache, instance, snap, blend, depth);
+                }
+            }
+            PrimitiveBatchData::CacheImage(ref instances) => {
+                for instance in instances {
+                    let child = self.task(instance.user_data[0]);
+                    let source = &cache[child[4] as usize];
+                    let rect = Bounds::from_rect(&self.geometry(instance.global_prim_id).local_rect);
+                    self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth, |x, y, _| {
+                        let u = child[0] + (x - rect.x0) / rect.width() * child[2];
+                        let v = child[1] + (y - rect.y0) / rect.height() * child[3];
+                        Some(source.sample(u, v))
+                    });
+                }
+            }
+            PrimitiveBatchData::Blend(ref instances) => {
+                for instance in instances {
+                    self.draw_blend(surface, cache, instance, blend, depth);
+                }
+            }
+            PrimitiveBatchData::Composite(ref instances) => {
+                for instance in instances {
+                    self.draw_composite(surface, cache, instance, blend, depth);
+                }
+            }
+        }
+    }
+
+    /// Fills the part of a primitive that lies inside its tile, calling
+    /// `shade` with the local position of each pixel and the snapped local
+    /// rectangle. This is the equivalent of `write_vertex` and `do_clip`.
+    fn draw_primitive<F>(&self,
+                         surface: &mut Surface,
+                         cache: &[Surface],
+                         instance: &PrimitiveInstance,
+                         local_rect: &Bounds,
+                         snap: bool,
+                         blend: PixelBlend,
+                         depth: DepthMode,
+                         mut shade: F)
+                         where F: FnMut(f32, f32, &Bounds) -> Option<Rgba> {
+        let dpr = self.device_pixel_ratio;
+        let tile = self.task(instance.task_index);
+        let layer = self.layer(instance.layer_index);
+        let geometry = self.geometry(instance.global_prim_id);
+
+        let mut rect = *local_rect;
+        let mut clip_rect = Bounds::from_rect(&geometry.local_clip_rect);
+        if snap {
+            rect = rect.snap(dpr);
+            clip_rect = clip_rect.snap(dpr);
+        }
+        let bounds = rect.intersect(&clip_rect)
+                         .intersect(&Bounds::from_rect(&layer.local_clip_rect));
+        if bounds.is_empty() {
+            return;
+        }
+
+        let (x0, x1) = pixel_range(tile[2], tile[2] + tile[4], surface.width);
+        let (y0, y1) = pixel_range(tile[3], tile[3] + tile[5], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let device_x = x as f32 + 0.5 - tile[2] + tile[0];
+                let device_y = y as f32 + 0.5 - tile[3] + tile[1];
+                let (lx, ly) = layer_pos(layer, device_x / dpr, device_y / dpr);
+                if !bounds.contains(lx, ly) {
+                    continue;
+                }
+                if let Some(mut color) = shade(lx, ly, &rect) {
+                    color[3] *= self.clip_alpha(instance.clip_task_index, device_x, device_y, cache);
+                    surface.write(x, y, instance.z_sort_index, color, blend, depth);
+                }
+            }
+        }
+    }
+
+    fn clip_alpha(&self, clip_task_index: i32, device_x: f32, device_y: f32, cache: &[Surface]) -> f32 {
+        if clip_task_index == OPAQUE_TASK_INDEX {
+            return 1.0;
+        }
+
+        let area = self.task(clip_task_index);
+        if area[0] == area[2] && area[1] == area[3] {
+            return 1.0;
+        }
+
+        let u = device_x + area[0] - area[4];
+        let v = device_y + area[1] - area[5];
+        if u < area[0] || v < area[1] || u > area[2] || v > area[3] {
+            return 0.0;
+        }
+
+        cache[area[6] as usize].texel(u.floor() as i32, v.floor() as i32)[0]
+    }
+
+    fn draw_glyph(&self,
+                  surface: &mut Surface,
+                  cache: &[Surface],
+                  instance: &PrimitiveInstance,
+                  texture: Option<&CpuTexture>,
+                  snap: bool,
+                  blend: PixelBlend,
+                  depth: DepthMode) {
+        let color = *self.data16(instance.prim_address.0);
+        let glyph = self.data16(instance.sub_index);
+        let res = self.resource_rect(instance.user_data[0]);
+        let dpr = self.device_pixel_ratio;
+        let rect = Bounds::new(glyph[0],
+                               glyph[1],
+                               (res.uv1.x - res.uv0.x) / dpr,
+                               (res.uv1.y - res.uv0.y) / dpr);
+
+        self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth, |x, y, rect| {
+            let u = mix(res.uv0.x, res.uv1.x, (x - rect.x0) / rect.width());
+            let v = mix(res.uv0.y, res.uv1.y, (y - rect.y0) / rect.height());
+            let texel = self.sample(texture, u, v);
+            match blend {
+                PixelBlend::Subpixel(..) => Some(texel),
+                _ => Some([color[0], color[1], color[2], color[3] * texel[3]]),
+            }
+        });
+    }
+
+    fn draw_image(&self,
+                  surface: &mut Surface,
+                  cache: &[Surface],
+                  instance: &PrimitiveInstance,
+                  texture: Option<&CpuTexture>,
+                  snap: bool,
+                  blend: PixelBlend,
+                  depth: DepthMode) {
+        let image = *self.data16(instance.prim_address.0);
+        let (stretch_width, stretch_height) = (image[0], image[1]);
+        let (spacing_width, spacing_height) = (image[2], image[3]);
+        let res = self.resource_rect(instance.user_data[0]);
+        let rect = Bounds::from_rect(&self.geometry(instance.global_prim_id).local_rect);
+
+        self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth, |x, y, rect| {
+            let tile_x = modulo(x - rect.x0, stretch_width + spacing_width);
+            let tile_y = modulo(y - rect.y0, stretch_height + spacing_height);
+            if tile_x > stretch_width || tile_y > stretch_height {
+                return None;
+            }
+            let u = mix(res.uv0.x, res.uv1.x, tile_x / stretch_width);
+            let v = mix(res.uv0.y, res.uv1.y, tile_y / stretch_height);
+            Some(self.sample(texture, u, v))
+        });
+    }
+
+    fn draw_border_image(&self,
+                         surface: &mut Surface,
+                         cache: &[Surface],
+                         instance: &PrimitiveInstance,
+                         texture: Option<&CpuTexture>,
+                         snap: bool,
+                         blend: PixelBlend,
+                         depth: DepthMode) {
+        let patch = *self.data64(instance.sub_index);
+        let rect = Bounds::new(patch[0], patch[1], patch[2], patch[3]);
+        let (src_x0, src_y0, src_x1, src_y1) = (patch[4], patch[5], patch[6], patch[7]);
+        let (tile_width, tile_height) = (patch[8], patch[9]);
+        let (tile_x0, tile_y0) = (rect.x0 + patch[10], rect.y0 + patch[11]);
+        let res = self.resource_rect(instance.user_data[0]);
+
+        // The source rect is relative to the size of the image. As in the
+        // shader, the samples stay half a texel inside the patch.
+        let (src_x0, src_x1) = (mix(res.uv0.x, res.uv1.x, src_x0), mix(res.uv0.x, res.uv1.x, src_x1));
+        let (src_y0, src_y1) = (mix(res.uv0.y, res.uv1.y, src_y0), mix(res.uv0.y, res.uv1.y, src_y1));
+        let (u0, u1) = (src_x0 + 0.5, src_x1 - 0.5);
+        let (v0, v1) = (src_y0 + 0.5, src_y1 - 0.5);
+
+        self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth, |x, y, _| {
+            let fx = modulo(x - tile_x0, tile_width) / tile_width;
+            let fy = modulo(y - tile_y0, tile_height) / tile_height;
+            let u = mix(src_x0, src_x1, fx).max(u0).min(u1);
+            let v = mix(src_y0, src_y1, fy).max(v0).min(v1);
+            Some(self.sample(texture, u, v))
+        });
+    }
+
+    fn draw_yuv_image(&self,
+                      surface: &mut Surface,
+                      cache: &[Surface],
+                      instance: &PrimitiveInstance,
+                      format: YuvFormat,
+                      planes: &[Option<&CpuTexture>; 3],
+                      snap: bool,
+                      blend: PixelBlend,
+                      depth: DepthMode) {
+        let yuv = *self.data64(instance.prim_address.0);
+        let (width, height) = (yuv[12], yuv[13]);
+        let (color_space, color_range) = (yuv[14], yuv[15]);
+        let rect = Bounds::from_rect(&self.geometry(instance.global_prim_id).local_rect);
+
+        if format == YuvFormat::DctCoefficients {
+            // The planes were decoded to RGB by the render task given in the
+            // user data, which is sampled half a texel inside its bounds.
+            let child = self.task(instance.user_data[0]);
+            let source = &cache[child[4] as usize];
+            self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth, |x, y, rect| {
+                let u = child[0] + (x - rect.x0) / width * child[2];
+                let v = child[1] + (y - rect.y0) / height * child[3];
+                let u = u.max(child[0] + 0.5).min(child[0] + child[2] - 0.5);
+                let v = v.max(child[1] + 0.5).min(child[1] + child[3] - 0.5);
+                let texel = source.sample(u, v);
+                Some([texel[0], texel[1], texel[2], 1.0])
+            });
+            return;
+        }
+
+        self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth, |x, y, rect| {
+            let fx = (x - rect.x0) / width;
+            let fy = (y - rect.y0) / height;
+            let plane_uv = |i: usize| {
+                let base = i * 4;
+                (mix(yuv[base], yuv[base + 2], fx), mix(yuv[base + 1], yuv[base + 3], fy))
+            };
+            let (y_uv, u_uv, v_uv) = (plane_uv(0), plane_uv(1), plane_uv(2));
+            let channels = match format {
+                YuvFormat::PlanarYCbCr => [self.sample(planes[0], y_uv.0, y_uv.1)[0],
+                                           self.sample(planes[1], u_uv.0, u_uv.1)[0],
+                                           self.sample(planes[2], v_uv.0, v_uv.1)[0]],
+                // The UV plane is RG8, which samples as (u, u, u, v).
+                YuvFormat::NV12 => {
+                    let uv = self.sample(planes[1], u_uv.0, u_uv.1);
+                    [self.sample(planes[0], y_uv.0, y_uv.1)[0], uv[0], uv[3]]
+                }
+                // Each texel packs Y0 U Y1 V, which reads back as (Y1, U, Y0, V).
+                YuvFormat::InterleavedYCbCr => match planes[0] {
+                    Some(texture) => {
+                        let yuyv = texture.texel(y_uv.0.floor() as i32, y_uv.1.floor() as i32);
+                        let luma = if y_uv.0.fract() < 0.5 { yuyv[2] } else { yuyv[0] };
+                        [luma, yuyv[1], yuyv[3]]
+                    }
+                    None => [0.0; 3],
+                },
+                YuvFormat::DctCoefficients => unreachable!(),
+            };
+            let rgb = yuv_to_rgb(channels[0], channels[1], channels[2], color_space, color_range);
+            Some([rgb[0], rgb[1], rgb[2], 1.0])
+        });
+    }
+
+    fn draw_aligned_gradient(&self,
+                             surface: &mut Surface,
+                             cache: &[Surface],
+                             instance: &PrimitiveInstance,
+                             snap: bool,
+                             blend: PixelBlend,
+                             depth: DepthMode) {
+        let gradient = self.data32(instance.prim_address.0);
+        let (start_x, start_y, end_x, end_y) = (gradient[0], gradient[1], gradient[2], gradient[3]);
+        let g0 = self.data32(instance.sub_index);
+        let g1 = self.data32(instance.sub_index + 1);
+        let mut c0 = [g0[0], g0[1], g0[2], g0[3]];
+        let mut c1 = [g1[0], g1[1], g1[2], g1[3]];
+        let local_rect = Bounds::from_rect(&self.geometry(instance.global_prim_id).local_rect);
+
+        // Move the stops into the repetition that this instance draws,
+        // as ps_gradient.vs.glsl does.
+        let repetition = instance.user_data[0];
+        let (first, period) = (gradient[6], gradient[7]);
+        let (mut o0, mut o1) = (g0[4], g1[4]);
+        // Must match ExtendMode in webrender_traits.
+        if gradient[5] as i32 == 2 && repetition % 2 != 0 {
+            o0 = 2.0 * first + period - o0;
+            o1 = 2.0 * first + period - o1;
+        }
+        o0 += repetition as f32 * period;
+        o1 += repetition as f32 * period;
+
+        // Must match GradientType in prim_store.rs.
+        let horizontal = match gradient[4] as i32 {
+            0 => true,
+            1 => false,
+            _ => return,
+        };
+        let mut segment = if horizontal {
+            Bounds {
+                x0: mix(start_x, end_x, o0),
+                x1: mix(start_x, end_x, o1),
+                .. local_rect
+            }
+        } else {
+            Bounds {
+                y0: mix(start_y, end_y, o0),
+                y1: mix(start_y, end_y, o1),
+                .. local_rect
+            }
+        };
+        if segment.x1 < segment.x0 || segment.y1 < segment.y0 {
+            segment = Bounds {
+                x0: segment.x0.min(segment.x1),
+                y0: segment.y0.min(segment.y1),
+                x1: segment.x0.max(segment.x1),
+                y1: segment.y0.max(segment.y1),
+            };
+            mem::swap(&mut c0, &mut c1);
+        }
+        let clipped = segment.intersect(&local_rect);
+
+        self.draw_primitive(surface, cache, instance, &clipped, snap, blend, depth, |x, y, _| {
+            let f = if horizontal {
+                (x - segment.x0) / segment.width()
+            } else {
+                (y - segment.y0) / segment.height()
+            };
+            Some(mix_color(&c0, &c1, clamp01(f)))
+        });
+    }
+
+    fn draw_angle_gradient(&self,
+                           surface: &mut Surface,
+                           cache: &[Surface],
+                           instance: &PrimitiveInstance,
+                           snap: bool,
+                           blend: PixelBlend,
+                           depth: DepthMode) {
+        let gradient = self.data32(instance.prim_address.0);
+        let dpr = self.device_pixel_ratio;
+        let snap_coord = |v: f32| (0.5 + v * dpr).floor() / dpr;
+        let (start_x, start_y) = (snap_coord(gradient[0]), snap_coord(gradient[1]));
+        let (end_x, end_y) = (snap_coord(gradient[2]), snap_coord(gradient[3]));
+
+        let angle = (-(end_y - start_y)).atan2(end_x - start_x);
+        let (sa, ca) = angle.sin_cos();
+        let start = start_x * ca - start_y * sa;
+        let end = end_x * ca - end_y * sa;
+        let length = end - start;
+        let extend_mode = gradient[5] as i32;
+
+        let stops: Vec<(Rgba, f32)> = (0..instance.user_data[0]).map(|i| {
+            let stop = self.data32(instance.sub_index + i);
+            ([stop[0], stop[1], stop[2], stop[3]], start + length * stop[4])
+        }).collect();
+        if stops.is_empty() {
+            return;
+        }
+        let first = stops[0].1;
+        let period = stops[stops.len() - 1].1 - first;
+
+        let rect = Bounds::from_rect(&self.geometry(instance.global_prim_id).local_rect);
+        self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth, |x, y, _| {
+            let pos = extend_stop_position(x * ca - y * sa, first, period, extend_mode);
+            let mut color = stops[0].0;
+            for pair in stops.windows(2) {
+                let f = linear_step(pair[0].1, pair[1].1, pos);
+                color = mix_color(&color, &pair[1].0, f);
+            }
+            Some(color)
+        });
+    }
+
+    fn draw_radial_gradient(&self,
+                            surface: &mut Surface,
+                            cache: &[Surface],
+                            instance: &PrimitiveInstance,
+                            snap: bool,
+                            blend: PixelBlend,
+                            depth: DepthMode) {
+        // There is no limit on the stops here, so the instance of the first
+        // segment of the stops draws all of them, and the others nothing.
+        if instance.user_data[1] != 0 {
+            return;
+        }
+
+        let gradient = self.data32(instance.prim_address.0);
+        let (center_x, center_y) = (gradient[0], gradient[1]);
+        let (start, end) = (gradient[2], gradient[3]);
+        let ratio_xy = gradient[4];
+        let extend_mode = gradient[5] as i32;
+        let length = end - start;
+
+        let stops: Vec<(Rgba, f32)> = (0..instance.user_data[0]).map(|i| {
+            let stop = self.data32(instance.sub_index + i);
+            ([stop[0], stop[1], stop[2], stop[3]], start + length * stop[4])
+        }).collect();
+        if stops.is_empty() {
+            return;
+        }
+        let first = stops[0].1;
+        let period = stops[stops.len() - 1].1 - first;
+
+        let rect = Bounds::from_rect(&self.geometry(instance.global_prim_id).local_rect);
+        self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth, |x, y, _| {
+            let dx = x - center_x;
+            let dy = (y - center_y) * ratio_xy;
+            let pos = extend_stop_position((dx * dx + dy * dy).sqrt(), first, period, extend_mode);
+            let mut color = stops[0].0;
+            for pair in stops.windows(2) {
+                let f = linear_step(pair[0].1, pair[1].1, pos);
+                color = mix_color(&color, &pair[1].0, f);
+            }
+            Some(color)
+        });
+    }
+
+    fn draw_conic_gradient(&self,
+                           surface: &mut Surface,
+                           cache: &[Surface],
+                           instance: &PrimitiveInstance,
+                           snap: bool,
+                           blend: PixelBlend,
+                           depth: DepthMode) {
+        // As for radial gradients, the first segment of the stops draws them all.
+        if instance.user_data[1] != 0 {
+            return;
+        }
+
+        let gradient = self.data32(instance.prim_address.0);
+        let (center_x, center_y) = (gradient[0], gradient[1]);
+        let start_angle = gradient[2];
+        let extend_mode = gradient[3] as i32;
+
+        let stops: Vec<(Rgba, f32)> = (0..instance.user_data[0]).map(|i| {
+            let stop = self.data32(instance.sub_index + i);
+            ([stop[0], stop[1], stop[2], stop[3]], stop[4])
+        }).collect();
+        if stops.is_empty() {
+            return;
+        }
+        let first = stops[0].1;
+        let period = stops[stops.len() - 1].1 - first;
+
+        let rect = Bounds::from_rect(&self.geometry(instance.global_prim_id).local_rect);
+        self.draw_primitive(surface, cache, instance, &rect, snap, blend, depth, |x, y, _| {
+            let angle = (x - center_x).atan2(center_y - y) - start_angle;
+            let turns = angle / (2.0 * f32::consts::PI);
+            let pos = extend_stop_position(turns - turns.floor(), first, period, extend_mode);
+            let mut color = stops[0].0;
+            for pair in stops.windows(2) {
+                let f = linear_step(pair[0].1, pair[1].1, pos);
+                color = mix_color(&color, &pair[1].0, f);
+            }
+            Some(color)
+        });
+    }
+
+    fn draw_box_shadow(&self,
+                       surface: &mut Surface,
+                       cache: &[Surface],
+                       instance: &PrimitiveInstance,
+                       snap: bool,
+                       blend: PixelBlend,
+                       depth: DepthMode) {
+        let bs = self.data64(instance.prim_address.0);
+        let color = [bs[8], bs[9], bs[10], bs[11]];
+        let segment = self.data16(instance.sub_index);
+        let segment = Bounds::new(segment[0], segment[1], segment[2], segment[3]);
+        let local_rect = Bounds::from_rect(&self.geometry(instance.global_prim_id).local_rect);
+        let dpr = self.device_pixel_ratio;
+
+        // The cached patch has a one pixel border for bilinear filtering.
+        let child = self.task(instance.user_data[0]);
+        let source = &cache[child[4] as usize];
+        let patch_x = child[0] + 1.0;
+        let patch_y = child[1] + 1.0;
+        let patch_width = child[2] - 2.0;
+        let patch_height = child[3] - 2.0;
+        let mirror_x = 0.5 * local_rect.width() / (patch_width / dpr);
+        let mirror_y = 0.5 * local_rect.height() / (patch_height / dpr);
+
+        self.draw_primitive(surface, cache, instance, &segment, snap, blend, depth, |x, y, _| {
+            let u = (x - local_rect.x0) / (patch_width / dpr);
+            let v = (y - local_rect.y0) / (patch_height / dpr);
+            let u = (mirror_x - (u - mirror_x).abs()).min(1.0);
+            let v = (mirror_y - (v - mirror_y).abs()).min(1.0);
+            let texel = source.sample(patch_x + u * patch_width, patch_y + v * patch_height);
+            Some([color[0] * texel[0], color[1] * texel[1], color[2] * texel[2], color[3] * texel[3]])
+        });
+    }
+
+    fn draw_blend(&self,
+                  surface: &mut Surface,
+                  cache: &[Surface],
+                  instance: &PrimitiveInstance,
+                  blend: PixelBlend,
+                  depth: DepthMode) {
+        let src = self.task(instance.user_data[0]);
+        let dest = self.task(instance.task_index);
+        let source = &cache[src[6] as usize];
+        let op = instance.sub_index;
+        let amount = instance.user_data[1] as f32 / 65535.0;
+
+        let origin_x = dest[2] - dest[0] + src[0];
+        let origin_y = dest[3] - dest[1] + src[1];
+        let (x0, x1) = pixel_range(origin_x, origin_x + src[4], surface.width);
+        let (y0, y1) = pixel_range(origin_y, origin_y + src[5], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let cs = source.sample(src[2] + x as f32 + 0.5 - origin_x,
+                                       src[3] + y as f32 + 0.5 - origin_y);
+                if cs[3] == 0.0 {
+                    continue;
+                }
+                let color = apply_filter(op, cs, amount);
+                surface.write(x, y, instance.z_sort_index, color, blend, depth);
+            }
+        }
+    }
+
+    fn draw_composite(&self,
+                      surface: &mut Surface,
+                      cache: &[Surface],
+                      instance: &PrimitiveInstance,
+                      blend: PixelBlend,
+                      depth: DepthMode) {
+        let src0 = self.task(instance.user_data[0]);
+        let src1 = self.task(instance.user_data[1]);
+        let dest = self.task(instance.task_index);
+        let op = instance.sub_index;
+
+        let (x0, x1) = pixel_range(dest[2], dest[2] + dest[4], surface.width);
+        let (y0, y1) = pixel_range(dest[3], dest[3] + dest[5], surface.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let fx = (x as f32 + 0.5 - dest[2]) / dest[4];
+                let fy = (y as f32 + 0.5 - dest[3]) / dest[5];
+                let cb = cache[src0[6] as usize].sample(src0[2] + fx * src0[4],
+                                                        src0[3] + fy * src0[5]);
+
+                let fx1 = (dest[0] + fx * dest[4] - src1[0]) / src1[4];
+                let fy1 = (dest[1] + fy * dest[5] - src1[1]) / src1[5];
+                let color = if fx1 < 0.0 || fx1 > 1.0 || fy1 < 0.0 || fy1 > 1.0 {
+                    cb
+                } else {
+                    let cs = cache[src1[6] as usize].sample(src1[2] + fx1 * src1[4],
+                                                            src1[3] + fy1 * src1[5]);
+                    mix_blend(op, cb, cs)
+                };
+
+                surface.write(x, y, instance.z_sort_index, color, blend, depth);
+            }
+        }
+    }
+}
+
+fn modulo(x: f32, y: f32) -> f32 {
+    x - y * (x / y).floor()
+}
+
+fn linear_step(lo: f32, hi: f32, x: f32) -> f32 {
+    let d = hi - lo;
+    let mut v = x - lo;
+    if d != 0.0 {
+        v /= d;
+    }
+    clamp01(v)
+}
+
+/// Wraps a position along a gradient into the range covered by its stops.
+/// See `extendStopPosition` in prim_shared.glsl.
+fn extend_stop_position(pos: f32, first: f32, period: f32, extend_mode: i32) -> f32 {
+    if period <= 0.0 {
+        return pos;
+    }
+    let wrapped = (pos - first) % (2.0 * period);
+    let wrapped = if wrapped < 0.0 { wrapped + 2.0 * period } else { wrapped };
+    // Must match ExtendMode in webrender_traits.
+    match extend_mode {
+        1 => first + wrapped % period,
+        2 => first + period - (wrapped - period).abs(),
+        _ => pos,
+    }
+}
+
+fn gauss(x: f32, sigma: f32) -> f32 {
+    if sigma == 0.0 {
+        return 1.0;
+    }
+    let sigma_pow2 = sigma * sigma;
+    1.0 / (6.283185307179586 * sigma_pow2).sqrt() * (-(x * x) / (2.0 * sigma_pow2)).exp()
+}
+
+/// See the derivation in cs_box_shadow.fs.glsl.
+fn erf(x: f32) -> f32 {
+    let negative = x < 0.0;
+    let x = x.abs();
+    let x2 = x * x;
+    let x3 = x2 * x;
+    let x4 = x2 * x2;
+    let denom = 1.0 + 0.278393 * x + 0.230389 * x2 + 0.000972 * x3 + 0.078108 * x4;
+    let result = 1.0 - 1.0 / (denom * denom * denom * denom);
+    if negative { -result } else { result }
+}
+
+fn erf_sigma(x: f32, sigma: f32) -> f32 {
+    erf(x / (sigma * 1.4142135623730951))
+}
+
+fn box_shadow_cutout(x0l: f32, x0r: f32, y0: f32, y_min: f32, y_max: f32, radius: f32, sigma: f32) -> f32 {
+    let mut sum = 0.0;
+    let mut y = y_min;
+    while y <= y_max {
+        let step = (y - y0) / radius;
+        let ellipse_point = radius * (1.0 - step * step).sqrt();
+        sum += gauss(y, sigma) *
+            (erf_sigma(x0r + radius, sigma) - erf_sigma(x0r + ellipse_point, sigma) +
+             erf_sigma(x0l - ellipse_point, sigma) - erf_sigma(x0l - radius, sigma));
+        y += 1.0;
+    }
+    sum / 2.0
+}
+
+fn box_shadow_color(x: f32, y: f32, rect: &Bounds, radius: f32, sigma: f32) -> f32 {
+    let (p0x, p0y) = (rect.x0 - x, rect.y0 - y);
+    let (p1x, p1y) = (rect.x1 - x, rect.y1 - y);
+
+    let color = (erf_sigma(p1x, sigma) - erf_sigma(p0x, sigma)) *
+                (erf_sigma(p1y, sigma) - erf_sigma(p0y, sigma)) / 4.0;
+    if radius == 0.0 {
+        return color;
+    }
+
+    let x0l = p0x + radius;
+    let y0t = p1y - radius;
+    let x0r = p1x - radius;
+    let y0b = p0y + radius;
+    let top = box_shadow_cutout(x0l, x0r, y0t, y0t, y0t + radius, radius, sigma);
+    let bottom = box_shadow_cutout(x0l, x0r, y0b, y0b - radius, y0b, radius, sigma);
+    color - (top + bottom)
+}
+
+/// The coverage of a rounded rectangle clip, with the corner radii ordered
+/// top left, top right, bottom right, bottom left.
+fn rounded_rect_alpha(x: f32, y: f32, rect: &Bounds, radii: &[f32; 4], pixels_per_fragment: f32) -> f32 {
+    let corners = [(rect.x0 + radii[0], rect.y0 + radii[0], radii[0]),
+                   (rect.x1 - radii[1], rect.y0 + radii[1], radii[1]),
+                   (rect.x1 - radii[2], rect.y1 - radii[2], radii[2]),
+                   (rect.x0 + radii[3], rect.y1 - radii[3], radii[3])];
+    let inside = [(x < corners[0].0, y < corners[0].1),
+                  (x > corners[1].0, y < corners[1].1),
+                  (x > corners[2].0, y > corners[2].1),
+                  (x < corners[3].0, y > corners[3].1)];
+
+    for (&(cx, cy, radius), &(in_x, in_y)) in corners.iter().zip(inside.iter()) {
+        if radius > 0.0 && in_x && in_y {
+            let distance = ((x - cx) * (x - cx) + (y - cy) * (y - cy)).sqrt();
+            let distance_from_border = (distance - radius + 0.5 * pixels_per_fragment).max(0.0) /
+                                       pixels_per_fragment;
+            let t = clamp01(distance_from_border);
+            return 1.0 - t * t * (3.0 - 2.0 * t);
+        }
+    }
+
+    1.0
+}
+
+/// Returns true if the point is outside an ellipse centered on a corner of
+/// a rounded rectangle, while being in the area of that corner.
+fn outside_corner(x: f32, y: f32, rect: &Bounds, radii: &[(f32, f32); 4]) -> bool {
+    let corners = [(rect.x0 + radii[0].0, rect.y0 + radii[0].1, radii[0]),
+                   (rect.x1 - radii[1].0, rect.y0 + radii[1].1, radii[1]),
+                   (rect.x1 - radii[2].0, rect.y1 - radii[2].1, radii[2]),
+                   (rect.x0 + radii[3].0, rect.y1 - radii[3].1, radii[3])];
+    let inside = [x < corners[0].0 && y < corners[0].1,
+                  x > corners[1].0 && y < corners[1].1,
+                  x > corners[2].0 && y > corners[2].1,
+                  x < corners[3].0 && y > corners[3].1];
+
+    for (&(cx, cy, (rx, ry)), &in_corner) in corners.iter().zip(inside.iter()) {
+        if in_corner && rx > 0.0 && ry > 0.0 {
+            let dx = (x - cx) / rx;
+            let dy = (y - cy) / ry;
+            return dx * dx + dy * dy > 1.0;
+        }
+    }
+
+    false
+}
+
+/// Evaluates a whole border at a local position. The data layout is the one
+/// written by `BorderPrimitiveGpu`: styles, widths, colors and radii, with
+/// the sides in left, top, right, bottom order.
+fn border_color(border: &[f32; 32], x: f32, y: f32, rect: &Bounds) -> Option<Rgba> {
+    const LEFT: usize = 0;
+    const TOP: usize = 1;
+    const RIGHT: usize = 2;
+    const BOTTOM: usize = 3;
+
+    let widths = [border[4], border[5], border[6], border[7]];
+    let radii = [(border[24], border[25]),
+                 (border[26], border[27]),
+                 (border[28], border[29]),
+                 (border[30], border[31])];
+
+    if !rect.contains(x, y) || outside_corner(x, y, rect, &radii) {
+        return None;
+    }
+
+    let inner = Bounds {
+        x0: rect.x0 + widths[LEFT],
+        y0: rect.y0 + widths[TOP],
+        x1: rect.x1 - widths[RIGHT],
+        y1: rect.y1 - widths[BOTTOM],
+    };
+    let inner_radii = [((radii[0].0 - widths[LEFT]).max(0.0), (radii[0].1 - widths[TOP]).max(0.0)),
+                       ((radii[1].0 - widths[RIGHT]).max(0.0), (radii[1].1 - widths[TOP]).max(0.0)),
+                       ((radii[2].0 - widths[RIGHT]).max(0.0), (radii[2].1 - widths[BOTTOM]).max(0.0)),
+                       ((radii[3].0 - widths[LEFT]).max(0.0), (radii[3].1 - widths[BOTTOM]).max(0.0))];
+    if x > inner.x0 && x < inner.x1 && y > inner.y0 && y < inner.y1 &&
+       !outside_corner(x, y, &inner, &inner_radii) {
+        return None;
+    }
+
+    // In the corners, the side is picked by the diagonal going from the
+    // outer corner to the inner one.
+    let corner = |dx: f32, dy: f32, wx: f32, wy: f32, vertical: usize, horizontal: usize| {
+        if dy * wx > dx * wy { vertical } else { horizontal }
+    };
+    let (side, in_corner) = if x < rect.x0 + radii[0].0.max(widths[LEFT]) &&
+                               y < rect.y0 + radii[0].1.max(widths[TOP]) {
+        (corner(x - rect.x0, y - rect.y0, widths[LEFT], widths[TOP], LEFT, TOP), true)
+    } else if x > rect.x1 - radii[1].0.max(widths[RIGHT]) &&
+              y < rect.y0 + radii[1].1.max(widths[TOP]) {
+        (corner(rect.x1 - x, y - rect.y0, widths[RIGHT], widths[TOP], RIGHT, TOP), true)
+    } else if x > rect.x1 - radii[2].0.max(widths[RIGHT]) &&
+              y > rect.y1 - radii[2].1.max(widths[BOTTOM]) {
+        (corner(rect.x1 - x, rect.y1 - y, widths[RIGHT], widths[BOTTOM], RIGHT, BOTTOM), true)
+    } else if x < rect.x0 + radii[3].0.max(widths[LEFT]) &&
+              y > rect.y1 - radii[3].1.max(widths[BOTTOM]) {
+        (corner(x - rect.x0, rect.y1 - y, widths[LEFT], widths[BOTTOM], LEFT, BOTTOM), true)
+    } else if x < inner.x0 {
+        (LEFT, false)
+    } else if x > inner.x1 {
+        (RIGHT, false)
+    } else if y < inner.y0 {
+        (TOP, false)
+    } else {
+        (BOTTOM, false)
+    };
+
+    let width = widths[side];
+    // The distance from the start of the piece of this side, as the border
+    // shader measures it.
+    let distance = match side {
+        LEFT => x - rect.x0,
+        TOP => y - rect.y0,
+        RIGHT => x - inner.x1,
+        _ => y - inner.y1,
+    };
+    let color_offset = 8 + side * 4;
+    let color = [border[color_offset],
+                 border[color_offset + 1],
+                 border[color_offset + 2],
+                 border[color_offset + 3]];
+
+    // Must match the BorderStyle values in webrender_traits.
+    match border[side] as i32 {
+        // Hidden.
+        5 => None,
+        // Double: the middle third is left empty.
+        2 => {
+            let f = distance / width;
+            if f >= 1.0 / 3.0 && f < 2.0 / 3.0 { None } else { Some(color) }
+        }
+        // Groove and ridge.
+        6 | 7 => {
+            let brightness_mod = if border[side] as i32 == 6 { (1.3, 0.7) } else { (0.7, 1.3) };
+            let modulation = if distance / width > 0.5 { brightness_mod.0 } else { brightness_mod.1 };
+            Some([color[0] * modulation, color[1] * modulation, color[2] * modulation, color[3]])
+        }
+        // Dotted and dashed edges. Like in the border shader, the corners
+        // are drawn solid and the pattern starts where the corner ends.
+        3 | 4 if !in_corner => {
+            // The position along the edge piece, and its length.
+            let (along, length) = match side {
+                LEFT => {
+                    let start = rect.y0 + radii[0].1.max(widths[TOP]);
+                    let end = rect.y1 - radii[3].1.max(widths[BOTTOM]);
+                    (y - start, end - start)
+                }
+                RIGHT => {
+                    let start = rect.y0 + radii[1].1.max(widths[TOP]);
+                    let end = rect.y1 - radii[2].1.max(widths[BOTTOM]);
+                    (y - start, end - start)
+                }
+                TOP => {
+                    let start = rect.x0 + radii[0].0.max(widths[LEFT]);
+                    let end = rect.x1 - radii[1].0.max(widths[RIGHT]);
+                    (x - start, end - start)
+                }
+                _ => {
+                    let start = rect.x0 + radii[3].0.max(widths[LEFT]);
+                    let end = rect.x1 - radii[2].0.max(widths[RIGHT]);
+                    (x - start, end - start)
+                }
+            };
+
+            let visible = if border[side] as i32 == 4 {
+                dashed_edge(along, width)
+            } else {
+                dotted_edge(along, length, distance, width)
+            };
+            if visible { Some(color) } else { None }
+        }
+        // None, solid, inset and outset.
+        _ => Some(color),
+    }
+}
+
+/// Returns true if a position along a dashed edge is in a dash, like
+/// `draw_dashed_edge` in ps_border: dashes are three times as long as the
+/// border is wide, and the edge starts with a gap.
+fn dashed_edge(along: f32, width: f32) -> bool {
+    let segment = (along / (width * 3.0)).floor();
+    modulo(segment, 2.0) == 1.0
+}
+
+/// Returns true if a position is in one of the dots of a dotted edge, like
+/// `draw_dotted_edge` in ps_border: the dots are spread evenly along the
+/// edge, which ends with half dots that meet the corners.
+fn dotted_edge(along: f32, length: f32, across: f32, width: f32) -> bool {
+    let diameter = width;
+    let edge_available = length - diameter * 2.0;
+    let number_of_circles = (edge_available / (diameter * 2.0)).floor();
+
+    // The distance from the center of the closest dot, along the edge.
+    let mut distance = along.min(length - along);
+    if number_of_circles > 0.0 {
+        let space_for_each_circle = (edge_available / number_of_circles).ceil();
+        let circle_index = ((along - diameter) / space_for_each_circle).max(0.0)
+                                                                        .min(number_of_circles - 1.0)
+                                                                        .floor();
+        let circle_center = circle_index * space_for_each_circle + space_for_each_circle / 2.0 + diameter;
+        distance = distance.min((circle_center - along).abs());
+    }
+
+    let dx = across - width / 2.0;
+    (dx * dx + distance * distance).sqrt() <= diameter / 2.0
+}
+
+/// The weight of the DCT coefficient at `frequency` for the sample at `pos`
+/// within its block, see `idct_basis` in cs_idct.fs.glsl.
+fn idct_basis(pos: i32, frequency: i32) -> f32 {
+    let scale = if frequency == 0 { f32::consts::FRAC_1_SQRT_2 } else { 1.0 };
+    scale * ((2 * pos + 1) as f32 * frequency as f32 * f32::consts::PI / 16.0).cos()
+}
+
+/// Converts a YUV sample to RGB, see `get_yuv_color_matrix` in prim_shared.glsl.
+fn yuv_to_rgb(y: f32, u: f32, v: f32, color_space: f32, color_range: f32) -> [f32; 3] {
+    let (kr, kb) = match color_space as i32 {
+        1 => (0.299, 0.114),   // Rec601
+        2 => (0.2126, 0.0722), // Rec709
+        _ => (0.2627, 0.0593), // Rec2020
+    };
+    let kg = 1.0 - kr - kb;
+
+    let (y, u, v) = if color_range as i32 == 1 {
+        // Full range
+        (y, u - 0.50196, v - 0.50196)
+    } else {
+        // Limited range
+        ((y - 0.06275) * 255.0 / 219.0,
+         (u - 0.50196) * 255.0 / 224.0,
+         (v - 0.50196) * 255.0 / 224.0)
+    };
+
+    let rgb = [y + 2.0 * (1.0 - kr) * v,
+               y - 2.0 * kb * (1.0 - kb) / kg * u - 2.0 * kr * (1.0 - kr) / kg * v,
+               y + 2.0 * (1.0 - kb) * u];
+    if color_space as i32 == 3 {
+        rec2020_to_rec709(rgb)
+    } else {
+        rgb
+    }
+}
+
+/// Converts a color with Rec2020 primaries to Rec709 ones, see
+/// `rec2020_to_rec709` in prim_shared.glsl.
+fn rec2020_to_rec709(rgb: [f32; 3]) -> [f32; 3] {
+    const PRIMARIES: [[f32; 3]; 3] = [[ 1.66050, -0.58764, -0.07286],
+                                      [-0.12455,  1.13290, -0.00835],
+                                      [-0.01815, -0.10058,  1.11873]];
+    let linear = [rgb[0].max(0.0).min(1.0).powf(2.4),
+                  rgb[1].max(0.0).min(1.0).powf(2.4),
+                  rgb[2].max(0.0).min(1.0).powf(2.4)];
+    let mut result = [0.0; 3];
+    for (channel, row) in result.iter_mut().zip(PRIMARIES.iter()) {
+        let value = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+        *channel = value.max(0.0).min(1.0).powf(1.0 / 2.4);
+    }
+    result
+}
+
+fn rgb_to_hsv(c: &[f32; 3]) -> [f32; 3] {
+    let value = c[0].max(c[1]).max(c[2]);
+    let chroma = value - c[0].min(c[1]).min(c[2]);
+    if chroma == 0.0 {
+        return [0.0; 3];
+    }
+    let saturation = chroma / value;
+    let mut hue = if c[0] == value {
+        (c[1] - c[2]) / chroma
+    } else if c[1] == value {
+        2.0 + (c[2] - c[0]) / chroma
+    } else {
+        4.0 + (c[0] - c[1]) / chroma
+    };
+    hue *= 1.0 / 6.0;
+    if hue < 0.0 {
+        hue += 1.0;
+    }
+    [hue, saturation, value]
+}
+
+fn hsv_to_rgb(c: &[f32; 3]) -> [f32; 3] {
+    let (h, s, v) = (c[0], c[1], c[2]);
+    if s == 0.0 {
+        return [v; 3];
+    }
+    let hue = h * 6.0;
+    let sector = hue as i32;
+    let residual = hue - sector as f32;
+    let (p, q, t) = (v * (1.0 - s), v * (1.0 - s * residual), v * (1.0 - s * (1.0 - residual)));
+    match sector {
+        0 => [v, t, p],
+        1 => [q, v, p],
+        2 => [p, v, t],
+        3 => [p, q, v],
+        4 => [t, p, v],
+        _ => [v, p, q],
+    }
+}
+
+/// Applies a filter to a premultiplied color, see ps_blend.fs.glsl.
+fn apply_filter(op: i32, cs: Rgba, amount: f32) -> Rgba {
+    let ia = 1.0 - amount;
+    match op {
+        1 => {
+            [cs[0] * amount - 0.5 * amount + 0.5,
+             cs[1] * amount - 0.5 * amount + 0.5,
+             cs[2] * amount - 0.5 * amount + 0.5,
+             1.0]
+        }
+        2 => {
+            [cs[0] * (0.2126 + 0.7874 * ia) + cs[1] * (0.7152 - 0.7152 * ia) + cs[2] * (0.0722 - 0.0722 * ia),
+             cs[0] * (0.2126 - 0.2126 * ia) + cs[1] * (0.7152 + 0.2848 * ia) + cs[2] * (0.0722 - 0.0722 * ia),
+             cs[0] * (0.2126 - 0.2126 * ia) + cs[1] * (0.7152 - 0.7152 * ia) + cs[2] * (0.0722 + 0.9278 * ia),
+             cs[3]]
+        }
+        3 => {
+            let mut hsv = rgb_to_hsv(&[cs[0], cs[1], cs[2]]);
+            hsv[0] = modulo(hsv[0] + amount / 6.283185307179586, 1.0);
+            let rgb = hsv_to_rgb(&hsv);
+            [rgb[0], rgb[1], rgb[2], cs[3]]
+        }
+        4 => {
+            [mix(cs[0], 1.0 - cs[0], amount),
+             mix(cs[1], 1.0 - cs[1], amount),
+             mix(cs[2], 1.0 - cs[2], amount),
+             cs[3]]
+        }
+        5 => {
+            let hsv = rgb_to_hsv(&[cs[0], cs[1], cs[2]]);
+            let rgb = hsv_to_rgb(&[hsv[0].min(1.0), (hsv[1] * amount).min(1.0), hsv[2].min(1.0)]);
+            [rgb[0], rgb[1], rgb[2], cs[3]]
+        }
+        6 => {
+            [cs[0] * (0.393 + 0.607 * ia) + cs[1] * (0.769 - 0.769 * ia) + cs[2] * (0.189 - 0.189 * ia),
+             cs[0] * (0.349 - 0.349 * ia) + cs[1] * (0.686 + 0.314 * ia) + cs[2] * (0.168 - 0.168 * ia),
+             cs[0] * (0.272 - 0.272 * ia) + cs[1] * (0.534 - 0.534 * ia) + cs[2] * (0.131 + 0.869 * ia),
+             cs[3]]
+        }
+        7 => [cs[0] * amount, cs[1] * amount, cs[2] * amount, cs[3]],
+        8 => [cs[0], cs[1], cs[2], cs[3] * amount],
+        // Blurs are drawn by the blur tasks, this just passes the result through.
+        _ => cs,
+    }
+}
+
+fn lum(c: &[f32; 3]) -> f32 {
+    c[0] * 0.3 + c[1] * 0.59 + c[2] * 0.11
+}
+
+fn sat(c: &[f32; 3]) -> f32 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn set_lum(c: &[f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    let mut c = [c[0] + d, c[1] + d, c[2] + d];
+    let l = lum(&c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    if n < 0.0 {
+        for v in &mut c {
+            *v = l + (*v - l) * l / (l - n);
+        }
+    }
+    if x > 1.0 {
+        for v in &mut c {
+            *v = l + (*v - l) * (1.0 - l) / (x - l);
+        }
+    }
+    c
+}
+
+fn set_sat(c: &[f32; 3], s: f32) -> [f32; 3] {
+    let mut order = [0, 1, 2];
+    order.sort_by(|a, b| c[*a].partial_cmp(&c[*b]).unwrap());
+    let (min, mid, max) = (order[0], order[1], order[2]);
+    let mut result = [0.0; 3];
+    if c[max] > c[min] {
+        result[mid] = (c[mid] - c[min]) * s / (c[max] - c[min]);
+        result[max] = s;
+    }
+    result
+}
+
+/// Evaluates a mix blend mode, see ps_composite.fs.glsl.
+fn mix_blend(op: i32, cb: Rgba, cs: Rgba) -> Rgba {
+    let b = [cb[0], cb[1], cb[2]];
+    let s = [cs[0], cs[1], cs[2]];
+    let per_channel = |f: &Fn(f32, f32) -> f32| [f(b[0], s[0]), f(b[1], s[1]), f(b[2], s[2])];
+    let screen = |b: f32, s: f32| b + s - b * s;
+    let hard_light = |b: f32, s: f32| if s >= 0.5 { screen(b, 2.0 * s - 1.0) } else { b * 2.0 * s };
+
+    let rgb = match op {
+        2 => per_channel(&screen),
+        3 => per_channel(&|b, s| hard_light(s, b)),
+        6 => per_channel(&|b, s| {
+            if b == 0.0 { 0.0 } else if s == 1.0 { 1.0 } else { (b / (1.0 - s)).min(1.0) }
+        }),
+        7 => per_channel(&|b, s| {
+            if b == 1.0 { 1.0 } else if s == 0.0 { 0.0 } else { 1.0 - ((1.0 - b) / s).min(1.0) }
+        }),
+        8 => per_channel(&hard_light),
+        9 => per_channel(&|b, s| {
+            if s <= 0.5 {
+                b - (1.0 - 2.0 * s) * b * (1.0 - b)
+            } else {
+                let d = if b <= 0.25 { ((16.0 * b - 12.0) * b + 4.0) * b } else { b.sqrt() };
+                b + (2.0 * s - 1.0) * (d - b)
+            }
+        }),
+        10 => per_channel(&|b, s| (b - s).abs()),
+        11 => per_channel(&|b, s| b + s - 2.0 * b * s),
+        12 => set_lum(&set_sat(&s, sat(&b)), lum(&b)),
+        13 => set_lum(&set_sat(&b, sat(&s)), lum(&b)),
+        14 => set_lum(&s, lum(&b)),
+        15 => set_lum(&b, lum(&s)),
+        // Yellow shows that the mode isn't handled, like the shader does.
+        _ => [1.0, 1.0, 0.0],
+    };
+
+    [rgb[0], rgb[1], rgb[2], 1.0]
+}
+
+/// Draws frames built by the render backend into a framebuffer in system
+/// memory, without using the GPU.
+pub struct SoftwareRasterizer {
+    cache_textures: Vec<Option<CpuTexture>>,
+    framebuffer: Vec<u8>,
+    framebuffer_size: DeviceUintSize,
+    clear_color: ColorF,
+
+    external_image_handler: Option<Box<ExternalImageHandler>>,
+    /// The external images locked for the frame being drawn.
+    locked_external_images: Vec<ExternalImageId>,
+    raw_external_images: HashMap<ExternalImageId, RawExternalImage>,
+}
+
+impl SoftwareRasterizer {
+    pub fn new(clear_color: ColorF) -> SoftwareRasterizer {
+        SoftwareRasterizer {
+            cache_textures: Vec::new(),
+            framebuffer: Vec::new(),
+            framebuffer_size: DeviceUintSize::zero(),
+            clear_color: clear_color,
+            external_image_handler: None,
+            locked_external_images: Vec::new(),
+            raw_external_images: HashMap::new(),
+        }
+    }
+
+    pub fn set_external_image_handler(&mut self, handler: Box<ExternalImageHandler>) {
+        self.external_image_handler = Some(handler);
+    }
+
+    /// Applies the pending texture updates, drawing each rendered image once
+    /// the updates it was built against are applied, then draws the frame.
+    pub fn render(&mut self,
+                  frame: &mut Frame,
+                  framebuffer_size: DeviceUintSize,
+                  texture_updates: Vec<TextureUpdateList>,
+                  rendered_images: Vec<(usize, CacheTextureId, RendererFrame)>) {
+        let mut rendered_images = rendered_images.into_iter().peekable();
+        for (update_index, update_list) in texture_updates.into_iter().enumerate() {
+            self.update_texture_cache(update_list);
+
+            while rendered_images.peek().map_or(false, |&(update_count, _, _)| update_count == update_index + 1) {
+                let (_, texture_id, mut rendered_frame) = rendered_images.next().unwrap();
+                if let Some(ref mut rendered_frame) = rendered_frame.frame {
+                    self.draw_rendered_image(texture_id, rendered_frame);
+                }
+            }
+        }
+
+        self.draw_frame(frame, framebuffer_size);
+    }
+
+    pub fn release_external_images(&mut self, mut pending_external_image_updates: ExternalImageUpdateList) {
+        if !pending_external_image_updates.is_empty() {
+            let handler = self.external_image_handler
+                              .as_mut()
+                              .expect("found external image updates, but no handler set!");
+
+            for external_id in pending_external_image_updates.drain(..) {
+                self.raw_external_images.remove(&external_id);
+                handler.release(external_id);
+            }
+        }
+    }
+
+    /// Locks the external images of a frame and patches their resource
+    /// rects, like the GL renderer does. Only `RawData` images can be drawn,
+    /// by copying their data; native textures are left transparent.
+    fn update_deferred_resolves(&mut self, frame: &mut Frame) {
+        if frame.deferred_resolves.is_empty() {
+            return;
+        }
+
+        let handler = self.external_image_handler
+                          .as_mut()
+                          .expect("Found external image, but no handler set!");
+
+        for deferred_resolve in &frame.deferred_resolves {
+            let props = &deferred_resolve.image_properties;
+            let external_id = props.external_id
+                                   .expect("BUG: Deferred resolves must be external images!");
+            let image = handler.lock(external_id);
+            self.locked_external_images.push(external_id);
+
+            match image.source {
+                ExternalImageSource::NativeTexture(..) => {
+                    warn!("Native external textures can't be drawn by the software rasterizer");
+                }
+                ExternalImageSource::RawData(data, size) => {
+                    let (has_same_size, is_current) = match self.raw_external_images.get(&external_id) {
+                        Some(raw_image) => {
+                            let has_same_size = raw_image.texture.width == props.width &&
+                                                raw_image.texture.height == props.height &&
+                                                raw_image.texture.format == props.format;
+                            (has_same_size, has_same_size && raw_image.generation == image.generation)
+                        }
+                        None => (false, false),
+                    };
+
+                    if !is_current {
+                        let required = props.data_size();
+                        if size < required {
+                            // The previous copy is still drawn if it fits, and
+                            // else nothing is, as for a missing texture.
+                            warn!("External image {:?} has {} bytes, {} are needed", external_id, size, required);
+                            if !has_same_size {
+                                self.raw_external_images.remove(&external_id);
+                            }
+                        } else {
+                            let mut texture = CpuTexture::new(props.width,
+                                                              props.height,
+                                                              props.format,
+                                                              TextureFilter::Linear,
+                                                              None);
+                            let data = unsafe { slice::from_raw_parts(data, size) };
+                            texture.update(0, 0, props.width, props.height, props.stride, data);
+                            self.raw_external_images.insert(external_id, RawExternalImage {
+                                texture: texture,
+                                generation: image.generation,
+                            });
+                        }
+                    }
+                }
+            }
+
+            let resource_rect_index = deferred_resolve.resource_address.0 as usize;
+            let resource_rect = &mut frame.gpu_resource_rects[resource_rect_index];
+            resource_rect.uv0 = DevicePoint::new(image.u0, image.v0);
+            resource_rect.uv1 = DevicePoint::new(image.u1, image.v1);
+        }
+    }
+
+    fn unlock_external_images(&mut self) {
+        if !self.locked_external_images.is_empty() {
+            let handler = self.external_image_handler
+                              .as_mut()
+                              .expect("Found external image, but no handler set!");
+
+            for external_id in self.locked_external_images.drain(..) {
+                handler.unlock(external_id);
+            }
+        }
+    }
+
+    /// Applies the texture cache updates sent by the render backend to the
+    /// CPU copies of the cache textures.
+    fn update_texture_cache(&mut self, update_list: TextureUpdateList) {
+        for update in update_list.updates {
+            let CacheTextureId(index) = update.id;
+            match update.op {
+                TextureUpdateOp::Create(width, height, format, filter, _, maybe_bytes) => {
+                    while self.cache_textures.len() <= index {
+                        self.cache_textures.push(None);
+                    }
+                    let bytes = maybe_bytes.as_ref().map(|bytes| bytes.as_slice());
+                    self.cache_textures[index] = Some(CpuTexture::new(width,
+                                                                      height,
+                                                                      format,
+                                                                      filter,
+                                                                      bytes));
+                }
+                TextureUpdateOp::Grow(width, height, format, filter, _) => {
+                    let texture = self.cache_textures[index].as_mut()
+                                                            .expect("Grow of an unknown texture");
+                    texture.resize(width, height, format, filter);
+                }
+                TextureUpdateOp::Update(x, y, width, height, bytes, stride) => {
+                    let texture = self.cache_textures[index].as_mut()
+                                                            .expect("Update of an unknown texture");
+                    texture.update(x, y, width, height, stride, bytes.as_slice());
+                }
+                TextureUpdateOp::Copy(src_id, src_rect, dest_origin) => {
+                    let bytes = self.cache_textures[src_id.0].as_ref()
+                                                             .expect("Copy from an unknown texture")
+                                                             .read(src_rect);
+                    let texture = self.cache_textures[index].as_mut()
+                                                            .expect("Copy to an unknown texture");
+                    texture.update(dest_origin.x,
+                                   dest_origin.y,
+                                   src_rect.size.width,
+                                   src_rect.size.height,
+                                   None,
+                                   &bytes);
+                }
+                TextureUpdateOp::Free => {
+                    self.cache_textures[index] = None;
+                }
+            }
+        }
+    }
+
+    /// Draws a frame, replacing the contents of the framebuffer.
+    fn draw_frame(&mut self, frame: &mut Frame, framebuffer_size: DeviceUintSize) {
+        let clear_color = if frame.passes.is_empty() {
+            self.clear_color
+        } else {
+            frame.background_color.unwrap_or(self.clear_color)
+        };
+        self.update_deferred_resolves(frame);
+        let framebuffer = self.rasterize(frame, framebuffer_size, clear_color);
+        self.unlock_external_images();
+
+        self.framebuffer.clear();
+        for pixel in &framebuffer.pixels {
+            for channel in pixel {
+                self.framebuffer.push((clamp01(*channel) * 255.0 + 0.5) as u8);
+            }
+        }
+        self.framebuffer_size = framebuffer_size;
+    }
+
+    /// Draws a frame into the render target of a rendered image, which is
+    /// transparent where the frame has no background color.
+    fn draw_rendered_image(&mut self, texture_id: CacheTextureId, frame: &mut Frame) {
+        let CacheTextureId(index) = texture_id;
+        let size = {
+            let texture = self.cache_textures[index].as_ref()
+                                                    .expect("Draw into an unknown texture");
+            debug_assert!(texture.format == ImageFormat::RGBA8);
+            DeviceUintSize::new(texture.width, texture.height)
+        };
+
+        let clear_color = frame.background_color.unwrap_or(ColorF::new(0.0, 0.0, 0.0, 0.0));
+        self.update_deferred_resolves(frame);
+        let surface = self.rasterize(frame, size, clear_color);
+        self.unlock_external_images();
+
+        // RGBA8 textures hold their texels as BGRA.
+        let mut bytes = Vec::with_capacity(surface.pixels.len() * 4);
+        for pixel in &surface.pixels {
+            for &channel in &[pixel[2], pixel[1], pixel[0], pixel[3]] {
+                bytes.push((clamp01(channel) * 255.0 + 0.5) as u8);
+            }
+        }
+
+        let texture = self.cache_textures[index].as_mut().unwrap();
+        texture.update(0, 0, size.width, size.height, None, &bytes);
+    }
+
+    fn rasterize(&self,
+                 frame: &Frame,
+                 size: DeviceUintSize,
+                 clear_color: ColorF) -> Surface {
+        let context = FrameContext {
+            frame: frame,
+            textures: &self.cache_textures,
+            external_textures: &self.raw_external_images,
+            device_pixel_ratio: frame.device_pixel_ratio,
+        };
+
+        let mut output = Surface::new(size.width as i32,
+                                      size.height as i32,
+                                      clear_color.to_array());
+
+        // The render targets of the previous pass, which the current pass
+        // reads from.
+        let mut cache: Vec<Surface> = Vec::new();
+        for pass in &frame.passes {
+            if pass.is_framebuffer {
+                for target in &pass.targets {
+                    context.draw_target(&mut output, target, &cache);
+                }
+            } else {
+                let targets = pass.targets.iter().map(|target| {
+                    let mut surface = Surface::new(frame.cache_size.width as i32,
+                                                   frame.cache_size.height as i32,
+                                                   TARGET_CLEAR_COLOR);
+                    context.draw_target(&mut surface, target, &cache);
+                    surface
+                }).collect();
+                cache = targets;
+            }
+        }
+
+        output
+    }
+
+    /// The RGBA8 contents of the framebuffer, with the rows stored top down.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    pub fn framebuffer_size(&self) -> DeviceUintSize {
+        self.framebuffer_size
+    }
+}
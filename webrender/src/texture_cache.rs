@@ -8,7 +8,7 @@ use freelist::{FreeList, FreeListItem, FreeListItemId};
 use internal_types::{TextureUpdate, TextureUpdateOp};
 use internal_types::{CacheTextureId, RenderTargetMode, TextureUpdateList, RectUv};
 use std::cmp::{self, Ordering};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::Entry;
 use std::hash::BuildHasherDefault;
 use std::mem;
@@ -50,6 +50,15 @@ const COALESCING_TIMEOUT: u64 = 100;
 /// the timeout.
 const COALESCING_TIMEOUT_CHECKING_INTERVAL: usize = 256;
 
+/// Texture pages that use less than this fraction of their area are emptied when the
+/// cache is compacted, if their items fit in the other pages.
+const COMPACTION_OCCUPANCY_THRESHOLD: f32 = 0.25;
+
+/// Moving items forces every document to build its frame again, so the
+/// cache is compacted at most once in this many frames, unless it is over
+/// its budget.
+const COMPACTION_FRAME_INTERVAL: usize = 60;
+
 pub type TextureCacheItemId = FreeListItemId;
 
 #[inline]
@@ -426,6 +435,9 @@ pub struct TextureCacheItem {
     // Identifies the texture and array slice
     pub texture_id: CacheTextureId,
 
+    // The format of the texture
+    pub format: ImageFormat,
+
     // The texture coordinates for this item
     pub pixel_rect: RectUv<i32, DevicePixel>,
 
@@ -469,12 +481,14 @@ impl FreeListItem for TextureCacheItem {
 
 impl TextureCacheItem {
     fn new(texture_id: CacheTextureId,
+           format: ImageFormat,
            allocated_rect: DeviceUintRect,
            requested_rect: DeviceUintRect,
            texture_size: &DeviceUintSize)
            -> TextureCacheItem {
         TextureCacheItem {
             texture_id: texture_id,
+            format: format,
             texture_size: *texture_size,
             pixel_rect: RectUv {
                 top_left: DeviceIntPoint::new(requested_rect.origin.x as i32,
@@ -490,31 +504,49 @@ impl TextureCacheItem {
             requested_rect: requested_rect,
         }
     }
+
+    /// The number of bytes of texture memory used by the item.
+    fn allocated_bytes(&self) -> usize {
+        self.format.packed_size(self.allocated_rect.size.width,
+                                self.allocated_rect.size.height).unwrap_or(0) as usize
+    }
 }
 
+/// The texture pages, kept apart by format and by filter, since both are
+/// properties of the whole texture.
 struct TextureCacheArena {
-    pages_a8: Vec<TexturePage>,
-    pages_rgb8: Vec<TexturePage>,
-    pages_rgba8: Vec<TexturePage>,
+    pages: HashMap<(ImageFormat, TextureFilter), Vec<TexturePage>>,
 }
 
 impl TextureCacheArena {
     fn new() -> TextureCacheArena {
         TextureCacheArena {
-            pages_a8: Vec::new(),
-            pages_rgb8: Vec::new(),
-            pages_rgba8: Vec::new(),
+            pages: HashMap::new(),
         }
     }
 
-    fn texture_page_for_id(&mut self, id: CacheTextureId) -> Option<&mut TexturePage> {
-        for page in self.pages_a8.iter_mut().chain(self.pages_rgb8.iter_mut())
-                                            .chain(self.pages_rgba8.iter_mut()) {
-            if page.texture_id == id {
-                return Some(page)
-            }
+    fn pages_for_format(&self, format: ImageFormat, filter: TextureFilter) -> &[TexturePage] {
+        match self.pages.get(&(format, filter)) {
+            Some(pages) => &pages[..],
+            None => &[],
         }
-        None
+    }
+
+    fn pages_for_format_mut(&mut self,
+                            format: ImageFormat,
+                            filter: TextureFilter) -> &mut Vec<TexturePage> {
+        self.pages.entry((format, filter)).or_insert_with(Vec::new)
+    }
+
+    /// The formats and filters that have pages.
+    fn page_kinds(&self) -> Vec<(ImageFormat, TextureFilter)> {
+        self.pages.keys().cloned().collect()
+    }
+
+    fn texture_page_for_id(&mut self, id: CacheTextureId) -> Option<&mut TexturePage> {
+        self.pages.values_mut()
+                  .flat_map(|pages| pages.iter_mut())
+                  .find(|page| page.texture_id == id)
     }
 }
 
@@ -550,10 +582,27 @@ impl CacheTextureIdList {
 
 pub struct TextureCache {
     cache_id_list: CacheTextureIdList,
-    free_texture_levels: HashMap<ImageFormat, Vec<FreeTextureLevel>, BuildHasherDefault<FnvHasher>>,
+    free_texture_levels: HashMap<(ImageFormat, TextureFilter),
+                                 Vec<FreeTextureLevel>,
+                                 BuildHasherDefault<FnvHasher>>,
     items: FreeList<TextureCacheItem>,
     arena: TextureCacheArena,
     pending_updates: TextureUpdateList,
+    needs_compaction: bool,
+    frames_since_compaction: usize,
+    pinned_items: HashSet<TextureCacheItemId>,
+    render_target_items: HashSet<TextureCacheItemId>,
+    /// The bytes of texture memory in use: whole texture pages, including
+    /// the empty ones kept for later allocations, and standalone textures.
+    allocated_bytes: usize,
+    /// The bytes used by the items themselves. Evicting items brings it
+    /// down, and `allocated_bytes` follows once the pages are compacted.
+    item_bytes: usize,
+    pinned_bytes: usize,
+    render_target_bytes: usize,
+    budget: Option<usize>,
+    compressed_formats: Vec<ImageFormat>,
+    expanded_formats: Vec<ImageFormat>,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -569,16 +618,86 @@ pub struct AllocationResult {
 }
 
 impl TextureCache {
-    pub fn new() -> TextureCache {
+    /// Creates a texture cache. When there is a `budget`, the texture memory
+    /// used by the items that can be evicted should be at most that many
+    /// bytes, see `is_over_budget`.
+    /// `compressed_formats` are the compressed formats the device can sample from,
+    /// and `expanded_formats` the uncompressed ones it can't.
+    pub fn new(budget: Option<usize>,
+               compressed_formats: Vec<ImageFormat>,
+               expanded_formats: Vec<ImageFormat>) -> TextureCache {
         TextureCache {
             cache_id_list: CacheTextureIdList::new(),
             free_texture_levels: HashMap::with_hasher(Default::default()),
             items: FreeList::new(),
             pending_updates: TextureUpdateList::new(),
             arena: TextureCacheArena::new(),
+            needs_compaction: false,
+            frames_since_compaction: 0,
+            pinned_items: HashSet::new(),
+            render_target_items: HashSet::new(),
+            allocated_bytes: 0,
+            item_bytes: 0,
+            pinned_bytes: 0,
+            render_target_bytes: 0,
+            budget: budget,
+            compressed_formats: compressed_formats,
+            expanded_formats: expanded_formats,
+        }
+    }
+
+    /// Returns false for the compressed formats that have to be decoded, and the
+    /// uncompressed ones that have to be expanded to RGBA8, before they can be
+    /// inserted.
+    pub fn supports_format(&self, format: ImageFormat) -> bool {
+        if format.is_compressed() {
+            self.compressed_formats.contains(&format)
+        } else {
+            !self.expanded_formats.contains(&format)
+        }
+    }
+
+    pub fn has_budget(&self) -> bool {
+        self.budget.is_some()
+    }
+
+    /// Returns true if the texture memory used for items that can be evicted
+    /// is more than the budget allows. Pinned items and rendered images can't
+    /// be evicted, so they don't count.
+    pub fn is_over_budget(&self) -> bool {
+        match self.budget {
+            Some(budget) => self.allocated_bytes - self.unevictable_bytes() > budget,
+            None => false,
+        }
+    }
+
+    /// Returns true if the items that can be evicted don't fit in the budget
+    /// on their own, in which case the least recently used of them should be
+    /// freed. The texture pages they leave empty are released by `compact`.
+    pub fn needs_eviction(&self) -> bool {
+        match self.budget {
+            Some(budget) => self.item_bytes - self.unevictable_bytes() > budget,
+            None => false,
+        }
+    }
+
+    fn unevictable_bytes(&self) -> usize {
+        self.pinned_bytes + self.render_target_bytes
+    }
+
+    /// Marks an item as one that is never freed, e.g. because the renderer
+    /// relies on it. Pinned items don't count towards the budget.
+    pub fn pin(&mut self, id: TextureCacheItemId) {
+        if self.pinned_items.insert(id) {
+            self.pinned_bytes += self.items.get(id).allocated_bytes();
         }
     }
 
+    /// The number of bytes of texture memory used by all items.
+    pub fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes
+    }
+
     pub fn pending_updates(&mut self) -> TextureUpdateList {
         mem::replace(&mut self.pending_updates, TextureUpdateList::new())
     }
@@ -598,6 +717,7 @@ impl TextureCache {
             requested_rect: DeviceUintRect::zero(),
             texture_size: DeviceUintSize::zero(),
             texture_id: CacheTextureId(0),
+            format: ImageFormat::Invalid,
         };
         self.items.insert(new_item)
     }
@@ -611,19 +731,21 @@ impl TextureCache {
                     -> AllocationResult {
         let requested_size = DeviceUintSize::new(requested_width, requested_height);
 
-        // TODO(gw): For now, anything that requests nearest filtering
-        //           just fails to allocate in a texture page, and gets a standalone
-        //           texture. This isn't ideal, as it causes lots of batch breaks,
-        //           but is probably rare enough that it can be fixed up later (it's also
-        //           fairly trivial to implement, just tedious).
-        if filter == TextureFilter::Nearest {
-            // Fall back to standalone texture allocation.
+        // The mipmap levels of an item in a texture page would blend in the
+        // items next to it, so mipmapped items get a texture of their own.
+        // So do compressed items, which can't have the usual borders.
+        if filter == TextureFilter::Trilinear || format.is_compressed() {
+            assert!(self.supports_format(format));
+
             let texture_id = self.cache_id_list.allocate();
             let cache_item = TextureCacheItem::new(
                 texture_id,
+                format,
                 DeviceUintRect::new(DeviceUintPoint::zero(), requested_size),
                 DeviceUintRect::new(DeviceUintPoint::zero(), requested_size),
                 &requested_size);
+            self.allocated_bytes += cache_item.allocated_bytes();
+            self.item_bytes += cache_item.allocated_bytes();
             *self.items.get_mut(image_id) = cache_item;
 
             return AllocationResult {
@@ -633,12 +755,7 @@ impl TextureCache {
         }
 
         let mode = RenderTargetMode::SimpleRenderTarget;
-        let page_list = match format {
-            ImageFormat::A8 => &mut self.arena.pages_a8,
-            ImageFormat::RGBA8 => &mut self.arena.pages_rgba8,
-            ImageFormat::RGB8 => &mut self.arena.pages_rgb8,
-            ImageFormat::Invalid | ImageFormat::RGBAF32 => unreachable!(),
-        };
+        let page_list = self.arena.pages_for_format_mut(format, filter);
 
         let border_size = 1;
         let allocation_size = DeviceUintSize::new(requested_width + border_size * 2,
@@ -664,9 +781,11 @@ impl TextureCache {
                     requested_size);
 
                 let cache_item = TextureCacheItem::new(page.texture_id,
+                                                       format,
                                                        allocated_rect,
                                                        requested_rect,
                                                        &DeviceUintSize::new(page.texture_size, page.texture_size));
+                self.item_bytes += cache_item.allocated_bytes();
                 *self.items.get_mut(image_id) = cache_item;
 
                 return AllocationResult {
@@ -680,9 +799,11 @@ impl TextureCache {
                 // Grow the texture.
                 let texture_size = cmp::min(last_page.texture_size * 2,
                                             max_texture_size());
+                self.allocated_bytes += page_bytes(format, texture_size) -
+                                        page_bytes(format, last_page.texture_size);
                 self.pending_updates.push(TextureUpdate {
                     id: last_page.texture_id,
-                    op: texture_grow_op(texture_size, format, mode),
+                    op: texture_grow_op(texture_size, format, filter, mode),
                 });
                 last_page.grow(texture_size);
 
@@ -696,33 +817,68 @@ impl TextureCache {
             }
 
             // We need a new page.
-            let texture_size = initial_texture_size();
-            let free_texture_levels_entry = self.free_texture_levels.entry(format);
+            let free_texture_levels_entry = self.free_texture_levels.entry((format, filter));
             let mut free_texture_levels = match free_texture_levels_entry {
                 Entry::Vacant(entry) => entry.insert(Vec::new()),
                 Entry::Occupied(entry) => entry.into_mut(),
             };
             if free_texture_levels.is_empty() {
+                let texture_size = initial_texture_size();
                 let texture_id = self.cache_id_list.allocate();
+                self.allocated_bytes += page_bytes(format, texture_size);
 
                 let update_op = TextureUpdate {
                     id: texture_id,
-                    op: texture_create_op(texture_size, format, mode),
+                    op: texture_create_op(texture_size, format, filter, mode),
                 };
                 self.pending_updates.push(update_op);
 
                 free_texture_levels.push(FreeTextureLevel {
                     texture_id: texture_id,
+                    texture_size: texture_size,
                 });
             }
             let free_texture_level = free_texture_levels.pop().unwrap();
-            let texture_id = free_texture_level.texture_id;
 
-            let page = TexturePage::new(texture_id, texture_size);
+            let page = TexturePage::new(free_texture_level.texture_id,
+                                        free_texture_level.texture_size);
             page_list.push(page);
         }
     }
 
+    /// Allocates a standalone RGBA8 texture that the renderer can draw into,
+    /// and returns its id so the renderer can be told what to draw there.
+    pub fn allocate_render_target(&mut self,
+                                  image_id: TextureCacheItemId,
+                                  width: u32,
+                                  height: u32) -> CacheTextureId {
+        let size = DeviceUintSize::new(width, height);
+        let texture_id = self.cache_id_list.allocate();
+        let cache_item = TextureCacheItem::new(texture_id,
+                                               ImageFormat::RGBA8,
+                                               DeviceUintRect::new(DeviceUintPoint::zero(), size),
+                                               DeviceUintRect::new(DeviceUintPoint::zero(), size),
+                                               &size);
+        // Rendered images can't be evicted, so they are left out of the budget.
+        self.allocated_bytes += cache_item.allocated_bytes();
+        self.item_bytes += cache_item.allocated_bytes();
+        self.render_target_bytes += cache_item.allocated_bytes();
+        self.render_target_items.insert(image_id);
+        *self.items.get_mut(image_id) = cache_item;
+
+        self.pending_updates.push(TextureUpdate {
+            id: texture_id,
+            op: TextureUpdateOp::Create(width,
+                                        height,
+                                        ImageFormat::RGBA8,
+                                        TextureFilter::Linear,
+                                        RenderTargetMode::SimpleRenderTarget,
+                                        None),
+        });
+
+        texture_id
+    }
+
     pub fn update(&mut self,
                   image_id: TextureCacheItemId,
                   width: u32,
@@ -732,9 +888,9 @@ impl TextureCache {
                   bytes: Arc<Vec<u8>>) {
         let existing_item = self.items.get(image_id);
 
-        // TODO(gw): Handle updates to size/format!
-        debug_assert!(existing_item.requested_rect.size.width == width);
-        debug_assert!(existing_item.requested_rect.size.height == height);
+        // Updates that change the size need a new allocation, see `ResourceCache`.
+        assert!(existing_item.requested_rect.size.width == width);
+        assert!(existing_item.requested_rect.size.height == height);
 
         let op = TextureUpdateOp::Update(existing_item.requested_rect.origin.x,
                                          existing_item.requested_rect.origin.y,
@@ -751,6 +907,29 @@ impl TextureCache {
         self.pending_updates.push(update_op);
     }
 
+    /// Uploads new pixels for `dirty_rect`, given relative to the item.
+    pub fn update_region(&mut self,
+                         image_id: TextureCacheItemId,
+                         dirty_rect: DeviceUintRect,
+                         stride: Option<u32>,
+                         bytes: Arc<Vec<u8>>) {
+        let existing_item = self.items.get(image_id);
+        assert!(dirty_rect.max_x() <= existing_item.requested_rect.size.width);
+        assert!(dirty_rect.max_y() <= existing_item.requested_rect.size.height);
+
+        let op = TextureUpdateOp::Update(existing_item.requested_rect.origin.x + dirty_rect.origin.x,
+                                         existing_item.requested_rect.origin.y + dirty_rect.origin.y,
+                                         dirty_rect.size.width,
+                                         dirty_rect.size.height,
+                                         bytes,
+                                         stride);
+
+        self.pending_updates.push(TextureUpdate {
+            id: existing_item.texture_id,
+            op: op,
+        });
+    }
+
     pub fn insert(&mut self,
                   image_id: TextureCacheItemId,
                   width: u32,
@@ -767,12 +946,7 @@ impl TextureCache {
 
         let op = match result.kind {
             AllocationKind::TexturePage => {
-                let bpp = match format {
-                    ImageFormat::A8 => 1,
-                    ImageFormat::RGB8 => 3,
-                    ImageFormat::RGBA8 => 4,
-                    ImageFormat::Invalid | ImageFormat::RGBAF32 => unreachable!(),
-                };
+                let bpp = format.bytes_per_pixel().unwrap();
 
                 let mut top_row_bytes = Vec::new();
                 let mut bottom_row_bytes = Vec::new();
@@ -870,13 +1044,23 @@ impl TextureCache {
     }
 
     pub fn free(&mut self, id: TextureCacheItemId) {
+        assert!(!self.pinned_items.contains(&id), "Pinned texture cache items can't be freed!");
+
         {
             let item = self.items.get(id);
+            self.item_bytes -= item.allocated_bytes();
+            if self.render_target_items.remove(&id) {
+                self.render_target_bytes -= item.allocated_bytes();
+            }
             match self.arena.texture_page_for_id(item.texture_id) {
-                Some(texture_page) => texture_page.free(&item.allocated_rect),
+                Some(texture_page) => {
+                    texture_page.free(&item.allocated_rect);
+                    self.needs_compaction = true;
+                }
                 None => {
                     // This is a standalone texture allocation. Just push it back onto the free
                     // list.
+                    self.allocated_bytes -= item.allocated_bytes();
                     self.pending_updates.push(TextureUpdate {
                         id: item.texture_id,
                         op: TextureUpdateOp::Free,
@@ -888,21 +1072,195 @@ impl TextureCache {
 
         self.items.free(id)
     }
+
+    /// Moves the items of sparsely used texture pages into the other pages of the
+    /// same kind, so that fewer textures are needed to draw them. The pages that
+    /// end up empty are kept in `free_texture_levels` for later allocations,
+    /// unless the cache is over its budget, in which case they are released.
+    ///
+    /// The items are updated in place, so this must happen before the items are
+    /// requested for a frame, or the frame would use their old texture coordinates.
+    /// It is meant to be called once per frame, and only does anything every
+    /// `COMPACTION_FRAME_INTERVAL` calls. Returns true if any item was moved.
+    pub fn compact(&mut self) -> bool {
+        self.frames_since_compaction += 1;
+        if !self.needs_compaction ||
+           (self.frames_since_compaction < COMPACTION_FRAME_INTERVAL && !self.is_over_budget()) {
+            return false
+        }
+        self.needs_compaction = false;
+        self.frames_since_compaction = 0;
+
+        // The area in use in each page, kept up to date as items move.
+        let mut used_areas = HashMap::new();
+        for id in self.items.ids() {
+            let item = self.items.get(id);
+            let area = item.allocated_rect.size.width * item.allocated_rect.size.height;
+            *used_areas.entry(item.texture_id).or_insert(0) += area;
+        }
+
+        let mut moved_items = false;
+        for (format, filter) in self.arena.page_kinds() {
+            let mut pages_to_keep = vec![];
+            while let Some(texture_id) = self.sparsest_page(format, filter, &used_areas, &pages_to_keep) {
+                if self.evacuate_page(format, filter, texture_id, &mut used_areas) {
+                    moved_items = true;
+                } else {
+                    pages_to_keep.push(texture_id);
+                }
+            }
+        }
+
+        if self.is_over_budget() {
+            self.release_free_texture_levels();
+        }
+
+        moved_items
+    }
+
+    /// Frees the textures of the empty pages kept for later allocations.
+    fn release_free_texture_levels(&mut self) {
+        for (&(format, _), levels) in &mut self.free_texture_levels {
+            for level in levels.drain(..) {
+                self.allocated_bytes -= page_bytes(format, level.texture_size);
+                self.pending_updates.push(TextureUpdate {
+                    id: level.texture_id,
+                    op: TextureUpdateOp::Free,
+                });
+                self.cache_id_list.free(level.texture_id);
+            }
+        }
+    }
+
+    /// Returns the page with the smallest area in use, if it is sparse enough
+    /// to be worth emptying.
+    fn sparsest_page(&self,
+                     format: ImageFormat,
+                     filter: TextureFilter,
+                     used_areas: &HashMap<CacheTextureId, u32>,
+                     pages_to_keep: &[CacheTextureId]) -> Option<CacheTextureId> {
+        let pages = self.arena.pages_for_format(format, filter);
+        if pages.len() < 2 {
+            return None
+        }
+
+        let mut sparsest = None;
+        for page in pages {
+            if pages_to_keep.contains(&page.texture_id) {
+                continue
+            }
+            let used_area = used_areas.get(&page.texture_id).cloned().unwrap_or(0);
+            let occupancy = used_area as f32 / (page.texture_size * page.texture_size) as f32;
+            if occupancy >= COMPACTION_OCCUPANCY_THRESHOLD {
+                continue
+            }
+            match sparsest {
+                Some((_, sparsest_occupancy)) if sparsest_occupancy <= occupancy => {}
+                _ => sparsest = Some((page.texture_id, occupancy)),
+            }
+        }
+
+        sparsest.map(|(texture_id, _)| texture_id)
+    }
+
+    /// Copies all the items of a page into the other pages of the same format and
+    /// filter, and frees the page. Returns false, leaving everything as it was, if
+    /// they don't fit.
+    fn evacuate_page(&mut self,
+                     format: ImageFormat,
+                     filter: TextureFilter,
+                     texture_id: CacheTextureId,
+                     used_areas: &mut HashMap<CacheTextureId, u32>) -> bool {
+        let mut item_ids: Vec<TextureCacheItemId> = self.items.ids().into_iter().filter(|&id| {
+            let item = self.items.get(id);
+            item.format == format && item.texture_id == texture_id
+        }).collect();
+
+        // Place the largest items first, since they are the hardest to fit.
+        item_ids.sort_by(|&a, &b| {
+            let a_size = self.items.get(a).allocated_rect.size;
+            let b_size = self.items.get(b).allocated_rect.size;
+            (b_size.width * b_size.height).cmp(&(a_size.width * a_size.height))
+        });
+
+        let pages = self.arena.pages_for_format_mut(format, filter);
+
+        let mut moves = vec![];
+        for id in item_ids {
+            let size = self.items.get(id).allocated_rect.size;
+            let location = pages.iter_mut().enumerate().filter(|&(_, ref page)| {
+                page.texture_id != texture_id
+            }).filter_map(|(page_index, page)| {
+                page.allocate(&size).map(|origin| (page_index, origin))
+            }).next();
+
+            match location {
+                Some((page_index, origin)) => {
+                    moves.push((id, page_index, DeviceUintRect::new(origin, size)));
+                }
+                None => {
+                    for &(_, page_index, ref allocated_rect) in &moves {
+                        pages[page_index].free(allocated_rect);
+                    }
+                    return false
+                }
+            }
+        }
+
+        for (id, page_index, allocated_rect) in moves {
+            let page = &pages[page_index];
+            let old_item = self.items.get(id).clone();
+            *used_areas.entry(page.texture_id).or_insert(0) += allocated_rect.size.width *
+                                                               allocated_rect.size.height;
+
+            self.pending_updates.push(TextureUpdate {
+                id: page.texture_id,
+                op: TextureUpdateOp::Copy(texture_id,
+                                          old_item.allocated_rect,
+                                          allocated_rect.origin),
+            });
+
+            let requested_rect = DeviceUintRect::new(
+                DeviceUintPoint::new(
+                    allocated_rect.origin.x + old_item.requested_rect.origin.x - old_item.allocated_rect.origin.x,
+                    allocated_rect.origin.y + old_item.requested_rect.origin.y - old_item.allocated_rect.origin.y),
+                old_item.requested_rect.size);
+            *self.items.get_mut(id) = TextureCacheItem::new(page.texture_id,
+                                                            format,
+                                                            allocated_rect,
+                                                            requested_rect,
+                                                            &DeviceUintSize::new(page.texture_size, page.texture_size));
+        }
+
+        used_areas.remove(&texture_id);
+        let page_index = pages.iter().position(|page| page.texture_id == texture_id).unwrap();
+        let page = pages.remove(page_index);
+        self.free_texture_levels.entry((format, filter)).or_insert(Vec::new()).push(FreeTextureLevel {
+            texture_id: page.texture_id,
+            texture_size: page.texture_size,
+        });
+
+        true
+    }
 }
 
-fn texture_create_op(texture_size: u32, format: ImageFormat, mode: RenderTargetMode)
+fn texture_create_op(texture_size: u32,
+                     format: ImageFormat,
+                     filter: TextureFilter,
+                     mode: RenderTargetMode)
                      -> TextureUpdateOp {
-    TextureUpdateOp::Create(texture_size, texture_size, format, TextureFilter::Linear, mode, None)
+    TextureUpdateOp::Create(texture_size, texture_size, format, filter, mode, None)
 }
 
 fn texture_grow_op(texture_size: u32,
                    format: ImageFormat,
+                   filter: TextureFilter,
                    mode: RenderTargetMode)
                    -> TextureUpdateOp {
     TextureUpdateOp::Grow(texture_size,
                           texture_size,
                           format,
-                          TextureFilter::Linear,
+                          filter,
                           mode)
 }
 
@@ -920,6 +1278,7 @@ impl FitsInside for DeviceUintSize {
 #[derive(Clone, Copy)]
 pub struct FreeTextureLevel {
     texture_id: CacheTextureId,
+    texture_size: u32,
 }
 
 /// Returns the number of pixels on a side we start out with for our texture atlases.
@@ -932,8 +1291,13 @@ fn initial_texture_size() -> u32 {
     }
 }
 
+/// The bytes of texture memory used by a square texture page.
+fn page_bytes(format: ImageFormat, texture_size: u32) -> usize {
+    format.packed_size(texture_size, texture_size).unwrap_or(0) as usize
+}
+
 /// Returns the number of pixels on a side we're allowed to use for our texture atlases.
-fn max_texture_size() -> u32 {
+pub fn max_texture_size() -> u32 {
     let max_hardware_texture_size = *MAX_TEXTURE_SIZE as u32;
     if max_hardware_texture_size * max_hardware_texture_size > MAX_RGBA_PIXELS_PER_TEXTURE {
         SQRT_MAX_RGBA_PIXELS_PER_TEXTURE
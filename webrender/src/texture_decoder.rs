@@ -0,0 +1,1054 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! CPU decoders for the compressed image formats, used for the formats that
+//! the GL context can't sample from.
+//!
+//! Every supported format uses 4x4 blocks. The decoders fill in the texels of
+//! a block in row order, as RGBA.
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::cmp;
+use webrender_traits::ImageFormat;
+
+type Texel = [u8; 4];
+type Block = [Texel; 16];
+
+/// Decodes a compressed image with tightly packed blocks into RGBA8 pixels, in
+/// the BGRA byte order used for `ImageFormat::RGBA8` images.
+///
+/// Fails if the format isn't a compressed one, or if there are fewer bytes than
+/// the blocks of the image need.
+pub fn decode_image(format: ImageFormat,
+                    width: u32,
+                    height: u32,
+                    bytes: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let decode_block: fn(&[u8], &mut Block) = match format {
+        ImageFormat::BC1 => decode_bc1_rgba,
+        ImageFormat::BC2 => decode_bc2,
+        ImageFormat::BC3 => decode_bc3,
+        ImageFormat::ETC2RGB8 => decode_etc2_rgb,
+        ImageFormat::ETC2RGBA8 => decode_etc2_rgba,
+        ImageFormat::ASTC4x4 => decode_astc,
+        ImageFormat::Invalid |
+        ImageFormat::A8 |
+        ImageFormat::RGB8 |
+        ImageFormat::RGBA8 |
+        ImageFormat::RGBAF32 |
+        ImageFormat::R16 |
+        ImageFormat::RG8 |
+        ImageFormat::RGBA16F => return Err("Not a compressed format"),
+    };
+
+    let (block_size, block_bytes) = format.compressed_block_size().unwrap();
+    debug_assert!(block_size == 4);
+    let blocks_x = ((width + block_size - 1) / block_size) as usize;
+    let blocks_y = ((height + block_size - 1) / block_size) as usize;
+    let block_bytes = block_bytes as usize;
+    if bytes.len() < blocks_x * blocks_y * block_bytes {
+        return Err("Not enough bytes for the blocks of the image");
+    }
+
+    let mut pixels = vec![0; (width * height * 4) as usize];
+    let mut texels = [[0; 4]; 16];
+
+    for block_y in 0..blocks_y {
+        for block_x in 0..blocks_x {
+            let offset = (block_y * blocks_x + block_x) * block_bytes;
+            decode_block(&bytes[offset..offset + block_bytes], &mut texels);
+
+            let (block_x, block_y) = (block_x as u32, block_y as u32);
+            for y in 0..cmp::min(4, height - block_y * 4) {
+                for x in 0..cmp::min(4, width - block_x * 4) {
+                    let texel = texels[(y * 4 + x) as usize];
+                    let offset = (((block_y * 4 + y) * width + block_x * 4 + x) * 4) as usize;
+                    pixels[offset + 0] = texel[2];
+                    pixels[offset + 1] = texel[1];
+                    pixels[offset + 2] = texel[0];
+                    pixels[offset + 3] = texel[3];
+                }
+            }
+        }
+    }
+
+    Ok(pixels)
+}
+
+fn clamp_u8(value: i32) -> u8 {
+    cmp::max(0, cmp::min(255, value)) as u8
+}
+
+/// Extends a value of `bits` bits to `to_bits` bits by repeating its bits.
+fn replicate(value: u32, bits: u32, to_bits: u32) -> u32 {
+    if bits == 0 {
+        return 0;
+    }
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < to_bits {
+        result = (result << bits) | value;
+        filled += bits;
+    }
+    result >> (filled - to_bits)
+}
+
+// BC1-3 (S3TC)
+
+fn rgb565(color: u16) -> [i32; 3] {
+    let color = color as u32;
+    [replicate((color >> 11) & 0x1F, 5, 8) as i32,
+     replicate((color >> 5) & 0x3F, 6, 8) as i32,
+     replicate(color & 0x1F, 5, 8) as i32]
+}
+
+/// Decodes the color half of a BC block. Only BC1 blocks can select
+/// transparent black, BC2 and BC3 always use four colors.
+fn decode_bc1(block: &[u8], allow_alpha: bool, texels: &mut Block) {
+    let color0 = LittleEndian::read_u16(&block[0..2]);
+    let color1 = LittleEndian::read_u16(&block[2..4]);
+    let (c0, c1) = (rgb565(color0), rgb565(color1));
+
+    let mut palette = [[0; 4]; 4];
+    for i in 0..3 {
+        palette[0][i] = c0[i] as u8;
+        palette[1][i] = c1[i] as u8;
+        if color0 > color1 || !allow_alpha {
+            palette[2][i] = ((2 * c0[i] + c1[i]) / 3) as u8;
+            palette[3][i] = ((c0[i] + 2 * c1[i]) / 3) as u8;
+        } else {
+            palette[2][i] = ((c0[i] + c1[i]) / 2) as u8;
+        }
+    }
+    palette[0][3] = 255;
+    palette[1][3] = 255;
+    palette[2][3] = 255;
+    palette[3][3] = if color0 > color1 || !allow_alpha { 255 } else { 0 };
+
+    let indices = LittleEndian::read_u32(&block[4..8]);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (2 * i)) & 3) as usize];
+    }
+}
+
+fn decode_bc1_rgba(block: &[u8], texels: &mut Block) {
+    decode_bc1(block, true, texels);
+}
+
+fn decode_bc2(block: &[u8], texels: &mut Block) {
+    decode_bc1(&block[8..16], false, texels);
+
+    let alpha = LittleEndian::read_u64(&block[0..8]);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = ((alpha >> (4 * i)) & 0xF) as u8 * 17;
+    }
+}
+
+fn decode_bc3(block: &[u8], texels: &mut Block) {
+    decode_bc1(&block[8..16], false, texels);
+
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [0; 8];
+    palette[0] = a0;
+    palette[1] = a1;
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    let mut indices = 0u64;
+    for i in 0..6 {
+        indices |= (block[2 + i] as u64) << (8 * i);
+    }
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = palette[((indices >> (3 * i)) & 7) as usize] as u8;
+    }
+}
+
+// ETC2 and EAC
+
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183],
+];
+
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// Returns bits `high` to `low` (inclusive) of a big endian ETC block.
+fn etc_bits(data: u64, high: u32, low: u32) -> i32 {
+    ((data >> low) & ((1 << (high - low + 1)) - 1)) as i32
+}
+
+/// The two bit index of a texel of an ETC block, where the texels are
+/// stored column by column.
+fn etc_index(data: u64, x: usize, y: usize) -> usize {
+    let i = x * 4 + y;
+    ((((data >> (16 + i)) & 1) << 1) | ((data >> i) & 1)) as usize
+}
+
+fn offset_color(color: [i32; 3], offset: i32) -> Texel {
+    [clamp_u8(color[0] + offset), clamp_u8(color[1] + offset), clamp_u8(color[2] + offset), 255]
+}
+
+fn decode_etc2_rgb(block: &[u8], texels: &mut Block) {
+    let data = BigEndian::read_u64(block);
+    let extend_4 = |c: i32| (c << 4) | c;
+    let extend_5 = |c: i32| (c << 3) | (c >> 2);
+
+    if etc_bits(data, 33, 33) == 0 {
+        // Individual mode.
+        let base = [[extend_4(etc_bits(data, 63, 60)),
+                     extend_4(etc_bits(data, 55, 52)),
+                     extend_4(etc_bits(data, 47, 44))],
+                    [extend_4(etc_bits(data, 59, 56)),
+                     extend_4(etc_bits(data, 51, 48)),
+                     extend_4(etc_bits(data, 43, 40))]];
+        decode_etc_subblocks(data, base, texels);
+        return;
+    }
+
+    let signed_3 = |d: i32| if d >= 4 { d - 8 } else { d };
+    let (r, dr) = (etc_bits(data, 63, 59), signed_3(etc_bits(data, 58, 56)));
+    let (g, dg) = (etc_bits(data, 55, 51), signed_3(etc_bits(data, 50, 48)));
+    let (b, db) = (etc_bits(data, 47, 43), signed_3(etc_bits(data, 42, 40)));
+    let overflows = |c: i32| c < 0 || c > 31;
+
+    if overflows(r + dr) {
+        // T mode.
+        let c0 = [extend_4((etc_bits(data, 60, 59) << 2) | etc_bits(data, 57, 56)),
+                  extend_4(etc_bits(data, 55, 52)),
+                  extend_4(etc_bits(data, 51, 48))];
+        let c1 = [extend_4(etc_bits(data, 47, 44)),
+                  extend_4(etc_bits(data, 43, 40)),
+                  extend_4(etc_bits(data, 39, 36))];
+        let distance = ETC_DISTANCES[((etc_bits(data, 35, 34) << 1) | etc_bits(data, 32, 32)) as usize];
+        let paint = [offset_color(c0, 0),
+                     offset_color(c1, distance),
+                     offset_color(c1, 0),
+                     offset_color(c1, -distance)];
+        decode_etc_paint_colors(data, paint, texels);
+    } else if overflows(g + dg) {
+        // H mode.
+        let r0 = etc_bits(data, 62, 59);
+        let g0 = (etc_bits(data, 58, 56) << 1) | etc_bits(data, 52, 52);
+        let b0 = (etc_bits(data, 51, 51) << 3) | etc_bits(data, 49, 47);
+        let r1 = etc_bits(data, 46, 43);
+        let g1 = etc_bits(data, 42, 39);
+        let b1 = etc_bits(data, 38, 35);
+        let order = ((r0 << 8) | (g0 << 4) | b0) >= ((r1 << 8) | (g1 << 4) | b1);
+        let distance = ETC_DISTANCES[((etc_bits(data, 34, 34) << 2) |
+                                      (etc_bits(data, 32, 32) << 1) |
+                                      order as i32) as usize];
+        let c0 = [extend_4(r0), extend_4(g0), extend_4(b0)];
+        let c1 = [extend_4(r1), extend_4(g1), extend_4(b1)];
+        let paint = [offset_color(c0, distance),
+                     offset_color(c0, -distance),
+                     offset_color(c1, distance),
+                     offset_color(c1, -distance)];
+        decode_etc_paint_colors(data, paint, texels);
+    } else if overflows(b + db) {
+        decode_etc_planar(data, texels);
+    } else {
+        // Differential mode.
+        let base = [[extend_5(r), extend_5(g), extend_5(b)],
+                    [extend_5(r + dr), extend_5(g + dg), extend_5(b + db)]];
+        decode_etc_subblocks(data, base, texels);
+    }
+}
+
+/// The individual and differential modes, where each half of the block has
+/// a base color and a table of offsets.
+fn decode_etc_subblocks(data: u64, base: [[i32; 3]; 2], texels: &mut Block) {
+    let tables = [etc_bits(data, 39, 37) as usize, etc_bits(data, 36, 34) as usize];
+    let flip = etc_bits(data, 32, 32) == 1;
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let subblock = if flip { y / 2 } else { x / 2 };
+            let modifiers = ETC_MODIFIERS[tables[subblock]];
+            let offset = match etc_index(data, x, y) {
+                0 => modifiers[0],
+                1 => modifiers[1],
+                2 => -modifiers[0],
+                _ => -modifiers[1],
+            };
+            texels[y * 4 + x] = offset_color(base[subblock], offset);
+        }
+    }
+}
+
+/// The T and H modes, where each texel picks one of four colors.
+fn decode_etc_paint_colors(data: u64, paint: [Texel; 4], texels: &mut Block) {
+    for y in 0..4 {
+        for x in 0..4 {
+            texels[y * 4 + x] = paint[etc_index(data, x, y)];
+        }
+    }
+}
+
+fn decode_etc_planar(data: u64, texels: &mut Block) {
+    let extend_6 = |c: i32| (c << 2) | (c >> 4);
+    let extend_7 = |c: i32| (c << 1) | (c >> 6);
+
+    let origin = [extend_6(etc_bits(data, 62, 57)),
+                  extend_7((etc_bits(data, 56, 56) << 6) | etc_bits(data, 54, 49)),
+                  extend_6((etc_bits(data, 48, 48) << 5) |
+                           (etc_bits(data, 44, 43) << 3) |
+                           etc_bits(data, 41, 39))];
+    let horizontal = [extend_6((etc_bits(data, 38, 34) << 1) | etc_bits(data, 32, 32)),
+                      extend_7(etc_bits(data, 31, 25)),
+                      extend_6(etc_bits(data, 24, 19))];
+    let vertical = [extend_6(etc_bits(data, 18, 13)),
+                    extend_7(etc_bits(data, 12, 6)),
+                    extend_6(etc_bits(data, 5, 0))];
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let texel = &mut texels[y * 4 + x];
+            for i in 0..3 {
+                let value = (x as i32 * (horizontal[i] - origin[i]) +
+                             y as i32 * (vertical[i] - origin[i]) +
+                             4 * origin[i] + 2) >> 2;
+                texel[i] = clamp_u8(value);
+            }
+            texel[3] = 255;
+        }
+    }
+}
+
+/// ETC2 RGBA8 blocks hold the EAC alpha first, then the color.
+fn decode_etc2_rgba(block: &[u8], texels: &mut Block) {
+    decode_etc2_rgb(&block[8..16], texels);
+    decode_eac_alpha(&block[0..8], texels);
+}
+
+fn decode_eac_alpha(block: &[u8], texels: &mut Block) {
+    let data = BigEndian::read_u64(block);
+    let base = etc_bits(data, 63, 56);
+    let multiplier = etc_bits(data, 55, 52);
+    let modifiers = EAC_MODIFIERS[etc_bits(data, 51, 48) as usize];
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let i = (x * 4 + y) as u32;
+            let index = etc_bits(data, 47 - 3 * i, 45 - 3 * i) as usize;
+            texels[y * 4 + x][3] = clamp_u8(base + modifiers[index] * multiplier);
+        }
+    }
+}
+
+// ASTC
+
+/// The color of blocks that are invalid, or that use HDR endpoints.
+const ASTC_ERROR_COLOR: Texel = [255, 0, 255, 255];
+
+/// The ranges of color endpoint values, from smallest to largest.
+const ASTC_COLOR_LEVELS: [u32; 17] = [
+    6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256,
+];
+
+/// How a range of values is stored in the bounded integer sequence encoding.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum IntegerEncoding {
+    Bits(u32),
+    /// A trit, plus the given number of bits.
+    Trits(u32),
+    /// A quint, plus the given number of bits.
+    Quints(u32),
+}
+
+impl IntegerEncoding {
+    fn for_levels(levels: u32) -> IntegerEncoding {
+        let log2 = |value: u32| 31 - value.leading_zeros();
+        if levels % 3 == 0 {
+            IntegerEncoding::Trits(log2(levels / 3))
+        } else if levels % 5 == 0 {
+            IntegerEncoding::Quints(log2(levels / 5))
+        } else {
+            IntegerEncoding::Bits(log2(levels))
+        }
+    }
+
+    /// The number of bits used to store `count` values.
+    fn size(&self, count: u32) -> u32 {
+        match *self {
+            IntegerEncoding::Bits(bits) => count * bits,
+            IntegerEncoding::Trits(bits) => count * bits + (8 * count + 4) / 5,
+            IntegerEncoding::Quints(bits) => count * bits + (7 * count + 2) / 3,
+        }
+    }
+
+    /// Reads `count` values starting at bit `start`. The values are returned
+    /// as a trit or quint, and the remaining bits.
+    fn decode(&self, data: &[u8], start: u32, count: u32) -> Vec<(u32, u32)> {
+        // The last group can be cut short, the missing bits are zero.
+        let end = start + self.size(count);
+        let read = |position: u32, bits: u32| {
+            if position >= end {
+                0
+            } else {
+                read_bits(data, position, cmp::min(bits, end - position))
+            }
+        };
+
+        let (bits, group_size, packed_bits): (u32, u32, &[u32]) = match *self {
+            IntegerEncoding::Bits(bits) => (bits, 1, &[0]),
+            IntegerEncoding::Trits(bits) => (bits, 5, &[2, 2, 1, 2, 1]),
+            IntegerEncoding::Quints(bits) => (bits, 3, &[3, 2, 2]),
+        };
+
+        let mut values = Vec::with_capacity(count as usize);
+        let mut position = start;
+        while (values.len() as u32) < count {
+            let mut low_bits = [0; 5];
+            let mut packed = 0;
+            let mut packed_shift = 0;
+            for i in 0..group_size as usize {
+                low_bits[i] = read(position, bits);
+                position += bits;
+                packed |= read(position, packed_bits[i]) << packed_shift;
+                position += packed_bits[i];
+                packed_shift += packed_bits[i];
+            }
+
+            let high = match *self {
+                IntegerEncoding::Bits(..) => [0; 5],
+                IntegerEncoding::Trits(..) => decode_trits(packed),
+                IntegerEncoding::Quints(..) => {
+                    let quints = decode_quints(packed);
+                    [quints[0], quints[1], quints[2], 0, 0]
+                }
+            };
+
+            for i in 0..group_size as usize {
+                if (values.len() as u32) < count {
+                    values.push((high[i], low_bits[i]));
+                }
+            }
+        }
+        values
+    }
+}
+
+/// Reads `count` bits starting at bit `start` of a little endian block.
+fn read_bits(data: &[u8], start: u32, count: u32) -> u32 {
+    let mut value = 0;
+    for i in 0..count {
+        let bit = (start + i) as usize;
+        value |= (((data[bit / 8] >> (bit % 8)) & 1) as u32) << i;
+    }
+    value
+}
+
+fn decode_trits(packed: u32) -> [u32; 5] {
+    let bit = |i: u32| (packed >> i) & 1;
+
+    let (c, t3, t4) = if (packed >> 2) & 7 == 7 {
+        ((((packed >> 5) & 7) << 2) | (packed & 3), 2, 2)
+    } else if (packed >> 5) & 3 == 3 {
+        (packed & 0x1F, bit(7), 2)
+    } else {
+        (packed & 0x1F, (packed >> 5) & 3, bit(7))
+    };
+
+    let c_bit = |i: u32| (c >> i) & 1;
+    let (t0, t1, t2) = if c & 3 == 3 {
+        ((c_bit(3) << 1) | (c_bit(2) & !c_bit(3) & 1), c_bit(4), 2)
+    } else if (c >> 2) & 3 == 3 {
+        (c & 3, 2, 2)
+    } else {
+        ((c_bit(1) << 1) | (c_bit(0) & !c_bit(1) & 1), (c >> 2) & 3, c_bit(4))
+    };
+
+    [t0, t1, t2, t3, t4]
+}
+
+fn decode_quints(packed: u32) -> [u32; 3] {
+    let bit = |i: u32| (packed >> i) & 1;
+
+    if (packed >> 1) & 3 == 3 && (packed >> 5) & 3 == 0 {
+        let q2 = (bit(0) << 2) | ((bit(4) & !bit(0) & 1) << 1) | (bit(3) & !bit(0) & 1);
+        return [4, 4, q2];
+    }
+
+    let (c, q2) = if (packed >> 1) & 3 == 3 {
+        ((((packed >> 3) & 3) << 3) | ((!(packed >> 5) & 3) << 1) | bit(0), 4)
+    } else {
+        (packed & 0x1F, (packed >> 5) & 3)
+    };
+
+    if c & 7 == 5 {
+        [(c >> 3) & 3, 4, q2]
+    } else {
+        [c & 7, (c >> 3) & 3, q2]
+    }
+}
+
+/// Returns the `B` and `C` constants of the unquantization of trits and
+/// quints, see `unquantize_color` and `unquantize_weight`.
+fn unquantize_constants(encoding: IntegerEncoding, low_bits: u32, for_weights: bool) -> (u32, u32) {
+    let bit = |i: u32| (low_bits >> i) & 1;
+    let (b, c, d, e, f) = (bit(1), bit(2), bit(3), bit(4), bit(5));
+
+    if for_weights {
+        match encoding {
+            IntegerEncoding::Trits(1) => (0, 50),
+            IntegerEncoding::Trits(2) => ((b << 6) | (b << 2) | b, 23),
+            IntegerEncoding::Trits(3) => ((c << 6) | (b << 5) | (c << 1) | b, 11),
+            IntegerEncoding::Quints(1) => (0, 28),
+            IntegerEncoding::Quints(2) => ((b << 6) | (b << 1), 13),
+            _ => unreachable!(),
+        }
+    } else {
+        match encoding {
+            IntegerEncoding::Trits(1) => (0, 204),
+            IntegerEncoding::Trits(2) => ((b << 8) | (b << 4) | (b << 2) | (b << 1), 93),
+            IntegerEncoding::Trits(3) => ((c << 8) | (b << 7) | (c << 3) | (b << 2) | (c << 1) | b, 44),
+            IntegerEncoding::Trits(4) => ((d << 8) | (c << 7) | (b << 6) | (d << 2) | (c << 1) | b, 22),
+            IntegerEncoding::Trits(5) => ((e << 8) | (d << 7) | (c << 6) | (b << 5) | (e << 1) | d, 11),
+            IntegerEncoding::Trits(6) => ((f << 8) | (e << 7) | (d << 6) | (c << 5) | (b << 4) | f, 5),
+            IntegerEncoding::Quints(1) => (0, 113),
+            IntegerEncoding::Quints(2) => ((b << 8) | (b << 3) | (b << 2), 54),
+            IntegerEncoding::Quints(3) => ((c << 8) | (b << 7) | (c << 2) | (b << 1) | c, 26),
+            IntegerEncoding::Quints(4) => ((d << 8) | (c << 7) | (b << 6) | (d << 1) | c, 13),
+            IntegerEncoding::Quints(5) => ((e << 8) | (d << 7) | (c << 6) | (b << 5) | e, 6),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Maps a color endpoint value to 0..255.
+fn unquantize_color(encoding: IntegerEncoding, (high, low_bits): (u32, u32)) -> i32 {
+    match encoding {
+        IntegerEncoding::Bits(bits) => replicate(low_bits, bits, 8) as i32,
+        IntegerEncoding::Trits(..) | IntegerEncoding::Quints(..) => {
+            let a = if low_bits & 1 == 1 { 0x1FF } else { 0 };
+            let (b, c) = unquantize_constants(encoding, low_bits, false);
+            let t = (high * c + b) ^ a;
+            ((a & 0x80) | (t >> 2)) as i32
+        }
+    }
+}
+
+/// Maps a weight to 0..64.
+fn unquantize_weight(encoding: IntegerEncoding, (high, low_bits): (u32, u32)) -> u32 {
+    let value = match encoding {
+        IntegerEncoding::Bits(bits) => replicate(low_bits, bits, 6),
+        IntegerEncoding::Trits(0) => return [0, 32, 64][high as usize],
+        IntegerEncoding::Quints(0) => return [0, 16, 32, 48, 64][high as usize],
+        IntegerEncoding::Trits(..) | IntegerEncoding::Quints(..) => {
+            let a = if low_bits & 1 == 1 { 0x7F } else { 0 };
+            let (b, c) = unquantize_constants(encoding, low_bits, true);
+            let t = (high * c + b) ^ a;
+            (a & 0x20) | (t >> 2)
+        }
+    };
+    if value > 32 { value + 1 } else { value }
+}
+
+struct AstcBlockMode {
+    grid_width: u32,
+    grid_height: u32,
+    dual_plane: bool,
+    weight_levels: u32,
+}
+
+fn decode_astc_block_mode(mode: u32) -> Option<AstcBlockMode> {
+    let bit = |i: u32| (mode >> i) & 1;
+    let bits = |i: u32, count: u32| (mode >> i) & ((1 << count) - 1);
+
+    let (range, grid_width, grid_height, high_precision, dual_plane);
+    if bits(0, 2) != 0 {
+        range = bit(4) | (bits(0, 2) << 1);
+        high_precision = bit(9);
+        dual_plane = bit(10);
+        let (a, b) = (bits(5, 2), bits(7, 2));
+        let (width, height) = match bits(2, 2) {
+            0 => (b + 4, a + 2),
+            1 => (b + 8, a + 2),
+            2 => (a + 2, b + 8),
+            _ if bit(8) == 0 => (a + 2, (b & 1) + 6),
+            _ => ((b & 1) + 2, a + 2),
+        };
+        grid_width = width;
+        grid_height = height;
+    } else {
+        range = bit(4) | (bits(2, 2) << 1);
+        let (a, b) = (bits(5, 2), bits(9, 2));
+        let (width, height, extended) = match bits(7, 2) {
+            0 => (12, a + 2, true),
+            1 => (a + 2, 12, true),
+            2 => (a + 6, b + 6, false),
+            _ => match a {
+                0 => (6, 10, true),
+                1 => (10, 6, true),
+                _ => return None,
+            },
+        };
+        grid_width = width;
+        grid_height = height;
+        high_precision = if extended { bit(9) } else { 0 };
+        dual_plane = if extended { bit(10) } else { 0 };
+    }
+
+    if range < 2 {
+        return None;
+    }
+
+    let levels = [[2, 3, 4, 5, 6, 8], [10, 12, 16, 20, 24, 32]];
+    Some(AstcBlockMode {
+        grid_width: grid_width,
+        grid_height: grid_height,
+        dual_plane: dual_plane == 1,
+        weight_levels: levels[high_precision as usize][range as usize - 2],
+    })
+}
+
+fn decode_astc(block: &[u8], texels: &mut Block) {
+    if !decode_astc_block(block, texels) {
+        *texels = [ASTC_ERROR_COLOR; 16];
+    }
+}
+
+/// Returns false if the block is invalid or needs HDR decoding.
+fn decode_astc_block(block: &[u8], texels: &mut Block) -> bool {
+    let mode = read_bits(block, 0, 11);
+
+    // A void extent block has a single 16 bit per channel color.
+    if mode & 0x1FF == 0x1FC {
+        if mode & 0x200 != 0 {
+            return false;
+        }
+        let mut color = [0; 4];
+        for (i, channel) in color.iter_mut().enumerate() {
+            *channel = (read_bits(block, 64 + 16 * i as u32, 16) >> 8) as u8;
+        }
+        *texels = [color; 16];
+        return true;
+    }
+
+    let block_mode = match decode_astc_block_mode(mode) {
+        Some(block_mode) => block_mode,
+        None => return false,
+    };
+    if block_mode.grid_width > 4 || block_mode.grid_height > 4 {
+        return false;
+    }
+
+    let plane_count = if block_mode.dual_plane { 2 } else { 1 };
+    let weight_count = block_mode.grid_width * block_mode.grid_height * plane_count;
+    let weight_encoding = IntegerEncoding::for_levels(block_mode.weight_levels);
+    let weight_bits = weight_encoding.size(weight_count);
+    if weight_bits < 24 || weight_bits > 96 {
+        return false;
+    }
+
+    let partition_count = read_bits(block, 11, 2) + 1;
+    if block_mode.dual_plane && partition_count == 4 {
+        return false;
+    }
+
+    // The weights are stored backwards from the end of the block, and are
+    // preceded by the remaining bits of the endpoint modes and by the dual
+    // plane channel.
+    let mut below_weights = 128 - weight_bits;
+    let mut endpoint_modes = [0; 4];
+    let (partition_seed, color_start);
+    if partition_count == 1 {
+        endpoint_modes[0] = read_bits(block, 13, 4);
+        partition_seed = 0;
+        color_start = 17;
+    } else {
+        partition_seed = read_bits(block, 13, 10);
+        color_start = 29;
+
+        let mut modes = read_bits(block, 23, 6);
+        if modes & 3 == 0 {
+            for p in 0..partition_count as usize {
+                endpoint_modes[p] = modes >> 2;
+            }
+        } else {
+            let extra_bits = 3 * partition_count - 4;
+            below_weights -= extra_bits;
+            modes |= read_bits(block, below_weights, extra_bits) << 6;
+
+            let base_class = (modes & 3) - 1;
+            for p in 0..partition_count {
+                let class = base_class + ((modes >> (2 + p)) & 1);
+                let mode = (modes >> (2 + partition_count + 2 * p)) & 3;
+                endpoint_modes[p as usize] = (class << 2) | mode;
+            }
+        }
+    }
+
+    let plane2_channel = if block_mode.dual_plane {
+        below_weights -= 2;
+        Some(read_bits(block, below_weights, 2) as usize)
+    } else {
+        None
+    };
+
+    let color_count = endpoint_modes[..partition_count as usize]
+        .iter()
+        .fold(0, |count, mode| count + ((mode >> 2) + 1) * 2);
+    if color_count > 18 || below_weights < color_start {
+        return false;
+    }
+
+    // The endpoints use the largest range that fits in the remaining bits.
+    let color_bits = below_weights - color_start;
+    let color_encoding = ASTC_COLOR_LEVELS.iter()
+                                          .rev()
+                                          .map(|&levels| IntegerEncoding::for_levels(levels))
+                                          .find(|encoding| encoding.size(color_count) <= color_bits);
+    let color_encoding = match color_encoding {
+        Some(color_encoding) => color_encoding,
+        None => return false,
+    };
+    let colors: Vec<i32> = color_encoding.decode(block, color_start, color_count)
+                                         .into_iter()
+                                         .map(|value| unquantize_color(color_encoding, value))
+                                         .collect();
+
+    let mut endpoints = [[[0; 4]; 2]; 4];
+    let mut color_index = 0;
+    for p in 0..partition_count as usize {
+        let count = (((endpoint_modes[p] >> 2) + 1) * 2) as usize;
+        endpoints[p] = match decode_astc_endpoints(endpoint_modes[p],
+                                                   &colors[color_index..color_index + count]) {
+            Some(partition_endpoints) => partition_endpoints,
+            None => return false,
+        };
+        color_index += count;
+    }
+
+    let mut reversed_block = [0; 16];
+    for i in 0..16 {
+        let mut byte = block[15 - i];
+        for bit in 0..8 {
+            reversed_block[i] |= (byte & 1) << (7 - bit);
+            byte >>= 1;
+        }
+    }
+    let weights: Vec<u32> = weight_encoding.decode(&reversed_block, 0, weight_count)
+                                           .into_iter()
+                                           .map(|value| unquantize_weight(weight_encoding, value))
+                                           .collect();
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let partition = if partition_count > 1 {
+                astc_partition(partition_seed, x, y, partition_count)
+            } else {
+                0
+            };
+            let plane_weights = [
+                astc_texel_weight(&weights, &block_mode, 0, x, y),
+                if block_mode.dual_plane { astc_texel_weight(&weights, &block_mode, 1, x, y) } else { 0 },
+            ];
+
+            let partition_endpoints = &endpoints[partition as usize];
+            let texel = &mut texels[(y * 4 + x) as usize];
+            for channel in 0..4 {
+                let weight = if plane2_channel == Some(channel) {
+                    plane_weights[1]
+                } else {
+                    plane_weights[0]
+                };
+                let c0 = partition_endpoints[0][channel] as u32 * 257;
+                let c1 = partition_endpoints[1][channel] as u32 * 257;
+                let value = (c0 * (64 - weight) + c1 * weight + 32) >> 6;
+                texel[channel] = (value >> 8) as u8;
+            }
+        }
+    }
+
+    true
+}
+
+/// Interpolates the weight grid at a texel of the 4x4 block.
+fn astc_texel_weight(weights: &[u32], block_mode: &AstcBlockMode, plane: u32, x: u32, y: u32) -> u32 {
+    let (grid_width, grid_height) = (block_mode.grid_width, block_mode.grid_height);
+    let plane_count = if block_mode.dual_plane { 2 } else { 1 };
+
+    let scale = (1024 + 4 / 2) / (4 - 1);
+    let gs = (scale * x * (grid_width - 1) + 32) >> 6;
+    let gt = (scale * y * (grid_height - 1) + 32) >> 6;
+    let (js, fs) = (gs >> 4, gs & 0xF);
+    let (jt, ft) = (gt >> 4, gt & 0xF);
+
+    let w11 = (fs * ft + 8) >> 4;
+    let w10 = ft - w11;
+    let w01 = fs - w11;
+    let w00 = 16 - fs - ft + w11;
+
+    let weight = |s: u32, t: u32| {
+        if s < grid_width && t < grid_height {
+            weights[((t * grid_width + s) * plane_count + plane) as usize]
+        } else {
+            0
+        }
+    };
+
+    (weight(js, jt) * w00 + weight(js + 1, jt) * w01 +
+     weight(js, jt + 1) * w10 + weight(js + 1, jt + 1) * w11 + 8) >> 4
+}
+
+fn bit_transfer_signed(a: i32, b: i32) -> (i32, i32) {
+    let b = (b >> 1) | (a & 0x80);
+    let mut a = (a >> 1) & 0x3F;
+    if a & 0x20 != 0 {
+        a -= 0x40;
+    }
+    (a, b)
+}
+
+fn blue_contract(color: [i32; 4]) -> [i32; 4] {
+    [(color[0] + color[2]) >> 1, (color[1] + color[2]) >> 1, color[2], color[3]]
+}
+
+/// Decodes the LDR color endpoint modes. Returns None for the HDR modes.
+fn decode_astc_endpoints(mode: u32, v: &[i32]) -> Option<[[i32; 4]; 2]> {
+    let endpoints = match mode {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xC0);
+            let l1 = cmp::min(l0 + (v[1] & 0x3F), 255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let (d0, l0) = bit_transfer_signed(v[1], v[0]);
+            let (d1, a0) = bit_transfer_signed(v[3], v[2]);
+            [[l0, l0, l0, a0], [l0 + d0, l0 + d0, l0 + d0, a0 + d1]]
+        }
+        6 => [[(v[0] * v[3]) >> 8, (v[1] * v[3]) >> 8, (v[2] * v[3]) >> 8, 255],
+              [v[0], v[1], v[2], 255]],
+        8 | 12 => {
+            let (a0, a1) = if mode == 12 { (v[6], v[7]) } else { (255, 255) };
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [[v[0], v[2], v[4], a0], [v[1], v[3], v[5], a1]]
+            } else {
+                [blue_contract([v[1], v[3], v[5], a1]), blue_contract([v[0], v[2], v[4], a0])]
+            }
+        }
+        9 | 13 => {
+            let (d_r, r) = bit_transfer_signed(v[1], v[0]);
+            let (d_g, g) = bit_transfer_signed(v[3], v[2]);
+            let (d_b, b) = bit_transfer_signed(v[5], v[4]);
+            let (d_a, a) = if mode == 13 { bit_transfer_signed(v[7], v[6]) } else { (0, 255) };
+            if d_r + d_g + d_b >= 0 {
+                [[r, g, b, a], [r + d_r, g + d_g, b + d_b, a + d_a]]
+            } else {
+                [blue_contract([r + d_r, g + d_g, b + d_b, a + d_a]), blue_contract([r, g, b, a])]
+            }
+        }
+        10 => [[(v[0] * v[3]) >> 8, (v[1] * v[3]) >> 8, (v[2] * v[3]) >> 8, v[4]],
+               [v[0], v[1], v[2], v[5]]],
+        _ => return None,
+    };
+
+    let mut clamped = [[0; 4]; 2];
+    for (i, endpoint) in endpoints.iter().enumerate() {
+        for (c, value) in endpoint.iter().enumerate() {
+            clamped[i][c] = cmp::max(0, cmp::min(255, *value));
+        }
+    }
+    Some(clamped)
+}
+
+fn astc_hash(seed: u32) -> u32 {
+    let mut p = seed;
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
+
+/// Returns the partition of a texel of a 4x4 block.
+fn astc_partition(seed: u32, x: u32, y: u32, partition_count: u32) -> u32 {
+    // Blocks of fewer than 31 texels use every other position.
+    let (x, y) = (x << 1, y << 1);
+    let seed = seed + (partition_count - 1) * 1024;
+    let rnum = astc_hash(seed);
+
+    let mut seeds = [0u32; 8];
+    for (i, s) in seeds.iter_mut().enumerate() {
+        let value = (rnum >> (4 * i)) & 0xF;
+        *s = value * value;
+    }
+
+    let (sh1, sh2) = if seed & 1 != 0 {
+        (if seed & 2 != 0 { 4 } else { 5 }, if partition_count == 3 { 6 } else { 5 })
+    } else {
+        (if partition_count == 3 { 6 } else { 5 }, if seed & 2 != 0 { 4 } else { 5 })
+    };
+
+    let a = ((seeds[0] >> sh1) * x + (seeds[1] >> sh2) * y + (rnum >> 14)) & 0x3F;
+    let b = ((seeds[2] >> sh1) * x + (seeds[3] >> sh2) * y + (rnum >> 10)) & 0x3F;
+    let c = if partition_count < 3 {
+        0
+    } else {
+        ((seeds[4] >> sh1) * x + (seeds[5] >> sh2) * y + (rnum >> 6)) & 0x3F
+    };
+    let d = if partition_count < 4 {
+        0
+    } else {
+        ((seeds[6] >> sh1) * x + (seeds[7] >> sh2) * y + (rnum >> 2)) & 0x3F
+    };
+
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_image;
+    use webrender_traits::ImageFormat;
+
+    /// Decodes a single 4x4 block, and returns its texels as RGBA.
+    fn decode_block(format: ImageFormat, block: &[u8]) -> Vec<[u8; 4]> {
+        decode_image(format, 4, 4, block).unwrap()
+                                         .chunks(4)
+                                         .map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]])
+                                         .collect()
+    }
+
+    fn assert_all(texels: &[[u8; 4]], expected: [u8; 4]) {
+        for texel in texels {
+            assert_eq!(*texel, expected);
+        }
+    }
+
+    #[test]
+    fn bc1() {
+        // Red and blue endpoints, picking the first and second color.
+        let red = [0x00, 0xF8, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_all(&decode_block(ImageFormat::BC1, &red), [255, 0, 0, 255]);
+        let blue = [0x00, 0xF8, 0x1F, 0x00, 0x55, 0x55, 0x55, 0x55];
+        assert_all(&decode_block(ImageFormat::BC1, &blue), [0, 0, 255, 255]);
+
+        // With the endpoints swapped, the fourth color is transparent black.
+        let transparent = [0x1F, 0x00, 0x00, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert_all(&decode_block(ImageFormat::BC1, &transparent), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bc2() {
+        // Alternating 4 bit alphas of 0 and 15, over red.
+        let block = [0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0,
+                     0x00, 0xF8, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let texels = decode_block(ImageFormat::BC2, &block);
+        for (i, texel) in texels.iter().enumerate() {
+            let alpha = if i % 2 == 0 { 0 } else { 255 };
+            assert_eq!(*texel, [255, 0, 0, alpha]);
+        }
+    }
+
+    #[test]
+    fn bc3() {
+        // Every texel picks the first alpha endpoint.
+        let block = [0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                     0x00, 0xF8, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_all(&decode_block(ImageFormat::BC3, &block), [255, 0, 0, 128]);
+
+        // The second endpoint, with an index of 1 in every 3 bits.
+        let block = [0x80, 0x40, 0x49, 0x92, 0x24, 0x49, 0x92, 0x24,
+                     0x00, 0xF8, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_all(&decode_block(ImageFormat::BC3, &block), [255, 0, 0, 64]);
+    }
+
+    #[test]
+    fn etc2_rgb() {
+        // An individual mode block with a base color of 0x88 and the first
+        // table, whose offsets are 2 and 8.
+        let plus_small = [0x88, 0x88, 0x88, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_all(&decode_block(ImageFormat::ETC2RGB8, &plus_small), [138, 138, 138, 255]);
+        let minus_large = [0x88, 0x88, 0x88, 0x00, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert_all(&decode_block(ImageFormat::ETC2RGB8, &minus_large), [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn etc2_rgba() {
+        // A base alpha of 128 and a multiplier of 1, with the first table. The
+        // first index subtracts 3, the fifth adds 2.
+        let block = [0x80, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                     0x88, 0x88, 0x88, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_all(&decode_block(ImageFormat::ETC2RGBA8, &block), [138, 138, 138, 125]);
+        let block = [0x80, 0x10, 0x92, 0x49, 0x24, 0x92, 0x49, 0x24,
+                     0x88, 0x88, 0x88, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_all(&decode_block(ImageFormat::ETC2RGBA8, &block), [138, 138, 138, 130]);
+    }
+
+    #[test]
+    fn astc_void_extent() {
+        // A void extent block covering the whole image, with a color of
+        // (0xFFFF, 0x8000, 0, 0xFFFF).
+        let block = [0xFC, 0xFD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                     0xFF, 0xFF, 0x00, 0x80, 0x00, 0x00, 0xFF, 0xFF];
+        assert_all(&decode_block(ImageFormat::ASTC4x4, &block), [255, 128, 0, 255]);
+
+        // HDR void extent blocks aren't supported, and get the error color.
+        let block = [0xFC, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                     0xFF, 0xFF, 0x00, 0x80, 0x00, 0x00, 0xFF, 0xFF];
+        assert_all(&decode_block(ImageFormat::ASTC4x4, &block), [255, 0, 255, 255]);
+    }
+
+    #[test]
+    fn partial_blocks() {
+        // A 5x3 image needs two blocks, of which only some texels are kept.
+        let red = [0x00, 0xF8, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let bytes: Vec<u8> = red.iter().chain(red.iter()).cloned().collect();
+        let pixels = decode_image(ImageFormat::BC1, 5, 3, &bytes).unwrap();
+        assert_eq!(pixels.len(), 5 * 3 * 4);
+        for bgra in pixels.chunks(4) {
+            assert_eq!(bgra, &[0, 0, 255, 255]);
+        }
+    }
+
+    #[test]
+    fn invalid_input() {
+        // Short data is an error rather than a panic.
+        assert!(decode_image(ImageFormat::BC1, 8, 8, &[0; 24]).is_err());
+        assert!(decode_image(ImageFormat::RGBA8, 4, 4, &[0; 64]).is_err());
+    }
+}
@@ -12,16 +12,18 @@ use internal_types::{BatchTextures, CacheTextureId, SourceTexture};
 use layer::Layer;
 use mask_cache::{ClipSource, MaskCacheInfo};
 use prim_store::{PrimitiveGeometry, RectanglePrimitive, PrimitiveContainer};
-use prim_store::{BorderPrimitiveCpu, BorderPrimitiveGpu, BoxShadowPrimitiveGpu};
+use prim_store::{BorderImagePatch, BorderPrimitiveCpu, BorderPrimitiveGpu, BoxShadowPrimitiveGpu};
 use prim_store::{ImagePrimitiveCpu, ImagePrimitiveGpu, YuvImagePrimitiveCpu, YuvImagePrimitiveGpu, ImagePrimitiveKind, };
 use prim_store::{PrimitiveKind, PrimitiveIndex, PrimitiveMetadata, TexelRect};
 use prim_store::{CLIP_DATA_GPU_SIZE, DeferredResolve};
 use prim_store::{GradientPrimitiveCpu, GradientPrimitiveGpu, GradientType};
+use prim_store::{RadialGradientPrimitiveCpu, RadialGradientPrimitiveGpu};
+use prim_store::{ConicGradientPrimitiveCpu, ConicGradientPrimitiveGpu};
 use prim_store::{PrimitiveCacheKey, TextRunPrimitiveGpu, TextRunPrimitiveCpu};
 use prim_store::{PrimitiveStore, GpuBlock16, GpuBlock32, GpuBlock64, GpuBlock128};
 use profiler::FrameProfileCounters;
 use renderer::BlendMode;
-use resource_cache::ResourceCache;
+use resource_cache::{ImageTiling, ResourceCache, TileOffset};
 use std::cmp;
 use std::collections::{HashMap};
 use std::{i32, f32};
@@ -32,8 +34,8 @@ use std::usize;
 use texture_cache::TexturePage;
 use util::{self, rect_from_points, rect_from_points_f};
 use util::{TransformedRect, TransformedRectKind, subtract_rect, pack_as_float};
-use webrender_traits::{ColorF, FontKey, ImageKey, ImageRendering, MixBlendMode};
-use webrender_traits::{BorderDisplayItem, BorderSide, BorderStyle, YuvColorSpace};
+use webrender_traits::{ColorF, ExtendMode, FontKey, ImageKey, ImageRendering, MixBlendMode};
+use webrender_traits::{BorderDisplayItem, BorderImageDisplayItem, RepeatMode, YuvColorRange, YuvColorSpace, YuvData, YuvFormat};
 use webrender_traits::{AuxiliaryLists, ItemRange, BoxShadowClipMode, ClipRegion};
 use webrender_traits::{PipelineId, ScrollLayerId, WebGLContextId, FontRenderMode};
 use webrender_traits::{DeviceIntRect, DeviceIntPoint, DeviceIntSize, DeviceIntLength, device_length};
@@ -52,6 +54,10 @@ const OPAQUE_TASK_INDEX: RenderTaskIndex = RenderTaskIndex(i32::MAX as usize);
 
 const FLOATS_PER_RENDER_TASK_INFO: usize = 8;
 
+// The number of stops the gradient shaders that take their stops in
+// varyings can draw. Must match MAX_STOPS_PER_GRADIENT in prim_shared.glsl.
+const MAX_STOPS_PER_GRADIENT: usize = 8;
+
 pub type LayerMap = HashMap<ScrollLayerId,
                             Layer,
                             BuildHasherDefault<FnvHasher>>;
@@ -86,7 +92,11 @@ impl AlphaBatchHelpers for PrimitiveStore {
             PrimitiveKind::Border => AlphaBatchKind::Border,
             PrimitiveKind::BoxShadow => AlphaBatchKind::BoxShadow,
             PrimitiveKind::Image => AlphaBatchKind::Image,
-            PrimitiveKind::YuvImage => AlphaBatchKind::YuvImage,
+            PrimitiveKind::BorderImage => AlphaBatchKind::BorderImage,
+            PrimitiveKind::YuvImage => {
+                let image_cpu = &self.cpu_yuv_images[metadata.cpu_prim_index.0];
+                AlphaBatchKind::YuvImage(image_cpu.format)
+            }
             PrimitiveKind::Rectangle => AlphaBatchKind::Rectangle,
             PrimitiveKind::TextRun => {
                 let text_run_cpu = &self.cpu_text_runs[metadata.cpu_prim_index.0];
@@ -110,6 +120,8 @@ impl AlphaBatchHelpers for PrimitiveStore {
                     }
                 }
             }
+            PrimitiveKind::RadialGradient => AlphaBatchKind::RadialGradient,
+            PrimitiveKind::ConicGradient => AlphaBatchKind::ConicGradient,
         };
 
         batch_kind
@@ -121,14 +133,22 @@ impl AlphaBatchHelpers for PrimitiveStore {
             PrimitiveKind::Border |
             PrimitiveKind::BoxShadow |
             PrimitiveKind::Rectangle |
-            PrimitiveKind::Gradient => [invalid; 3],
-            PrimitiveKind::Image => {
+            PrimitiveKind::Gradient |
+            PrimitiveKind::RadialGradient |
+            PrimitiveKind::ConicGradient => [invalid; 3],
+            PrimitiveKind::Image |
+            PrimitiveKind::BorderImage => {
                 let image_cpu = &self.cpu_images[metadata.cpu_prim_index.0];
                 [image_cpu.color_texture_id, invalid, invalid]
             }
             PrimitiveKind::YuvImage => {
                 let image_cpu = &self.cpu_yuv_images[metadata.cpu_prim_index.0];
-                [image_cpu.y_texture_id, image_cpu.u_texture_id, image_cpu.v_texture_id]
+                match image_cpu.format {
+                    // The planes are read by the render tasks that decode
+                    // them, and the primitive only reads the cache.
+                    YuvFormat::DctCoefficients => [invalid; 3],
+                    _ => image_cpu.yuv_texture_id,
+                }
             }
             PrimitiveKind::TextRun => {
                 let text_run_cpu = &self.cpu_text_runs[metadata.cpu_prim_index.0];
@@ -172,8 +192,11 @@ impl AlphaBatchHelpers for PrimitiveStore {
             PrimitiveKind::Rectangle |
             PrimitiveKind::TextRun |
             PrimitiveKind::Image |
+            PrimitiveKind::BorderImage |
             PrimitiveKind::YuvImage |
             PrimitiveKind::Gradient |
+            PrimitiveKind::RadialGradient |
+            PrimitiveKind::ConicGradient |
             PrimitiveKind::BoxShadow => true,
             PrimitiveKind::Border => {
                 let border = &self.cpu_borders[metadata.cpu_prim_index.0];
@@ -257,7 +280,33 @@ impl AlphaBatchHelpers for PrimitiveStore {
                     z_sort_index: z_sort_index,
                 });
             }
-            &mut PrimitiveBatchData::YuvImage(ref mut data) => {
+            &mut PrimitiveBatchData::BorderImage(ref mut data) => {
+                let image_cpu = &self.cpu_images[metadata.cpu_prim_index.0];
+
+                for patch_index in 0..metadata.gpu_data_count {
+                    data.push(PrimitiveInstance {
+                        task_index: task_index,
+                        clip_task_index: clip_task_index,
+                        layer_index: layer_index,
+                        global_prim_id: global_prim_id,
+                        prim_address: prim_address,
+                        sub_index: metadata.gpu_data_address.0 + patch_index,
+                        user_data: [ image_cpu.resource_address.0, 0 ],
+                        z_sort_index: z_sort_index,
+                    });
+                }
+            }
+            &mut PrimitiveBatchData::YuvImage(format, ref mut data) => {
+                // Images of DCT coefficients are decoded to RGB by their
+                // render tasks, so pass the shader the one to sample from.
+                let cache_task_index = match format {
+                    YuvFormat::DctCoefficients => {
+                        let cache_task_id = &metadata.render_task.as_ref().unwrap().id;
+                        render_tasks.get_task_index(cache_task_id, child_pass_index).0 as i32
+                    }
+                    _ => 0,
+                };
+
                 data.push(PrimitiveInstance {
                     task_index: task_index,
                     clip_task_index: clip_task_index,
@@ -265,7 +314,7 @@ impl AlphaBatchHelpers for PrimitiveStore {
                     global_prim_id: global_prim_id,
                     prim_address: prim_address,
                     sub_index: 0,
-                    user_data: [ 0, 0 ],
+                    user_data: [ cache_task_index, 0 ],
                     z_sort_index: z_sort_index,
                 });
             }
@@ -284,17 +333,21 @@ impl AlphaBatchHelpers for PrimitiveStore {
                 }
             }
             &mut PrimitiveBatchData::AlignedGradient(ref mut data) => {
-                for part_index in 0..(metadata.gpu_data_count - 1) {
-                    data.push(PrimitiveInstance {
-                        task_index: task_index,
-                        clip_task_index: clip_task_index,
-                        layer_index: layer_index,
-                        global_prim_id: global_prim_id,
-                        prim_address: prim_address,
-                        sub_index: metadata.gpu_data_address.0 + part_index,
-                        user_data: [ 0, 0 ],
-                        z_sort_index: z_sort_index,
-                    });
+                let gradient_cpu = &self.cpu_gradients[metadata.cpu_prim_index.0];
+
+                for repetition in gradient_cpu.repetitions.clone() {
+                    for part_index in 0..(metadata.gpu_data_count - 1) {
+                        data.push(PrimitiveInstance {
+                            task_index: task_index,
+                            clip_task_index: clip_task_index,
+                            layer_index: layer_index,
+                            global_prim_id: global_prim_id,
+                            prim_address: prim_address,
+                            sub_index: metadata.gpu_data_address.0 + part_index,
+                            user_data: [ repetition, 0 ],
+                            z_sort_index: z_sort_index,
+                        });
+                    }
                 }
             }
             &mut PrimitiveBatchData::AngleGradient(ref mut data) => {
@@ -309,6 +362,29 @@ impl AlphaBatchHelpers for PrimitiveStore {
                     z_sort_index: z_sort_index,
                 });
             }
+            &mut PrimitiveBatchData::RadialGradient(ref mut data) |
+            &mut PrimitiveBatchData::ConicGradient(ref mut data) => {
+                // Stops that don't fit in the varyings of the shader are
+                // drawn by more instances, each starting at the last stop
+                // of the previous one.
+                let mut first_stop = 0;
+                loop {
+                    data.push(PrimitiveInstance {
+                        task_index: task_index,
+                        clip_task_index: clip_task_index,
+                        layer_index: layer_index,
+                        global_prim_id: global_prim_id,
+                        prim_address: prim_address,
+                        sub_index: metadata.gpu_data_address.0,
+                        user_data: [ metadata.gpu_data_count, first_stop ],
+                        z_sort_index: z_sort_index,
+                    });
+                    first_stop += MAX_STOPS_PER_GRADIENT as i32 - 1;
+                    if first_stop >= metadata.gpu_data_count - 1 {
+                        break;
+                    }
+                }
+            }
             &mut PrimitiveBatchData::CacheImage(ref mut data) => {
                 // Find the render task index for the render task
                 // that this primitive depends on. Pass it to the
@@ -405,6 +481,12 @@ pub enum RenderTaskKey {
     VerticalBlur(i32, PrimitiveIndex),
     /// Apply a horizontal blur pass of given radius for this primitive.
     HorizontalBlur(i32, PrimitiveIndex),
+    /// Apply the inverse DCT along the rows of a plane of this YUV image.
+    IdctRows(PrimitiveIndex, usize),
+    /// Apply the inverse DCT along the columns of a plane of this YUV image.
+    IdctColumns(PrimitiveIndex, usize),
+    /// Upsample the chroma of this YUV image and convert it to RGB.
+    YuvToRgb(PrimitiveIndex),
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -485,7 +567,7 @@ impl RenderTaskCollection {
 
 #[derive(Debug, Clone)]
 pub struct RenderTaskData {
-    data: [f32; FLOATS_PER_RENDER_TASK_INFO],
+    pub data: [f32; FLOATS_PER_RENDER_TASK_INFO],
 }
 
 impl RenderTaskData {
@@ -789,7 +871,7 @@ impl ClipBatcher {
             }));
 
             if let Some((ref mask, address)) = info.image {
-                let cache_item = resource_cache.get_cached_image(mask.image, ImageRendering::Auto);
+                let cache_item = resource_cache.get_cached_image(mask.image, ImageRendering::Auto, None);
                 self.images.entry(cache_item.texture_id)
                            .or_insert(Vec::new())
                            .push(CacheClipInstance {
@@ -834,6 +916,12 @@ pub struct RenderTarget {
     // List of blur operations to apply for this render target.
     pub vertical_blurs: Vec<BlurCommand>,
     pub horizontal_blurs: Vec<BlurCommand>,
+    // Passes of the YUV images of DCT coefficients decoded by this render
+    // target. The row passes read the coefficient plane images, so they are
+    // grouped by texture.
+    pub idct_rows: HashMap<SourceTexture, Vec<PrimitiveInstance>>,
+    pub idct_columns: Vec<PrimitiveInstance>,
+    pub yuv_to_rgb: Vec<PrimitiveInstance>,
     page_allocator: TexturePage,
 }
 
@@ -847,6 +935,9 @@ impl RenderTarget {
             text_run_textures: BatchTextures::no_texture(),
             vertical_blurs: Vec::new(),
             horizontal_blurs: Vec::new(),
+            idct_rows: HashMap::new(),
+            idct_columns: Vec::new(),
+            yuv_to_rgb: Vec::new(),
             page_allocator: TexturePage::new(CacheTextureId(0),
                                              RENDERABLE_CACHE_SIZE as u32),
         }
@@ -964,6 +1055,59 @@ impl RenderTarget {
                                       &task_info.clips,
                                       &ctx.resource_cache);
             }
+            RenderTaskKind::IdctRows(prim_index, plane) => {
+                let prim_metadata = ctx.prim_store.get_metadata(prim_index);
+                let image_cpu = &ctx.prim_store.cpu_yuv_images[prim_metadata.cpu_prim_index.0];
+                let instance = PrimitiveInstance {
+                    global_prim_id: prim_index.0 as i32,
+                    prim_address: prim_metadata.gpu_prim_index,
+                    task_index: render_tasks.get_task_index(&task.id, pass_index).0 as i32,
+                    clip_task_index: 0,
+                    layer_index: 0,
+                    sub_index: plane as i32,
+                    user_data: [0; 2],
+                    z_sort_index: 0,
+                };
+                self.idct_rows.entry(image_cpu.yuv_texture_id[plane])
+                              .or_insert(Vec::new())
+                              .push(instance);
+            }
+            RenderTaskKind::IdctColumns(prim_index, plane) => {
+                // Find the child render task holding the row pass of this plane.
+                let child_pass_index = RenderPassIndex(pass_index.0 - 1);
+                let src_id = RenderTaskId::Dynamic(RenderTaskKey::IdctRows(prim_index, plane));
+                let prim_metadata = ctx.prim_store.get_metadata(prim_index);
+                self.idct_columns.push(PrimitiveInstance {
+                    global_prim_id: prim_index.0 as i32,
+                    prim_address: prim_metadata.gpu_prim_index,
+                    task_index: render_tasks.get_task_index(&task.id, pass_index).0 as i32,
+                    clip_task_index: 0,
+                    layer_index: 0,
+                    sub_index: plane as i32,
+                    user_data: [ render_tasks.get_task_index(&src_id, child_pass_index).0 as i32, 0 ],
+                    z_sort_index: 0,
+                });
+            }
+            RenderTaskKind::YuvToRgb(prim_index) => {
+                let child_pass_index = RenderPassIndex(pass_index.0 - 1);
+                let plane_task_index = |plane| {
+                    let src_id = RenderTaskId::Dynamic(RenderTaskKey::IdctColumns(prim_index, plane));
+                    render_tasks.get_task_index(&src_id, child_pass_index).0 as i32
+                };
+                let prim_metadata = ctx.prim_store.get_metadata(prim_index);
+                // The user data only has room for two task indices, so the
+                // task of the luma plane goes in the sub index.
+                self.yuv_to_rgb.push(PrimitiveInstance {
+                    global_prim_id: prim_index.0 as i32,
+                    prim_address: prim_metadata.gpu_prim_index,
+                    task_index: render_tasks.get_task_index(&task.id, pass_index).0 as i32,
+                    clip_task_index: 0,
+                    layer_index: 0,
+                    sub_index: plane_task_index(0),
+                    user_data: [ plane_task_index(1), plane_task_index(2) ],
+                    z_sort_index: 0,
+                });
+            }
         }
     }
 }
@@ -1106,6 +1250,9 @@ pub enum RenderTaskKind {
     CacheMask(CacheMaskTask),
     VerticalBlur(DeviceIntLength, PrimitiveIndex),
     HorizontalBlur(DeviceIntLength, PrimitiveIndex),
+    IdctRows(PrimitiveIndex, usize),
+    IdctColumns(PrimitiveIndex, usize),
+    YuvToRgb(PrimitiveIndex),
 }
 
 // TODO(gw): Consider storing these in a separate array and having
@@ -1235,13 +1382,58 @@ impl RenderTask {
         blur_task_h
     }
 
+    // Construct the render tasks that decode a YUV image of DCT coefficients,
+    // given the size in samples of each of its planes. The inverse DCT is
+    // separable, so each plane takes two passes.
+    // The render task chain that is constructed looks like:
+    //
+    //    IdctRowsTask: Apply the inverse DCT along the rows of each block of
+    //           ^      the plane, for each plane.
+    //           |
+    //    IdctColumnsTask: Apply the inverse DCT along the columns, giving the
+    //           ^         samples of the plane.
+    //           |
+    //    YuvToRgbTask: Upsample the chroma planes to the size of the luma
+    //           |      plane and convert the samples to RGB.
+    //           |
+    //           +---- This is stored as the input task to the primitive shader.
+    //
+    pub fn new_dct_decode(plane_sizes: &[DeviceIntSize; 3],
+                          prim_index: PrimitiveIndex) -> RenderTask {
+        let plane_tasks = plane_sizes.iter().enumerate().map(|(plane, size)| {
+            let rows_task = RenderTask {
+                id: RenderTaskId::Dynamic(RenderTaskKey::IdctRows(prim_index, plane)),
+                children: Vec::new(),
+                location: RenderTaskLocation::Dynamic(None, *size),
+                kind: RenderTaskKind::IdctRows(prim_index, plane),
+            };
+
+            RenderTask {
+                id: RenderTaskId::Dynamic(RenderTaskKey::IdctColumns(prim_index, plane)),
+                children: vec![rows_task],
+                location: RenderTaskLocation::Dynamic(None, *size),
+                kind: RenderTaskKind::IdctColumns(prim_index, plane),
+            }
+        }).collect();
+
+        RenderTask {
+            id: RenderTaskId::Dynamic(RenderTaskKey::YuvToRgb(prim_index)),
+            children: plane_tasks,
+            location: RenderTaskLocation::Dynamic(None, plane_sizes[0]),
+            kind: RenderTaskKind::YuvToRgb(prim_index),
+        }
+    }
+
     fn as_alpha_batch<'a>(&'a mut self) -> &'a mut AlphaRenderTask {
         match self.kind {
             RenderTaskKind::Alpha(ref mut task) => task,
             RenderTaskKind::CachePrimitive(..) |
             RenderTaskKind::CacheMask(..) |
             RenderTaskKind::VerticalBlur(..) |
-            RenderTaskKind::HorizontalBlur(..) => unreachable!(),
+            RenderTaskKind::HorizontalBlur(..) |
+            RenderTaskKind::IdctRows(..) |
+            RenderTaskKind::IdctColumns(..) |
+            RenderTaskKind::YuvToRgb(..) => unreachable!(),
         }
     }
 
@@ -1266,7 +1458,10 @@ impl RenderTask {
                     ],
                 }
             }
-            RenderTaskKind::CachePrimitive(..) => {
+            RenderTaskKind::CachePrimitive(..) |
+            RenderTaskKind::IdctRows(..) |
+            RenderTaskKind::IdctColumns(..) |
+            RenderTaskKind::YuvToRgb(..) => {
                 RenderTaskData {
                     data: [
                         target_rect.origin.x as f32,
@@ -1367,17 +1562,19 @@ pub struct DebugRect {
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
-#[repr(u8)]
 enum AlphaBatchKind {
-    Composite = 0,
+    Composite,
     Blend,
     Rectangle,
     TextRun,
     Image,
-    YuvImage,
+    YuvImage(YuvFormat),
     Border,
+    BorderImage,
     AlignedGradient,
     AngleGradient,
+    RadialGradient,
+    ConicGradient,
     BoxShadow,
     CacheImage,
 }
@@ -1460,6 +1657,24 @@ pub enum BlurDirection {
     Vertical,
 }
 
+/// Returns the size of the tiles that fill `length` with a part of a border
+/// image, and the offset of the first one, given the size the part would
+/// have unscaled. A part without an unscaled size is stretched.
+fn repeat_tile(mode: RepeatMode, length: f32, natural_size: f32) -> (f32, f32) {
+    if natural_size <= 0.0 {
+        return (length, 0.0);
+    }
+
+    match mode {
+        RepeatMode::Stretch => (length, 0.0),
+        RepeatMode::Repeat => (natural_size, 0.5 * (length - natural_size)),
+        RepeatMode::Round => {
+            let count = (length / natural_size).round().max(1.0);
+            (length / count, 0.0)
+        }
+    }
+}
+
 #[inline]
 fn textures_compatible(t1: SourceTexture, t2: SourceTexture) -> bool {
     t1 == SourceTexture::Invalid || t2 == SourceTexture::Invalid || t1 == t2
@@ -1468,10 +1683,10 @@ fn textures_compatible(t1: SourceTexture, t2: SourceTexture) -> bool {
 // All Packed Primitives below must be 16 byte aligned.
 #[derive(Debug)]
 pub struct BlurCommand {
-    task_id: i32,
-    src_task_id: i32,
-    blur_direction: i32,
-    padding: i32,
+    pub task_id: i32,
+    pub src_task_id: i32,
+    pub blur_direction: i32,
+    pub padding: i32,
 }
 
 /// A clipping primitive drawn into the clipping mask.
@@ -1479,22 +1694,22 @@ pub struct BlurCommand {
 /// way `address` is treated.
 #[derive(Clone, Copy, Debug)]
 pub struct CacheClipInstance {
-    task_id: i32,
-    layer_index: i32,
-    address: GpuStoreAddress,
-    base_task_id: i32,
+    pub task_id: i32,
+    pub layer_index: i32,
+    pub address: GpuStoreAddress,
+    pub base_task_id: i32,
 }
 
 #[derive(Debug, Clone)]
 pub struct PrimitiveInstance {
-    global_prim_id: i32,
-    prim_address: GpuStoreAddress,
-    task_index: i32,
-    clip_task_index: i32,
-    layer_index: i32,
-    sub_index: i32,
-    z_sort_index: i32,
-    user_data: [i32; 2],
+    pub global_prim_id: i32,
+    pub prim_address: GpuStoreAddress,
+    pub task_index: i32,
+    pub clip_task_index: i32,
+    pub layer_index: i32,
+    pub sub_index: i32,
+    pub z_sort_index: i32,
+    pub user_data: [i32; 2],
 }
 
 impl PrimitiveInstance {
@@ -1537,10 +1752,13 @@ pub enum PrimitiveBatchData {
     Rectangles(Vec<PrimitiveInstance>),
     TextRun(Vec<PrimitiveInstance>),
     Image(Vec<PrimitiveInstance>),
-    YuvImage(Vec<PrimitiveInstance>),
+    YuvImage(YuvFormat, Vec<PrimitiveInstance>),
     Borders(Vec<PrimitiveInstance>),
+    BorderImage(Vec<PrimitiveInstance>),
     AlignedGradient(Vec<PrimitiveInstance>),
     AngleGradient(Vec<PrimitiveInstance>),
+    RadialGradient(Vec<PrimitiveInstance>),
+    ConicGradient(Vec<PrimitiveInstance>),
     BoxShadow(Vec<PrimitiveInstance>),
     CacheImage(Vec<PrimitiveInstance>),
     Blend(Vec<PrimitiveInstance>),
@@ -1623,10 +1841,13 @@ impl PrimitiveBatch {
             AlphaBatchKind::Rectangle => PrimitiveBatchData::Rectangles(Vec::new()),
             AlphaBatchKind::TextRun => PrimitiveBatchData::TextRun(Vec::new()),
             AlphaBatchKind::Image => PrimitiveBatchData::Image(Vec::new()),
-            AlphaBatchKind::YuvImage => PrimitiveBatchData::YuvImage(Vec::new()),
+            AlphaBatchKind::YuvImage(format) => PrimitiveBatchData::YuvImage(format, Vec::new()),
             AlphaBatchKind::Border => PrimitiveBatchData::Borders(Vec::new()),
+            AlphaBatchKind::BorderImage => PrimitiveBatchData::BorderImage(Vec::new()),
             AlphaBatchKind::AlignedGradient => PrimitiveBatchData::AlignedGradient(Vec::new()),
             AlphaBatchKind::AngleGradient => PrimitiveBatchData::AngleGradient(Vec::new()),
+            AlphaBatchKind::RadialGradient => PrimitiveBatchData::RadialGradient(Vec::new()),
+            AlphaBatchKind::ConicGradient => PrimitiveBatchData::ConicGradient(Vec::new()),
             AlphaBatchKind::BoxShadow => PrimitiveBatchData::BoxShadow(Vec::new()),
             AlphaBatchKind::Blend | AlphaBatchKind::Composite => unreachable!(),
             AlphaBatchKind::CacheImage => PrimitiveBatchData::CacheImage(Vec::new()),
@@ -1667,10 +1888,10 @@ struct StackingContext {
 
 #[derive(Debug, Clone)]
 pub struct PackedStackingContext {
-    transform: LayerToWorldTransform,
-    inv_transform: WorldToLayerTransform,
-    local_clip_rect: LayerRect,
-    screen_vertices: [WorldPoint4D; 4],
+    pub transform: LayerToWorldTransform,
+    pub inv_transform: WorldToLayerTransform,
+    pub local_clip_rect: LayerRect,
+    pub screen_vertices: [WorldPoint4D; 4],
 }
 
 impl Default for PackedStackingContext {
@@ -1732,6 +1953,13 @@ impl StackingContext {
             CompositeKind::Simple(..) => true,
         }
     }
+
+    /// The part of the stacking context that its primitives can be drawn in,
+    /// whichever way it is scrolled.
+    fn local_bounds(&self) -> Option<LayerRect> {
+        let local_clip_rect = self.clip_source.to_rect().unwrap_or(self.local_rect);
+        self.local_rect.intersection(&local_clip_rect)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -1765,6 +1993,8 @@ pub struct FrameBuilder {
 
     layer_store: Vec<StackingContext>,
     packed_layers: Vec<PackedStackingContext>,
+    /// The stacking contexts that primitives are currently added to.
+    layer_stack: Vec<StackingContextIndex>,
 
     scrollbar_prims: Vec<ScrollbarPrimitive>,
 }
@@ -2079,6 +2309,7 @@ impl FrameBuilder {
             cmds: Vec::new(),
             debug: debug,
             packed_layers: Vec::new(),
+            layer_stack: Vec::new(),
             scrollbar_prims: Vec::new(),
             config: config,
         }
@@ -2155,10 +2386,12 @@ impl FrameBuilder {
         });
 
         self.cmds.push(PrimitiveRunCmd::PushStackingContext(sc_index));
+        self.layer_stack.push(sc_index);
     }
 
     pub fn pop_layer(&mut self) {
         self.cmds.push(PrimitiveRunCmd::PopStackingContext);
+        self.layer_stack.pop();
     }
 
     pub fn add_solid_rectangle(&mut self,
@@ -2190,64 +2423,59 @@ impl FrameBuilder {
         }
     }
 
-    pub fn supported_style(&mut self, border: &BorderSide) -> bool {
-        match border.style {
-            BorderStyle::Solid |
-            BorderStyle::None |
-            BorderStyle::Dotted |
-            BorderStyle::Dashed |
-            BorderStyle::Inset |
-            BorderStyle::Ridge |
-            BorderStyle::Groove |
-            BorderStyle::Outset |
-            BorderStyle::Double => {
-                return true;
-            }
-            _ => {
-                println!("TODO: Other border styles {:?}", border.style);
-                return false;
-            }
-        }
-    }
-
     pub fn add_border(&mut self,
                       rect: LayerRect,
                       clip_region: &ClipRegion,
                       border: &BorderDisplayItem) {
         let radius = &border.radius;
-        let left = &border.left;
-        let right = &border.right;
-        let top = &border.top;
-        let bottom = &border.bottom;
-
-        if !self.supported_style(left) || !self.supported_style(right) ||
-           !self.supported_style(top) || !self.supported_style(bottom) {
-            println!("Unsupported border style, not rendering border");
+        let sides = [&border.left, &border.top, &border.right, &border.bottom];
+
+        if sides.iter().all(|side| !side.is_visible()) {
             return;
         }
 
         // These colors are used during inset/outset scaling.
-        let left_color      = left.border_color(1.0, 2.0/3.0, 0.3, 0.7);
-        let top_color       = top.border_color(1.0, 2.0/3.0, 0.3, 0.7);
-        let right_color     = right.border_color(2.0/3.0, 1.0, 0.7, 0.3);
-        let bottom_color    = bottom.border_color(2.0/3.0, 1.0, 0.7, 0.3);
+        let mut colors = [
+            border.left.border_color(1.0, 2.0/3.0, 0.3, 0.7),
+            border.top.border_color(1.0, 2.0/3.0, 0.3, 0.7),
+            border.right.border_color(2.0/3.0, 1.0, 0.7, 0.3),
+            border.bottom.border_color(2.0/3.0, 1.0, 0.7, 0.3),
+        ];
+        let mut widths = [0.0; 4];
+        let mut styles = [0.0; 4];
+
+        // As in CSS, a `none` or `hidden` side has no width and draws nothing,
+        // while the other sides are still drawn. The shader draws the corner
+        // that follows each side, in left, top, right, bottom order, with the
+        // style of that side. A missing side lends its corner the style of the
+        // next side, so the corner fades from that side into transparency.
+        for (i, side) in sides.iter().enumerate() {
+            if side.is_visible() {
+                widths[i] = side.width;
+                styles[i] = pack_as_float(side.style as u32);
+            } else {
+                colors[i] = ColorF::new(0.0, 0.0, 0.0, 0.0);
+                styles[i] = pack_as_float(sides[(i + 1) % 4].style as u32);
+            }
+        }
+        let (left_width, top_width, right_width, bottom_width) = (widths[0], widths[1], widths[2], widths[3]);
 
         let tl_outer = LayerPoint::new(rect.origin.x, rect.origin.y);
-        let tl_inner = tl_outer + LayerPoint::new(radius.top_left.width.max(left.width),
-                                                  radius.top_left.height.max(top.width));
+        let tl_inner = tl_outer + LayerPoint::new(radius.top_left.width.max(left_width),
+                                                  radius.top_left.height.max(top_width));
 
         let tr_outer = LayerPoint::new(rect.origin.x + rect.size.width, rect.origin.y);
-        let tr_inner = tr_outer + LayerPoint::new(-radius.top_right.width.max(right.width),
-                                                  radius.top_right.height.max(top.width));
+        let tr_inner = tr_outer + LayerPoint::new(-radius.top_right.width.max(right_width),
+                                                  radius.top_right.height.max(top_width));
 
         let bl_outer = LayerPoint::new(rect.origin.x, rect.origin.y + rect.size.height);
-        let bl_inner = bl_outer + LayerPoint::new(radius.bottom_left.width.max(left.width),
-                                                  -radius.bottom_left.height.max(bottom.width));
+        let bl_inner = bl_outer + LayerPoint::new(radius.bottom_left.width.max(left_width),
+                                                  -radius.bottom_left.height.max(bottom_width));
 
         let br_outer = LayerPoint::new(rect.origin.x + rect.size.width,
                                        rect.origin.y + rect.size.height);
-        let br_inner = br_outer - LayerPoint::new(radius.bottom_right.width.max(right.width),
-                                                  radius.bottom_right.height.max(bottom.width));
+        let br_inner = br_outer - LayerPoint::new(radius.bottom_right.width.max(right_width),
+                                                  radius.bottom_right.height.max(bottom_width));
 
         //Note: while similar to `ComplexClipRegion::get_inner_rect()` in spirit,
         // this code is a bit more complex and can not there for be merged.
@@ -2261,14 +2489,9 @@ impl FrameBuilder {
         };
 
         let prim_gpu = BorderPrimitiveGpu {
-            colors: [ left_color, top_color, right_color, bottom_color ],
-            widths: [ left.width, top.width, right.width, bottom.width ],
-            style: [
-                pack_as_float(left.style as u32),
-                pack_as_float(top.style as u32),
-                pack_as_float(right.style as u32),
-                pack_as_float(bottom.style as u32),
-            ],
+            colors: colors,
+            widths: widths,
+            style: styles,
             radii: [
                 radius.top_left,
                 radius.top_right,
@@ -2282,13 +2505,113 @@ impl FrameBuilder {
                            PrimitiveContainer::Border(prim_cpu, prim_gpu));
     }
 
+    pub fn add_border_image(&mut self,
+                            rect: LayerRect,
+                            clip_region: &ClipRegion,
+                            border: &BorderImageDisplayItem,
+                            image_size: DeviceUintSize) {
+        let slice = &border.slice;
+        let widths = &border.widths;
+        let image_width = image_size.width as f32;
+        let image_height = image_size.height as f32;
+
+        // The nine parts are laid out on the same 3x3 grid in the item
+        // and in the image.
+        let dest_x = [rect.origin.x,
+                      rect.origin.x + widths.left,
+                      rect.origin.x + rect.size.width - widths.right,
+                      rect.origin.x + rect.size.width];
+        let dest_y = [rect.origin.y,
+                      rect.origin.y + widths.top,
+                      rect.origin.y + rect.size.height - widths.bottom,
+                      rect.origin.y + rect.size.height];
+        let src_x = [0.0, slice.left, image_width - slice.right, image_width];
+        let src_y = [0.0, slice.top, image_height - slice.bottom, image_height];
+
+        // Each edge keeps the proportions of its slice, scaled to the
+        // width of the border. This is the size that its tiles repeat at.
+        let scale = |width: f32, slice_width: f32| if slice_width > 0.0 { width / slice_width } else { 0.0 };
+        let scale_left = scale(widths.left, slice.left);
+        let scale_top = scale(widths.top, slice.top);
+        let scale_right = scale(widths.right, slice.right);
+        let scale_bottom = scale(widths.bottom, slice.bottom);
+
+        let mut patches = Vec::new();
+        for row in 0..3 {
+            for column in 0..3 {
+                if row == 1 && column == 1 && !border.fill {
+                    continue;
+                }
+
+                let local_rect = rect_from_points_f(dest_x[column], dest_y[row],
+                                                    dest_x[column + 1], dest_y[row + 1]);
+                let src_rect = [src_x[column], src_y[row], src_x[column + 1], src_y[row + 1]];
+                if local_rect.size.width <= 0.0 || local_rect.size.height <= 0.0 ||
+                   src_rect[2] <= src_rect[0] || src_rect[3] <= src_rect[1] {
+                    continue;
+                }
+
+                // The corners are only ever stretched.
+                let (mode_x, scale_x) = match (row, column) {
+                    (0, 1) | (1, 1) => (border.repeat_top, scale_top),
+                    (2, 1) => (border.repeat_bottom, scale_bottom),
+                    _ => (RepeatMode::Stretch, 0.0),
+                };
+                let (mode_y, scale_y) = match (row, column) {
+                    (1, 0) | (1, 1) => (border.repeat_left, scale_left),
+                    (1, 2) => (border.repeat_right, scale_right),
+                    _ => (RepeatMode::Stretch, 0.0),
+                };
+
+                let (tile_width, offset_x) = repeat_tile(mode_x,
+                                                         local_rect.size.width,
+                                                         (src_rect[2] - src_rect[0]) * scale_x);
+                let (tile_height, offset_y) = repeat_tile(mode_y,
+                                                          local_rect.size.height,
+                                                          (src_rect[3] - src_rect[1]) * scale_y);
+
+                // The image may be cached at a smaller size than its own, see
+                // `ResourceCache::get_image_tiling`, so the patch refers to it
+                // relative to the size of the image.
+                patches.push(BorderImagePatch {
+                    local_rect: LayerRect::from_untyped(&local_rect),
+                    src_rect: [src_rect[0] / image_width,
+                               src_rect[1] / image_height,
+                               src_rect[2] / image_width,
+                               src_rect[3] / image_height],
+                    tile_size: LayerSize::new(tile_width, tile_height),
+                    tile_offset: LayerPoint::new(offset_x, offset_y),
+                    padding: [0.0; 4],
+                });
+            }
+        }
+
+        if patches.is_empty() {
+            return;
+        }
+
+        let prim_cpu = ImagePrimitiveCpu {
+            kind: ImagePrimitiveKind::Image(border.image_key,
+                                            border.image_rendering,
+                                            None,
+                                            LayerSize::zero()),
+            color_texture_id: SourceTexture::Invalid,
+            resource_address: GpuStoreAddress(0),
+        };
+
+        self.add_primitive(&rect,
+                           clip_region,
+                           PrimitiveContainer::BorderImage(prim_cpu, patches));
+    }
+
     pub fn add_gradient(&mut self,
                         rect: LayerRect,
                         clip_region: &ClipRegion,
                         start_point: LayerPoint,
                         end_point: LayerPoint,
-                        stops: ItemRange) {
-        // Fast paths for axis-aligned gradients:
+                        stops: ItemRange,
+                        extend_mode: ExtendMode) {
+        // Fast paths for axis-aligned gradients.
         let mut reverse_stops = false;
         let kind = if start_point.x == end_point.x {
             GradientType::Vertical
@@ -2302,7 +2625,9 @@ impl FrameBuilder {
         let gradient_cpu = GradientPrimitiveCpu {
             stops_range: stops,
             kind: kind,
+            extend_mode: extend_mode,
             reverse_stops: reverse_stops,
+            repetitions: 0..1,
             cache_dirty: true,
         };
 
@@ -2318,8 +2643,10 @@ impl FrameBuilder {
         let gradient_gpu = GradientPrimitiveGpu {
             start_point: sp,
             end_point: ep,
-            padding: [0.0, 0.0, 0.0],
             kind: pack_as_float(kind as u32),
+            extend_mode: pack_as_float(extend_mode as u32),
+            first_offset: 0.0,
+            period: 0.0,
         };
 
         self.add_primitive(&rect,
@@ -2327,6 +2654,76 @@ impl FrameBuilder {
                            PrimitiveContainer::Gradient(gradient_cpu, gradient_gpu));
     }
 
+    pub fn add_radial_gradient(&mut self,
+                               rect: LayerRect,
+                               clip_region: &ClipRegion,
+                               center: LayerPoint,
+                               radius: LayerSize,
+                               start_radius: f32,
+                               end_radius: f32,
+                               stops: ItemRange,
+                               extend_mode: ExtendMode) {
+        if stops.length == 0 {
+            return
+        }
+
+        // The shader scales the vertical distance from the center by
+        // this ratio, so that the ellipse can be treated as a circle.
+        // A degenerate ellipse is drawn with the color of its last stop,
+        // by putting all the stops before the center.
+        let (start_radius, end_radius, ratio_xy) = if radius.width <= 0.0 || radius.height <= 0.0 {
+            (-1.0, -1.0, 1.0)
+        } else {
+            (start_radius, end_radius, radius.width / radius.height)
+        };
+
+        let radial_gradient_cpu = RadialGradientPrimitiveCpu {
+            stops_range: stops,
+            cache_dirty: true,
+        };
+
+        let radial_gradient_gpu = RadialGradientPrimitiveGpu {
+            center: center,
+            start_radius: start_radius,
+            end_radius: end_radius,
+            ratio_xy: ratio_xy,
+            extend_mode: pack_as_float(extend_mode as u32),
+            padding: [0.0, 0.0],
+        };
+
+        self.add_primitive(&rect,
+                           clip_region,
+                           PrimitiveContainer::RadialGradient(radial_gradient_cpu, radial_gradient_gpu));
+    }
+
+    pub fn add_conic_gradient(&mut self,
+                              rect: LayerRect,
+                              clip_region: &ClipRegion,
+                              center: LayerPoint,
+                              angle: f32,
+                              stops: ItemRange,
+                              extend_mode: ExtendMode) {
+        if stops.length == 0 {
+            return
+        }
+
+        let conic_gradient_cpu = ConicGradientPrimitiveCpu {
+            stops_range: stops,
+            cache_dirty: true,
+        };
+
+        let conic_gradient_gpu = ConicGradientPrimitiveGpu {
+            center: center,
+            angle: angle,
+            extend_mode: pack_as_float(extend_mode as u32),
+            padding: [0.0; 4],
+        };
+
+        self.add_primitive(&rect,
+                           clip_region,
+                           PrimitiveContainer::ConicGradient(conic_gradient_cpu, conic_gradient_gpu));
+    }
+
     pub fn add_text(&mut self,
                     rect: LayerRect,
                     clip_region: &ClipRegion,
@@ -2484,10 +2881,128 @@ impl FrameBuilder {
                      stretch_size: &LayerSize,
                      tile_spacing: &LayerSize,
                      image_key: ImageKey,
-                     image_rendering: ImageRendering) {
+                     image_rendering: ImageRendering,
+                     tiling: Option<ImageTiling>) {
+        match tiling {
+            Some(tiling) => {
+                self.add_tiled_image(rect,
+                                     clip_region,
+                                     stretch_size,
+                                     tile_spacing,
+                                     image_key,
+                                     image_rendering,
+                                     tiling);
+            }
+            None => {
+                self.add_image_primitive(rect,
+                                         clip_region,
+                                         stretch_size,
+                                         tile_spacing,
+                                         image_key,
+                                         image_rendering,
+                                         None);
+            }
+        }
+    }
+
+    /// Adds an image that is too large for the texture cache as one primitive for
+    /// each of its tiles, leaving out the tiles that can never be seen: those that
+    /// are clipped away, or lie outside of the stacking context.
+    ///
+    /// The tiles that are scrolled out of the viewport are culled with the other
+    /// primitives in `cull_layers`, so only the tiles on screen are requested from
+    /// the resource cache in a frame.
+    fn add_tiled_image(&mut self,
+                       rect: LayerRect,
+                       clip_region: &ClipRegion,
+                       stretch_size: &LayerSize,
+                       tile_spacing: &LayerSize,
+                       image_key: ImageKey,
+                       image_rendering: ImageRendering,
+                       tiling: ImageTiling) {
+        let layer_bounds = match self.layer_stack.last() {
+            Some(sc_index) => self.layer_store[sc_index.0].local_bounds(),
+            None => Some(rect),
+        };
+        let visible_rect = match layer_bounds.and_then(|bounds| bounds.intersection(&rect))
+                                             .and_then(|rect| rect.intersection(&clip_region.main)) {
+            Some(visible_rect) => visible_rect,
+            None => return,
+        };
+        if stretch_size.width <= 0.0 || stretch_size.height <= 0.0 {
+            return;
+        }
+
+        let image_size = tiling.image_size;
+        let tile_size = tiling.tile_size;
+        let tile_count_x = (image_size.width + tile_size - 1) / tile_size;
+        let tile_count_y = (image_size.height + tile_size - 1) / tile_size;
+        let scale_x = stretch_size.width / image_size.width as f32;
+        let scale_y = stretch_size.height / image_size.height as f32;
+
+        // The image is repeated across the item, so find the repetitions that are
+        // visible and add the visible tiles of each of them.
+        let step_x = stretch_size.width + tile_spacing.width;
+        let step_y = stretch_size.height + tile_spacing.height;
+        let mut repeat_y = ((visible_rect.origin.y - rect.origin.y) / step_y).floor();
+        while rect.origin.y + repeat_y * step_y < visible_rect.max_y() {
+            let repeat_origin_y = rect.origin.y + repeat_y * step_y;
+            let mut repeat_x = ((visible_rect.origin.x - rect.origin.x) / step_x).floor();
+            while rect.origin.x + repeat_x * step_x < visible_rect.max_x() {
+                let repeat_origin_x = rect.origin.x + repeat_x * step_x;
+
+                for tile_y in 0..tile_count_y {
+                    for tile_x in 0..tile_count_x {
+                        let tile_origin = DeviceUintPoint::new(tile_x * tile_size, tile_y * tile_size);
+                        let tile_pixel_size =
+                            DeviceUintSize::new(cmp::min(tile_size, image_size.width - tile_origin.x),
+                                                cmp::min(tile_size, image_size.height - tile_origin.y));
+                        let tile_rect = LayerRect::new(
+                            LayerPoint::new(repeat_origin_x + tile_origin.x as f32 * scale_x,
+                                            repeat_origin_y + tile_origin.y as f32 * scale_y),
+                            LayerSize::new(tile_pixel_size.width as f32 * scale_x,
+                                           tile_pixel_size.height as f32 * scale_y));
+
+                        if !tile_rect.intersects(&visible_rect) {
+                            continue;
+                        }
+
+                        // Tiles of the last repetitions may be cut off by the edges
+                        // of the item. The tile is still stretched to its full size,
+                        // so only the part within the item is drawn.
+                        let prim_rect = match tile_rect.intersection(&rect) {
+                            Some(prim_rect) => prim_rect,
+                            None => continue,
+                        };
+
+                        self.add_image_primitive(prim_rect,
+                                                 clip_region,
+                                                 &tile_rect.size,
+                                                 &LayerSize::zero(),
+                                                 image_key,
+                                                 image_rendering,
+                                                 Some(TileOffset { x: tile_x, y: tile_y }));
+                    }
+                }
+
+                repeat_x += 1.0;
+            }
+            repeat_y += 1.0;
+        }
+    }
+
+    fn add_image_primitive(&mut self,
+                           rect: LayerRect,
+                           clip_region: &ClipRegion,
+                           stretch_size: &LayerSize,
+                           tile_spacing: &LayerSize,
+                           image_key: ImageKey,
+                           image_rendering: ImageRendering,
+                           tile: Option<TileOffset>) {
         let prim_cpu = ImagePrimitiveCpu {
             kind: ImagePrimitiveKind::Image(image_key,
                                             image_rendering,
+                                            tile,
                                             *tile_spacing),
             color_texture_id: SourceTexture::Invalid,
             resource_address: GpuStoreAddress(0),
@@ -2506,21 +3021,26 @@ impl FrameBuilder {
     pub fn add_yuv_image(&mut self,
                          rect: LayerRect,
                          clip_region: &ClipRegion,
-                         y_image_key: ImageKey,
-                         u_image_key: ImageKey,
-                         v_image_key: ImageKey,
-                         color_space: YuvColorSpace) {
+                         yuv_data: YuvData,
+                         color_space: YuvColorSpace,
+                         color_range: YuvColorRange) {
+
+        // Unused planes repeat the first key so that every slot holds a
+        // valid image, only the first `get_plane_num()` are requested.
+        let yuv_key = match yuv_data {
+            YuvData::PlanarYCbCr(y, u, v) |
+            YuvData::DctCoefficients(y, u, v) => [y, u, v],
+            YuvData::NV12(y, uv) => [y, uv, y],
+            YuvData::InterleavedYCbCr(yuv) => [yuv, yuv, yuv],
+        };
 
         let prim_cpu = YuvImagePrimitiveCpu {
-            y_key: y_image_key,
-            u_key: u_image_key,
-            v_key: v_image_key,
-            y_texture_id: SourceTexture::Invalid,
-            u_texture_id: SourceTexture::Invalid,
-            v_texture_id: SourceTexture::Invalid,
+            yuv_key: yuv_key,
+            yuv_texture_id: [SourceTexture::Invalid; 3],
+            format: yuv_data.get_format(),
         };
 
-        let prim_gpu = YuvImagePrimitiveGpu::new(rect.size, color_space);
+        let prim_gpu = YuvImagePrimitiveGpu::new(rect.size, color_space, color_range);
 
         self.add_primitive(&rect,
                            clip_region,
@@ -2567,10 +3087,8 @@ impl FrameBuilder {
                     let inv_layer_transform = layer.local_transform.inverse().unwrap();
                     let local_viewport_rect = as_scroll_parent_rect(&scroll_layer.combined_local_viewport_rect);
                     let viewport_rect = inv_layer_transform.transform_rect(&local_viewport_rect);
-                    let local_clip_rect = layer.clip_source.to_rect().unwrap_or(layer.local_rect);
-                    let layer_local_rect = layer.local_rect
-                                                .intersection(&viewport_rect)
-                                                .and_then(|rect| rect.intersection(&local_clip_rect));
+                    let layer_local_rect = layer.local_bounds()
+                                                .and_then(|rect| rect.intersection(&viewport_rect));
 
                     if let Some(layer_local_rect) = layer_local_rect {
                         let layer_xf_rect = TransformedRect::new(&layer_local_rect,
@@ -2617,7 +3135,7 @@ impl FrameBuilder {
                                          device_pixel_ratio,
                                          auxiliary_lists);
                         if let ClipSource::Region(ClipRegion{ image_mask: Some(ref mask), .. }) = layer.clip_source {
-                            resource_cache.request_image(mask.image, ImageRendering::Auto);
+                            resource_cache.request_image(mask.image, ImageRendering::Auto, None);
                             //Note: no need to add the layer for resolve, all layers get resolved
                         }
                     }
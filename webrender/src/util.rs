@@ -7,6 +7,7 @@ use euclid::{TypedRect, TypedPoint2D, TypedSize2D, TypedPoint4D, TypedMatrix4D};
 use webrender_traits::{DeviceIntRect, DeviceIntPoint, DeviceIntSize, DeviceIntLength};
 use webrender_traits::{LayerRect, WorldPoint4D, LayerPoint4D, LayerToWorldTransform};
 use num_traits::Zero;
+use std::f32;
 use time::precise_time_ns;
 
 #[allow(dead_code)]
@@ -303,3 +304,46 @@ impl TransformedRect {
 pub fn pack_as_float(value: u32) -> f32 {
     value as f32 + 0.5
 }
+
+/// Converts an IEEE 754 half precision float, as used by `ImageFormat::RGBA16F`.
+pub fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1F) as i32;
+    let mantissa = (half & 0x3FF) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Converts to an IEEE 754 half precision float, rounding to the nearest one.
+/// Values too large for a half float become infinite.
+pub fn f32_to_half(value: f32) -> u16 {
+    let sign = if value.is_sign_negative() { 0x8000 } else { 0 };
+    let value = value.abs();
+    if value.is_nan() {
+        return sign | 0x7E00;
+    }
+    if value < 2f32.powi(-14) {
+        // Subnormal. Rounding up to 0x400 gives the smallest normal number.
+        return sign | (value * 2f32.powi(24)).round() as u16;
+    }
+
+    let mut exponent = value.log2().floor() as i32;
+    if value / 2f32.powi(exponent) >= 2.0 {
+        exponent += 1;
+    } else if value / 2f32.powi(exponent) < 1.0 {
+        exponent -= 1;
+    }
+    let mut mantissa = ((value / 2f32.powi(exponent) - 1.0) * 1024.0).round() as u16;
+    if mantissa == 1024 {
+        exponent += 1;
+        mantissa = 0;
+    }
+    if exponent > 15 {
+        return sign | 0x7C00;
+    }
+    sign | (((exponent + 15) as u16) << 10) | mantissa
+}
@@ -6,11 +6,11 @@ use byteorder::{LittleEndian, WriteBytesExt};
 use channel::{self, MsgSender, PayloadHelperMethods, PayloadSender};
 use offscreen_gl_context::{GLContextAttributes, GLLimits};
 use std::cell::Cell;
-use {ApiMsg, ColorF, DisplayListBuilder, Epoch};
+use {ApiMsg, ColorF, DisplayListBuilder, DocumentId, Epoch};
 use {FontKey, IdNamespace, ImageFormat, ImageKey, NativeFontHandle, PipelineId};
 use {RenderApiSender, ResourceId, ScrollEventPhase, ScrollLayerState, ScrollLocation, ServoScrollRootId};
 use {GlyphKey, GlyphDimensions, ImageData, WebGLContextId, WebGLCommand};
-use {DeviceIntSize, LayoutPoint, LayoutSize, WorldPoint};
+use {DeviceIntSize, DeviceUintRect, LayoutPoint, LayoutSize, WorldPoint};
 use VRCompositorCommand;
 
 impl RenderApiSender {
@@ -36,6 +36,7 @@ impl RenderApiSender {
             payload_sender: payload_sender.clone(),
             id_namespace: sync_rx.recv().unwrap(),
             next_id: Cell::new(ResourceId(0)),
+            is_shut_down: false,
         }
     }
 }
@@ -45,6 +46,7 @@ pub struct RenderApi {
     pub payload_sender: PayloadSender,
     pub id_namespace: IdNamespace,
     pub next_id: Cell<ResourceId>,
+    is_shut_down: bool,
 }
 
 impl RenderApi {
@@ -68,6 +70,12 @@ impl RenderApi {
         key
     }
 
+    /// Deletes the specific font.
+    pub fn delete_font(&self, key: FontKey) {
+        let msg = ApiMsg::DeleteFont(key);
+        self.api_sender.send(msg).unwrap();
+    }
+
     /// Gets the dimensions for the supplied glyph keys
     ///
     /// Note: Internally, the internal texture cache doesn't store
@@ -102,8 +110,7 @@ impl RenderApi {
 
     /// Updates a specific image.
     ///
-    /// Currently doesn't support changing dimensions or format by updating.
-    // TODO: Support changing dimensions (and format) during image update?
+    /// The new data may have different dimensions or a different format.
     pub fn update_image(&self,
                         key: ImageKey,
                         width: u32,
@@ -114,25 +121,67 @@ impl RenderApi {
         self.api_sender.send(msg).unwrap();
     }
 
+    /// Updates the pixels of `dirty_rect` in a specific image, leaving the rest
+    /// of the image unchanged.
+    ///
+    /// `bytes` holds the pixels of the rectangle only, in the format of the image.
+    /// Each row is `stride` bytes long, or tightly packed if there is no stride.
+    /// Images with a compressed format can only be updated as a whole.
+    pub fn update_image_region(&self,
+                               key: ImageKey,
+                               dirty_rect: DeviceUintRect,
+                               bytes: Vec<u8>,
+                               stride: Option<u32>) {
+        let msg = ApiMsg::UpdateImageRegion(key, dirty_rect, bytes, stride);
+        self.api_sender.send(msg).unwrap();
+    }
+
     /// Deletes the specific image.
     pub fn delete_image(&self, key: ImageKey) {
         let msg = ApiMsg::DeleteImage(key);
         self.api_sender.send(msg).unwrap();
     }
 
-    /// Sets the root pipeline.
+    /// Adds a document and returns the corresponding `DocumentId`.
+    ///
+    /// Each document has its own root pipeline and frame, and is drawn with
+    /// `Renderer::render`. The documents share the display lists of the pipelines
+    /// and all the resources, such as images and fonts.
+    pub fn add_document(&self) -> DocumentId {
+        let new_id = self.next_unique_id();
+        let document_id = DocumentId(new_id.0, new_id.1);
+        let msg = ApiMsg::AddDocument(document_id);
+        self.api_sender.send(msg).unwrap();
+        document_id
+    }
+
+    /// Deletes the specific document.
+    pub fn delete_document(&self, document_id: DocumentId) {
+        let msg = ApiMsg::DeleteDocument(document_id);
+        self.api_sender.send(msg).unwrap();
+    }
+
+    /// Sets the root pipeline of a document.
     ///
     /// # Examples
     ///
     /// ```ignore
     /// let (mut renderer, sender) = webrender::renderer::Renderer::new(opts);
     /// let api = sender.create_api();
+    /// let document_id = api.add_document();
     /// ...
     /// let pipeline_id = PipelineId(0,0);
-    /// api.set_root_pipeline(pipeline_id);
+    /// api.set_root_pipeline(document_id, pipeline_id);
     /// ```
-    pub fn set_root_pipeline(&self, pipeline_id: PipelineId) {
-        let msg = ApiMsg::SetRootPipeline(pipeline_id);
+    pub fn set_root_pipeline(&self, document_id: DocumentId, pipeline_id: PipelineId) {
+        let msg = ApiMsg::SetRootPipeline(document_id, pipeline_id);
+        self.api_sender.send(msg).unwrap();
+    }
+
+    /// Sets the device pixel ratio of a document, e.g. when its window moves
+    /// to another screen, and draws it again.
+    pub fn set_device_pixel_ratio(&self, document_id: DocumentId, device_pixel_ratio: f32) {
+        let msg = ApiMsg::SetDevicePixelRatio(document_id, device_pixel_ratio);
         self.api_sender.send(msg).unwrap();
     }
 
@@ -144,6 +193,9 @@ impl RenderApi {
     ///
     /// Note: Scrolling doesn't require an own Frame.
     ///
+    /// The display list is shared by all the documents, and the frames of those
+    /// that show the pipeline are rebuilt.
+    ///
     /// Arguments:
     ///
     /// * `background_color`: The background color of this pipeline.
@@ -176,40 +228,95 @@ impl RenderApi {
         self.payload_sender.send_vec(payload).unwrap();
     }
 
+    /// Renders the display list of `pipeline_id` into an offscreen image and
+    /// returns the corresponding `ImageKey`.
+    ///
+    /// The pipeline is laid out with a viewport of `size`, and drawn at
+    /// `device_pixel_ratio`, so the image is `size * device_pixel_ratio` texels,
+    /// rounded up. The image can be drawn by other display lists with `push_image`,
+    /// and rendering again with `render_pipeline_to_image_key` refreshes its
+    /// contents. It is released with `delete_image`.
+    pub fn render_pipeline_to_image(&self,
+                                    pipeline_id: PipelineId,
+                                    size: LayoutSize,
+                                    device_pixel_ratio: f32) -> ImageKey {
+        let key = self.alloc_image();
+        self.render_pipeline_to_image_key(key, pipeline_id, size, device_pixel_ratio);
+        key
+    }
+
+    /// Renders the display list of `pipeline_id` into the image `key`, which must
+    /// either be unused or have been rendered previously.
+    ///
+    /// Frames that already draw the image show the new contents once they are
+    /// rendered again, e.g. after `generate_frame`.
+    pub fn render_pipeline_to_image_key(&self,
+                                        key: ImageKey,
+                                        pipeline_id: PipelineId,
+                                        size: LayoutSize,
+                                        device_pixel_ratio: f32) {
+        let msg = ApiMsg::RenderToImage(key, pipeline_id, size, device_pixel_ratio);
+        self.api_sender.send(msg).unwrap();
+    }
+
+    /// Renders a standalone display list into an offscreen image and returns
+    /// the corresponding `ImageKey`.
+    ///
+    /// The display list is supplied for the pipeline of the `builder` as with
+    /// `set_root_display_list`, so that pipeline should not be used for anything else.
+    pub fn render_display_list_to_image(&self,
+                                        background_color: Option<ColorF>,
+                                        epoch: Epoch,
+                                        size: LayoutSize,
+                                        device_pixel_ratio: f32,
+                                        builder: DisplayListBuilder) -> ImageKey {
+        let pipeline_id = builder.pipeline_id;
+        self.set_root_display_list(background_color, epoch, size, builder);
+        self.render_pipeline_to_image(pipeline_id, size, device_pixel_ratio)
+    }
+
     /// Scrolls the scrolling layer under the `cursor`
     ///
     /// Webrender looks for the layer closest to the user
     /// which has `ScrollPolicy::Scrollable` set.
-    pub fn scroll(&self, scroll_location: ScrollLocation, cursor: WorldPoint, phase: ScrollEventPhase) {
-        let msg = ApiMsg::Scroll(scroll_location, cursor, phase);
+    pub fn scroll(&self,
+                  document_id: DocumentId,
+                  scroll_location: ScrollLocation,
+                  cursor: WorldPoint,
+                  phase: ScrollEventPhase) {
+        let msg = ApiMsg::Scroll(document_id, scroll_location, cursor, phase);
         self.api_sender.send(msg).unwrap();
     }
 
     pub fn scroll_layers_with_scroll_root_id(&self,
+                                             document_id: DocumentId,
                                              new_scroll_origin: LayoutPoint,
                                              pipeline_id: PipelineId,
                                              scroll_root_id: ServoScrollRootId) {
-        let msg = ApiMsg::ScrollLayersWithScrollId(new_scroll_origin, pipeline_id, scroll_root_id);
+        let msg = ApiMsg::ScrollLayersWithScrollId(document_id,
+                                                   new_scroll_origin,
+                                                   pipeline_id,
+                                                   scroll_root_id);
         self.api_sender.send(msg).unwrap();
     }
 
-    pub fn tick_scrolling_bounce_animations(&self) {
-        let msg = ApiMsg::TickScrollingBounce;
+    pub fn tick_scrolling_bounce_animations(&self, document_id: DocumentId) {
+        let msg = ApiMsg::TickScrollingBounce(document_id);
         self.api_sender.send(msg).unwrap();
     }
 
     /// Translates a point from viewport coordinates to layer space
-    pub fn translate_point_to_layer_space(&self, point: &WorldPoint)
+    pub fn translate_point_to_layer_space(&self, document_id: DocumentId, point: &WorldPoint)
                                           -> (LayoutPoint, PipelineId) {
         let (tx, rx) = channel::msg_channel().unwrap();
-        let msg = ApiMsg::TranslatePointToLayerSpace(*point, tx);
+        let msg = ApiMsg::TranslatePointToLayerSpace(document_id, *point, tx);
         self.api_sender.send(msg).unwrap();
         rx.recv().unwrap()
     }
 
-    pub fn get_scroll_layer_state(&self) -> Vec<ScrollLayerState> {
+    pub fn get_scroll_layer_state(&self, document_id: DocumentId) -> Vec<ScrollLayerState> {
         let (tx, rx) = channel::msg_channel().unwrap();
-        let msg = ApiMsg::GetScrollLayerState(tx);
+        let msg = ApiMsg::GetScrollLayerState(document_id, tx);
         self.api_sender.send(msg).unwrap();
         rx.recv().unwrap()
     }
@@ -217,7 +324,7 @@ impl RenderApi {
     pub fn request_webgl_context(&self, size: &DeviceIntSize, attributes: GLContextAttributes)
                                  -> Result<(WebGLContextId, GLLimits), String> {
         let (tx, rx) = channel::msg_channel().unwrap();
-        let msg = ApiMsg::RequestWebGLContext(*size, attributes, tx);
+        let msg = ApiMsg::RequestWebGLContext(self.id_namespace, *size, attributes, tx);
         self.api_sender.send(msg).unwrap();
         rx.recv().unwrap()
     }
@@ -232,8 +339,8 @@ impl RenderApi {
         self.api_sender.send(msg).unwrap();
     }
 
-    pub fn generate_frame(&self) {
-        let msg = ApiMsg::GenerateFrame;
+    pub fn generate_frame(&self, document_id: DocumentId) {
+        let msg = ApiMsg::GenerateFrame(document_id);
         self.api_sender.send(msg).unwrap();
     }
 
@@ -242,6 +349,17 @@ impl RenderApi {
         self.api_sender.send(msg).unwrap();
     }
 
+    /// Frees all the fonts, images, WebGL contexts and documents created
+    /// through this API, e.g. when the tab that used it is closed.
+    ///
+    /// The display lists of other APIs may still refer to the fonts and
+    /// images. They are drawn without them from then on.
+    pub fn shut_down(mut self) {
+        let msg = ApiMsg::ClearNamespace(self.id_namespace);
+        self.api_sender.send(msg).unwrap();
+        self.is_shut_down = true;
+    }
+
     #[inline]
     fn next_unique_id(&self) -> (u32, u32) {
         let IdNamespace(namespace) = self.id_namespace;
@@ -251,3 +369,16 @@ impl RenderApi {
     }
 }
 
+/// Dropping the API frees its resources like `shut_down` does, if that
+/// wasn't called.
+impl Drop for RenderApi {
+    fn drop(&mut self) {
+        if self.is_shut_down {
+            return;
+        }
+        let msg = ApiMsg::ClearNamespace(self.id_namespace);
+        // The render backend may already be gone when shutting down.
+        self.api_sender.send(msg).ok();
+    }
+}
+
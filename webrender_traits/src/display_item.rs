@@ -4,7 +4,7 @@
 
 use display_list::AuxiliaryListsBuilder;
 use {BorderRadius, BorderDisplayItem, ClipRegion, ColorF, ComplexClipRegion};
-use {FontKey, ImageKey, PipelineId, ScrollLayerId, ScrollLayerInfo, ServoScrollRootId};
+use {FontKey, IdNamespace, ImageKey, PipelineId, ScrollLayerId, ScrollLayerInfo, ServoScrollRootId};
 use {ImageMask, ItemRange};
 use {LayoutSize, LayoutPoint, LayoutRect};
 
@@ -152,12 +152,22 @@ impl FontKey {
     pub fn new(key0: u32, key1: u32) -> FontKey {
         FontKey(key0, key1)
     }
+
+    /// Returns the namespace of the `RenderApi` that added the font.
+    pub fn namespace(&self) -> IdNamespace {
+        IdNamespace(self.0)
+    }
 }
 
 impl ImageKey {
     pub fn new(key0: u32, key1: u32) -> ImageKey {
         ImageKey(key0, key1)
     }
+
+    /// Returns the namespace of the `RenderApi` that added the image.
+    pub fn namespace(&self) -> IdNamespace {
+        IdNamespace(self.0)
+    }
 }
 
 impl ScrollLayerId {
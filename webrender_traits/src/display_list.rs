@@ -6,12 +6,14 @@ use app_units::Au;
 use std::mem;
 use std::slice;
 use {AuxiliaryLists, AuxiliaryListsDescriptor, BorderDisplayItem, BorderRadius};
-use {BorderSide, BoxShadowClipMode, BoxShadowDisplayItem, BuiltDisplayList};
-use {BuiltDisplayListDescriptor, ClipRegion, ComplexClipRegion, ColorF};
-use {DisplayItem, DisplayListMode, FilterOp, YuvColorSpace};
+use {BorderImageDisplayItem, BorderSide, BorderWidths, BoxShadowClipMode, BoxShadowDisplayItem};
+use {BuiltDisplayList, RepeatMode};
+use {BuiltDisplayListDescriptor, ClipRegion, ComplexClipRegion, ColorF, ConicGradientDisplayItem};
+use {DisplayItem, DisplayListMode, ExtendMode, FilterOp, YuvColorRange, YuvColorSpace, YuvData};
 use {FontKey, GlyphInstance, GradientDisplayItem, GradientStop, IframeDisplayItem};
 use {ImageDisplayItem, ImageKey, ImageMask, ImageRendering, ItemRange, MixBlendMode, PipelineId};
-use {PushScrollLayerItem, PushStackingContextDisplayItem, RectangleDisplayItem, ScrollLayerId};
+use {PushScrollLayerItem, PushStackingContextDisplayItem, RadialGradientDisplayItem};
+use {RectangleDisplayItem, ScrollLayerId};
 use {ScrollPolicy, ServoScrollRootId, SpecificDisplayItem, StackingContext, TextDisplayItem};
 use {WebGLContextId, WebGLDisplayItem, YuvImageDisplayItem};
 use {LayoutTransform, LayoutPoint, LayoutRect, LayoutSize};
@@ -114,16 +116,14 @@ impl DisplayListBuilder {
     pub fn push_yuv_image(&mut self,
                           rect: LayoutRect,
                           clip: ClipRegion,
-                          y_key: ImageKey,
-                          u_key: ImageKey,
-                          v_key: ImageKey,
-                          color_space: YuvColorSpace) {
+                          yuv_data: YuvData,
+                          color_space: YuvColorSpace,
+                          color_range: YuvColorRange) {
         self.list.push(DisplayItem {
             item: SpecificDisplayItem::YuvImage(YuvImageDisplayItem {
-                y_image_key: y_key,
-                u_image_key: u_key,
-                v_image_key: v_key,
+                yuv_data: yuv_data,
                 color_space: color_space,
+                color_range: color_range,
             }),
             rect: rect,
             clip: clip,
@@ -205,6 +205,39 @@ impl DisplayListBuilder {
         self.list.push(display_item);
     }
 
+    pub fn push_nine_patch(&mut self,
+                           rect: LayoutRect,
+                           clip: ClipRegion,
+                           image_key: ImageKey,
+                           image_rendering: ImageRendering,
+                           slice: BorderWidths,
+                           widths: BorderWidths,
+                           repeat_left: RepeatMode,
+                           repeat_top: RepeatMode,
+                           repeat_right: RepeatMode,
+                           repeat_bottom: RepeatMode,
+                           fill: bool) {
+        let item = BorderImageDisplayItem {
+            image_key: image_key,
+            image_rendering: image_rendering,
+            slice: slice,
+            widths: widths,
+            repeat_left: repeat_left,
+            repeat_top: repeat_top,
+            repeat_right: repeat_right,
+            repeat_bottom: repeat_bottom,
+            fill: fill,
+        };
+
+        let display_item = DisplayItem {
+            item: SpecificDisplayItem::BorderImage(item),
+            rect: rect,
+            clip: clip,
+        };
+
+        self.list.push(display_item);
+    }
+
     pub fn push_box_shadow(&mut self,
                            rect: LayoutRect,
                            clip: ClipRegion,
@@ -239,11 +272,13 @@ impl DisplayListBuilder {
                          clip: ClipRegion,
                          start_point: LayoutPoint,
                          end_point: LayoutPoint,
-                         stops: Vec<GradientStop>) {
+                         stops: Vec<GradientStop>,
+                         extend_mode: ExtendMode) {
         let item = GradientDisplayItem {
             start_point: start_point,
             end_point: end_point,
             stops: self.auxiliary_lists_builder.add_gradient_stops(&stops),
+            extend_mode: extend_mode,
         };
 
         let display_item = DisplayItem {
@@ -255,6 +290,56 @@ impl DisplayListBuilder {
         self.list.push(display_item);
     }
 
+    pub fn push_radial_gradient(&mut self,
+                                rect: LayoutRect,
+                                clip: ClipRegion,
+                                center: LayoutPoint,
+                                radius: LayoutSize,
+                                start_radius: f32,
+                                end_radius: f32,
+                                stops: Vec<GradientStop>,
+                                extend_mode: ExtendMode) {
+        let item = RadialGradientDisplayItem {
+            center: center,
+            radius: radius,
+            start_radius: start_radius,
+            end_radius: end_radius,
+            stops: self.auxiliary_lists_builder.add_gradient_stops(&stops),
+            extend_mode: extend_mode,
+        };
+
+        let display_item = DisplayItem {
+            item: SpecificDisplayItem::RadialGradient(item),
+            rect: rect,
+            clip: clip,
+        };
+
+        self.list.push(display_item);
+    }
+
+    pub fn push_conic_gradient(&mut self,
+                               rect: LayoutRect,
+                               clip: ClipRegion,
+                               center: LayoutPoint,
+                               angle: f32,
+                               stops: Vec<GradientStop>,
+                               extend_mode: ExtendMode) {
+        let item = ConicGradientDisplayItem {
+            center: center,
+            angle: angle,
+            stops: self.auxiliary_lists_builder.add_gradient_stops(&stops),
+            extend_mode: extend_mode,
+        };
+
+        let display_item = DisplayItem {
+            item: SpecificDisplayItem::ConicGradient(item),
+            rect: rect,
+            clip: clip,
+        };
+
+        self.list.push(display_item);
+    }
+
     pub fn push_stacking_context(&mut self,
                                  scroll_policy: ScrollPolicy,
                                  bounds: LayoutRect,
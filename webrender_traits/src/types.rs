@@ -19,21 +19,37 @@ use std::sync::Arc;
 pub enum RendererKind {
     Native,
     OSMesa,
+    /// Draws frames on the CPU, see `Renderer::software_framebuffer`.
+    /// No GL context is needed, and the renderer makes no GL calls.
+    Software,
 }
 
 #[derive(Clone, Deserialize, Serialize)]
 pub enum ApiMsg {
     AddRawFont(FontKey, Vec<u8>),
     AddNativeFont(FontKey, NativeFontHandle),
+    /// Drops a font and the glyphs rasterized from it.
+    DeleteFont(FontKey),
     /// Gets the glyph dimensions
     GetGlyphDimensions(Vec<GlyphKey>, MsgSender<Vec<Option<GlyphDimensions>>>),
     /// Adds an image from the resource cache.
     AddImage(ImageKey, u32, u32, Option<u32>, ImageFormat, ImageData),
     /// Updates the the resource cache with the new image data.
     UpdateImage(ImageKey, u32, u32, ImageFormat, Vec<u8>),
+    /// Updates a rectangle of an image with new pixels, which have the given
+    /// stride in bytes, or are tightly packed.
+    UpdateImageRegion(ImageKey, DeviceUintRect, Vec<u8>, Option<u32>),
     /// Drops an image from the resource cache.
     DeleteImage(ImageKey),
     CloneApi(MsgSender<IdNamespace>),
+    /// Frees all the fonts, images, WebGL contexts and documents of a namespace,
+    /// when the `RenderApi` that owns it goes away.
+    ClearNamespace(IdNamespace),
+    /// Adds a document, which has its own root pipeline and frame, and is
+    /// rendered separately from the other documents.
+    AddDocument(DocumentId),
+    /// Drops a document and its frame. The pipelines it showed are kept.
+    DeleteDocument(DocumentId),
     /// Supplies a new frame to WebRender.
     ///
     /// After receiving this message, WebRender will read the display list, followed by the
@@ -44,16 +60,26 @@ pub enum ApiMsg {
                        LayoutSize,
                        BuiltDisplayListDescriptor,
                        AuxiliaryListsDescriptor),
-    SetRootPipeline(PipelineId),
-    Scroll(ScrollLocation, WorldPoint, ScrollEventPhase),
-    ScrollLayersWithScrollId(LayoutPoint, PipelineId, ServoScrollRootId),
-    TickScrollingBounce,
-    TranslatePointToLayerSpace(WorldPoint, MsgSender<(LayoutPoint, PipelineId)>),
-    GetScrollLayerState(MsgSender<Vec<ScrollLayerState>>),
-    RequestWebGLContext(DeviceIntSize, GLContextAttributes, MsgSender<Result<(WebGLContextId, GLLimits), String>>),
+    SetRootPipeline(DocumentId, PipelineId),
+    /// Sets the device pixel ratio a document is drawn at. Documents start
+    /// with the one the `Renderer` was created with.
+    SetDevicePixelRatio(DocumentId, f32),
+    /// Renders the display list of a pipeline into an image, that other display
+    /// lists can then draw with the `ImageKey`. The pipeline is laid out with a
+    /// viewport of the given size, and drawn at the given device pixel ratio.
+    RenderToImage(ImageKey, PipelineId, LayoutSize, f32),
+    Scroll(DocumentId, ScrollLocation, WorldPoint, ScrollEventPhase),
+    ScrollLayersWithScrollId(DocumentId, LayoutPoint, PipelineId, ServoScrollRootId),
+    TickScrollingBounce(DocumentId),
+    TranslatePointToLayerSpace(DocumentId, WorldPoint, MsgSender<(LayoutPoint, PipelineId)>),
+    GetScrollLayerState(DocumentId, MsgSender<Vec<ScrollLayerState>>),
+    RequestWebGLContext(IdNamespace,
+                        DeviceIntSize,
+                        GLContextAttributes,
+                        MsgSender<Result<(WebGLContextId, GLLimits), String>>),
     ResizeWebGLContext(WebGLContextId, DeviceIntSize),
     WebGLCommand(WebGLContextId, WebGLCommand),
-    GenerateFrame,
+    GenerateFrame(DocumentId),
     // WebVR commands that must be called in the WebGL render thread.
     VRCompositorCommand(WebGLContextId, VRCompositorCommand)
 }
@@ -95,6 +121,45 @@ pub struct BorderDisplayItem {
     pub radius: BorderRadius,
 }
 
+/// An image cut into nine parts by `slice`, as with CSS `border-image`.
+///
+/// The corners are scaled to the widths of the border. Each edge fills its
+/// length with the repeat mode of its side. The middle is only drawn if
+/// `fill` is set, and uses the mode of the top side horizontally and the
+/// mode of the left side vertically.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BorderImageDisplayItem {
+    pub image_key: ImageKey,
+    pub image_rendering: ImageRendering,
+    /// The insets of the slices from the sides of the image, in image pixels.
+    pub slice: BorderWidths,
+    pub widths: BorderWidths,
+    pub repeat_left: RepeatMode,
+    pub repeat_top: RepeatMode,
+    pub repeat_right: RepeatMode,
+    pub repeat_bottom: RepeatMode,
+    pub fill: bool,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BorderWidths {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+/// How a part of a border image fills a length that differs from its own.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum RepeatMode {
+    /// The part is scaled to fill the length.
+    Stretch,
+    /// The part is tiled at its own size, centered on the length.
+    Repeat,
+    /// The part is tiled, and scaled so that a whole number of tiles fits.
+    Round,
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct BorderRadius {
     pub top_left: LayoutSize,
@@ -208,6 +273,9 @@ pub enum DisplayListMode {
     PseudoPositionedContent,
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct DocumentId(pub u32, pub u32);
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct Epoch(pub u32);
 
@@ -277,6 +345,19 @@ pub struct GradientDisplayItem {
     pub start_point: LayoutPoint,
     pub end_point: LayoutPoint,
     pub stops: ItemRange,
+    pub extend_mode: ExtendMode,
+}
+
+/// How a gradient is drawn past its first and last stops.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ExtendMode {
+    /// The colors of the first and last stops are extended.
+    Clamp,
+    /// The stops are repeated, as in `repeating-linear-gradient`.
+    Repeat,
+    /// The stops are repeated, with every other repetition reversed.
+    Reflect,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
@@ -286,6 +367,34 @@ pub struct GradientStop {
 }
 known_heap_size!(0, GradientStop);
 
+/// A gradient whose color changes with the distance from `center`.
+///
+/// The gradient is drawn along ellipses with the proportions of `radius`.
+/// `start_radius` and `end_radius` are measured along the horizontal axis,
+/// and are where the first and the last stops are.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RadialGradientDisplayItem {
+    pub center: LayoutPoint,
+    pub radius: LayoutSize,
+    pub start_radius: f32,
+    pub end_radius: f32,
+    pub stops: ItemRange,
+    pub extend_mode: ExtendMode,
+}
+
+/// A gradient whose color changes with the angle around `center`.
+///
+/// `angle` is in radians, clockwise from the top of the item, and is where
+/// the stops at offset 0 and 1 meet. A whole turn is the range from 0 to 1,
+/// so `extend_mode` only matters when the stops cover less than that.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConicGradientDisplayItem {
+    pub center: LayoutPoint,
+    pub angle: f32,
+    pub stops: ItemRange,
+    pub extend_mode: ExtendMode,
+}
+
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct PushStackingContextDisplayItem {
@@ -303,7 +412,7 @@ pub struct IframeDisplayItem {
     pub pipeline_id: PipelineId,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct IdNamespace(pub u32);
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
@@ -316,10 +425,9 @@ pub struct ImageDisplayItem {
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct YuvImageDisplayItem {
-    pub y_image_key: ImageKey,
-    pub u_image_key: ImageKey,
-    pub v_image_key: ImageKey,
+    pub yuv_data: YuvData,
     pub color_space: YuvColorSpace,
+    pub color_range: YuvColorRange,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -329,12 +437,159 @@ pub enum ImageFormat {
     RGB8,
     RGBA8,
     RGBAF32,
+    /// A single 16 bit channel, displayed as gray.
+    R16,
+    /// Two 8 bit channels, displayed as gray and alpha.
+    RG8,
+    /// Half float RGBA.
+    RGBA16F,
+    /// S3TC DXT1, with 1-bit alpha.
+    BC1,
+    /// S3TC DXT3, with explicit 4-bit alpha.
+    BC2,
+    /// S3TC DXT5, with interpolated alpha.
+    BC3,
+    ETC2RGB8,
+    /// ETC2 color with EAC alpha.
+    ETC2RGBA8,
+    /// LDR ASTC with 4x4 blocks.
+    ASTC4x4,
 }
 
+impl ImageFormat {
+    /// Returns None for compressed formats, which have no per-pixel size.
+    pub fn bytes_per_pixel(self) -> Option<u32> {
+        match self {
+            ImageFormat::A8 => Some(1),
+            ImageFormat::RGB8 => Some(3),
+            ImageFormat::RGBA8 => Some(4),
+            ImageFormat::RGBAF32 => Some(16),
+            ImageFormat::R16 => Some(2),
+            ImageFormat::RG8 => Some(2),
+            ImageFormat::RGBA16F => Some(8),
+            ImageFormat::BC1 |
+            ImageFormat::BC2 |
+            ImageFormat::BC3 |
+            ImageFormat::ETC2RGB8 |
+            ImageFormat::ETC2RGBA8 |
+            ImageFormat::ASTC4x4 |
+            ImageFormat::Invalid => None,
+        }
+    }
+
+    /// Returns the size in pixels of the (square) blocks of a compressed format,
+    /// and the number of bytes used to store each block.
+    pub fn compressed_block_size(self) -> Option<(u32, u32)> {
+        match self {
+            ImageFormat::BC1 |
+            ImageFormat::ETC2RGB8 => Some((4, 8)),
+            ImageFormat::BC2 |
+            ImageFormat::BC3 |
+            ImageFormat::ETC2RGBA8 |
+            ImageFormat::ASTC4x4 => Some((4, 16)),
+            ImageFormat::A8 |
+            ImageFormat::RGB8 |
+            ImageFormat::RGBA8 |
+            ImageFormat::RGBAF32 |
+            ImageFormat::R16 |
+            ImageFormat::RG8 |
+            ImageFormat::RGBA16F |
+            ImageFormat::Invalid => None,
+        }
+    }
+
+    pub fn is_compressed(self) -> bool {
+        self.compressed_block_size().is_some()
+    }
+
+    /// The number of bytes needed to store an image of this format, if the rows
+    /// (or rows of blocks) are tightly packed.
+    pub fn packed_size(self, width: u32, height: u32) -> Option<u32> {
+        match self.compressed_block_size() {
+            Some((block_size, block_bytes)) => {
+                let blocks_x = (width + block_size - 1) / block_size;
+                let blocks_y = (height + block_size - 1) / block_size;
+                Some(blocks_x * blocks_y * block_bytes)
+            }
+            None => self.bytes_per_pixel().map(|bpp| width * height * bpp),
+        }
+    }
+}
+
+/// The color space of the YUV samples, which gives the matrix that converts
+/// them to RGB.
+///
+/// Samples are read as normalized values, so those of more than 8 bits must
+/// fill the whole range of their `R16` plane: 10 bit video has to be stored
+/// in the high bits, as in P010, or it comes out dark.
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum YuvColorSpace {
     Rec601 = 1, // The values must match the ones in prim_shared.glsl
     Rec709 = 2,
+    /// BT.2020 with its SDR transfer function. The colors are converted to
+    /// the Rec709 primaries, clipping the ones outside of that gamut. HDR
+    /// video, with the PQ or HLG transfer functions, isn't supported and has
+    /// to be converted to SDR before it's drawn.
+    Rec2020 = 3,
+}
+
+/// The range of the YUV samples. Video is usually limited range, where
+/// luma covers 16-235 and chroma 16-240, while JPEG uses the full 0-255.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum YuvColorRange {
+    Limited = 0, // The values must match the ones in prim_shared.glsl
+    Full = 1,
+}
+
+/// The images holding the planes of a YUV image.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum YuvData {
+    /// Separate Y, U and V images, as in I420.
+    PlanarYCbCr(ImageKey, ImageKey, ImageKey),
+    /// A Y image followed by an `RG8` image of interleaved U and V samples
+    /// at half resolution, as in NV12.
+    NV12(ImageKey, ImageKey),
+    /// A single `RGBA8` image of half the frame width where each pixel holds
+    /// two horizontally adjacent samples packed as Y0, U, Y1, V (YUYV).
+    InterleavedYCbCr(ImageKey),
+    /// Separate Y, U and V images of dequantized 8x8 DCT coefficient blocks,
+    /// as found in JPEG. Each coefficient is a little endian `u16` biased by
+    /// 32768, and each `RGBA8` texel holds two horizontally adjacent ones, so
+    /// an 8x8 block takes 4x8 texels. The inverse DCT, chroma upsampling and
+    /// color conversion are done by render tasks on the GPU.
+    ///
+    /// The planes are used as they are, so each one must be at most 2048
+    /// samples wide and high. Larger images have to be split into several.
+    DctCoefficients(ImageKey, ImageKey, ImageKey),
+}
+
+impl YuvData {
+    pub fn get_format(&self) -> YuvFormat {
+        match *self {
+            YuvData::PlanarYCbCr(..) => YuvFormat::PlanarYCbCr,
+            YuvData::NV12(..) => YuvFormat::NV12,
+            YuvData::InterleavedYCbCr(..) => YuvFormat::InterleavedYCbCr,
+            YuvData::DctCoefficients(..) => YuvFormat::DctCoefficients,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum YuvFormat {
+    PlanarYCbCr,
+    NV12,
+    InterleavedYCbCr,
+    DctCoefficients,
+}
+
+impl YuvFormat {
+    pub fn get_plane_num(&self) -> usize {
+        match *self {
+            YuvFormat::PlanarYCbCr | YuvFormat::DctCoefficients => 3,
+            YuvFormat::NV12 => 2,
+            YuvFormat::InterleavedYCbCr => 1,
+        }
+    }
 }
 
 /// An arbitrary identifier for an external image provided by the
@@ -367,6 +622,9 @@ pub enum ImageRendering {
     Auto,
     CrispEdges,
     Pixelated,
+    /// Like `Auto`, but the image is mipmapped, so that it still looks smooth
+    /// when drawn much smaller than its actual size.
+    Mipmapped,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -508,8 +766,11 @@ pub enum SpecificDisplayItem {
     YuvImage(YuvImageDisplayItem),
     WebGL(WebGLDisplayItem),
     Border(BorderDisplayItem),
+    BorderImage(BorderImageDisplayItem),
     BoxShadow(BoxShadowDisplayItem),
     Gradient(GradientDisplayItem),
+    RadialGradient(RadialGradientDisplayItem),
+    ConicGradient(ConicGradientDisplayItem),
     Iframe(IframeDisplayItem),
     PushStackingContext(PushStackingContextDisplayItem),
     PopStackingContext,
@@ -78,8 +78,10 @@ impl BinaryFrameReader {
         match msg {
             &ApiMsg::AddRawFont(..) |
             &ApiMsg::AddNativeFont(..) |
+            &ApiMsg::DeleteFont(..) |
             &ApiMsg::AddImage(..) |
             &ApiMsg::UpdateImage(..) |
+            &ApiMsg::UpdateImageRegion(..) |
             &ApiMsg::DeleteImage(..) => {
                 true
             }
@@ -169,6 +171,8 @@ impl WrenchThing for BinaryFrameReader {
                         if !self.should_skip_upload_msg(&msg) {
                             match &msg {
                                 &ApiMsg::SetRootDisplayList(..) => { seen_set_root_dl = true; }
+                                // Show the recorded document instead of our own.
+                                &ApiMsg::AddDocument(document_id) => { wrench.document_id = document_id; }
                                 _ => { }
                             }
                             wrench.api.api_sender.send(msg).unwrap();
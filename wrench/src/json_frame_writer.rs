@@ -32,6 +32,19 @@ struct CachedImage {
     path: Option<PathBuf>,
 }
 
+fn packed_stride(width: u32, format: ImageFormat) -> u32 {
+    match format {
+        ImageFormat::A8 => width,
+        ImageFormat::RGBA8 | ImageFormat::RGB8 => width*4,
+        ImageFormat::RGBAF32 => width*16,
+        ImageFormat::R16 | ImageFormat::RG8 => width*2,
+        ImageFormat::RGBA16F => width*8,
+        // The stride of a compressed image is that of a row of blocks.
+        _ if format.is_compressed() => format.packed_size(width, 1).unwrap(),
+        _ => panic!("Invalid image format"),
+    }
+}
+
 pub struct JsonFrameWriter {
     frame_base: PathBuf,
     rsrc_base: PathBuf,
@@ -190,8 +203,9 @@ impl webrender::ApiRecordingReceiver for JsonFrameWriter {
     fn write_msg(&mut self, _: u32, msg: &ApiMsg) {
         match msg {
             &ApiMsg::SetRootPipeline(..) |
+            &ApiMsg::SetDevicePixelRatio(..) |
             &ApiMsg::Scroll(..) |
-            &ApiMsg::TickScrollingBounce |
+            &ApiMsg::TickScrollingBounce(..) |
             &ApiMsg::WebGLCommand(..) => {
             }
 
@@ -205,16 +219,7 @@ impl webrender::ApiRecordingReceiver for JsonFrameWriter {
 
             &ApiMsg::AddImage(ref key, width, height, stride,
                               format, ref data) => {
-                let stride = if let Some(stride) = stride {
-                    stride
-                } else {
-                    match format {
-                        ImageFormat::A8 => width,
-                        ImageFormat::RGBA8 | ImageFormat::RGB8 => width*4,
-                        ImageFormat::RGBAF32 => width*16,
-                        _ => panic!("Invalid image format"),
-                    }
-                };
+                let stride = stride.unwrap_or_else(|| packed_stride(width, format));
                 let bytes = match data {
                     &ImageData::Raw(ref v) => { (**v).clone() }
                     &ImageData::External(_) => { return; }
@@ -230,19 +235,46 @@ impl webrender::ApiRecordingReceiver for JsonFrameWriter {
             &ApiMsg::UpdateImage(ref key, width, height,
                                  format, ref bytes) => {
                 if let Some(ref mut data) = self.images.get_mut(key) {
-                    assert!(data.width == width);
-                    assert!(data.height == height);
-                    assert!(data.format == format);
+                    data.width = width;
+                    data.height = height;
+                    data.stride = packed_stride(width, format);
+                    data.format = format;
 
                     *data.path.borrow_mut() = None;
                     *data.bytes.borrow_mut() = Some(bytes.clone());
                 }
             }
 
+            &ApiMsg::UpdateImageRegion(ref key, ref dirty_rect, ref bytes, stride) => {
+                if let Some(ref mut data) = self.images.get_mut(key) {
+                    let bpp = data.format.bytes_per_pixel().unwrap() as usize;
+                    let row_len = dirty_rect.size.width as usize * bpp;
+                    let src_stride = stride.map_or(row_len, |stride| stride as usize);
+                    let dst_stride = data.stride as usize;
+                    match data.bytes {
+                        Some(ref mut image_bytes) => {
+                            for row in 0..dirty_rect.size.height as usize {
+                                let src = row * src_stride;
+                                let dst = (dirty_rect.origin.y as usize + row) * dst_stride +
+                                          dirty_rect.origin.x as usize * bpp;
+                                image_bytes[dst..dst + row_len].copy_from_slice(&bytes[src..src + row_len]);
+                            }
+                        }
+                        None => {
+                            warn!("Region update of image {:?} after it was written", key);
+                        }
+                    }
+                }
+            }
+
             &ApiMsg::DeleteImage(ref key) => {
                 self.images.remove(key);
             }
 
+            &ApiMsg::DeleteFont(ref key) => {
+                self.fonts.remove(key);
+            }
+
             &ApiMsg::SetRootDisplayList(ref background_color,
                                         ref epoch,
                                         ref pipeline_id,
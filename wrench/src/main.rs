@@ -17,6 +17,8 @@ extern crate image;
 extern crate clap;
 #[macro_use]
 extern crate lazy_static;
+#[macro_use]
+extern crate log;
 extern crate serde;
 extern crate serde_json;
 extern crate crossbeam;
@@ -38,6 +40,7 @@ use std::cmp::{min, max};
 use std::fs::File;
 use std::mem;
 use std::os::raw::c_void;
+use std::process;
 use std::ptr;
 use webrender_traits::*;
 
@@ -58,6 +61,8 @@ mod json_frame_writer;
 mod binary_frame_reader;
 use binary_frame_reader::BinaryFrameReader;
 
+mod reftest;
+
 lazy_static! {
     static ref PLATFORM_DEFAULT_FACE_NAME: String =
         if cfg!(target_os = "windows") {
@@ -160,6 +165,9 @@ impl HeadlessContext {
 pub enum WindowWrapper {
     Window(glutin::Window),
     Headless(HeadlessContext),
+    /// No window and no GL context, frames are drawn by the software
+    /// rasterizer.
+    Software(DeviceUintSize),
 }
 
 pub struct HeadlessEventIterater;
@@ -178,15 +186,25 @@ impl WindowWrapper {
                     HeadlessContext::get_proc_address(s)
                 });
             }
+            WindowWrapper::Software(..) => return,
         }
 
         gl::clear_color(0.3, 0.0, 0.0, 1.0);
     }
 
+    fn renderer_kind(&self) -> RendererKind {
+        match *self {
+            WindowWrapper::Window(..) |
+            WindowWrapper::Headless(..) => RendererKind::Native,
+            WindowWrapper::Software(..) => RendererKind::Software,
+        }
+    }
+
     fn swap_buffers(&self) {
         match *self {
             WindowWrapper::Window(ref window) => window.swap_buffers().unwrap(),
-            WindowWrapper::Headless(..) => {}
+            WindowWrapper::Headless(..) |
+            WindowWrapper::Software(..) => {}
         }
     }
 
@@ -194,27 +212,31 @@ impl WindowWrapper {
         match *self {
             WindowWrapper::Window(ref window) => window.get_inner_size().unwrap(),
             WindowWrapper::Headless(ref context) => (context.width, context.height),
+            WindowWrapper::Software(size) => (size.width, size.height),
         }
     }
 
     fn hidpi_factor(&self) -> f32 {
         match *self {
             WindowWrapper::Window(ref window) => window.hidpi_factor(),
-            WindowWrapper::Headless(..) => 1.0,
+            WindowWrapper::Headless(..) |
+            WindowWrapper::Software(..) => 1.0,
         }
     }
 
     fn create_window_proxy(&mut self) -> Option<WindowProxy> {
         match *self {
             WindowWrapper::Window(ref window) => Some(window.create_window_proxy()),
-            WindowWrapper::Headless(..) => None,
+            WindowWrapper::Headless(..) |
+            WindowWrapper::Software(..) => None,
         }
     }
 
     fn set_title(&mut self, title: &str) {
         match *self {
             WindowWrapper::Window(ref window) => window.set_title(title),
-            WindowWrapper::Headless(..) => ()
+            WindowWrapper::Headless(..) |
+            WindowWrapper::Software(..) => ()
         }
     }
 }
@@ -222,7 +244,13 @@ impl WindowWrapper {
 fn make_window(size: DeviceUintSize,
                dp_ratio: Option<f32>,
                vsync: bool,
-               headless: bool) -> WindowWrapper {
+               headless: bool,
+               software: bool) -> WindowWrapper {
+    if software {
+        println!("Software rasterizer");
+        return WindowWrapper::Software(size);
+    }
+
     let wrapper = if headless {
         WindowWrapper::Headless(HeadlessContext::new(size.width,
                                                      size.height))
@@ -276,12 +304,14 @@ fn main() {
             DeviceUintSize::new(w, h)
         }
     }).unwrap_or(DeviceUintSize::new(1920, 1080));
-    let is_headless = args.is_present("headless");
+    let is_software = args.is_present("software");
+    let is_headless = args.is_present("headless") || is_software;
 
     let mut window = make_window(size,
                                  dp_ratio,
                                  args.is_present("vsync"),
-                                 is_headless);
+                                 is_headless,
+                                 is_software);
     let dp_ratio = dp_ratio.unwrap_or(window.hidpi_factor());
     let mut wrench = Wrench::new(&mut window,
                                  res_path,
@@ -293,6 +323,22 @@ fn main() {
                                  args.is_present("debug"),
                                  args.is_present("verbose"));
 
+    if let Some(subargs) = args.subcommand_matches("reftest") {
+        let list_path = PathBuf::from(subargs.value_of("INPUT").unwrap_or("reftests/reftest.list"));
+        let (width, height) = window.get_inner_size();
+        let dim = DeviceUintSize::new(width, height);
+        wrench.update(dim);
+
+        match reftest::run_reftests(&mut wrench, dim, &list_path) {
+            Ok(0) => process::exit(0),
+            Ok(_) => process::exit(1),
+            Err(err) => {
+                println!("REFTEST ERROR | {}", err);
+                process::exit(2);
+            }
+        }
+    }
+
     let mut thing =
         if let Some(subargs) = args.subcommand_matches("show") {
             Box::new(YamlFrameReader::new_from_args(subargs)) as Box<WrenchThing>
@@ -360,7 +406,8 @@ fn main() {
         }
 
         let events = match window {
-            WindowWrapper::Headless(..) => {
+            WindowWrapper::Headless(..) |
+            WindowWrapper::Software(..) => {
                 vec![glutin::Event::Awakened]
             }
             WindowWrapper::Window(ref window) => {
@@ -477,20 +524,8 @@ fn main() {
     }
 
     if is_headless {
-        let mut pixels = gl::read_pixels(0, 0,
-                                         size.width as gl::GLsizei,
-                                         size.height as gl::GLsizei,
-                                         gl::RGB, gl::UNSIGNED_BYTE);
-
-        // flip image vertically (texture is upside down)
-        let orig_pixels = pixels.clone();
-        let stride = size.width as usize * 3;
-        for y in 0..size.height as usize {
-            let dst_start = y * stride;
-            let src_start = (size.height as usize - y - 1) * stride;
-            let src_slice = &orig_pixels[src_start .. src_start + stride];
-            (&mut pixels[dst_start .. dst_start + stride]).clone_from_slice(&src_slice[..stride]);
-        }
+        let rect = DeviceUintRect::new(DeviceUintPoint::zero(), size);
+        let pixels = wrench.renderer.read_pixels(rect, webrender::ReadPixelsFormat::Rgb8);
 
         let output = File::create("screenshot.png").unwrap();
         let encoder = PNGEncoder::new(output);
@@ -0,0 +1,125 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use webrender;
+use webrender_traits::*;
+use wrench::Wrench;
+use yaml_frame_reader::YamlFrameReader;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ReftestOp {
+    Equal,
+    NotEqual,
+}
+
+struct Reftest {
+    op: ReftestOp,
+    test: PathBuf,
+    reference: PathBuf,
+}
+
+/// Reads the reftests of a reftest list. Each line is either
+/// `== test.yaml reference.yaml`, `!= test.yaml reference.yaml` or
+/// `include other/reftest.list`, with paths relative to the list.
+/// Anything after a `#` is a comment.
+fn parse_reftest_list(list_path: &Path, reftests: &mut Vec<Reftest>) -> Result<(), String> {
+    let dir = list_path.parent().unwrap();
+    let file = try!(File::open(list_path).map_err(|err| {
+        format!("Unable to open reftest list {:?}: {}", list_path, err)
+    }));
+
+    for line in BufReader::new(file).lines() {
+        let line = try!(line.map_err(|err| format!("Unable to read reftest list {:?}: {}", list_path, err)));
+        let line = match line.find('#') {
+            Some(index) => &line[..index],
+            None => &line[..],
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.is_empty() {
+            continue;
+        }
+
+        if tokens[0] == "include" && tokens.len() == 2 {
+            try!(parse_reftest_list(&dir.join(tokens[1]), reftests));
+            continue;
+        }
+
+        let op = match tokens[0] {
+            "==" => ReftestOp::Equal,
+            "!=" => ReftestOp::NotEqual,
+            _ => return Err(format!("Unknown reftest line '{}' in {:?}", line, list_path)),
+        };
+        if tokens.len() != 3 {
+            return Err(format!("Reftest line '{}' in {:?} must have a test and a reference",
+                               line, list_path));
+        }
+
+        reftests.push(Reftest {
+            op: op,
+            test: dir.join(tokens[1]),
+            reference: dir.join(tokens[2]),
+        });
+    }
+
+    Ok(())
+}
+
+/// Draws the frame described by a YAML file, and reads it back as RGBA8.
+fn render_yaml(wrench: &mut Wrench, yaml_path: &Path, epoch: Epoch, size: DeviceUintSize) -> Vec<u8> {
+    let display_list = YamlFrameReader::new(yaml_path).display_list(wrench);
+    wrench.send_lists(epoch.0, display_list);
+
+    // Wait for the render backend to build the frame before drawing it.
+    loop {
+        wrench.renderer.update();
+        if wrench.renderer.current_epoch(wrench.root_pipeline_id) == Some(epoch) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    wrench.render();
+
+    let rect = DeviceUintRect::new(DeviceUintPoint::zero(), size);
+    wrench.renderer.read_pixels(rect, webrender::ReadPixelsFormat::Rgba8)
+}
+
+/// Runs the reftests of a reftest list, and returns how many of them failed.
+pub fn run_reftests(wrench: &mut Wrench, size: DeviceUintSize, list_path: &Path) -> Result<usize, String> {
+    let mut reftests = Vec::new();
+    try!(parse_reftest_list(list_path, &mut reftests));
+
+    let mut failures = 0;
+    for (index, reftest) in reftests.iter().enumerate() {
+        let epoch = 2 * index as u32 + 1;
+        let test_pixels = render_yaml(wrench, &reftest.test, Epoch(epoch), size);
+        let reference_pixels = render_yaml(wrench, &reftest.reference, Epoch(epoch + 1), size);
+
+        let differences = test_pixels.chunks(4)
+                                     .zip(reference_pixels.chunks(4))
+                                     .filter(|&(test, reference)| test != reference)
+                                     .count();
+        let passed = match reftest.op {
+            ReftestOp::Equal => differences == 0,
+            ReftestOp::NotEqual => differences != 0,
+        };
+
+        println!("REFTEST {} | {} {} {} | {} pixels differ",
+                 if passed { "PASS" } else { "FAIL" },
+                 reftest.test.display(),
+                 if reftest.op == ReftestOp::Equal { "==" } else { "!=" },
+                 reftest.reference.display(),
+                 differences);
+        if !passed {
+            failures += 1;
+        }
+    }
+
+    println!("REFTEST {} of {} passed", reftests.len() - failures, reftests.len());
+    Ok(failures)
+}
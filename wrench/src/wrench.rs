@@ -121,6 +121,7 @@ pub struct Wrench {
 
     pub renderer: webrender::renderer::Renderer,
     pub api: RenderApi,
+    pub document_id: DocumentId,
     pub root_pipeline_id: PipelineId,
 
     window_title_to_set: Option<String>,
@@ -164,6 +165,7 @@ impl Wrench {
             webrender::set_recording_detour(Some(recorder));
         }
 
+        let renderer_kind = window.renderer_kind();
         let opts = webrender::RendererOptions {
             device_pixel_ratio: dp_ratio,
             resource_override_path: shader_override_path,
@@ -174,21 +176,26 @@ impl Wrench {
             enable_subpixel_aa: subpixel_aa,
             debug: debug,
             precache_shaders: false,
-            renderer_kind: RendererKind::Native,
+            renderer_kind: renderer_kind,
             clear_framebuffer: true,
             clear_color: ColorF::new(1.0, 1.0, 1.0, 1.0),
             render_target_debug: false,
+            texture_cache_budget: None,
         };
 
         let (renderer, sender) = webrender::renderer::Renderer::new(opts);
         let api = sender.create_api();
+        let document_id = api.add_document();
 
         let (timing_sender, timing_receiver) = chase_lev::deque();
         let notifier = Box::new(Notifier::new(window.create_window_proxy(), timing_receiver, verbose));
         renderer.set_render_notifier(notifier);
 
-        let gl_version = gl::get_string(gl::VERSION);
-        let gl_renderer = gl::get_string(gl::RENDERER);
+        let (gl_version, gl_renderer) = match renderer_kind {
+            RendererKind::Software => (String::new(), String::from("Software")),
+            RendererKind::Native |
+            RendererKind::OSMesa => (gl::get_string(gl::VERSION), gl::get_string(gl::RENDERER)),
+        };
 
         let mut wrench = Wrench {
             window_size: size,
@@ -196,6 +203,7 @@ impl Wrench {
             renderer: renderer,
             sender: sender,
             api: api,
+            document_id: document_id,
             window_title_to_set: None,
 
             rebuild_display_lists: do_rebuild,
@@ -216,7 +224,7 @@ impl Wrench {
         // there's a "frame 0" that webrender itself renders; push this to
         // not confuse our notifier
         wrench.frame_start_sender.push(time::SteadyTime::now());
-        wrench.api.set_root_pipeline(wrench.root_pipeline_id);
+        wrench.api.set_root_pipeline(wrench.document_id, wrench.root_pipeline_id);
 
         wrench
     }
@@ -333,7 +341,11 @@ impl Wrench {
 
     pub fn update(&mut self, dim: DeviceUintSize) {
         if dim != self.window_size {
-            gl::viewport(0, 0, dim.width as i32, dim.height as i32);
+            // The software rasterizer has no GL context, and reads the
+            // framebuffer size from `render`.
+            if self.renderer.software_framebuffer().is_none() {
+                gl::viewport(0, 0, dim.width as i32, dim.height as i32);
+            }
             self.window_size = dim;
         }
     }
@@ -354,12 +366,12 @@ impl Wrench {
 
     pub fn render(&mut self) {
         self.renderer.update();
-        self.renderer.render(self.window_size);
+        self.renderer.render(self.document_id, self.window_size);
     }
 
     pub fn refresh(&mut self) {
         self.begin_frame();
-        self.api.generate_frame();
+        self.api.generate_frame(self.document_id);
     }
 
     pub fn show_onscreen_help(&mut self) {
@@ -371,7 +383,10 @@ impl Wrench {
         ];
 
         let color_and_offset = [ (*BLACK_COLOR, 2.0), (*WHITE_COLOR, 0.0) ];
-        let dr = self.renderer.debug_renderer();
+        let dr = match self.renderer.debug_renderer() {
+            Some(dr) => dr,
+            None => return,
+        };
 
         for ref co in color_and_offset.iter() {
             let x = self.device_pixel_ratio * (15.0 + co.1);
@@ -35,6 +35,15 @@ fn broadcast<T: Clone>(base_vals: &[T], num_items: usize) -> Vec<T>
     vals
 }
 
+fn extend_mode(yaml: &Yaml) -> ExtendMode {
+    match yaml.as_str() {
+        None | Some("clamp") => ExtendMode::Clamp,
+        Some("repeat") => ExtendMode::Repeat,
+        Some("reflect") => ExtendMode::Reflect,
+        Some(s) => panic!("Unknown gradient extend mode {}", s),
+    }
+}
+
 pub struct YamlFrameReader {
     frame_built: bool,
     yaml_path: PathBuf,
@@ -75,6 +84,13 @@ impl YamlFrameReader {
         y
     }
 
+    /// Builds the display list described by the YAML file.
+    pub fn display_list(&mut self, wrench: &mut Wrench) -> DisplayListBuilder {
+        self.builder = Some(DisplayListBuilder::new(wrench.root_pipeline_id));
+        self.build(wrench);
+        self.builder.take().unwrap()
+    }
+
     pub fn builder<'a>(&'a mut self) -> &'a mut DisplayListBuilder {
         self.builder.as_mut().unwrap()
     }
@@ -173,9 +189,46 @@ impl YamlFrameReader {
                 offset: chunk[0].as_force_f32().expect("gradient stop offset is not f32"),
                 color: chunk[1].as_colorf().expect("gradient stop color is not color"),
             }).collect::<Vec<_>>();
+        let extend_mode = extend_mode(&item["extend_mode"]);
+
+        let clip = self.to_clip_region(&item["clip"], &bounds, wrench).unwrap_or(*clip_region);
+        self.builder().push_gradient(bounds, clip, start, end, stops, extend_mode);
+    }
+
+    fn handle_radial_gradient(&mut self, wrench: &mut Wrench, clip_region: &ClipRegion, item: &Yaml) {
+        let bounds_key = if item["type"].is_badvalue() { "radial_gradient" } else { "bounds" };
+        let bounds = item[bounds_key].as_rect().expect("radial gradient must have bounds");
+        let center = item["center"].as_point().expect("radial gradient must have center");
+        let radius = item["radius"].as_size().expect("radial gradient must have radius");
+        let start_radius = item["start_radius"].as_force_f32().unwrap_or(0.0);
+        let end_radius = item["end_radius"].as_force_f32().unwrap_or(radius.width);
+        let stops = item["stops"].as_vec().expect("radial gradient must have stops")
+            .chunks(2).map(|chunk| GradientStop {
+                offset: chunk[0].as_force_f32().expect("gradient stop offset is not f32"),
+                color: chunk[1].as_colorf().expect("gradient stop color is not color"),
+            }).collect::<Vec<_>>();
+        let extend_mode = extend_mode(&item["extend_mode"]);
+
+        let clip = self.to_clip_region(&item["clip"], &bounds, wrench).unwrap_or(*clip_region);
+        self.builder().push_radial_gradient(bounds, clip, center, radius, start_radius, end_radius, stops,
+                                            extend_mode);
+    }
+
+    fn handle_conic_gradient(&mut self, wrench: &mut Wrench, clip_region: &ClipRegion, item: &Yaml) {
+        let bounds_key = if item["type"].is_badvalue() { "conic_gradient" } else { "bounds" };
+        let bounds = item[bounds_key].as_rect().expect("conic gradient must have bounds");
+        let center = item["center"].as_point().expect("conic gradient must have center");
+        // The angle is in degrees, as in CSS.
+        let angle = item["angle"].as_force_f32().unwrap_or(0.0).to_radians();
+        let stops = item["stops"].as_vec().expect("conic gradient must have stops")
+            .chunks(2).map(|chunk| GradientStop {
+                offset: chunk[0].as_force_f32().expect("gradient stop offset is not f32"),
+                color: chunk[1].as_colorf().expect("gradient stop color is not color"),
+            }).collect::<Vec<_>>();
+        let extend_mode = extend_mode(&item["extend_mode"]);
 
         let clip = self.to_clip_region(&item["clip"], &bounds, wrench).unwrap_or(*clip_region);
-        self.builder().push_gradient(bounds, clip, start, end, stops);
+        self.builder().push_conic_gradient(bounds, clip, center, angle, stops, extend_mode);
     }
 
     fn handle_border(&mut self, wrench: &mut Wrench, clip_region: &ClipRegion, item: &Yaml) {
@@ -214,6 +267,41 @@ impl YamlFrameReader {
         self.builder().push_border(bounds, clip, left, top, right, bottom, radius);
     }
 
+    fn handle_border_image(&mut self, wrench: &mut Wrench, clip_region: &ClipRegion, item: &Yaml) {
+        let bounds_key = if item["type"].is_badvalue() { "border_image" } else { "bounds" };
+        let bounds = item[bounds_key].as_rect().expect("border images must have bounds");
+        let (image_key, _) = wrench.add_or_get_image(&self.rsrc_path(&item["src"]));
+
+        // Slices and widths are given in CSS order: top, right, bottom, left.
+        let to_border_widths = |values: Vec<f32>| {
+            let values = broadcast(&values, 4);
+            BorderWidths { top: values[0], right: values[1], bottom: values[2], left: values[3] }
+        };
+        let slice = to_border_widths(item["slice"].as_vec_f32().expect("border images must have slice(s)"));
+        let widths = to_border_widths(item["width"].as_vec_f32().expect("border images must have width(s)"));
+
+        let repeat = item["repeat"].as_vec_string().unwrap_or(vec!["stretch".to_owned()]);
+        let repeat = broadcast(&repeat, 4).iter().map(|s| match s.as_str() {
+            "stretch" => RepeatMode::Stretch,
+            "repeat" => RepeatMode::Repeat,
+            "round" => RepeatMode::Round,
+            s => panic!("Unknown border image repeat mode '{}'", s),
+        }).collect::<Vec<RepeatMode>>();
+
+        let rendering = match item["rendering"].as_str() {
+            Some("auto") | None => ImageRendering::Auto,
+            Some("crisp_edges") => ImageRendering::CrispEdges,
+            Some("pixelated") => ImageRendering::Pixelated,
+            Some("mipmapped") => ImageRendering::Mipmapped,
+            Some(_) => panic!("ImageRendering can be auto, crisp_edges, pixelated or mipmapped -- got {:?}", item),
+        };
+        let fill = item["fill"].as_bool().unwrap_or(false);
+
+        let clip = self.to_clip_region(&item["clip"], &bounds, wrench).unwrap_or(*clip_region);
+        self.builder().push_nine_patch(bounds, clip, image_key, rendering, slice, widths,
+                                       repeat[3], repeat[0], repeat[1], repeat[2], fill);
+    }
+
     fn handle_box_shadow(&mut self, wrench: &mut Wrench, clip_region: &ClipRegion, item: &Yaml) {
         let bounds_key = if item["type"].is_badvalue() { "box_shadow" } else { "bounds" };
         let bounds = item[bounds_key].as_rect().expect("box shadow must have bounds");
@@ -269,7 +357,8 @@ impl YamlFrameReader {
             Some("auto") | None => ImageRendering::Auto,
             Some("crisp_edges") => ImageRendering::CrispEdges,
             Some("pixelated") => ImageRendering::Pixelated,
-            Some(_) => panic!("ImageRendering can be auto, crisp_edges, or pixelated -- got {:?}", item),
+            Some("mipmapped") => ImageRendering::Mipmapped,
+            Some(_) => panic!("ImageRendering can be auto, crisp_edges, pixelated or mipmapped -- got {:?}", item),
         };
         let clip = self.to_clip_region(&item["clip"], &bounds, wrench).unwrap_or(*clip_region);
         self.builder().push_image(bounds, clip, stretch_size, tile_spacing, rendering, image_key);
@@ -363,8 +452,11 @@ impl YamlFrameReader {
                 else if !item["items"].is_badvalue() { "stacking_context" }
                 // note: box_shadow shorthand check has to come before border
                 else if !item["box_shadow"].is_badvalue() { "box_shadow" }
+                else if !item["border_image"].is_badvalue() { "border_image" }
                 else if !item["border"].is_badvalue() { "border" }
                 else if !item["gradient"].is_badvalue() { "gradient" }
+                else if !item["radial_gradient"].is_badvalue() { "radial_gradient" }
+                else if !item["conic_gradient"].is_badvalue() { "conic_gradient" }
                 else { item["type"].as_str().unwrap_or("unknown") };
 
             if item_type != "stacking_context" &&
@@ -379,7 +471,10 @@ impl YamlFrameReader {
                 "text" | "glyphs" => self.handle_text(wrench, &full_clip_region, &item),
                 "stacking_context" => self.add_stacking_context_from_yaml(wrench, &item),
                 "border" => self.handle_border(wrench, &full_clip_region, &item),
+                "border_image" => self.handle_border_image(wrench, &full_clip_region, &item),
                 "gradient" => self.handle_gradient(wrench, &full_clip_region, &item),
+                "radial_gradient" => self.handle_radial_gradient(wrench, &full_clip_region, &item),
+                "conic_gradient" => self.handle_conic_gradient(wrench, &full_clip_region, &item),
                 "box_shadow" => self.handle_box_shadow(wrench, &full_clip_region, &item),
                 _ => {
                     //println!("Skipping {:?}", item);
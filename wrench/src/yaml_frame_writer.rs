@@ -102,6 +102,15 @@ fn table_node(parent: &mut Table, key: &str, value: Table) {
     yaml_node(parent, key, Yaml::Hash(value));
 }
 
+fn extend_mode_node(parent: &mut Table, key: &str, value: ExtendMode) {
+    let mode = match value {
+        ExtendMode::Clamp => "clamp",
+        ExtendMode::Repeat => "repeat",
+        ExtendMode::Reflect => "reflect",
+    };
+    str_node(parent, key, mode);
+}
+
 fn string_vec_yaml(value: &[String], check_unique: bool) -> Yaml {
     if value.len() > 0 && check_unique && array_elements_are_same(value) {
         Yaml::String(value[0].clone())
@@ -201,6 +210,19 @@ struct CachedImage {
     path: Option<PathBuf>,
 }
 
+fn packed_stride(width: u32, format: ImageFormat) -> u32 {
+    match format {
+        ImageFormat::A8 => width,
+        ImageFormat::RGBA8 | ImageFormat::RGB8 => width*4,
+        ImageFormat::RGBAF32 => width*16,
+        ImageFormat::R16 | ImageFormat::RG8 => width*2,
+        ImageFormat::RGBA16F => width*8,
+        // The stride of a compressed image is that of a row of blocks.
+        _ if format.is_compressed() => format.packed_size(width, 1).unwrap(),
+        _ => panic!("Invalid image format"),
+    }
+}
+
 pub struct YamlFrameWriter {
     frame_base: PathBuf,
     rsrc_base: PathBuf,
@@ -468,6 +490,7 @@ impl YamlFrameWriter {
                         ImageRendering::Auto => (),
                         ImageRendering::CrispEdges => str_node(&mut v, "rendering", "crisp-edges"),
                         ImageRendering::Pixelated => str_node(&mut v, "rendering", "pixelated"),
+                        ImageRendering::Mipmapped => str_node(&mut v, "rendering", "mipmapped"),
                     };
                 },
                 YuvImage(_) => {
@@ -506,6 +529,34 @@ impl YamlFrameWriter {
                         yaml_node(&mut v, "radius", radius_node);
                     }
                 },
+                BorderImage(item) => {
+                    str_node(&mut v, "type", "border_image");
+                    if let Some(path) = self.path_for_image(&item.image_key) {
+                        path_node(&mut v, "src", &path);
+                    }
+                    let slice = [item.slice.top, item.slice.right, item.slice.bottom, item.slice.left];
+                    let widths = [item.widths.top, item.widths.right, item.widths.bottom, item.widths.left];
+                    let repeat: Vec<String> = [item.repeat_top, item.repeat_right,
+                                               item.repeat_bottom, item.repeat_left].iter().map(|mode| {
+                        match *mode {
+                            RepeatMode::Stretch => "stretch",
+                            RepeatMode::Repeat => "repeat",
+                            RepeatMode::Round => "round",
+                        }.to_owned()
+                    }).collect();
+                    yaml_node(&mut v, "slice", f32_vec_yaml(&slice, true));
+                    yaml_node(&mut v, "width", f32_vec_yaml(&widths, true));
+                    yaml_node(&mut v, "repeat", string_vec_yaml(&repeat, true));
+                    match item.image_rendering {
+                        ImageRendering::Auto => (),
+                        ImageRendering::CrispEdges => str_node(&mut v, "rendering", "crisp_edges"),
+                        ImageRendering::Pixelated => str_node(&mut v, "rendering", "pixelated"),
+                        ImageRendering::Mipmapped => str_node(&mut v, "rendering", "mipmapped"),
+                    };
+                    if item.fill {
+                        bool_node(&mut v, "fill", true);
+                    }
+                },
                 BoxShadow(item) => {
                     str_node(&mut v, "type", "box-shadow");
                     rect_node(&mut v, "box_bounds", &item.box_bounds);
@@ -531,6 +582,33 @@ impl YamlFrameWriter {
                         stops.push(Yaml::String(color_to_string(stop.color)));
                     }
                     yaml_node(&mut v, "stops", Yaml::Array(stops));
+                    extend_mode_node(&mut v, "extend_mode", item.extend_mode);
+                },
+                RadialGradient(item) => {
+                    str_node(&mut v, "type", "radial_gradient");
+                    point_node(&mut v, "center", &item.center);
+                    size_node(&mut v, "radius", &item.radius);
+                    f32_node(&mut v, "start_radius", item.start_radius);
+                    f32_node(&mut v, "end_radius", item.end_radius);
+                    let mut stops = vec![];
+                    for stop in aux.gradient_stops(&item.stops) {
+                        stops.push(Yaml::Real(stop.offset.to_string()));
+                        stops.push(Yaml::String(color_to_string(stop.color)));
+                    }
+                    yaml_node(&mut v, "stops", Yaml::Array(stops));
+                    extend_mode_node(&mut v, "extend_mode", item.extend_mode);
+                },
+                ConicGradient(item) => {
+                    str_node(&mut v, "type", "conic_gradient");
+                    point_node(&mut v, "center", &item.center);
+                    f32_node(&mut v, "angle", item.angle.to_degrees());
+                    let mut stops = vec![];
+                    for stop in aux.gradient_stops(&item.stops) {
+                        stops.push(Yaml::Real(stop.offset.to_string()));
+                        stops.push(Yaml::String(color_to_string(stop.color)));
+                    }
+                    yaml_node(&mut v, "stops", Yaml::Array(stops));
+                    extend_mode_node(&mut v, "extend_mode", item.extend_mode);
                 },
                 Iframe(item) => {
                     str_node(&mut v, "type", "iframe");
@@ -571,8 +649,9 @@ impl webrender::ApiRecordingReceiver for YamlFrameWriter {
     fn write_msg(&mut self, _: u32, msg: &ApiMsg) {
         match msg {
             &ApiMsg::SetRootPipeline(..) |
+            &ApiMsg::SetDevicePixelRatio(..) |
             &ApiMsg::Scroll(..) |
-            &ApiMsg::TickScrollingBounce |
+            &ApiMsg::TickScrollingBounce(..) |
             &ApiMsg::WebGLCommand(..) => {
             }
 
@@ -586,16 +665,7 @@ impl webrender::ApiRecordingReceiver for YamlFrameWriter {
 
             &ApiMsg::AddImage(ref key, width, height, stride,
                               format, ref data) => {
-                let stride = if let Some(stride) = stride {
-                    stride
-                } else {
-                    match format {
-                        ImageFormat::A8 => width,
-                        ImageFormat::RGBA8 | ImageFormat::RGB8 => width*4,
-                        ImageFormat::RGBAF32 => width*16,
-                        _ => panic!("Invalid image format"),
-                    }
-                };
+                let stride = stride.unwrap_or_else(|| packed_stride(width, format));
                 let bytes = match data {
                     &ImageData::Raw(ref v) => { (**v).clone() }
                     &ImageData::External(_) => { return; }
@@ -611,19 +681,46 @@ impl webrender::ApiRecordingReceiver for YamlFrameWriter {
             &ApiMsg::UpdateImage(ref key, width, height,
                                  format, ref bytes) => {
                 if let Some(ref mut data) = self.images.get_mut(key) {
-                    assert!(data.width == width);
-                    assert!(data.height == height);
-                    assert!(data.format == format);
+                    data.width = width;
+                    data.height = height;
+                    data.stride = packed_stride(width, format);
+                    data.format = format;
 
                     *data.path.borrow_mut() = None;
                     *data.bytes.borrow_mut() = Some(bytes.clone());
                 }
             }
 
+            &ApiMsg::UpdateImageRegion(ref key, ref dirty_rect, ref bytes, stride) => {
+                if let Some(ref mut data) = self.images.get_mut(key) {
+                    let bpp = data.format.bytes_per_pixel().unwrap() as usize;
+                    let row_len = dirty_rect.size.width as usize * bpp;
+                    let src_stride = stride.map_or(row_len, |stride| stride as usize);
+                    let dst_stride = data.stride as usize;
+                    match data.bytes {
+                        Some(ref mut image_bytes) => {
+                            for row in 0..dirty_rect.size.height as usize {
+                                let src = row * src_stride;
+                                let dst = (dirty_rect.origin.y as usize + row) * dst_stride +
+                                          dirty_rect.origin.x as usize * bpp;
+                                image_bytes[dst..dst + row_len].copy_from_slice(&bytes[src..src + row_len]);
+                            }
+                        }
+                        None => {
+                            warn!("Region update of image {:?} after it was written", key);
+                        }
+                    }
+                }
+            }
+
             &ApiMsg::DeleteImage(ref key) => {
                 self.images.remove(key);
             }
 
+            &ApiMsg::DeleteFont(ref key) => {
+                self.fonts.remove(key);
+            }
+
             &ApiMsg::SetRootDisplayList(ref background_color,
                                         ref epoch,
                                         ref pipeline_id,